@@ -0,0 +1,181 @@
+use codespan_reporting::diagnostic::Diagnostic;
+
+use crate::{
+    errors::{
+        borrow_err::BorrowErr, emit, fold_err::FoldErr, ownership_err::OwnershipErr,
+        pattern_err::PatternErr, resolve_err::ResolveErr, tail_call_err::TailCallErr,
+        type_err::TypeErr, ErrorFormat,
+    },
+    hir::{fold::fold, resolve::resolve, Program},
+    mir::{check_borrows, check_tail_calls},
+    source::{FileId, SourceDb},
+    syntax::ast::{Item, Node},
+    typeck::{check_ownership, check_patterns, typecheck, Types},
+};
+
+/// The diagnostics of a step of checking a program
+#[derive(Debug)]
+pub enum Diagnostics<'a> {
+    Resolve(Vec<ResolveErr<'a>>),
+    Type(Vec<TypeErr>),
+    Pattern(Vec<PatternErr>),
+    Ownership(Vec<OwnershipErr>),
+    Borrow(Vec<BorrowErr>),
+    TailCall(Vec<TailCallErr>),
+    Fold(Vec<FoldErr>),
+}
+impl<'a> Diagnostics<'a> {
+    /// Whether there's an error, not only warnings
+    pub fn failed(&self) -> bool {
+        match self {
+            Diagnostics::Resolve(errors) => errors.iter().any(|e| e.is_error()),
+            Diagnostics::Type(errors) => !errors.is_empty(),
+            Diagnostics::Pattern(errors) => errors.iter().any(|e| e.is_error()),
+            Diagnostics::Ownership(errors) => !errors.is_empty(),
+            Diagnostics::Borrow(errors) => !errors.is_empty(),
+            Diagnostics::TailCall(errors) => !errors.is_empty(),
+            Diagnostics::Fold(_) => false,
+        }
+    }
+    fn is_empty(&self) -> bool {
+        match self {
+            Diagnostics::Resolve(errors) => errors.is_empty(),
+            Diagnostics::Type(errors) => errors.is_empty(),
+            Diagnostics::Pattern(errors) => errors.is_empty(),
+            Diagnostics::Ownership(errors) => errors.is_empty(),
+            Diagnostics::Borrow(errors) => errors.is_empty(),
+            Diagnostics::TailCall(errors) => errors.is_empty(),
+            Diagnostics::Fold(warnings) => warnings.is_empty(),
+        }
+    }
+    pub fn emit(self, db: &SourceDb, format: ErrorFormat) {
+        match self {
+            Diagnostics::Resolve(errors) => emit(db, format, errors),
+            Diagnostics::Type(errors) => emit(db, format, errors),
+            Diagnostics::Pattern(errors) => emit(db, format, errors),
+            Diagnostics::Ownership(errors) => emit(db, format, errors),
+            Diagnostics::Borrow(errors) => emit(db, format, errors),
+            Diagnostics::TailCall(errors) => emit(db, format, errors),
+            Diagnostics::Fold(warnings) => emit(db, format, warnings),
+        }
+    }
+}
+impl<'a> From<Diagnostics<'a>> for Vec<Diagnostic<FileId>> {
+    fn from(diagnostics: Diagnostics<'a>) -> Self {
+        fn all<E: Into<Diagnostic<FileId>>>(errors: Vec<E>) -> Vec<Diagnostic<FileId>> {
+            errors.into_iter().map(Into::into).collect()
+        }
+        match diagnostics {
+            Diagnostics::Resolve(errors) => all(errors),
+            Diagnostics::Type(errors) => all(errors),
+            Diagnostics::Pattern(errors) => all(errors),
+            Diagnostics::Ownership(errors) => all(errors),
+            Diagnostics::Borrow(errors) => all(errors),
+            Diagnostics::TailCall(errors) => all(errors),
+            Diagnostics::Fold(warnings) => all(warnings),
+        }
+    }
+}
+
+/// A program with the diagnostics of the steps checking it
+pub struct Checked<'a> {
+    pub program: Program<'a>,
+    /// Once the program is type checked, even with errors
+    pub types: Option<Types>,
+    /// In the order of the steps, only the ones that reported something
+    pub diagnostics: Vec<Diagnostics<'a>>,
+}
+impl<'a> Checked<'a> {
+    pub fn failed(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostics::failed)
+    }
+}
+
+/// Resolves, type checks and checks the patterns, the ownership, the borrows then the tail calls
+/// of `items`, and folds the checked program if `folding`. Each step only happens once the
+/// previous one succeeds, type checking elaborates the program and ownership checking its drops
+pub fn check_program<'a>(items: &[Node<Item<'a>>], folding: bool) -> Checked<'a> {
+    let (mut program, errors) = resolve(items);
+    let mut diagnostics = vec![];
+    let types = check_resolved(&mut program, errors, folding, &mut diagnostics);
+    Checked {
+        program,
+        types,
+        diagnostics,
+    }
+}
+
+// The steps after resolution, returning the types once there are
+fn check_resolved<'a>(
+    program: &mut Program<'a>,
+    errors: Vec<ResolveErr<'a>>,
+    folding: bool,
+    diagnostics: &mut Vec<Diagnostics<'a>>,
+) -> Option<Types> {
+    // Whether the step failed
+    let mut failed = |step: Diagnostics<'a>| {
+        let failed = step.failed();
+        if !step.is_empty() {
+            diagnostics.push(step);
+        }
+        failed
+    };
+    if failed(Diagnostics::Resolve(errors)) {
+        return None;
+    }
+    let (mut types, errors) = typecheck(program);
+    if failed(Diagnostics::Type(errors))
+        || failed(Diagnostics::Pattern(check_patterns(program)))
+        || failed(Diagnostics::Ownership(check_ownership(program, &mut types)))
+        || failed(Diagnostics::Borrow(check_borrows(program, &types)))
+        || failed(Diagnostics::TailCall(check_tail_calls(program, &types)))
+    {
+        return Some(types);
+    }
+    if folding {
+        failed(Diagnostics::Fold(fold(program)));
+    }
+    Some(types)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{prelude::prelude, syntax::parse_program};
+
+    /// The diagnostics of the step `$step` of `$checked`, asserting no other step failed
+    macro_rules! step_errors {
+        ($checked:expr, $step:ident) => {{
+            let mut errors = vec![];
+            for diagnostics in std::mem::take(&mut $checked.diagnostics) {
+                match diagnostics {
+                    $crate::check::Diagnostics::$step(e) => errors = e,
+                    d => assert!(!d.failed(), "{:?}", d),
+                }
+            }
+            errors
+        }};
+    }
+    pub(crate) use step_errors;
+
+    // `code`, after the prelude, checked
+    pub(crate) fn check_code(code: &str, folding: bool) -> Checked<'static> {
+        let db = Box::leak(Box::new(SourceDb::new()));
+        let mut items = prelude(db);
+        items.extend(parse_program(1, Box::leak(code.into())).expect(code));
+        check_program(&items, folding)
+    }
+    // A program, with the prelude, that checks, and its types
+    pub(crate) fn program(code: &str, folding: bool) -> (Program<'static>, Types) {
+        let db = Box::leak(Box::new(SourceDb::new()));
+        let mut items = prelude(db);
+        items.extend(parse_program(1, Box::leak(code.into())).expect(code));
+        checked(&items, folding)
+    }
+    // The program of `items`, which must check, and its types
+    pub(crate) fn checked<'a>(items: &[Node<Item<'a>>], folding: bool) -> (Program<'a>, Types) {
+        let checked = check_program(items, folding);
+        assert!(!checked.failed(), "{:?}", checked.diagnostics);
+        (checked.program, checked.types.unwrap())
+    }
+}
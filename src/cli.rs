@@ -0,0 +1,671 @@
+use std::{
+    fmt, fs,
+    io::{self, Read},
+    path::PathBuf,
+    time::Instant,
+};
+
+use crate::{
+    check::{check_program, Checked},
+    errors::{
+        apply_suggestions,
+        codes::{explain, ErrorCode},
+        emit, Applicability, ErrorFormat, Report, Suggestion,
+    },
+    errors::{resolve_err::ResolveErr, runtime_err::RuntimeErr},
+    eval::{with_stack, Interpreter},
+    hir::{resolve::resolve, DefId, Expr, Kind, Program},
+    mir::{lower, lower_expr, Machine},
+    native,
+    prelude::prelude,
+    repl::Repl,
+    source::SourceDb,
+    syntax::{
+        ast::{Item, Node},
+        insensitive_layout::block_inference,
+        lex, parse_program,
+        tokens::pretty_print_tokens,
+    },
+    typeck::Types,
+    vm::{compile, compile_expr, HeapStats, Vm},
+    wasm,
+};
+
+const USAGE: &str = "\
+Usage: kamai [options] <command> [file]
+
+Commands:
+    run       Check a program and print the value of its `main`
+    build     Compile a program to a native executable printing the value of its `main`
+    c         Print a program compiled to C, which any C compiler builds to an executable
+              printing the value of its `main`
+    wasm      Compile a program to a WebAssembly module for WASI runtimes, printing the
+              value of its `main`
+    check     Report the errors of a program without running it
+    tokens    Print the tokens of a program
+    layout    Print the tokens of a program with its inferred blocks
+    ast       Print the syntax tree of a program
+    types     Print the inferred type of each item of a program
+    mir       Print the control-flow graphs of the items of a program
+    fix       Print a program with the fixes suggested by its diagnostics applied, reporting
+              the diagnostics they don't fix
+    repl      Start an interactive session
+    bench     Time the evaluation of a program's `main` on each backend
+    explain   Print the explanation of an error code, like `kamai explain E0001`
+    help      Print this message
+
+Options:
+    --error-format=<human|json>
+              Print diagnostics for humans (the default) or as JSON objects, one per line
+    --backend=<vm|tree|mir>
+              Evaluate programs with the bytecode VM (the default), the tree-walking
+              interpreter or the interpreter of the MIR
+    --gc-stats
+              Print the collections, pause times and heap size of the VM's garbage
+              collector once a program ran
+    --output=<path>
+              Where `build` writes the executable, the program's file without its
+              extension by default, and where `wasm` writes the module, with a `.wasm`
+              extension by default
+
+When no file (or `-`) is given, the program is read from stdin.
+`repl` loads the declarations of its file, if any, before starting.";
+
+const EXIT_ERRORS: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run,
+    Build,
+    C,
+    Wasm,
+    Check,
+    Tokens,
+    Layout,
+    Ast,
+    Types,
+    Mir,
+    Fix,
+    Repl,
+    Bench,
+    Explain(String),
+    Help,
+}
+
+/// What evaluates programs
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Backend {
+    Vm,
+    Tree,
+    Mir,
+}
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::Vm => write!(f, "vm"),
+            Backend::Tree => write!(f, "tree"),
+            Backend::Mir => write!(f, "mir"),
+        }
+    }
+}
+/// What to evaluate
+pub enum Target<'p> {
+    Item(DefId),
+    /// An expression using the items of the program, for the REPL
+    Expr(&'p Node<Expr<'p>>),
+}
+impl Backend {
+    /// Evaluates the target of a checked program and shows its value
+    pub fn eval<'p>(
+        self,
+        program: &'p Program<'p>,
+        types: &Types,
+        target: Target<'p>,
+    ) -> Result<String, RuntimeErr> {
+        self.eval_with_stats(program, types, target).0
+    }
+    /// Evaluates the target of a checked program and shows its value, with the statistics of
+    /// the garbage collected heap for the VM. The interpreters' values are reference counted
+    /// instead
+    pub fn eval_with_stats<'p>(
+        self,
+        program: &'p Program<'p>,
+        types: &Types,
+        target: Target<'p>,
+    ) -> (Result<String, RuntimeErr>, Option<HeapStats>) {
+        match self {
+            Backend::Tree => with_stack(|| {
+                let mut interpreter = Interpreter::new(program);
+                let value = match target {
+                    Target::Item(id) => interpreter.item(id),
+                    Target::Expr(e) => interpreter.expr(e),
+                };
+                (value.map(|v| v.to_string()), None)
+            }),
+            Backend::Vm => {
+                let bytecode;
+                let mut vm;
+                let value = match target {
+                    Target::Item(id) => {
+                        bytecode = compile(program);
+                        vm = Vm::new(&bytecode);
+                        vm.item(id)
+                    }
+                    Target::Expr(e) => {
+                        let entry;
+                        (bytecode, entry) = compile_expr(program, e);
+                        vm = Vm::new(&bytecode);
+                        vm.run(entry)
+                    }
+                };
+                let value = value.map(|v| vm.show(v).to_string());
+                (value, Some(vm.heap_stats().clone()))
+            }
+            Backend::Mir => {
+                let value = match target {
+                    Target::Item(id) => Machine::new(program, &lower(program, types)).item(id),
+                    Target::Expr(e) => {
+                        let (mir, entry) = lower_expr(program, e, types);
+                        Machine::new(program, &mir).run(entry)
+                    }
+                };
+                (value.map(|v| v.to_string()), None)
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    File(PathBuf),
+    Stdin,
+}
+impl Input {
+    fn name(&self) -> String {
+        match self {
+            Input::File(path) => path.display().to_string(),
+            Input::Stdin => "<stdin>".to_string(),
+        }
+    }
+    // Where `build` writes the executable by default, never over the program
+    fn executable(&self) -> PathBuf {
+        match self {
+            Input::File(path) if path.extension().is_some() => path.with_extension(""),
+            Input::File(path) => path.with_extension("out"),
+            Input::Stdin => PathBuf::from("main"),
+        }
+    }
+    fn read(&self) -> io::Result<String> {
+        match self {
+            Input::File(path) => fs::read_to_string(path),
+            Input::Stdin => {
+                let mut code = String::new();
+                io::stdin().read_to_string(&mut code)?;
+                Ok(code)
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    pub command: Command,
+    pub input: Input,
+    pub error_format: ErrorFormat,
+    pub backend: Backend,
+    pub gc_stats: bool,
+    pub output: Option<PathBuf>,
+}
+
+// The value of an option when `arg` is it, given as `--option=value` or `--option value`
+fn option(
+    arg: &str,
+    name: &str,
+    args: &mut impl Iterator<Item = String>,
+) -> Option<Option<String>> {
+    match arg.strip_prefix(name) {
+        Some("") => Some(args.next()),
+        Some(value) if value.starts_with('=') => Some(Some(value[1..].to_string())),
+        _ => None,
+    }
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut error_format = ErrorFormat::Human;
+    let mut backend = Backend::Vm;
+    let mut gc_stats = false;
+    let mut output = None;
+    let mut positional = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if let Some(format) = option(&arg, "--error-format", &mut args) {
+            error_format = match format.as_deref() {
+                Some("human") => ErrorFormat::Human,
+                Some("json") => ErrorFormat::Json,
+                Some(format) => return Err(format!("unknown error format `{}`", format)),
+                None => return Err("missing error format after `--error-format`".to_string()),
+            };
+        } else if let Some(name) = option(&arg, "--backend", &mut args) {
+            backend = match name.as_deref() {
+                Some("vm") => Backend::Vm,
+                Some("tree") => Backend::Tree,
+                Some("mir") => Backend::Mir,
+                Some(name) => return Err(format!("unknown backend `{}`", name)),
+                None => return Err("missing backend after `--backend`".to_string()),
+            };
+        } else if let Some(path) = option(&arg, "--output", &mut args) {
+            match path {
+                Some(path) => output = Some(PathBuf::from(path)),
+                None => return Err("missing path after `--output`".to_string()),
+            }
+        } else if arg == "--gc-stats" {
+            gc_stats = true;
+        } else {
+            positional.push(arg);
+        }
+    }
+    if gc_stats && backend != Backend::Vm {
+        let msg = "`--gc-stats` needs the `vm` backend, whose heap is garbage collected";
+        return Err(msg.to_string());
+    }
+    let mut positional = positional.into_iter();
+    let command = match positional.next().as_deref() {
+        Some("run") => Command::Run,
+        Some("build") => Command::Build,
+        Some("c") => Command::C,
+        Some("wasm") => Command::Wasm,
+        Some("check") => Command::Check,
+        Some("tokens") => Command::Tokens,
+        Some("layout") => Command::Layout,
+        Some("ast") => Command::Ast,
+        Some("types") => Command::Types,
+        Some("mir") => Command::Mir,
+        Some("fix") => Command::Fix,
+        Some("repl") => Command::Repl,
+        Some("bench") => Command::Bench,
+        Some("explain") => match positional.next() {
+            Some(code) => Command::Explain(code),
+            None => return Err("missing error code after `explain`".to_string()),
+        },
+        Some("help" | "-h" | "--help") | None => Command::Help,
+        Some(cmd) => return Err(format!("unknown command `{}`", cmd)),
+    };
+    let input = match positional.next().as_deref() {
+        None | Some("-") => Input::Stdin,
+        Some(path) => Input::File(PathBuf::from(path)),
+    };
+    if let Some(arg) = positional.next() {
+        return Err(format!("unexpected argument `{}`", arg));
+    }
+    Ok(Args {
+        command,
+        input,
+        error_format,
+        backend,
+        gc_stats,
+        output,
+    })
+}
+
+pub fn run(args: impl IntoIterator<Item = String>) -> i32 {
+    let Args {
+        command,
+        input,
+        error_format,
+        backend,
+        gc_stats,
+        output,
+    } = match parse_args(args) {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("error: {}\n\n{}", msg, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    match command {
+        Command::Help => {
+            println!("{}", USAGE);
+            return 0;
+        }
+        Command::Explain(code) => {
+            return match explain(&code) {
+                Some(ErrorCode {
+                    code,
+                    title,
+                    description,
+                    example,
+                }) => {
+                    println!("{}: {}\n\n{}\n\nExample:\n", code, title, description);
+                    for line in example.lines() {
+                        println!("    {}", line);
+                    }
+                    0
+                }
+                None => {
+                    eprintln!("error: unknown error code `{}`", code);
+                    EXIT_USAGE
+                }
+            };
+        }
+        Command::Repl => {
+            let mut repl = Repl::new(error_format, backend);
+            if let Input::File(path) = &input {
+                repl.load(path);
+            }
+            return repl.run();
+        }
+        _ => (),
+    }
+    let db = SourceDb::new();
+    let file = match input.read() {
+        Ok(code) => db.add(input.name(), code),
+        Err(e) => {
+            eprintln!("error: couldn't read {}: {}", input.name(), e);
+            return EXIT_USAGE;
+        }
+    };
+    let code = db.source(file);
+    match command {
+        Command::Tokens => {
+            pretty_print_tokens(&lex(code));
+            println!();
+            0
+        }
+        Command::Layout => match block_inference(file, lex(code)) {
+            Ok(tokens) => {
+                pretty_print_tokens(&tokens);
+                println!();
+                0
+            }
+            Err(e) => {
+                emit(&db, error_format, vec![e]);
+                EXIT_ERRORS
+            }
+        },
+        // Syntax errors are fixed first, then resolution errors
+        Command::Fix => match parse_program(file, code) {
+            Ok(items) => {
+                let prelude = prelude(&db);
+                let errors = resolution_errors(&prelude, &items);
+                fix(&db, error_format, code, errors, |fixed| {
+                    parse_program(file, fixed)
+                        .is_ok_and(|items| resolution_errors(&prelude, &items).is_empty())
+                })
+            }
+            Err(errors) => fix(&db, error_format, code, errors, |fixed| {
+                parse_program(file, fixed).is_ok()
+            }),
+        },
+        Command::Ast => match parse_program(file, code) {
+            Ok(items) => {
+                println!("{:#?}", items);
+                0
+            }
+            Err(errors) => {
+                emit(&db, error_format, errors);
+                EXIT_ERRORS
+            }
+        },
+        Command::Types
+        | Command::Mir
+        | Command::Check
+        | Command::Run
+        | Command::Build
+        | Command::C
+        | Command::Wasm
+        | Command::Bench => {
+            let (program, types) = match parse_program(file, code) {
+                Ok(items) => {
+                    let items = [prelude(&db), items].concat();
+                    match analyze(&db, error_format, &items) {
+                        Some(analyzed) => analyzed,
+                        None => return EXIT_ERRORS,
+                    }
+                }
+                Err(errors) => {
+                    emit(&db, error_format, errors);
+                    return EXIT_ERRORS;
+                }
+            };
+            match command {
+                Command::Types => {
+                    let classes = program.classes.iter().zip(&types.kinds.classes);
+                    for (class, kind) in classes.filter(|(c, _)| c.name.span.file == file) {
+                        match kind {
+                            Kind::Star => println!("{} :: * -> Constraint", class.name.value),
+                            _ => println!("{} :: ({}) -> Constraint", class.name.value, kind),
+                        }
+                    }
+                    let datas = program.datas.iter().zip(&types.kinds.datas);
+                    for ((data, kind), ctors) in datas.zip(&types.ctors) {
+                        match data.owned {
+                            true => println!("owned {} :: {}", data.name.value, kind),
+                            false => println!("{} :: {}", data.name.value, kind),
+                        }
+                        for (ctor, scheme) in data.ctors.iter().zip(ctors) {
+                            println!("{} : {}", ctor.name.value, scheme);
+                        }
+                    }
+                    let items = program.items.iter().zip(&types.items);
+                    // Instance methods have the type of their class' method
+                    for (item, scheme) in items.filter(|(item, _)| item.instance.is_none()) {
+                        println!("{} : {}", item.name.value, scheme);
+                    }
+                    0
+                }
+                Command::Mir => {
+                    let mir = lower(&program, &types);
+                    let bodies = mir.bodies.iter().enumerate();
+                    for (idx, body) in bodies.filter(|(_, body)| body.span.file == file) {
+                        println!("#{} {}", idx, body);
+                    }
+                    0
+                }
+                Command::Check => 0,
+                _ => {
+                    let main = program
+                        .items
+                        .iter()
+                        .find(|item| item.name.value == "main" && item.name.span.file == file);
+                    let main = match main {
+                        Some(main) => main.id,
+                        None => {
+                            eprintln!("error: {} has no `main` to run", input.name());
+                            return EXIT_ERRORS;
+                        }
+                    };
+                    if command == Command::C {
+                        print!("{}", native::emit_c(&db, &program, &types, main));
+                        return 0;
+                    }
+                    if command == Command::Wasm {
+                        let output =
+                            output.unwrap_or_else(|| input.executable().with_extension("wasm"));
+                        let module = wasm::build(&db, &program, &types, main);
+                        return match fs::write(&output, module) {
+                            Ok(()) => 0,
+                            Err(e) => {
+                                eprintln!("error: couldn't write {}: {}", output.display(), e);
+                                EXIT_ERRORS
+                            }
+                        };
+                    }
+                    if command == Command::Build {
+                        let output = output.unwrap_or_else(|| input.executable());
+                        return match native::build(&db, &program, &types, main, &output) {
+                            Ok(()) => 0,
+                            Err(e) => {
+                                eprintln!("error: {}", e);
+                                EXIT_ERRORS
+                            }
+                        };
+                    }
+                    let backends = match command {
+                        Command::Bench => vec![Backend::Tree, Backend::Vm, Backend::Mir],
+                        _ => vec![backend],
+                    };
+                    for backend in backends {
+                        let start = Instant::now();
+                        let (value, stats) =
+                            backend.eval_with_stats(&program, &types, Target::Item(main));
+                        match value {
+                            Ok(_) if command == Command::Bench => {
+                                println!("{:>4}: {:?}", backend, start.elapsed())
+                            }
+                            Ok(value) => println!("{}", value),
+                            Err(e) => {
+                                emit(&db, error_format, vec![e]);
+                                return EXIT_ERRORS;
+                            }
+                        }
+                        if let (true, Some(stats)) = (gc_stats, stats) {
+                            eprintln!("{}", stats);
+                        }
+                    }
+                    0
+                }
+            }
+        }
+        Command::Help | Command::Repl | Command::Explain(_) => unreachable!(),
+    }
+}
+
+/// Prints `code` with the machine applicable fixes of `errors` applied. When the fixed code still
+/// isn't `fixed`, reports the errors without a fix and fails
+fn fix<E: Report>(
+    db: &SourceDb,
+    error_format: ErrorFormat,
+    code: &str,
+    errors: Vec<E>,
+    fixed: impl Fn(&str) -> bool,
+) -> i32 {
+    let suggestions: Vec<_> = errors.iter().flat_map(|e| e.suggestions()).collect();
+    let code = apply_suggestions(code, &suggestions);
+    print!("{}", code);
+    if fixed(&code) {
+        return 0;
+    }
+    let unfixed = errors.into_iter().filter(|e| {
+        let fix = |s: &Suggestion| s.applicability == Applicability::MachineApplicable;
+        !e.suggestions().iter().any(fix)
+    });
+    emit(db, error_format, unfixed);
+    EXIT_ERRORS
+}
+
+// The resolution errors of `items`, after the prelude
+fn resolution_errors<'a>(
+    prelude: &[Node<Item<'a>>],
+    items: &[Node<Item<'a>>],
+) -> Vec<ResolveErr<'a>> {
+    let (_, errors) = resolve(&[prelude, items].concat());
+    errors.into_iter().filter(|e| e.is_error()).collect()
+}
+
+/// Checks `items`, reporting every diagnostic along the way, and returns the folded program once
+/// it checks
+fn analyze<'a>(
+    db: &SourceDb,
+    error_format: ErrorFormat,
+    items: &[Node<Item<'a>>],
+) -> Option<(Program<'a>, Types)> {
+    let checked = check_program(items, true);
+    let failed = checked.failed();
+    let Checked {
+        program,
+        types,
+        diagnostics,
+    } = checked;
+    for diagnostics in diagnostics {
+        diagnostics.emit(db, error_format);
+    }
+    match failed {
+        true => None,
+        false => Some((program, types.unwrap())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn subcommands() {
+        assert_eq!(
+            args(&["check", "main.ka"]),
+            Ok(Args {
+                command: Command::Check,
+                input: Input::File(PathBuf::from("main.ka")),
+                error_format: ErrorFormat::Human,
+                backend: Backend::Vm,
+                gc_stats: false,
+                output: None,
+            })
+        );
+        assert_eq!(
+            args(&["layout", "-"]),
+            Ok(Args {
+                command: Command::Layout,
+                input: Input::Stdin,
+                error_format: ErrorFormat::Human,
+                backend: Backend::Vm,
+                gc_stats: false,
+                output: None,
+            })
+        );
+        assert_eq!(
+            args(&["check", "--error-format=json", "main.ka"]).map(|a| a.error_format),
+            Ok(ErrorFormat::Json)
+        );
+        assert_eq!(
+            args(&["--error-format", "json", "check"]).map(|a| a.error_format),
+            Ok(ErrorFormat::Json)
+        );
+        assert_eq!(
+            args(&["run", "--backend", "tree", "main.ka"]).map(|a| a.backend),
+            Ok(Backend::Tree)
+        );
+        assert_eq!(
+            args(&["run", "--backend=mir", "main.ka"]).map(|a| a.backend),
+            Ok(Backend::Mir)
+        );
+        assert_eq!(
+            args(&["run", "--gc-stats", "main.ka"]).map(|a| a.gc_stats),
+            Ok(true)
+        );
+        assert_eq!(
+            args(&["mir", "main.ka"]).map(|a| a.command),
+            Ok(Command::Mir)
+        );
+        assert_eq!(
+            args(&["build", "--output=out/main", "main.ka"]).map(|a| (a.command, a.output)),
+            Ok((Command::Build, Some(PathBuf::from("out/main"))))
+        );
+        assert_eq!(args(&["c", "main.ka"]).map(|a| a.command), Ok(Command::C));
+        assert_eq!(
+            args(&["wasm", "main.ka"]).map(|a| a.command),
+            Ok(Command::Wasm)
+        );
+        assert_eq!(args(&[]).map(|a| a.command), Ok(Command::Help));
+        assert_eq!(
+            args(&["explain", "E0001"]).map(|a| a.command),
+            Ok(Command::Explain("E0001".to_string()))
+        );
+    }
+
+    #[test]
+    fn bad_arguments() {
+        assert!(args(&["frobnicate"]).is_err());
+        assert!(args(&["run", "a.ka", "b.ka"]).is_err());
+        assert!(args(&["check", "--error-format=xml"]).is_err());
+        assert!(args(&["check", "--error-format"]).is_err());
+        assert!(args(&["run", "--backend=jit"]).is_err());
+        // The interpreter has no garbage collector
+        assert!(args(&["run", "--backend=tree", "--gc-stats"]).is_err());
+    }
+}
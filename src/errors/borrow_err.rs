@@ -0,0 +1,318 @@
+use super::{ErrorKind, Report, Suggestion};
+use crate::{
+    source::{FileId, Span},
+    typeck::{Type, TypeNames},
+};
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+#[derive(Debug)]
+pub struct BorrowErr {
+    pub span: Span,
+    pub kind: BorrowErrKind,
+}
+
+/// How a borrowed place is used
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Access {
+    Read,
+    /// Borrowed again, mutably when it's `true`
+    Borrow(bool),
+    Move,
+    Write,
+}
+
+/// A later use of a borrow, which keeps it alive
+#[derive(Debug, PartialEq, Clone)]
+pub struct Later {
+    pub span: Span,
+    /// Whether the borrow is returned by its function
+    pub returned: bool,
+}
+
+/// The places are written like in the source, `None` standing for a temporary value
+#[derive(Debug)]
+pub enum BorrowErrKind {
+    /// `place`, borrowed at `borrow` (mutably when `mutable`), accessed in a way conflicting
+    /// with the borrow while it's still used `later`
+    Conflict {
+        place: Option<String>,
+        access: Access,
+        borrow: Span,
+        mutable: bool,
+        later: Option<Later>,
+    },
+    /// A borrow of `place` used `later` than `place` is dropped, at `dropped`
+    NotLiveLongEnough {
+        place: Option<String>,
+        dropped: Span,
+        later: Option<Later>,
+    },
+    /// A borrow of `place`, of the owned type `ty`, after it was moved at `moved`
+    BorrowOfMoved {
+        place: Option<String>,
+        ty: Type,
+        moved: Span,
+    },
+    /// A value of the owned type `ty` moved out of the reference it's behind
+    MoveOutOfRef { place: Option<String>, ty: Type },
+    /// A local `name` holding references used by a lambda
+    Captured { name: String, ty: Type },
+    /// An argument holding references kept by the partial application it's given to
+    PartiallyApplied { ty: Type },
+    /// A field of the data type `data` whose type holds references
+    RefField { data: String, ty: Type },
+    /// A borrow of a temporary value of the owned type `ty`, which nothing would drop
+    OwnedTemporary { ty: Type },
+    /// An assignment to the local `name`, already bound at `binding` without `mut`
+    AssignTwice { name: String, binding: Span },
+    /// A mutable borrow of the local `name`, bound at `binding` without `mut`
+    BorrowImmutable { name: String, binding: Span },
+    /// An assignment to the local `name` captured by a lambda, or a mutable borrow of it
+    MutateCaptured { name: String, assign: bool },
+}
+impl ErrorKind for BorrowErrKind {
+    fn code(&self) -> &'static str {
+        match self {
+            BorrowErrKind::Conflict { .. } => "E0027",
+            BorrowErrKind::NotLiveLongEnough { .. } => "E0028",
+            BorrowErrKind::BorrowOfMoved { .. } => "E0029",
+            BorrowErrKind::MoveOutOfRef { .. } => "E0030",
+            BorrowErrKind::Captured { .. } | BorrowErrKind::PartiallyApplied { .. } => "E0031",
+            BorrowErrKind::RefField { .. } => "E0032",
+            BorrowErrKind::OwnedTemporary { .. } => "E0033",
+            BorrowErrKind::AssignTwice { .. } => "E0034",
+            BorrowErrKind::BorrowImmutable { .. } => "E0035",
+            BorrowErrKind::MutateCaptured { .. } => "E0036",
+        }
+    }
+}
+impl Report for BorrowErr {
+    fn suggestions(&self) -> Vec<Suggestion> {
+        match &self.kind {
+            BorrowErrKind::AssignTwice { binding, .. }
+            | BorrowErrKind::BorrowImmutable { binding, .. } => {
+                vec![add_mut(binding)]
+            }
+            _ => vec![],
+        }
+    }
+}
+
+// Makes a binding mutable
+fn add_mut(binding: &Span) -> Suggestion {
+    let start = binding.range.start;
+    Suggestion::replace(Span::new(binding.file, start..start), "mut ")
+}
+
+fn show(place: &Option<String>) -> String {
+    match place {
+        Some(place) => format!("`{}`", place),
+        None => "temporary value".to_string(),
+    }
+}
+fn later_label(later: Option<Later>, what: &str) -> Option<Label<FileId>> {
+    later.map(|Later { span, returned }| {
+        let message = match returned {
+            true => format!("{} later returned here", what),
+            false => format!("{} later used here", what),
+        };
+        Label::secondary(span.file, span.range).with_message(message)
+    })
+}
+
+impl From<BorrowErr> for Diagnostic<FileId> {
+    fn from(BorrowErr { span, kind }: BorrowErr) -> Self {
+        let mut names = TypeNames::default();
+        let diag = Diagnostic::error().with_code(kind.code());
+        let primary = Label::primary(span.file, span.range);
+        match kind {
+            BorrowErrKind::Conflict {
+                place,
+                access,
+                borrow,
+                mutable,
+                later,
+            } => {
+                let name = show(&place);
+                let borrowed = Label::secondary(borrow.file, borrow.range);
+                let (message, borrowed, primary) = match (access, mutable) {
+                    (Access::Read, _) => (
+                        format!("Cannot use {} because it is mutably borrowed", name),
+                        borrowed.with_message(format!("{} is borrowed here", name)),
+                        primary.with_message(format!("use of borrowed {}", name)),
+                    ),
+                    (Access::Borrow(false), _) => (
+                        format!(
+                            "Cannot borrow {} as immutable because it is also borrowed as mutable",
+                            name
+                        ),
+                        borrowed.with_message("mutable borrow occurs here"),
+                        primary.with_message("immutable borrow occurs here"),
+                    ),
+                    (Access::Borrow(true), false) => (
+                        format!(
+                            "Cannot borrow {} as mutable because it is also borrowed as immutable",
+                            name
+                        ),
+                        borrowed.with_message("immutable borrow occurs here"),
+                        primary.with_message("mutable borrow occurs here"),
+                    ),
+                    (Access::Borrow(true), true) => (
+                        format!("Cannot borrow {} as mutable more than once at a time", name),
+                        borrowed.with_message("first mutable borrow occurs here"),
+                        primary.with_message("second mutable borrow occurs here"),
+                    ),
+                    (Access::Move, _) => (
+                        format!("Cannot move out of {} because it is borrowed", name),
+                        borrowed.with_message(format!("borrow of {} occurs here", name)),
+                        primary.with_message(format!("move out of {} occurs here", name)),
+                    ),
+                    (Access::Write, _) => (
+                        format!("Cannot assign to {} because it is borrowed", name),
+                        borrowed.with_message(format!("{} is borrowed here", name)),
+                        primary.with_message(format!("{} is assigned to here", name)),
+                    ),
+                };
+                let what = if mutable {
+                    "mutable borrow"
+                } else {
+                    "immutable borrow"
+                };
+                let mut labels = vec![primary, borrowed];
+                labels.extend(later_label(later, what));
+                diag.with_message(message).with_labels(labels)
+            }
+            BorrowErrKind::NotLiveLongEnough {
+                place,
+                dropped,
+                later,
+            } => {
+                let message = match &place {
+                    Some(_) => format!("{} does not live long enough", show(&place)),
+                    None => "Temporary value dropped while borrowed".to_string(),
+                };
+                let mut labels = vec![
+                    primary.with_message("borrowed value does not live long enough"),
+                    Label::secondary(dropped.file, dropped.range).with_message(format!(
+                        "{} dropped here while still borrowed",
+                        show(&place)
+                    )),
+                ];
+                labels.extend(later_label(later, "borrow"));
+                diag.with_message(message).with_labels(labels)
+            }
+            BorrowErrKind::BorrowOfMoved { place, ty, moved } => {
+                let name = show(&place);
+                diag.with_message(format!("Borrow of moved value {}", name))
+                    .with_labels(vec![
+                        primary.with_message("value borrowed here after move"),
+                        Label::secondary(moved.file, moved.range)
+                            .with_message(format!("{} moved here", name)),
+                    ])
+                    .with_notes(vec![format!(
+                        "{} has the owned type `{}`, using it gives it to its new owner",
+                        name,
+                        names.show(&ty)
+                    )])
+            }
+            BorrowErrKind::MoveOutOfRef { place, ty } => diag
+                .with_message(format!(
+                    "Cannot move out of {}, which is behind a reference",
+                    show(&place)
+                ))
+                .with_labels(vec![primary.with_message(format!(
+                    "this has the owned type `{}`, which the reference doesn't own",
+                    names.show(&ty)
+                ))])
+                .with_notes(vec![
+                    "Borrow it instead, or only take apart its fields that aren't owned"
+                        .to_string(),
+                ]),
+            BorrowErrKind::Captured { name, ty } => diag
+                .with_message(format!("Reference `{}` captured by a lambda", name))
+                .with_labels(vec![primary.with_message(format!(
+                    "`{}` has the type `{}`",
+                    name,
+                    names.show(&ty)
+                ))])
+                .with_notes(vec![
+                    "Functions are garbage collected, so they could outlive what the reference \
+                     borrows: pass it to the lambda as a parameter instead"
+                        .to_string(),
+                ]),
+            BorrowErrKind::PartiallyApplied { ty } => diag
+                .with_message("Reference captured by a partial application")
+                .with_labels(vec![primary.with_message(format!(
+                    "this has the type `{}`, but the call still waits for arguments",
+                    names.show(&ty)
+                ))])
+                .with_notes(vec![
+                    "Functions are garbage collected, so they could outlive what the reference \
+                     borrows: give the other arguments in the same call"
+                        .to_string(),
+                ]),
+            BorrowErrKind::RefField { data, ty } => diag
+                .with_message(format!("Reference in a field of the data type `{}`", data))
+                .with_labels(vec![
+                    primary.with_message(format!("`{}` holds references", names.show(&ty)))
+                ])
+                .with_notes(vec![
+                    "Data values can be kept for any time, which a reference can't".to_string(),
+                ]),
+            BorrowErrKind::OwnedTemporary { ty } => diag
+                .with_message("Borrow of a temporary value of an owned type")
+                .with_labels(vec![primary.with_message(format!(
+                    "this has the owned type `{}`, nothing would drop it",
+                    names.show(&ty)
+                ))])
+                .with_notes(vec![
+                    "Bind it with `let` first, so that it's dropped at the end of its scope"
+                        .to_string(),
+                ]),
+            BorrowErrKind::AssignTwice { name, binding } => {
+                let help = add_mut(&binding);
+                diag.with_message(format!(
+                    "Cannot assign twice to immutable variable `{}`",
+                    name
+                ))
+                .with_labels(vec![
+                    primary.with_message(format!("cannot assign twice to `{}`", name)),
+                    Label::secondary(binding.file, binding.range)
+                        .with_message(format!("first assignment to `{}`", name)),
+                    Label::secondary(help.span.file, help.span.range.clone())
+                        .with_message(help.message()),
+                ])
+            }
+            BorrowErrKind::BorrowImmutable { name, binding } => {
+                let help = add_mut(&binding);
+                diag.with_message(format!(
+                    "Cannot borrow `{}` as mutable, as it is not declared as mutable",
+                    name
+                ))
+                .with_labels(vec![
+                    primary.with_message("cannot borrow as mutable"),
+                    Label::secondary(help.span.file, help.span.range.clone())
+                        .with_message(help.message()),
+                ])
+            }
+            BorrowErrKind::MutateCaptured { name, assign } => {
+                let (message, label) = match assign {
+                    true => (
+                        format!("Cannot assign to `{}`, captured by a lambda", name),
+                        "cannot assign",
+                    ),
+                    false => (
+                        format!("Cannot borrow `{}` as mutable, captured by a lambda", name),
+                        "cannot borrow as mutable",
+                    ),
+                };
+                diag.with_message(message)
+                    .with_labels(vec![primary.with_message(label)])
+                    .with_notes(vec![format!(
+                        "Lambdas capture the value of `{}`, so changing it wouldn't change the local",
+                        name
+                    )])
+            }
+        }
+    }
+}
@@ -0,0 +1,682 @@
+/// A stable error code, with what `kamai explain` prints about it
+pub struct ErrorCode {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub description: &'static str,
+    /// A minimal program triggering the error
+    pub example: &'static str,
+}
+
+pub const ERROR_CODES: &[ErrorCode] = &[
+    ErrorCode {
+        code: "E0001",
+        title: "Unexpected token",
+        description: "\
+The parser found a token that can't appear at this point of the program. The
+label tells what was expected instead.
+
+Keep in mind that blocks are inferred from the indentation: a line starting at or
+before the column of the `=`, `then` or `else` that opened a block closes it, so
+a badly indented line often shows up as an unexpected token.",
+        example: "a = 1 ) 2",
+    },
+    ErrorCode {
+        code: "E0002",
+        title: "Unexpected end of file",
+        description: "\
+The file ended in the middle of a construct, for instance right after an
+opening parenthesis. Finish the construct or remove it.",
+        example: "a = (",
+    },
+    ErrorCode {
+        code: "E0003",
+        title: "Unclosed delimiter",
+        description: "\
+A `(` or a `{` was opened but never closed. The error points at where the closing
+delimiter was expected, and at the delimiter left open. Add the matching closing
+delimiter where the construct should end.",
+        example: "a = { 1",
+    },
+    ErrorCode {
+        code: "E0004",
+        title: "Unexpected expression",
+        description: "\
+An expression was found where an item was expected. Expressions can only
+appear inside of items, give it a name to turn it into an item:
+
+    a = 1 + 2",
+        example: "1 + 2",
+    },
+    ErrorCode {
+        code: "E0005",
+        title: "Unexpected end of block",
+        description: "\
+A block inferred from the indentation ended before its content was complete.
+Blocks are opened by `=`, `then` and `else`, and end before the first line that
+isn't indented past their opener (or past the line of their opener, when the
+opener ends its line). Here, the block of `a` ends before the `else` branch of
+the `if` expression, which is missing:
+
+    a = if b then 1 else 2",
+        example: "a = if b then 1",
+    },
+    ErrorCode {
+        code: "E0006",
+        title: "Unbound identifier",
+        description: "\
+A name is used but nothing by this name is visible from there. Items are visible
+from the whole program, parameters from the body of their function or lambda,
+and a `let` from the rest of its block, but not from its own value.
+
+Check the spelling of the name, or define it.",
+        example: "a = b",
+    },
+    ErrorCode {
+        code: "E0007",
+        title: "Duplicate definition",
+        description: "\
+Two items, or two parameters of the same function, have the same name. Rename or
+remove one of them.",
+        example: "a = 1\na = 2",
+    },
+    ErrorCode {
+        code: "W0001",
+        title: "Shadowed definition",
+        description: "\
+A binding has the same name as a binding or an item visible where it's defined,
+making the earlier one unreachable from its scope. This is allowed, but often a
+mistake. Rename one of them, or start the name of the new binding with `_` to
+silence this warning:
+
+    f x = \\_x -> _x",
+        example: "f x = \\x -> x",
+    },
+    ErrorCode {
+        code: "E0008",
+        title: "Mismatched types",
+        description: "\
+An expression doesn't have the type its context expects. The primary label shows
+both types, and a secondary label shows what the expected type comes from: the
+other branch of an `if`, the other side of an `==`, an operator, or the function
+the expression is passed to.
+
+Both sides of an operator have the same type, `&&` and `||` take `Bool`s, and
+both branches of an `if` must have the same type.",
+        example: "a = 1 + true",
+    },
+    ErrorCode {
+        code: "E0009",
+        title: "Infinite type",
+        description: "\
+Inference would need a type to contain itself, like `a = a -> b`, which can't be
+written down. This usually comes from a function applied to itself, or passed to
+itself as an argument.",
+        example: "f x = x x",
+    },
+    ErrorCode {
+        code: "E0010",
+        title: "Not a function",
+        description: "\
+A value that isn't a function is applied to an argument. Remember that
+juxtaposition is function application: `f x y` calls `f` with `x`, then calls
+the result with `y`. An operator may be missing between the two expressions:
+
+    a = 1 + 2
+
+`f ()` is a call too, of `f` with the unit value. Items without parameters are
+values rather than functions, they are used without `()`:
+
+    b = a",
+        example: "a = 1 2",
+    },
+    ErrorCode {
+        code: "E0011",
+        title: "Not a method of the class",
+        description: "\
+An instance defines a function that its class doesn't declare. Instances can only
+define the methods of their class, other functions go at the top level.",
+        example: "\
+class Size a =
+  size : a -> Int
+instance Size Bool =
+  size b = 1
+  half b = 0",
+    },
+    ErrorCode {
+        code: "E0012",
+        title: "Missing methods",
+        description: "\
+An instance doesn't define every method of its class. The label lists the
+methods that are missing.",
+        example: "\
+class Size a =
+  size : a -> Int
+  empty : a
+instance Size Bool =
+  size b = 1",
+    },
+    ErrorCode {
+        code: "E0013",
+        title: "No instance for a type",
+        description: "\
+A value is used with a class that its type has no instance of, like `+` on a
+`Bool`. Operators are methods of the prelude's classes: `+`, `-`, `*`, `/` and
+negation belong to `Num`, `==` and `!=` to `Eq`, and the comparisons to `Ord`.
+Either use a type that has an instance, or declare one:
+
+    instance Num Bool =
+      ...",
+        example: "a = true + false",
+    },
+    ErrorCode {
+        code: "E0014",
+        title: "Conflicting instances",
+        description: "\
+A class has two instances for the same type, and nothing could tell which one a
+use should pick. Keep only one of them.",
+        example: "\
+instance Eq Int =
+  eq x y = true
+  ne x y = false",
+    },
+    ErrorCode {
+        code: "E0015",
+        title: "Ambiguous type",
+        description: "\
+A class is used on a type that nothing determines, so there's no way to pick its
+instance. This happens when the result of an overloaded expression is discarded,
+like an argument that is never used.",
+        example: "a = (\\x -> 1) (\\y -> y == y)",
+    },
+    ErrorCode {
+        code: "E0016",
+        title: "Mismatched kinds",
+        description: "\
+A type is used where a type of another kind is expected. Kinds are the types of
+types: the types of values, like `Int` or `Option Int`, have kind `*`, while a
+type constructor taking one type, like `Option`, has kind `* -> *`.
+
+Fields and signatures need types of kind `*`, so a type constructor must be
+applied to all of its parameters there. A class like `Functor f`, whose parameter
+is applied to a type in its methods, only has instances for type constructors:
+
+    instance Functor Option =
+      ...",
+        example: "\
+class Functor f =
+  fmap : (a -> b) -> f a -> f b
+instance Functor Int =
+  fmap f x = x",
+    },
+    ErrorCode {
+        code: "E0017",
+        title: "Infinite kind",
+        description: "\
+A type parameter is applied to itself, so its kind would need to contain itself,
+like `k = k -> *`, which can't be written down.",
+        example: "\
+data T: A =
+  C (A A)",
+    },
+    ErrorCode {
+        code: "E0018",
+        title: "Clauses with different numbers of parameters",
+        description: "\
+The clauses of a function are tried in order on the same arguments, so they all
+take the same number of parameters. Wrap parameters in a tuple, or give the
+function another name:
+
+    f 1 = 1
+    f x = 2",
+        example: "f 1 = 1\nf x y = 2",
+    },
+    ErrorCode {
+        code: "E0019",
+        title: "Wrong number of fields in a pattern",
+        description: "\
+A constructor pattern has a pattern for each field of the constructor, as it is
+declared in its data type. Use `_` for the fields that don't matter:
+
+    data T =
+      C Int Bool
+    f (C x _) = x",
+        example: "data T =\n  C Int\nf (C x y) = 1",
+    },
+    ErrorCode {
+        code: "E0020",
+        title: "Non-exhaustive patterns",
+        description: "\
+The clauses of a function don't match every argument they could be called with,
+or the pattern of a `let` or lambda doesn't match every value of its type. The
+label shows a value that isn't covered, written as a pattern. Add a clause for
+it, or a last clause with wildcards:
+
+    unwrap (Some a) = a
+    unwrap None = 0",
+        example: "\
+data Option: T =
+  None
+  Some T
+unwrap (Some a) = a",
+    },
+    ErrorCode {
+        code: "E0021",
+        title: "Owned type used for a type variable",
+        description: "\
+A generic item, method or local is used at an owned type. Generic code can copy
+or discard the values of its type variables, so it can't be given values with a
+single owner. Write a version of it for the owned type, or give the value to a
+function taking it:
+
+    owned data File =
+      File Int
+    close (File n) = n",
+        example: "\
+owned data File =
+  File Int
+id x = x
+main = id (File 1)",
+    },
+    ErrorCode {
+        code: "E0022",
+        title: "Use of a moved value",
+        description: "\
+A local of an owned type is used after its value was moved out of it. Using an
+owned local gives its value to its new owner, like the function it's passed to,
+so it can only be used once on each path. The secondary label shows where it
+was moved.",
+        example: "\
+owned data File =
+  File Int
+close (File n) = n
+main =
+  let f = File 1
+  close f + close f",
+    },
+    ErrorCode {
+        code: "E0023",
+        title: "Owned value captured by a function",
+        description: "\
+Functions are garbage collected and can be called any number of times, so they
+can't own values. A lambda can't use an owned local of the function it's in,
+and a partial application can't be given an owned argument. Pass the value as a
+parameter of the lambda, or give all of the arguments in the same call:
+
+    pair (File a) (File b) = a + b
+    main = pair (File 1) (File 2)",
+        example: "\
+owned data File =
+  File Int
+pair (File a) (File b) = a + b
+main = pair (File 1)",
+    },
+    ErrorCode {
+        code: "E0024",
+        title: "Owned field in a garbage collected type",
+        description: "\
+The values of garbage collected data types are shared, so their fields can't be
+owned. Declare the type as owned too:
+
+    owned data Files =
+      Files File File",
+        example: "\
+owned data File =
+  File Int
+data Files =
+  Files File File",
+    },
+    ErrorCode {
+        code: "E0025",
+        title: "Pattern taking apart a value with a destructor",
+        description: "\
+The type of the value has a `Drop` instance, whose `drop` method runs when the
+value is dropped. Taking the value apart would move its fields out without
+running it, so only `drop` itself can do so.",
+        example: "\
+owned data File =
+  File Int
+instance Drop File =
+  drop (File n) = ()
+size (File n) = n",
+    },
+    ErrorCode {
+        code: "E0026",
+        title: "Reference type used for a type variable",
+        description: "\
+Generic code can put the values of its type variables anywhere, in a lambda or a
+data value for instance, which could outlive what a reference borrows. So a type
+variable can't be a reference type, or a type containing references. Write a
+version of the generic item taking the reference:
+
+    get r = *r",
+        example: "\
+id x = x
+main = *(id &1)",
+    },
+    ErrorCode {
+        code: "E0027",
+        title: "Conflicting borrow",
+        description: "\
+A value borrowed mutably can't be used in any other way while the borrow is
+alive, and a value borrowed immutably can't be borrowed mutably, moved or
+assigned to. A borrow is alive until the last use of the references made from
+it, not until the end of its scope, so using the first borrow before the
+conflicting access fixes the error:
+
+    main =
+      let mut x = 1
+      let m = &mut x
+      let n = get m
+      get &x + n",
+        example: "\
+get r = *r
+main =
+  let mut x = 1
+  let m = &mut x
+  get &x + get m",
+    },
+    ErrorCode {
+        code: "E0028",
+        title: "Borrowed value does not live long enough",
+        description: "\
+A reference is used after the value it borrows was dropped: at the end of the
+block declaring it, or when its function returns. Declare the value in an
+enclosing block, or return the value itself rather than a reference to it.",
+        example: "f x = &x",
+    },
+    ErrorCode {
+        code: "E0029",
+        title: "Borrow of a moved value",
+        description: "\
+A local of an owned type is borrowed after its value was moved out of it, so it
+has nothing left to borrow. Borrow it before moving it, or borrow the new owner.",
+        example: "\
+owned data File =
+  File Int
+get r =
+  let File n = *r
+  n
+main =
+  let f = File 1
+  let g = f
+  get &f",
+    },
+    ErrorCode {
+        code: "E0030",
+        title: "Move out of a reference",
+        description: "\
+A reference doesn't own the value it borrows, so it can't give the value away
+when its type is owned. Borrow it again instead, or only take apart its fields
+that aren't owned:
+
+    size r =
+      let File n = *r
+      n",
+        example: "\
+owned data File =
+  File Int
+size (File n) = n
+get r = size (*r)",
+    },
+    ErrorCode {
+        code: "E0031",
+        title: "Reference captured by a function",
+        description: "\
+Functions are garbage collected, so they could be called after what a reference
+borrows is dropped. A lambda can't use a local holding references from the
+function it's in, and a partial application can't be given a reference. Pass
+the reference as a parameter of the lambda, or give all of the arguments in the
+same call.",
+        example: "\
+plus a b = *a + b
+main =
+  let x = 1
+  let f = plus &x
+  f 2",
+    },
+    ErrorCode {
+        code: "E0032",
+        title: "Reference in a data type",
+        description: "\
+Data values can be kept for any time, by a lambda or in another data value, so
+their fields can't hold references. Store the value itself instead.",
+        example: "\
+data Ref =
+  Ref &Int",
+    },
+    ErrorCode {
+        code: "E0033",
+        title: "Borrow of a temporary value of an owned type",
+        description: "\
+A value of an owned type that isn't bound to a local has no owner to drop it once
+it's borrowed. Bind it with `let` first, it's dropped at the end of its scope:
+
+    main =
+      let f = File 1
+      size &f",
+        example: "\
+owned data File =
+  File Int
+size r =
+  let File n = *r
+  n
+main = size &(File 1)",
+    },
+    ErrorCode {
+        code: "E0034",
+        title: "Assignment to an immutable local",
+        description: "\
+A local keeps the value it's bound to, unless it's declared with `mut`. Add `mut`
+to its binding to assign it again:
+
+    main =
+      let mut x = 1
+      x = 2
+      x",
+        example: "\
+main =
+  let x = 1
+  x = 2
+  x",
+    },
+    ErrorCode {
+        code: "E0035",
+        title: "Mutable borrow of an immutable local",
+        description: "\
+A mutable reference can change the value it borrows, so only locals declared with
+`mut` can be borrowed mutably. Add `mut` to the binding, or borrow it immutably
+with `&` if the reference isn't written through.",
+        example: "\
+set r = *r = 2
+main =
+  let x = 1
+  set &mut x",
+    },
+    ErrorCode {
+        code: "E0036",
+        title: "Mutation of a local captured by a lambda",
+        description: "\
+A lambda captures the values of the locals it uses, not the locals themselves,
+so assigning a captured local or borrowing it mutably wouldn't change the local
+of the enclosing function. Give the value to a function binding it to a new `mut`
+local instead:
+
+    add (mut z) y =
+      z = z + y
+      z
+    main =
+      let x = 1
+      let f = \\y -> add x y
+      f 2",
+        example: "\
+main =
+  let mut x = 1
+  let f = \\y -> { x = y }
+  f 2
+  x",
+    },
+    ErrorCode {
+        code: "E0037",
+        title: "Invalid left-hand side of assignment",
+        description: "\
+Only places can be assigned: locals, like `x`, and the values behind mutable
+references, like `*r`. Other expressions evaluate to new values, which nothing
+would see once assigned.",
+        example: "\
+main =
+  let mut x = 1
+  x + 1 = 2
+  x",
+    },
+    ErrorCode {
+        code: "E0038",
+        title: "Recursive call not in tail position",
+        description: "\
+A function marked `tailrec` must only call itself, and the functions it's
+mutually recursive with, in tail position: as the last thing it does, its value
+being the value of the call. Those functions must also call it in tail position. Tail calls reuse the frame of their caller, so
+such functions recurse as deep as they need without overflowing the stack.
+
+A call is in tail position when it's the value of the function, of a branch of
+an `if` in tail position, of the last expression of a block in tail position,
+of the right operand of `&&` or `||` in tail position, or of a `return`. It
+isn't when its value is used afterwards, when owned values are dropped after
+it, or when it's made by a lambda. A function borrowing its own values can't
+give its frame away either, as references may point to it.
+
+Pass what's left to compute as an accumulator instead:
+
+    tailrec sum n acc = if n == 0 then acc else sum (n - 1) (acc + n)",
+        example: "\
+tailrec sum n = if n == 0 then 0 else n + sum (n - 1)
+main = sum 10",
+    },
+    ErrorCode {
+        code: "E0039",
+        title: "Invalid type variable in an instance",
+        description: "\
+The context of an instance, before its `=>`, constrains the type variables of
+the instance's type: the instances of those variables are what its methods
+use.
+
+    instance Eq a => Eq (Option a) =
+      ...
+
+Other types can't be constrained there, nor can the type of an instance for
+every type, like `instance C a => D a`. `Drop` instances have no type
+variables at all, since destructors are found by the type they drop.",
+        example: "\
+data Option: T =
+  None
+  Some T
+class Default a =
+  default : a
+instance Default b => Default (Option a) =
+  default = None",
+    },
+    ErrorCode {
+        code: "E0040",
+        title: "Data type named after a built-in type",
+        description: "\
+`Int` and `Bool` are built into the language, a data type can't take their
+name: it would be shadowed by them everywhere. Give it another name instead.",
+        example: "\
+data Bool =
+  Yes
+  No",
+    },
+    ErrorCode {
+        code: "E0041",
+        title: "Literal out of range",
+        description: "\
+A number literal is larger than the largest `Int`, 9223372036854775807, which
+is 2^63 - 1. Integers are 64 bits wide and signed.",
+        example: "main = 99999999999999999999",
+    },
+    ErrorCode {
+        code: "E0042",
+        title: "`break` or `continue` outside of a loop",
+        description: "\
+`break` and `continue` leave or restart a loop, but there are no loops yet: a
+function repeats by calling itself. Calls in tail position don't grow the
+stack, so recursion can repeat as many times as a loop would:
+
+    count n = if n == 0 then 0 else count (n - 1)",
+        example: "\
+f x =
+  break x",
+    },
+    ErrorCode {
+        code: "E0043",
+        title: "`main` can't have constraints",
+        description: "\
+The type of `main` has constraints, like `Num a => a -> a`. An overloaded item
+takes the instances of its constraints from the code using it, but nothing uses
+`main`: the program runs it as it is, so there's no way to pick them.
+
+Use the overloaded operations of `main` on values of a known type, like `Int`s,
+or give it a value rather than an overloaded function:
+
+    double x = x + x
+    main = double 21",
+        example: "main = \\x -> x + x",
+    },
+    ErrorCode {
+        code: "W0002",
+        title: "Unreachable clause",
+        description: "\
+Every argument a clause matches is already matched by the clauses before it, so
+it is never tried. Remove it, or move it before the clauses covering it.",
+        example: "f _ = 1\nf 1 = 2",
+    },
+    ErrorCode {
+        code: "W0003",
+        title: "Operation always fails",
+        description: "\
+An operation on literals, like a division by 0 or an addition whose result
+doesn't fit in an `Int`, can't be folded at compile time: it stops the program
+whenever it's evaluated. It's only a warning, as it may never be.",
+        example: "main = 1 / 0",
+    },
+];
+
+pub fn explain(code: &str) -> Option<&'static ErrorCode> {
+    ERROR_CODES
+        .iter()
+        .find(|c| c.code.eq_ignore_ascii_case(code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        check::check_program,
+        prelude::prelude,
+        source::{FileId, SourceDb},
+        syntax::parse_program,
+    };
+    use codespan_reporting::diagnostic::Diagnostic;
+
+    fn first_diagnostic(code: &'static str) -> Diagnostic<FileId> {
+        let db = SourceDb::new();
+        let mut items = prelude(&db);
+        match parse_program(1, code) {
+            Ok(parsed) => items.extend(parsed),
+            Err(errors) => return errors.into_iter().next().unwrap().into(),
+        };
+        let diagnostics = check_program(&items, true)
+            .diagnostics
+            .into_iter()
+            .next()
+            .expect(code);
+        Vec::from(diagnostics).remove(0)
+    }
+
+    #[test]
+    fn examples_trigger_their_error() {
+        for ErrorCode { code, example, .. } in ERROR_CODES {
+            let diagnostic = first_diagnostic(example);
+            assert_eq!(diagnostic.code.as_deref(), Some(*code), "{}", example);
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use super::Report;
+use crate::source::{FileId, Span};
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+/// Only a warning, an operation on literals that fails whenever it's evaluated, which it may
+/// never be
+#[derive(Debug)]
+pub struct FoldErr {
+    pub span: Span,
+    pub kind: FoldErrKind,
+}
+
+#[derive(Debug)]
+pub enum FoldErrKind {
+    DivisionByZero,
+    /// The result of the operation, like `a + b`, doesn't fit in an `Int`
+    Overflow(String),
+}
+impl Report for FoldErr {}
+
+impl From<FoldErr> for Diagnostic<FileId> {
+    fn from(FoldErr { span, kind }: FoldErr) -> Self {
+        let primary = Label::primary(span.file, span.range);
+        let label = match kind {
+            FoldErrKind::DivisionByZero => primary.with_message("the divisor is 0"),
+            FoldErrKind::Overflow(operation) => {
+                primary.with_message(format!("`{}` doesn't fit in an `Int`", operation))
+            }
+        };
+        Diagnostic::warning()
+            .with_message("Operation always fails")
+            .with_labels(vec![label])
+            .with_notes(vec!["It stops the program when evaluated".to_string()])
+            .with_code("W0003")
+    }
+}
@@ -0,0 +1,266 @@
+//! Machine-readable diagnostics, emitted as one JSON object per line:
+//!
+//! ```text
+//! {"severity": "bug" | "error" | "warning" | "note" | "help",
+//!  "code": null | string,
+//!  "message": string,
+//!  "labels": [{"style": "primary" | "secondary",
+//!              "file": string,
+//!              "start": {"offset": number, "line": number, "column": number},
+//!              "end": {"offset": number, "line": number, "column": number},
+//!              "message": string}],
+//!  "expected": [string],
+//!  "notes": [string],
+//!  "suggestions": [{"file": string,
+//!                   "start": {"offset": number, "line": number, "column": number},
+//!                   "end": {"offset": number, "line": number, "column": number},
+//!                   "replacement": string,
+//!                   "applicability": "machine-applicable" | "maybe-incorrect"
+//!                                    | "has-placeholders"}]}
+//! ```
+//!
+//! Lines and columns start at 1, offsets are byte offsets and `end` is exclusive. A suggestion
+//! replaces the text between `start` and `end` by `replacement`, inserting it when they're equal.
+use std::fmt;
+
+use codespan_reporting::{
+    diagnostic::{Diagnostic, LabelStyle, Severity},
+    files::Files,
+};
+
+use super::{Applicability, Suggestion};
+use crate::source::{FileId, SourceDb};
+
+pub enum Json {
+    Null,
+    Num(usize),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Num(n) => write!(f, "{}", n),
+            Json::Str(s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\r' => write!(f, "\\r")?,
+                        '\t' => write!(f, "\\t")?,
+                        c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Json::Array(v) => {
+                write!(f, "[")?;
+                for (idx, e) in v.iter().enumerate() {
+                    if idx != 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", e)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (idx, (key, value)) in fields.iter().enumerate() {
+                    if idx != 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "\"{}\":{}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn strings(v: &[String]) -> Json {
+    Json::Array(v.iter().cloned().map(Json::Str).collect())
+}
+
+fn position(db: &SourceDb, file: FileId, offset: usize) -> Json {
+    let (line, column) =
+        Files::location(db, file, offset).map_or((0, 0), |l| (l.line_number, l.column_number));
+    Json::Object(vec![
+        ("offset", Json::Num(offset)),
+        ("line", Json::Num(line)),
+        ("column", Json::Num(column)),
+    ])
+}
+
+fn suggestion(db: &SourceDb, suggestion: &Suggestion) -> Json {
+    let Suggestion {
+        span,
+        replacement,
+        applicability,
+    } = suggestion;
+    Json::Object(vec![
+        ("file", Json::Str(db.name(span.file).to_string())),
+        ("start", position(db, span.file, span.range.start)),
+        ("end", position(db, span.file, span.range.end)),
+        ("replacement", Json::Str(replacement.clone())),
+        (
+            "applicability",
+            Json::Str(
+                match applicability {
+                    Applicability::MachineApplicable => "machine-applicable",
+                    Applicability::MaybeIncorrect => "maybe-incorrect",
+                    Applicability::HasPlaceholders => "has-placeholders",
+                }
+                .to_string(),
+            ),
+        ),
+    ])
+}
+
+pub fn diagnostic_to_json(
+    db: &SourceDb,
+    diagnostic: &Diagnostic<FileId>,
+    expected: &[String],
+    suggestions: &[Suggestion],
+) -> Json {
+    let severity = match diagnostic.severity {
+        Severity::Bug => "bug",
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    };
+    let labels = diagnostic
+        .labels
+        .iter()
+        .map(|label| {
+            Json::Object(vec![
+                (
+                    "style",
+                    Json::Str(
+                        match label.style {
+                            LabelStyle::Primary => "primary",
+                            LabelStyle::Secondary => "secondary",
+                        }
+                        .to_string(),
+                    ),
+                ),
+                ("file", Json::Str(db.name(label.file_id).to_string())),
+                ("start", position(db, label.file_id, label.range.start)),
+                ("end", position(db, label.file_id, label.range.end)),
+                ("message", Json::Str(label.message.clone())),
+            ])
+        })
+        .collect();
+    Json::Object(vec![
+        ("severity", Json::Str(severity.to_string())),
+        (
+            "code",
+            diagnostic.code.clone().map_or(Json::Null, Json::Str),
+        ),
+        ("message", Json::Str(diagnostic.message.clone())),
+        ("labels", Json::Array(labels)),
+        ("expected", strings(expected)),
+        ("notes", strings(&diagnostic.notes)),
+        (
+            "suggestions",
+            Json::Array(suggestions.iter().map(|s| suggestion(db, s)).collect()),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{errors::Report, hir::resolve::resolve, syntax::parse_program};
+
+    fn to_json<E: Report>(db: &SourceDb, errors: Vec<E>) -> Vec<String> {
+        errors
+            .into_iter()
+            .map(|err| {
+                let expected = err.expected();
+                let suggestions = err.suggestions();
+                diagnostic_to_json(db, &err.into(), &expected, &suggestions).to_string()
+            })
+            .collect()
+    }
+    fn json_errors(code: &str) -> Vec<String> {
+        let db = SourceDb::new();
+        let file = db.add("main.ka", code.to_string());
+        let errors = parse_program(file, db.source(file)).unwrap_err();
+        to_json(&db, errors)
+    }
+    fn json_resolve_errors(code: &str) -> Vec<String> {
+        let db = SourceDb::new();
+        let file = db.add("main.ka", code.to_string());
+        let items = parse_program(file, db.source(file)).unwrap();
+        to_json(&db, resolve(&items).1)
+    }
+
+    #[test]
+    fn syntax_error_schema() {
+        assert_eq!(
+            json_errors("a = 1\nb = 2 *\n"),
+            vec![concat!(
+                r#"{"severity":"error","code":"E0001","message":"Unexpected `*`","#,
+                r#""labels":[{"style":"primary","file":"main.ka","#,
+                r#""start":{"offset":12,"line":2,"column":7},"#,
+                r#""end":{"offset":13,"line":2,"column":8},"#,
+                r#""message":"Expected an expression"}],"#,
+                r#""expected":["an expression"],"notes":[],"suggestions":[]}"#
+            )]
+        );
+    }
+
+    #[test]
+    fn notes() {
+        assert_eq!(
+            json_errors("= 1"),
+            vec![concat!(
+                r#"{"severity":"error","code":"E0001","message":"Unexpected `=`","#,
+                r#""labels":[{"style":"primary","file":"main.ka","#,
+                r#""start":{"offset":0,"line":1,"column":1},"#,
+                r#""end":{"offset":1,"line":1,"column":2},"#,
+                r#""message":"Expected an item"}],"#,
+                r#""expected":["an item"],"notes":["Maybe you meant to declare an item?"],"#,
+                r#""suggestions":[]}"#
+            )]
+        );
+    }
+
+    #[test]
+    fn suggestions() {
+        assert_eq!(
+            json_resolve_errors("a = False"),
+            vec![concat!(
+                r#"{"severity":"error","code":"E0006","#,
+                r#""message":"Cannot find `False` in this scope","#,
+                r#""labels":[{"style":"primary","file":"main.ka","#,
+                r#""start":{"offset":4,"line":1,"column":5},"#,
+                r#""end":{"offset":9,"line":1,"column":10},"#,
+                r#""message":"not found in this scope"},"#,
+                r#"{"style":"secondary","file":"main.ka","#,
+                r#""start":{"offset":4,"line":1,"column":5},"#,
+                r#""end":{"offset":9,"line":1,"column":10},"#,
+                r#""message":"help: replace with `false`"}],"#,
+                r#""expected":[],"notes":["Booleans are written in lowercase"],"#,
+                r#""suggestions":[{"file":"main.ka","#,
+                r#""start":{"offset":4,"line":1,"column":5},"#,
+                r#""end":{"offset":9,"line":1,"column":10},"#,
+                r#""replacement":"false","applicability":"machine-applicable"}]}"#
+            )]
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            Json::Str("a \"quoted\"\n\\path\u{1}".to_string()).to_string(),
+            r#""a \"quoted\"\n\\path\u0001""#
+        );
+    }
+}
@@ -1 +1,178 @@
+pub mod borrow_err;
+pub mod codes;
+pub mod fold_err;
+pub mod json;
+pub mod ownership_err;
+pub mod pattern_err;
+pub mod resolve_err;
+pub mod runtime_err;
 pub mod syntax_err;
+pub mod tail_call_err;
+pub mod type_err;
+
+use codespan_reporting::{
+    diagnostic::Diagnostic,
+    term::{
+        self,
+        termcolor::{ColorChoice, StandardStream},
+    },
+};
+
+use crate::source::{FileId, SourceDb, Span};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ErrorFormat {
+    Human,
+    Json,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Applicability {
+    /// Applying the edit fixes the error
+    MachineApplicable,
+    /// The edit is a guess, like a similar name, and may not be what was meant
+    MaybeIncorrect,
+    /// The replacement contains placeholders, like `<expr>`, that must be filled by hand
+    HasPlaceholders,
+}
+
+/// An edit that could fix an error: `span` is replaced by `replacement`, an empty span
+/// being an insertion
+#[derive(Debug, PartialEq, Clone)]
+pub struct Suggestion {
+    pub span: Span,
+    pub replacement: String,
+    pub applicability: Applicability,
+}
+impl Suggestion {
+    pub fn insert(at: Span, text: impl Into<String>) -> Self {
+        Self {
+            span: Span::new(at.file, at.range.end..at.range.end),
+            replacement: text.into(),
+            applicability: Applicability::MachineApplicable,
+        }
+    }
+    pub fn replace(span: Span, text: impl Into<String>) -> Self {
+        Self {
+            span,
+            replacement: text.into(),
+            applicability: Applicability::MachineApplicable,
+        }
+    }
+    pub fn maybe_incorrect(self) -> Self {
+        Self {
+            applicability: Applicability::MaybeIncorrect,
+            ..self
+        }
+    }
+    pub fn with_placeholders(self) -> Self {
+        Self {
+            applicability: Applicability::HasPlaceholders,
+            ..self
+        }
+    }
+    pub fn message(&self) -> String {
+        if self.span.range.is_empty() {
+            format!("help: insert `{}`", self.replacement.trim())
+        } else {
+            format!("help: replace with `{}`", self.replacement)
+        }
+    }
+}
+
+/// Applies the machine applicable `suggestions` made for `code`, they must not overlap
+pub fn apply_suggestions(code: &str, suggestions: &[Suggestion]) -> String {
+    let mut suggestions: Vec<_> = suggestions
+        .iter()
+        .filter(|s| s.applicability == Applicability::MachineApplicable)
+        .collect();
+    suggestions.sort_by_key(|s| s.span.range.start);
+    let mut fixed = String::new();
+    let mut last = 0;
+    for Suggestion {
+        span, replacement, ..
+    } in suggestions
+    {
+        fixed.push_str(&code[last..span.range.start]);
+        fixed.push_str(replacement);
+        last = span.range.end;
+    }
+    fixed.push_str(&code[last..]);
+    fixed
+}
+
+/// The kinds of errors that have a code
+pub trait ErrorKind {
+    /// The stable code of the error, explained by `kamai explain`
+    fn code(&self) -> &'static str;
+}
+
+/// Errors that can be reported to the user
+pub trait Report: Into<Diagnostic<FileId>> {
+    /// What was expected where the error occured, for tools that want more than the message
+    fn expected(&self) -> Vec<String> {
+        vec![]
+    }
+    /// Edits that could fix the error
+    fn suggestions(&self) -> Vec<Suggestion> {
+        vec![]
+    }
+}
+
+pub fn emit<E: Report>(db: &SourceDb, format: ErrorFormat, errors: impl IntoIterator<Item = E>) {
+    let writer = StandardStream::stderr(ColorChoice::Auto);
+    let config = term::Config::default();
+    for err in errors {
+        match format {
+            ErrorFormat::Human => term::emit(&mut writer.lock(), &config, db, &err.into())
+                .expect("Failed to write on stderr"),
+            ErrorFormat::Json => {
+                let expected = err.expected();
+                let suggestions = err.suggestions();
+                eprintln!(
+                    "{}",
+                    json::diagnostic_to_json(db, &err.into(), &expected, &suggestions)
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse_program;
+
+    fn fix(code: &str) -> String {
+        let suggestions: Vec<_> = parse_program(0, code)
+            .unwrap_err()
+            .iter()
+            .flat_map(|e| e.suggestions())
+            .collect();
+        apply_suggestions(code, &suggestions)
+    }
+
+    #[test]
+    fn machine_applicable_fixes() {
+        for (code, fixed) in [
+            ("a = (1 + 2", "a = (1 + 2)"),
+            ("a = { 1", "a = { 1}"),
+            // The braces close after the statements that failed to parse
+            ("a = { { 1", "a = { { 1}}"),
+            ("a = { (1", "a = { (1)}"),
+        ] {
+            assert_eq!(fix(code), fixed);
+            assert!(parse_program(0, fixed).is_ok(), "{}", fixed);
+        }
+    }
+
+    #[test]
+    fn placeholders_are_not_applied() {
+        let errors = parse_program(0, "a = if b then 1").unwrap_err();
+        let suggestions = errors[0].suggestions();
+        assert_eq!(suggestions[0].replacement, " else <expr>");
+        assert_eq!(suggestions[0].span.range, 15..15);
+        assert_eq!(suggestions[0].applicability, Applicability::HasPlaceholders);
+        assert_eq!(fix("a = if b then 1"), "a = if b then 1");
+    }
+}
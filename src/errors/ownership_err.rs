@@ -0,0 +1,118 @@
+use super::{ErrorKind, Report};
+use crate::{
+    source::{FileId, Span},
+    typeck::{Type, TypeNames},
+};
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+#[derive(Debug)]
+pub struct OwnershipErr {
+    pub span: Span,
+    pub kind: OwnershipErrKind,
+}
+
+#[derive(Debug)]
+pub enum OwnershipErrKind {
+    /// The local `name`, of the owned type `ty`, used after it was moved at `moved`
+    UseAfterMove { name: String, ty: Type, moved: Span },
+    /// An owned local of an enclosing function used by a lambda, declared at `declared`
+    Captured {
+        name: String,
+        ty: Type,
+        declared: Span,
+    },
+    /// An owned argument kept by the partial application it's given to
+    PartiallyApplied { ty: Type },
+    /// A field of the garbage collected data type `data` whose type is owned
+    OwnedField { data: String, ty: Type },
+    /// A pattern taking apart a value whose type has a destructor, outside of it
+    Destructured { ty: Type },
+}
+impl ErrorKind for OwnershipErrKind {
+    fn code(&self) -> &'static str {
+        match self {
+            OwnershipErrKind::UseAfterMove { .. } => "E0022",
+            OwnershipErrKind::Captured { .. } | OwnershipErrKind::PartiallyApplied { .. } => {
+                "E0023"
+            }
+            OwnershipErrKind::OwnedField { .. } => "E0024",
+            OwnershipErrKind::Destructured { .. } => "E0025",
+        }
+    }
+}
+impl Report for OwnershipErr {}
+
+impl From<OwnershipErr> for Diagnostic<FileId> {
+    fn from(OwnershipErr { span, kind }: OwnershipErr) -> Self {
+        let mut names = TypeNames::default();
+        let diag = Diagnostic::error().with_code(kind.code());
+        let primary = Label::primary(span.file, span.range);
+        match kind {
+            OwnershipErrKind::UseAfterMove { name, ty, moved } => diag
+                .with_message(format!("Use of moved value `{}`", name))
+                .with_labels(vec![
+                    primary.with_message("value used here after move"),
+                    Label::secondary(moved.file, moved.range)
+                        .with_message(format!("`{}` moved here", name)),
+                ])
+                .with_notes(vec![format!(
+                    "`{}` has the owned type `{}`, using it gives it to its new owner",
+                    name,
+                    names.show(&ty)
+                )]),
+            OwnershipErrKind::Captured { name, ty, declared } => diag
+                .with_message(format!("Owned value `{}` captured by a lambda", name))
+                .with_labels(vec![
+                    primary.with_message(format!(
+                        "`{}` has the owned type `{}`",
+                        name,
+                        names.show(&ty)
+                    )),
+                    Label::secondary(declared.file, declared.range)
+                        .with_message("owned by the enclosing function"),
+                ])
+                .with_notes(vec![
+                    "Functions are garbage collected, so they can't own values: pass it to the \
+                     lambda as a parameter instead"
+                        .to_string(),
+                ]),
+            OwnershipErrKind::PartiallyApplied { ty } => diag
+                .with_message("Owned value captured by a partial application")
+                .with_labels(vec![primary.with_message(format!(
+                    "this has the owned type `{}`, but the call still waits for arguments",
+                    names.show(&ty)
+                ))])
+                .with_notes(vec![
+                    "Functions are garbage collected, so they can't own values: give the other \
+                     arguments in the same call"
+                        .to_string(),
+                ]),
+            OwnershipErrKind::OwnedField { data, ty } => diag
+                .with_message(format!(
+                    "Owned field in the garbage collected type `{}`",
+                    data
+                ))
+                .with_labels(vec![
+                    primary.with_message(format!("`{}` is owned", names.show(&ty)))
+                ])
+                .with_notes(vec![format!(
+                    "Declare it as `owned data {}` so that its values own their fields",
+                    data
+                )]),
+            OwnershipErrKind::Destructured { ty } => {
+                let ty = names.show(&ty);
+                diag.with_message(format!(
+                    "Pattern taking apart `{}`, which has a destructor",
+                    ty
+                ))
+                .with_labels(vec![
+                    primary.with_message("this would move its fields out without dropping it")
+                ])
+                .with_notes(vec![format!(
+                    "Only the `drop` method of the `Drop` instance of `{}` can take it apart",
+                    ty
+                )])
+            }
+        }
+    }
+}
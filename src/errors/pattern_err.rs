@@ -0,0 +1,68 @@
+use super::{ErrorKind, Report};
+use crate::source::{FileId, Span};
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+#[derive(Debug)]
+pub struct PatternErr {
+    pub span: Span,
+    pub kind: PatternErrKind,
+}
+
+/// Witnesses are values that patterns miss, written as patterns
+#[derive(Debug)]
+pub enum PatternErrKind {
+    /// The clauses of `name` miss a call, like `unwrap None`
+    NonExhaustive { name: String, witness: String },
+    /// A `let` or lambda pattern that doesn't match every value of its type
+    Refutable { witness: String },
+    /// Only a warning, a clause that matches no value the clauses before it don't
+    Unreachable,
+}
+impl ErrorKind for PatternErrKind {
+    fn code(&self) -> &'static str {
+        match self {
+            PatternErrKind::NonExhaustive { .. } | PatternErrKind::Refutable { .. } => "E0020",
+            PatternErrKind::Unreachable => "W0002",
+        }
+    }
+}
+impl PatternErr {
+    pub fn is_error(&self) -> bool {
+        !matches!(self.kind, PatternErrKind::Unreachable)
+    }
+}
+impl Report for PatternErr {}
+
+impl From<PatternErr> for Diagnostic<FileId> {
+    fn from(PatternErr { span, kind }: PatternErr) -> Self {
+        let code = kind.code();
+        let primary = Label::primary(span.file, span.range);
+        let diag = match kind {
+            PatternErrKind::NonExhaustive { name, witness } => Diagnostic::error()
+                .with_message(format!("Non-exhaustive clauses in `{}`", name))
+                .with_labels(vec![
+                    primary.with_message(format!("`{}` not covered", witness))
+                ])
+                .with_notes(vec![
+                    "Add a clause for it, or a last one with a wildcard `_` in its place"
+                        .to_string(),
+                ]),
+            PatternErrKind::Refutable { witness } => Diagnostic::error()
+                .with_message("Refutable pattern")
+                .with_labels(vec![
+                    primary.with_message(format!("`{}` not covered", witness))
+                ])
+                .with_notes(vec![
+                    "The patterns of `let`s and lambdas must match every value of their type, \
+                     use a function with several clauses instead"
+                        .to_string(),
+                ]),
+            PatternErrKind::Unreachable => Diagnostic::warning()
+                .with_message("Unreachable clause")
+                .with_labels(vec![
+                    primary.with_message("already covered by the clauses before")
+                ]),
+        };
+        diag.with_code(code)
+    }
+}
@@ -0,0 +1,238 @@
+use super::{ErrorKind, Report, Suggestion};
+use crate::source::{FileId, Span};
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+#[derive(Debug)]
+pub struct ResolveErr<'a> {
+    pub span: Span,
+    pub kind: ResolveErrKind<'a>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+#[derive(Debug)]
+pub enum ResolveErrKind<'a> {
+    Unbound(&'a str),
+    Duplicate {
+        name: &'a str,
+        first: Span,
+    },
+    /// Only a warning, shadowing is allowed
+    Shadowing {
+        name: &'a str,
+        shadowed: Span,
+    },
+    NotAMethod {
+        method: &'a str,
+        class: &'a str,
+    },
+    MissingMethods {
+        class: &'a str,
+        missing: Vec<&'a str>,
+    },
+    /// A clause of `name` doesn't take as many parameters as its first one
+    ClauseArity {
+        name: &'a str,
+        expected: usize,
+        found: usize,
+        first: Span,
+    },
+    CtorArity {
+        ctor: &'a str,
+        expected: usize,
+        found: usize,
+    },
+    /// The left side of an assignment isn't a place
+    InvalidAssign,
+    /// A constraint of an instance's context isn't on a variable inside the instance's type,
+    /// the `whole` type being a variable
+    ContextType {
+        whole: bool,
+    },
+    /// The type of a `Drop` instance has a type variable, `var`
+    DropTypeVar {
+        var: &'a str,
+    },
+    /// A data type named like a built-in type, which its name would still refer to
+    BuiltinType(&'a str),
+    /// A `break` or `continue`, the `keyword`, outside of a loop, which there are none of yet
+    NotInLoop {
+        keyword: &'static str,
+    },
+}
+impl<'a> ErrorKind for ResolveErrKind<'a> {
+    fn code(&self) -> &'static str {
+        match self {
+            ResolveErrKind::Unbound(_) => "E0006",
+            ResolveErrKind::Duplicate { .. } => "E0007",
+            ResolveErrKind::Shadowing { .. } => "W0001",
+            ResolveErrKind::NotAMethod { .. } => "E0011",
+            ResolveErrKind::MissingMethods { .. } => "E0012",
+            ResolveErrKind::ClauseArity { .. } => "E0018",
+            ResolveErrKind::CtorArity { .. } => "E0019",
+            ResolveErrKind::InvalidAssign => "E0037",
+            ResolveErrKind::ContextType { .. } | ResolveErrKind::DropTypeVar { .. } => "E0039",
+            ResolveErrKind::BuiltinType(_) => "E0040",
+            ResolveErrKind::NotInLoop { .. } => "E0042",
+        }
+    }
+}
+impl<'a> ResolveErr<'a> {
+    pub fn is_error(&self) -> bool {
+        !matches!(self.kind, ResolveErrKind::Shadowing { .. })
+    }
+}
+impl<'a> Report for ResolveErr<'a> {
+    fn suggestions(&self) -> Vec<Suggestion> {
+        self.suggestions.clone()
+    }
+}
+
+impl<'a> From<ResolveErr<'a>> for Diagnostic<FileId> {
+    fn from(
+        ResolveErr {
+            span,
+            kind,
+            suggestions,
+        }: ResolveErr<'a>,
+    ) -> Self {
+        let code = kind.code();
+        let primary = Label::primary(span.file, span.range);
+        let (diag, labels) = match kind {
+            ResolveErrKind::Unbound(name) => {
+                let diag =
+                    Diagnostic::error().with_message(format!("Cannot find `{}` in this scope", name));
+                let notes = match name {
+                    "True" | "False" => vec!["Booleans are written in lowercase".to_string()],
+                    _ => vec![],
+                };
+                (
+                    diag.with_notes(notes),
+                    vec![primary.with_message("not found in this scope")],
+                )
+            }
+            ResolveErrKind::Duplicate { name, first } => (
+                Diagnostic::error().with_message(format!("`{}` is defined multiple times", name)),
+                vec![
+                    primary.with_message(format!("`{}` redefined here", name)),
+                    Label::secondary(first.file, first.range)
+                        .with_message(format!("first definition of `{}`", name)),
+                ],
+            ),
+            ResolveErrKind::Shadowing { name, shadowed } => (
+                Diagnostic::warning()
+                    .with_message(format!("`{}` shadows an earlier definition", name))
+                    .with_notes(vec![format!(
+                        "Prefix it with an underscore, like `_{}`, to silence this warning",
+                        name
+                    )]),
+                vec![
+                    primary.with_message(format!("this `{}`", name)),
+                    Label::secondary(shadowed.file, shadowed.range)
+                        .with_message(format!("shadows this `{}`", name)),
+                ],
+            ),
+            ResolveErrKind::NotAMethod { method, class } => (
+                Diagnostic::error()
+                    .with_message(format!("`{}` is not a method of `{}`", method, class)),
+                vec![primary.with_message(format!("not a member of `{}`", class))],
+            ),
+            ResolveErrKind::MissingMethods { class, missing } => {
+                let missing: Vec<_> = missing.iter().map(|m| format!("`{}`", m)).collect();
+                (
+                    Diagnostic::error()
+                        .with_message(format!("Missing methods in this instance of `{}`", class)),
+                    vec![primary.with_message(format!("missing {}", missing.join(", ")))],
+                )
+            }
+            ResolveErrKind::ClauseArity {
+                name,
+                expected,
+                found,
+                first,
+            } => (
+                Diagnostic::error().with_message(format!(
+                    "The clauses of `{}` take different numbers of parameters",
+                    name
+                )),
+                vec![
+                    primary
+                        .with_message(format!("this clause takes {}", plural(found, "parameter"))),
+                    Label::secondary(first.file, first.range).with_message(format!(
+                        "the first one takes {}",
+                        plural(expected, "parameter")
+                    )),
+                ],
+            ),
+            ResolveErrKind::CtorArity {
+                ctor,
+                expected,
+                found,
+            } => (
+                Diagnostic::error().with_message(format!(
+                    "`{}` has {}, but this pattern has {}",
+                    ctor,
+                    plural(expected, "field"),
+                    found
+                )),
+                vec![primary.with_message(format!("expected {}", plural(expected, "field")))],
+            ),
+            ResolveErrKind::InvalidAssign => (
+                Diagnostic::error()
+                    .with_message("Invalid left-hand side of assignment")
+                    .with_notes(vec![
+                        "Only locals and dereferenced references, like `x` or `*r`, can be assigned"
+                            .to_string(),
+                    ]),
+                vec![primary.with_message("cannot assign to this expression")],
+            ),
+            ResolveErrKind::ContextType { whole } => (
+                Diagnostic::error()
+                    .with_message("Instance contexts constrain the variables inside the instance's type")
+                    .with_notes(vec![
+                        "Like `a` in `instance Eq a => Eq (Option a)`".to_string(),
+                    ]),
+                vec![primary.with_message(match whole {
+                    true => "the instance's type is this variable itself",
+                    false => "not a variable of the instance's type",
+                })],
+            ),
+            ResolveErrKind::DropTypeVar { var } => (
+                Diagnostic::error()
+                    .with_message(format!(
+                        "Type variable `{}` in the type of a `Drop` instance",
+                        var
+                    ))
+                    .with_notes(vec![
+                        "Destructors are declared for types without variables, like `Handle Int`"
+                            .to_string(),
+                    ]),
+                vec![primary.with_message(format!("`{}` is a type variable", var))],
+            ),
+            ResolveErrKind::BuiltinType(name) => (
+                Diagnostic::error().with_message(format!("`{}` is a built-in type", name)),
+                vec![primary.with_message("data types can't be named after built-in types")],
+            ),
+            ResolveErrKind::NotInLoop { keyword } => (
+                Diagnostic::error()
+                    .with_message(format!("`{}` outside of a loop", keyword))
+                    .with_notes(vec![
+                        "Repeat with a recursive function instead, its tail calls don't grow the stack"
+                            .to_string(),
+                    ]),
+                vec![primary.with_message(format!("cannot `{}` outside of a loop", keyword))],
+            ),
+        };
+        let mut labels = labels;
+        labels.extend(suggestions.iter().map(|s| {
+            Label::secondary(s.span.file, s.span.range.clone()).with_message(s.message())
+        }));
+        diag.with_code(code).with_labels(labels)
+    }
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {}", noun),
+        count => format!("{} {}s", count, noun),
+    }
+}
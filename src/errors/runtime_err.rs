@@ -0,0 +1,60 @@
+use super::Report;
+use crate::source::{FileId, Span};
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+/// How many calls can be nested in each other before a stack overflow is reported, by every
+/// backend
+pub const MAX_DEPTH: usize = 100_000;
+
+/// An error stopping the evaluation of a program, at the node that caused it
+#[derive(Debug, PartialEq, Clone)]
+pub struct RuntimeErr {
+    pub span: Span,
+    pub kind: RuntimeErrKind,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RuntimeErrKind {
+    DivisionByZero,
+    /// The result of an operation, like `a + b`, doesn't fit in an `Int`
+    Overflow(String),
+    /// Too many calls nested in each other, usually an unbounded recursion
+    StackOverflow,
+}
+impl RuntimeErrKind {
+    /// What went wrong, the natively compiled programs report it with where it happened
+    pub fn message(&self) -> String {
+        match self {
+            RuntimeErrKind::DivisionByZero => "Division by zero".to_string(),
+            RuntimeErrKind::Overflow(_) => "Integer overflow".to_string(),
+            RuntimeErrKind::StackOverflow => "Stack overflow".to_string(),
+        }
+    }
+}
+impl Report for RuntimeErr {}
+
+impl From<RuntimeErr> for Diagnostic<FileId> {
+    fn from(RuntimeErr { span, kind }: RuntimeErr) -> Self {
+        let primary = Label::primary(span.file, span.range);
+        let diagnostic = Diagnostic::error().with_message(kind.message());
+        match kind {
+            RuntimeErrKind::DivisionByZero => {
+                diagnostic.with_labels(vec![primary.with_message("the divisor is 0")])
+            }
+            RuntimeErrKind::Overflow(operation) => diagnostic
+                .with_labels(vec![
+                    primary.with_message(format!("`{}` doesn't fit in an `Int`", operation))
+                ])
+                .with_notes(vec![format!(
+                    "`Int`s are between {} and {}",
+                    i64::MIN,
+                    i64::MAX
+                )]),
+            RuntimeErrKind::StackOverflow => diagnostic
+                .with_labels(vec![primary.with_message("too many nested calls")])
+                .with_notes(vec![
+                    "A recursion might never reach its base case".to_string()
+                ]),
+        }
+    }
+}
@@ -1,6 +1,10 @@
-use std::{fmt, ops::Range};
+use std::fmt;
 
-use crate::syntax::{ast::Expr, tokens::TokenKind};
+use super::{ErrorKind, Report, Suggestion};
+use crate::{
+    source::{FileId, Span},
+    syntax::{ast::Expr, tokens::TokenKind},
+};
 use codespan_reporting::diagnostic::{Diagnostic, Label};
 
 #[derive(Debug)]
@@ -8,14 +12,27 @@ pub enum Delimiter {
     Paren,
     Brace,
 }
+impl fmt::Display for Delimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Delimiter::Paren => write!(f, "("),
+            Delimiter::Brace => write!(f, "{{"),
+        }
+    }
+}
 type HelpNote = &'static str;
 
 #[derive(Debug)]
 pub enum Expected {
     Item,
     Expr,
+    Pattern,
+    Name,
+    Type,
+    Kind,
     Operator,
     Semicolon,
+    Token(&'static str),
     None,
     OneOf(Vec<Expected>),
 }
@@ -24,8 +41,13 @@ impl fmt::Display for Expected {
         match self {
             Expected::Item => write!(f, "an item"),
             Expected::Expr => write!(f, "an expression"),
+            Expected::Pattern => write!(f, "a pattern"),
+            Expected::Name => write!(f, "a name"),
+            Expected::Type => write!(f, "a type"),
+            Expected::Kind => write!(f, "a kind, like `*` or `* -> *`"),
             Expected::Operator => write!(f, "an operator"),
             Expected::Semicolon => write!(f, "a `;`"),
+            Expected::Token(t) => write!(f, "`{}`", t),
             Expected::OneOf(v) => {
                 write!(f, "one of [")?;
                 for e in v.iter().take(v.len() - 1) {
@@ -37,57 +59,124 @@ impl fmt::Display for Expected {
         }
     }
 }
+impl Expected {
+    fn flatten(&self, acc: &mut Vec<String>) {
+        match self {
+            Expected::None => (),
+            Expected::OneOf(v) => v.iter().for_each(|e| e.flatten(acc)),
+            e => acc.push(e.to_string()),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct SyntaxErr<'a> {
-    pub span: Range<usize>,
+    pub span: Span,
     pub kind: SyntaxErrKind<'a>,
     pub expected: Expected,
     pub note: Option<HelpNote>,
+    pub suggestions: Vec<Suggestion>,
 }
 
 #[derive(Debug)]
 pub enum SyntaxErrKind<'a> {
     UnexpectedToken(TokenKind<'a>),
-    Unclosed(Delimiter),
+    Unclosed {
+        delimiter: Delimiter,
+        opened: Span,
+    },
     UnexpectedExpr(Expr<'a>),
     UnexpectedEOF,
+    UnexpectedEndOfBlock {
+        opener: TokenKind<'a>,
+        opened: Span,
+    },
+    /// A number literal that doesn't fit in an `Int`
+    LiteralOutOfRange,
 }
-impl<'a> From<SyntaxErr<'a>> for Diagnostic<()> {
+impl<'a> ErrorKind for SyntaxErrKind<'a> {
+    fn code(&self) -> &'static str {
+        match self {
+            SyntaxErrKind::UnexpectedToken(_) => "E0001",
+            SyntaxErrKind::UnexpectedEOF => "E0002",
+            SyntaxErrKind::Unclosed { .. } => "E0003",
+            SyntaxErrKind::UnexpectedExpr(_) => "E0004",
+            SyntaxErrKind::UnexpectedEndOfBlock { .. } => "E0005",
+            SyntaxErrKind::LiteralOutOfRange => "E0041",
+        }
+    }
+}
+impl<'a> Report for SyntaxErr<'a> {
+    fn expected(&self) -> Vec<String> {
+        let mut acc = vec![];
+        self.expected.flatten(&mut acc);
+        acc
+    }
+    fn suggestions(&self) -> Vec<Suggestion> {
+        self.suggestions.clone()
+    }
+}
+
+impl<'a> From<SyntaxErr<'a>> for Diagnostic<FileId> {
     fn from(
         SyntaxErr {
             span,
             kind,
             expected,
             note,
+            suggestions,
         }: SyntaxErr<'a>,
     ) -> Self {
-        let mut diag = Diagnostic::error();
+        let mut diag = Diagnostic::error().with_code(kind.code());
+        let mut secondary = vec![];
         match kind {
+            SyntaxErrKind::UnexpectedToken(TokenKind::Error) => {
+                diag = diag.with_message("Unrecognized token");
+            }
+            SyntaxErrKind::UnexpectedToken(TokenKind::Newline) => {
+                diag = diag.with_message("Unexpected new line");
+            }
             SyntaxErrKind::UnexpectedToken(t) => {
-                diag = diag.with_message(format!("Unexpected {:#?}", t));
+                diag = diag.with_message(format!("Unexpected `{}`", t));
             }
             SyntaxErrKind::UnexpectedEOF => {
-                diag = diag.with_message(format!("Unexpected EOF"));
+                diag = diag.with_message("Unexpected end of file");
             }
-            SyntaxErrKind::Unclosed(delimiter) => {
-                diag = diag.with_message(format!("Unclosed {:#?}", delimiter));
+            SyntaxErrKind::Unclosed { delimiter, opened } => {
+                diag = diag.with_message(format!("Unclosed `{}`", delimiter));
+                secondary.push(
+                    Label::secondary(opened.file, opened.range)
+                        .with_message(format!("unclosed `{}` opened here", delimiter)),
+                );
             }
-            SyntaxErrKind::UnexpectedExpr(_) => {
-                diag = diag.with_message(format!("Unexpected expression"))
+            SyntaxErrKind::UnexpectedEndOfBlock { opener, opened } => {
+                diag = diag.with_message("Unexpected end of block");
+                secondary.push(
+                    Label::secondary(opened.file, opened.range)
+                        .with_message(format!("block opened by this `{}`", opener)),
+                );
             }
-        }
-        match note {
-            Some(s) => diag = diag.with_notes(vec![s.to_string()]),
-            None => (),
-        }
-        match expected {
-            Expected::None => (),
-            _ => {
-                diag = diag.with_labels(vec![
-                    Label::primary((), span).with_message(format!("Expected {}", expected))
-                ])
+            SyntaxErrKind::UnexpectedExpr(_) => diag = diag.with_message("Unexpected expression"),
+            SyntaxErrKind::LiteralOutOfRange => {
+                diag = diag.with_message("Literal out of range for `Int`");
+                secondary.push(
+                    Label::primary(span.file, span.range.clone())
+                        .with_message("doesn't fit in an `Int`"),
+                );
             }
         }
-        diag
+        if let Some(s) = note {
+            diag = diag.with_notes(vec![s.to_string()]);
+        }
+        let mut labels = match expected {
+            Expected::None => vec![],
+            _ => vec![Label::primary(span.file, span.range)
+                .with_message(format!("Expected {}", expected))],
+        };
+        labels.extend(secondary);
+        labels.extend(suggestions.iter().map(|s| {
+            Label::secondary(s.span.file, s.span.range.clone()).with_message(s.message())
+        }));
+        diag.with_labels(labels)
     }
 }
@@ -0,0 +1,94 @@
+use super::{ErrorKind, Report};
+use crate::source::{FileId, Span};
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+/// A recursive call between an item marked `tailrec` and the items it's mutually recursive with
+/// that isn't a tail call
+#[derive(Debug)]
+pub struct TailCallErr {
+    pub span: Span,
+    pub kind: TailCallErrKind,
+}
+
+/// `caller` makes the call of `callee`, one of them being the `tailrec` item, named `tailrec` at
+/// `name`
+#[derive(Debug)]
+pub enum TailCallErrKind {
+    /// A call whose value is used by `caller` before it returns, by a lambda in it when
+    /// `lambda`
+    NotInTailPosition {
+        callee: String,
+        caller: String,
+        tailrec: String,
+        name: Span,
+        lambda: bool,
+    },
+    /// A call in tail position, which can't reuse the frame of `caller` as it borrows one of
+    /// its locals or temporaries at `borrow`
+    Borrowed {
+        callee: String,
+        caller: String,
+        tailrec: String,
+        name: Span,
+        borrow: Span,
+    },
+}
+impl ErrorKind for TailCallErrKind {
+    fn code(&self) -> &'static str {
+        "E0038"
+    }
+}
+impl Report for TailCallErr {}
+
+impl From<TailCallErr> for Diagnostic<FileId> {
+    fn from(TailCallErr { span, kind }: TailCallErr) -> Self {
+        let diag = Diagnostic::error().with_code(kind.code());
+        let primary = Label::primary(span.file, span.range);
+        let marked = |name: Span, tailrec: &str| {
+            Label::secondary(name.file, name.range)
+                .with_message(format!("`{}` is marked `tailrec`", tailrec))
+        };
+        match kind {
+            TailCallErrKind::NotInTailPosition {
+                callee,
+                caller,
+                tailrec,
+                name,
+                lambda,
+            } => {
+                let (label, note) = match lambda {
+                    true => (
+                        format!("`{}` is called by a lambda", callee),
+                        "Calls made by lambdas are tail calls of the lambda, not of the function it's in",
+                    ),
+                    false => (
+                        format!("the value of `{}` is used before `{}` returns", callee, caller),
+                        "A call is only a tail call when its value is the value of the function, \
+                         with no owned value dropped after it",
+                    ),
+                };
+                diag.with_message("Recursive call not in tail position")
+                    .with_labels(vec![primary.with_message(label), marked(name, &tailrec)])
+                    .with_notes(vec![note.to_string()])
+            }
+            TailCallErrKind::Borrowed {
+                callee,
+                caller,
+                tailrec,
+                name,
+                borrow,
+            } => diag
+                .with_message("Recursive call can't reuse the frame of its caller")
+                .with_labels(vec![
+                    primary.with_message(format!("`{}` is called in tail position", callee)),
+                    Label::secondary(borrow.file, borrow.range)
+                        .with_message(format!("but `{}` borrows one of its values here", caller)),
+                    marked(name, &tailrec),
+                ])
+                .with_notes(vec![
+                    "References can point to the values of a function's frame, which must stay while they're used"
+                        .to_string(),
+                ]),
+        }
+    }
+}
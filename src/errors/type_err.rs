@@ -0,0 +1,229 @@
+use super::{ErrorKind, Report};
+use crate::{
+    hir::Kind,
+    source::{FileId, Span},
+    syntax::ast::BinOp,
+    typeck::{Type, TypeNames},
+};
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+
+#[derive(Debug)]
+pub struct TypeErr {
+    pub span: Span,
+    pub kind: TypeErrKind,
+}
+
+/// Why a type was expected, labelled at the expression that imposed it
+#[derive(Debug)]
+pub enum Because {
+    /// The other branch of an `if` or side of an `==`
+    Is(Type),
+    /// The function applied to the expression
+    Expects(Type),
+    Operator(BinOp),
+}
+
+#[derive(Debug)]
+pub enum TypeErrKind {
+    Mismatch {
+        expected: Type,
+        found: Type,
+        because: Option<(Span, Because)>,
+    },
+    Infinite {
+        var: Type,
+        ty: Type,
+    },
+    NotAFunction {
+        ty: Type,
+        arg: Span,
+        /// Whether the argument is the `()` of an `EmptyCall`
+        unit: bool,
+    },
+    NoInstance {
+        class: String,
+        ty: Type,
+    },
+    ConflictingInstances {
+        class: String,
+        ty: Type,
+        first: Span,
+    },
+    Ambiguous {
+        class: String,
+        var: Type,
+    },
+    KindMismatch {
+        expected: Kind,
+        found: Kind,
+    },
+    InfiniteKind,
+    /// A type variable of the generic item or local `name` instantiated with the owned `ty`
+    OwnedVar {
+        ty: Type,
+        name: String,
+    },
+    /// A type variable of the generic item or local `name` instantiated with `ty`, which
+    /// contains references
+    BorrowedVar {
+        ty: Type,
+        name: String,
+    },
+    /// `main` has a type with constraints, `scheme`, whose instances nothing can pick
+    ConstrainedMain {
+        scheme: String,
+    },
+}
+impl ErrorKind for TypeErrKind {
+    fn code(&self) -> &'static str {
+        match self {
+            TypeErrKind::Mismatch { .. } => "E0008",
+            TypeErrKind::Infinite { .. } => "E0009",
+            TypeErrKind::NotAFunction { .. } => "E0010",
+            TypeErrKind::NoInstance { .. } => "E0013",
+            TypeErrKind::ConflictingInstances { .. } => "E0014",
+            TypeErrKind::Ambiguous { .. } => "E0015",
+            TypeErrKind::KindMismatch { .. } => "E0016",
+            TypeErrKind::InfiniteKind => "E0017",
+            TypeErrKind::OwnedVar { .. } => "E0021",
+            TypeErrKind::BorrowedVar { .. } => "E0026",
+            TypeErrKind::ConstrainedMain { .. } => "E0043",
+        }
+    }
+}
+impl Report for TypeErr {}
+
+impl From<TypeErr> for Diagnostic<FileId> {
+    fn from(TypeErr { span, kind }: TypeErr) -> Self {
+        let mut names = TypeNames::default();
+        let diag = Diagnostic::error().with_code(kind.code());
+        let primary = Label::primary(span.file, span.range);
+        match kind {
+            TypeErrKind::Mismatch {
+                expected,
+                found,
+                because,
+            } => {
+                let (expected, found) = (names.show(&expected), names.show(&found));
+                let mut labels = vec![
+                    primary.with_message(format!("expected `{}`, found `{}`", expected, found))
+                ];
+                if let Some((span, because)) = because {
+                    let message = match because {
+                        Because::Is(ty) => format!("this is `{}`", names.show(&ty)),
+                        Because::Expects(ty) => format!("this expects `{}`", names.show(&ty)),
+                        Because::Operator(op) => format!("`{}` takes `{}` operands", op, expected),
+                    };
+                    labels.push(Label::secondary(span.file, span.range).with_message(message));
+                }
+                diag.with_message("Mismatched types").with_labels(labels)
+            }
+            TypeErrKind::Infinite { var, ty } => {
+                let (var, ty) = (names.show(&var), names.show(&ty));
+                diag.with_message("Infinite type")
+                    .with_labels(vec![
+                        primary.with_message(format!("this would need `{}` to be `{}`", var, ty))
+                    ])
+                    .with_notes(vec![
+                        "A type can't contain itself, this is often a function applied to itself"
+                            .to_string(),
+                    ])
+            }
+            TypeErrKind::NotAFunction { ty, arg, unit } => {
+                let diag = diag
+                    .with_message(format!("`{}` is not a function", names.show(&ty)))
+                    .with_labels(vec![
+                        primary.with_message("this is called"),
+                        Label::secondary(arg.file, arg.range).with_message("with this argument"),
+                    ]);
+                match unit {
+                    true => diag.with_notes(vec![
+                        "`f ()` applies `f` to the unit value, items without parameters are \
+                         values and are used without `()`"
+                            .to_string(),
+                    ]),
+                    false => diag,
+                }
+            }
+            TypeErrKind::NoInstance { class, ty } => {
+                let ty = names.show(&ty);
+                diag.with_message(format!("No instance of `{}` for `{}`", class, ty))
+                    .with_labels(vec![
+                        primary.with_message(format!("this needs `{} {}`", class, ty))
+                    ])
+            }
+            TypeErrKind::ConflictingInstances { class, ty, first } => {
+                let ty = names.show(&ty);
+                diag.with_message(format!("Conflicting instances of `{} {}`", class, ty))
+                    .with_labels(vec![
+                        primary.with_message("conflicting instance"),
+                        Label::secondary(first.file, first.range)
+                            .with_message("first instance here"),
+                    ])
+            }
+            TypeErrKind::Ambiguous { class, var } => {
+                let var = names.show(&var);
+                diag.with_message(format!("Ambiguous type in `{} {}`", class, var))
+                    .with_labels(vec![primary.with_message(format!(
+                        "can't tell which instance of `{}` this needs",
+                        class
+                    ))])
+                    .with_notes(vec![format!(
+                        "Nothing determines `{}`, it's only used by values that are discarded",
+                        var
+                    )])
+            }
+            TypeErrKind::KindMismatch { expected, found } => diag
+                .with_message("Mismatched kinds")
+                .with_labels(vec![primary.with_message(format!(
+                    "expected a type of kind `{}`, found one of kind `{}`",
+                    expected, found
+                ))])
+                .with_notes(vec![
+                    "Values have types of kind `*`, like `Int`. Type constructors take types, \
+                     like `Option` which has kind `* -> *`"
+                        .to_string(),
+                ]),
+            TypeErrKind::InfiniteKind => diag.with_message("Infinite kind").with_labels(vec![
+                primary.with_message("this type would need to be applied to itself"),
+            ]),
+            TypeErrKind::OwnedVar { ty, name } => {
+                let ty = names.show(&ty);
+                diag.with_message(format!("Owned type `{}` used for a type variable", ty))
+                    .with_labels(vec![primary.with_message(format!(
+                        "`{}` is generic, it can't take values of type `{}`",
+                        name, ty
+                    ))])
+                    .with_notes(vec![
+                        "Owned values have a single owner, which generic code can't keep track \
+                         of since it could copy or discard them. Write a version of it for this \
+                         type instead"
+                            .to_string(),
+                    ])
+            }
+            TypeErrKind::BorrowedVar { ty, name } => {
+                let ty = names.show(&ty);
+                diag.with_message(format!("Reference type `{}` used for a type variable", ty))
+                    .with_labels(vec![primary.with_message(format!(
+                        "`{}` is generic, it can't take values of type `{}`",
+                        name, ty
+                    ))])
+                    .with_notes(vec![
+                        "Generic code could keep its values in a function, which would outlive \
+                         what they borrow. Write a version of it for this type instead"
+                            .to_string(),
+                    ])
+            }
+            TypeErrKind::ConstrainedMain { scheme } => diag
+                .with_message("`main` can't have constraints")
+                .with_labels(vec![
+                    primary.with_message(format!("its type is `{}`", scheme))
+                ])
+                .with_notes(vec![
+                    "The program runs `main` without anything telling which instances its \
+                     constraints need. Use its overloaded operations on values of a known type"
+                        .to_string(),
+                ]),
+        }
+    }
+}
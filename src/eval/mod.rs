@@ -0,0 +1,749 @@
+//! A tree-walking interpreter for programs that resolved, type checked and whose patterns are
+//! exhaustive. It runs the elaborated HIR: dictionaries are values, and the operators
+//! overloaded by a dictionary parameter are primitive when it's the instance for `Int`.
+//!
+//! Calls in tail position unwind to the call of the function they're in, which makes them, so
+//! that their frames don't pile up on the stack.
+mod value;
+
+use std::{cell::RefCell, collections::HashSet, panic, rc::Rc, thread};
+
+pub use value::Value;
+use value::{Callee, Closure, Env, Pap};
+
+use crate::{
+    errors::runtime_err::{RuntimeErr, RuntimeErrKind, MAX_DEPTH},
+    hir::*,
+    source::Span,
+};
+
+/// The stack of the thread programs run on
+const STACK_SIZE: usize = 512 * 1024 * 1024;
+/// How much of it calls can use before a stack overflow is reported, the rest being left for
+/// the call that reaches it
+const STACK_LIMIT: usize = STACK_SIZE - 16 * 1024 * 1024;
+
+/// Runs `f` on a thread with a stack large enough for the interpreter's recursion, which
+/// interpreters must run on
+pub fn with_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    thread::scope(|scope| {
+        let thread = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("Failed to spawn the interpreter's thread");
+        match thread.join() {
+            Ok(value) => value,
+            Err(e) => panic::resume_unwind(e),
+        }
+    })
+}
+
+// Why the evaluation of an expression stopped before giving its value
+enum Unwind<'p> {
+    Return(Value<'p>),
+    Err(RuntimeErr),
+    /// A function applied to arguments in tail position, which the caller of the function the
+    /// call is in makes
+    TailCall(Value<'p>, Vec<Value<'p>>, Span),
+}
+impl<'p> From<RuntimeErr> for Unwind<'p> {
+    fn from(e: RuntimeErr) -> Self {
+        Unwind::Err(e)
+    }
+}
+
+type Eval<'p> = Result<Value<'p>, Unwind<'p>>;
+
+pub struct Interpreter<'p> {
+    program: &'p Program<'p>,
+    // Where the stack was when the interpreter was created
+    base: usize,
+    // How many calls are nested
+    depth: usize,
+}
+impl<'p> Interpreter<'p> {
+    pub fn new(program: &'p Program<'p>) -> Self {
+        Self {
+            program,
+            base: stack_address(),
+            depth: 0,
+        }
+    }
+    /// The value of an item, which is only evaluated when it takes no parameter nor dictionary
+    pub fn item(&mut self, id: DefId) -> Result<Value<'p>, RuntimeErr> {
+        let span = self.program.items[id.0].name.span.clone();
+        self.def(id, &span)
+    }
+    /// The value of an expression using the items of the program, for the REPL
+    pub fn expr(&mut self, e: &'p Node<Expr<'p>>) -> Result<Value<'p>, RuntimeErr> {
+        let value = self.eval(e, &mut Env::new(), false);
+        self.returned(value)
+    }
+
+    fn def(&mut self, id: DefId, span: &Span) -> Result<Value<'p>, RuntimeErr> {
+        match self.program.items[id.0].arity() {
+            0 => {
+                let value = self.call(id, &[], span);
+                self.returned(value)
+            }
+            _ => Ok(pap(Callee::Item(id))),
+        }
+    }
+    // The value of an expression, `tail` being whether it's the value of the function it's in
+    fn eval(
+        &mut self,
+        Node { value, span }: &'p Node<Expr<'p>>,
+        env: &mut Env<'p>,
+        tail: bool,
+    ) -> Eval<'p> {
+        self.eval_in(value, span, env, tail)
+    }
+    fn boxed(
+        &mut self,
+        Node { value, span }: &'p BoxNode<Expr<'p>>,
+        env: &mut Env<'p>,
+        tail: bool,
+    ) -> Eval<'p> {
+        self.eval_in(value, span, env, tail)
+    }
+    fn eval_in(
+        &mut self,
+        value: &'p Expr<'p>,
+        span: &'p Span,
+        env: &mut Env<'p>,
+        tail: bool,
+    ) -> Eval<'p> {
+        Ok(match value {
+            Expr::Literal(l) => literal(l),
+            Expr::Var(Res::Local(id), _) => match self.program.mutable.contains(id) {
+                true => deref(&env[id]),
+                false => env[id].clone(),
+            },
+            Expr::Var(Res::Def(id), _) => self.def(*id, span)?,
+            Expr::Var(Res::Ctor(data, idx), _) => {
+                let ctor = &self.program.datas[data.0].ctors[*idx];
+                match ctor.fields.len() {
+                    0 => Value::Data(*idx, ctor.name.value, Rc::new([])),
+                    _ => pap(Callee::Ctor(*data, *idx)),
+                }
+            }
+            Expr::Method(dict, _, idx) => {
+                let (instance, context) = self.instance(dict, env);
+                self.method(instance, *idx, &context, span)?
+            }
+            Expr::Unary(UnOp::Ref | UnOp::RefMut, e) => {
+                Value::Ref(self.cell(&e.value, &e.span, env)?)
+            }
+            Expr::Unary(UnOp::Pos, e) => self.boxed(e, env, tail)?,
+            Expr::Unary(op, e) => {
+                let value = self.boxed(e, env, false)?;
+                match (op, value) {
+                    (UnOp::Not, Value::Bool(b)) => Value::Bool(!b),
+                    (UnOp::Neg, Value::Int(n)) => negate(n, span)?,
+                    (UnOp::Deref, value) => deref(&value),
+                    (op, value) => unreachable!("Can't apply {:?} to {:?}", op, value),
+                }
+            }
+            Expr::Binary(BinOp::And, l, r) => match self.boxed(l, env, false)? {
+                Value::Bool(true) => self.boxed(r, env, tail)?,
+                value => value,
+            },
+            Expr::Binary(BinOp::Or, l, r) => match self.boxed(l, env, false)? {
+                Value::Bool(false) => self.boxed(r, env, tail)?,
+                value => value,
+            },
+            Expr::Binary(op, l, r) => {
+                let l = self.boxed(l, env, false)?;
+                let r = self.boxed(r, env, false)?;
+                binary(*op, l, r, span)?
+            }
+            // Lambdas capture the values of `mut` locals, which they can't change
+            Expr::Lambda(param, body) => Value::Closure(Rc::new(Closure {
+                param,
+                body,
+                env: (env.iter())
+                    .map(|(id, value)| match self.program.mutable.contains(id) {
+                        true => (*id, Value::Ref(Rc::new(RefCell::new(deref(value))))),
+                        false => (*id, value.clone()),
+                    })
+                    .collect(),
+            })),
+            // The function is evaluated first, then its arguments from left to right, and it's
+            // only applied once they all have their value
+            Expr::Call(..) | Expr::EmptyCall(..) | Expr::DictApp(..) => {
+                let (f, args) = value.spine().expect("Calls have a spine");
+                let value = match &*f.value {
+                    // Items given all of their arguments are called without a partial application
+                    Expr::Var(Res::Def(id), _) if self.arity(Callee::Item(*id)) == args.len() => {
+                        let args = self.args(&args, env)?;
+                        if tail {
+                            return Err(Unwind::TailCall(
+                                pap(Callee::Item(*id)),
+                                args,
+                                span.clone(),
+                            ));
+                        }
+                        self.call(*id, &args, span)
+                    }
+                    _ => {
+                        let f = self.boxed(f, env, false)?;
+                        let args = self.args(&args, env)?;
+                        if tail {
+                            return Err(Unwind::TailCall(f, args, span.clone()));
+                        }
+                        self.apply(f, args, span)
+                    }
+                };
+                self.returned(value)?
+            }
+            Expr::Tuple(elements) => Value::Tuple(
+                elements
+                    .iter()
+                    .map(|e| self.eval(e, env, false))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Expr::Block {
+                statements,
+                returns,
+            } => self.block(statements, *returns, env, tail)?,
+            Expr::If { cond, then, else_ } => match self.boxed(cond, env, false)? {
+                Value::Bool(true) => self.boxed(then, env, tail)?,
+                _ => self.boxed(else_, env, tail)?,
+            },
+            // Operators overloaded by a dictionary parameter are primitive on `Int`s, and call
+            // the method of the other instances
+            Expr::OverloadedUnary(_, dict, e) => {
+                let (class, idx) = self.program.negation();
+                let value = self.boxed(e, env, false)?;
+                let (instance, context) = self.instance(dict, env);
+                match (self.program.int_instance(class) == Some(instance), value) {
+                    (true, Value::Int(n)) => negate(n, span)?,
+                    (_, value) => {
+                        let f = self.method(instance, idx, &context, span)?;
+                        return self.method_call(f, vec![value], span, tail);
+                    }
+                }
+            }
+            Expr::OverloadedBinary(op, dict, l, r) => {
+                let (class, idx) = self.program.operator(*op);
+                let l = self.boxed(l, env, false)?;
+                let r = self.boxed(r, env, false)?;
+                let (instance, context) = self.instance(dict, env);
+                match self.program.int_instance(class) == Some(instance) {
+                    true => binary(*op, l, r, span)?,
+                    false => {
+                        let f = self.method(instance, idx, &context, span)?;
+                        return self.method_call(f, vec![l, r], span, tail);
+                    }
+                }
+            }
+            Expr::Var(Res::Method(..), _) | Expr::Error => {
+                unreachable!("Only elaborated programs are evaluated")
+            }
+        })
+    }
+    // The cell a reference to a place shares, a new one for other expressions
+    fn cell(
+        &mut self,
+        value: &'p Expr<'p>,
+        span: &'p Span,
+        env: &mut Env<'p>,
+    ) -> Result<Rc<RefCell<Value<'p>>>, Unwind<'p>> {
+        let reference = match value {
+            Expr::Var(Res::Local(id), _) if self.program.mutable.contains(id) => env[id].clone(),
+            Expr::Unary(UnOp::Deref, e) => self.boxed(e, env, false)?,
+            _ => match value.single() {
+                Some(e) => return self.cell(&e.value, &e.span, env),
+                None => {
+                    return Ok(Rc::new(RefCell::new(
+                        self.eval_in(value, span, env, false)?,
+                    )))
+                }
+            },
+        };
+        match reference {
+            Value::Ref(cell) => Ok(cell),
+            value => unreachable!("Expected a reference, got {:?}", value),
+        }
+    }
+    fn args(
+        &mut self,
+        args: &[Arg<'p, 'p>],
+        env: &mut Env<'p>,
+    ) -> Result<Vec<Value<'p>>, Unwind<'p>> {
+        args.iter()
+            .map(|arg| match arg {
+                Arg::Expr(e) => self.boxed(e, env, false),
+                Arg::Unit => Ok(Value::Unit),
+                Arg::Dict(dict) => Ok(self.dict(dict, env)),
+            })
+            .collect()
+    }
+    fn dict(&self, dict: &Dict, env: &Env<'p>) -> Value<'p> {
+        match dict {
+            Dict::Instance(instance, context) => Value::Dict(
+                *instance,
+                context.iter().map(|dict| self.dict(dict, env)).collect(),
+            ),
+            Dict::Param(id) => env[id].clone(),
+            Dict::Hole(_) => unreachable!("Expressions with constraints aren't evaluated"),
+        }
+    }
+    fn instance(&self, dict: &Dict, env: &Env<'p>) -> (InstanceId, Rc<[Value<'p>]>) {
+        match self.dict(dict, env) {
+            Value::Dict(instance, context) => (instance, context),
+            value => unreachable!("Expected a dictionary, got {:?}", value),
+        }
+    }
+    // A method of an instance, given the dictionaries of the instance's context
+    fn method(
+        &mut self,
+        instance: InstanceId,
+        idx: usize,
+        context: &[Value<'p>],
+        span: &Span,
+    ) -> Result<Value<'p>, RuntimeErr> {
+        let id = self.program.instances[instance.0].methods[idx];
+        match context {
+            [] => self.def(id, span),
+            _ => {
+                let value = self.apply(pap(Callee::Item(id)), context.to_vec(), span);
+                self.returned(value)
+            }
+        }
+    }
+    // Calls a method, for an overloaded operator
+    fn method_call(
+        &mut self,
+        f: Value<'p>,
+        args: Vec<Value<'p>>,
+        span: &Span,
+        tail: bool,
+    ) -> Eval<'p> {
+        if tail {
+            return Err(Unwind::TailCall(f, args, span.clone()));
+        }
+        let value = self.apply(f, args, span);
+        Ok(self.returned(value)?)
+    }
+    fn block(
+        &mut self,
+        statements: &'p [Node<Statement<'p>>],
+        returns: bool,
+        env: &mut Env<'p>,
+        tail: bool,
+    ) -> Eval<'p> {
+        let mut last = Value::Unit;
+        for (idx, Node { value, .. }) in statements.iter().enumerate() {
+            last = match value {
+                Statement::Let(pattern, e) => {
+                    let value = self.eval(e, env, false)?;
+                    let bound = bind(&pattern.value, value, env, &self.program.mutable);
+                    assert!(bound, "`let` patterns are irrefutable");
+                    Value::Unit
+                }
+                // The value is evaluated before the place it's assigned to
+                Statement::Assign(place, e) => {
+                    let value = self.eval(e, env, false)?;
+                    *self.cell(&place.value, &place.span, env)?.borrow_mut() = value;
+                    Value::Unit
+                }
+                Statement::Return(e) => return Err(Unwind::Return(self.eval(e, env, true)?)),
+                Statement::Expr(e) => {
+                    self.eval(e, env, tail && returns && idx == statements.len() - 1)?
+                }
+            };
+        }
+        Ok(match returns {
+            true => last,
+            false => Value::Unit,
+        })
+    }
+    // Applies a function to arguments. A function given fewer arguments than it takes is
+    // partially applied to them, and the value of a call given more is applied to the rest.
+    // The last call is left for the caller to make the tail call it ends with
+    fn apply(&mut self, f: Value<'p>, args: Vec<Value<'p>>, span: &Span) -> Eval<'p> {
+        let mut args = args.into_iter();
+        let mut value = Ok(f);
+        while !args.as_slice().is_empty() {
+            value = match self.returned(value)? {
+                Value::Pap(pap) => {
+                    let callee = pap.callee;
+                    let arity = self.arity(callee);
+                    // The arguments can be taken when nothing else shares the partial application
+                    let mut taken = match Rc::try_unwrap(pap) {
+                        Ok(pap) => pap.args,
+                        Err(pap) => pap.args.clone(),
+                    };
+                    taken.extend(args.by_ref().take(arity - taken.len()));
+                    if taken.len() < arity {
+                        return Ok(Value::Pap(Rc::new(Pap {
+                            callee,
+                            args: taken,
+                        })));
+                    }
+                    match callee {
+                        Callee::Item(id) => self.call(id, &taken, span),
+                        Callee::Ctor(data, idx) => {
+                            let name = self.program.datas[data.0].ctors[idx].name.value;
+                            Ok(Value::Data(idx, name, taken.into()))
+                        }
+                    }
+                }
+                Value::Closure(closure) => {
+                    self.enter(span)?;
+                    let mut env = closure.env.clone();
+                    let arg = args.next().expect("There are arguments left");
+                    let bound = bind(&closure.param.value, arg, &mut env, &self.program.mutable);
+                    assert!(bound, "Lambda patterns are irrefutable");
+                    let value = self.boxed(closure.body, &mut env, true);
+                    self.depth -= 1;
+                    value
+                }
+                f => unreachable!("Only functions are applied, not {:?}", f),
+            };
+        }
+        value
+    }
+    // The value a function gives once the tail calls it ends with are made
+    fn returned(&mut self, mut value: Eval<'p>) -> Result<Value<'p>, RuntimeErr> {
+        while let Err(Unwind::TailCall(f, args, span)) = value {
+            value = self.apply(f, args, &span);
+        }
+        value.or_else(function_err)
+    }
+    fn arity(&self, callee: Callee) -> usize {
+        match callee {
+            Callee::Item(id) => self.program.items[id.0].arity(),
+            Callee::Ctor(data, idx) => self.program.datas[data.0].ctors[idx].fields.len(),
+        }
+    }
+    // Calls an item with its dictionaries then its parameters, using the first clause whose
+    // patterns match them. Its value is left for the caller to make the tail call it ends with
+    fn call(&mut self, id: DefId, args: &[Value<'p>], span: &Span) -> Eval<'p> {
+        self.enter(span)?;
+        let item = &self.program.items[id.0];
+        let (dicts, args) = args.split_at(item.dicts.len());
+        let mut env: Env<'p> = item
+            .dicts
+            .iter()
+            .copied()
+            .zip(dicts.iter().cloned())
+            .collect();
+        let clause = item
+            .clauses
+            .iter()
+            .find(|clause| {
+                let params = clause.params.iter().zip(args);
+                let mutable = &self.program.mutable;
+                params
+                    .into_iter()
+                    .all(|(p, arg)| bind(&p.value, arg.clone(), &mut env, mutable))
+            })
+            .expect("Clauses are exhaustive");
+        let value = self.eval(&clause.body, &mut env, true);
+        self.depth -= 1;
+        value
+    }
+    // Calls check how many of them are nested, and how deep the stack is too, as the size of
+    // their frames depends on the expressions they go through
+    fn enter(&mut self, span: &Span) -> Result<(), RuntimeErr> {
+        match self.depth == MAX_DEPTH || self.base.abs_diff(stack_address()) > STACK_LIMIT {
+            true => Err(RuntimeErr {
+                span: span.clone(),
+                kind: RuntimeErrKind::StackOverflow,
+            }),
+            false => {
+                self.depth += 1;
+                Ok(())
+            }
+        }
+    }
+}
+
+fn pap<'p>(callee: Callee) -> Value<'p> {
+    Value::Pap(Rc::new(Pap {
+        callee,
+        args: vec![],
+    }))
+}
+
+fn stack_address() -> usize {
+    let marker = 0u8;
+    &marker as *const u8 as usize
+}
+
+// A `return` gives the value of the function it's in
+fn function_err(unwind: Unwind) -> Result<Value, RuntimeErr> {
+    match unwind {
+        Unwind::Return(value) => Ok(value),
+        Unwind::Err(e) => Err(e),
+        Unwind::TailCall(..) => unreachable!("Tail calls are made before functions return"),
+    }
+}
+
+// Binds the locals of a pattern if it matches the value, those in `mutable` to a new cell
+fn bind<'p>(
+    pattern: &'p Pattern<'p>,
+    value: Value<'p>,
+    env: &mut Env<'p>,
+    mutable: &HashSet<LocalId>,
+) -> bool {
+    match (pattern, value) {
+        (Pattern::Wildcard, _) => true,
+        (Pattern::Binding(id, _), value) => {
+            let value = match mutable.contains(id) {
+                true => Value::Ref(Rc::new(RefCell::new(value))),
+                false => value,
+            };
+            env.insert(*id, value);
+            true
+        }
+        (Pattern::Literal(l), value) => equal(&literal(l), &value),
+        (Pattern::Ctor(_, idx, patterns), Value::Data(ctor, _, fields)) => {
+            *idx == ctor && bind_all(patterns, &fields, env, mutable)
+        }
+        (Pattern::Tuple(patterns), Value::Tuple(elements)) => {
+            bind_all(patterns, &elements, env, mutable)
+        }
+        (pattern, value) => unreachable!("{:?} can't match {:?}", pattern, value),
+    }
+}
+fn bind_all<'p>(
+    patterns: &'p [Node<Pattern<'p>>],
+    values: &[Value<'p>],
+    env: &mut Env<'p>,
+    mutable: &HashSet<LocalId>,
+) -> bool {
+    let pairs = patterns.iter().zip(values);
+    pairs
+        .into_iter()
+        .all(|(p, value)| bind(&p.value, value.clone(), env, mutable))
+}
+// The value a reference points to
+fn deref<'p>(reference: &Value<'p>) -> Value<'p> {
+    match reference {
+        Value::Ref(cell) => cell.borrow().clone(),
+        value => unreachable!("Expected a reference, got {:?}", value),
+    }
+}
+
+fn literal<'p>(l: &Literal) -> Value<'p> {
+    match l {
+        Literal::Num(n) => Value::Int(*n),
+        Literal::Bool(b) => Value::Bool(*b),
+        Literal::Unit => Value::Unit,
+    }
+}
+
+// Only the primitive instances of `Eq` compare values directly
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Int(a), Value::Int(b)) => a == b,
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        (Value::Unit, Value::Unit) => true,
+        (a, b) => unreachable!("Can't compare {:?} and {:?}", a, b),
+    }
+}
+
+fn negate<'p>(n: i64, span: &Span) -> Result<Value<'p>, RuntimeErr> {
+    match n.checked_neg() {
+        Some(n) => Ok(Value::Int(n)),
+        None => Err(overflow(span, format!("-({})", n))),
+    }
+}
+
+fn binary<'p>(op: BinOp, l: Value<'p>, r: Value<'p>, span: &Span) -> Result<Value<'p>, RuntimeErr> {
+    let (a, b) = match (op, &l, &r) {
+        (BinOp::EqEq, ..) => return Ok(Value::Bool(equal(&l, &r))),
+        (BinOp::NotEq, ..) => return Ok(Value::Bool(!equal(&l, &r))),
+        (_, Value::Int(a), Value::Int(b)) => (*a, *b),
+        _ => unreachable!("Can't apply `{}` to {:?} and {:?}", op, l, r),
+    };
+    let result = match op {
+        BinOp::Add => a.checked_add(b),
+        BinOp::Sub => a.checked_sub(b),
+        BinOp::Mul => a.checked_mul(b),
+        BinOp::Div if b == 0 => {
+            return Err(RuntimeErr {
+                span: span.clone(),
+                kind: RuntimeErrKind::DivisionByZero,
+            })
+        }
+        BinOp::Div => a.checked_div(b),
+        BinOp::LT => return Ok(Value::Bool(a < b)),
+        BinOp::LTE => return Ok(Value::Bool(a <= b)),
+        BinOp::GT => return Ok(Value::Bool(a > b)),
+        BinOp::GTE => return Ok(Value::Bool(a >= b)),
+        BinOp::And | BinOp::Or | BinOp::EqEq | BinOp::NotEq => unreachable!(),
+    };
+    match result {
+        Some(n) => Ok(Value::Int(n)),
+        None => Err(overflow(span, format!("{} {} {}", a, op, b))),
+    }
+}
+
+fn overflow(span: &Span, operation: String) -> RuntimeErr {
+    RuntimeErr {
+        span: span.clone(),
+        kind: RuntimeErrKind::Overflow(operation),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::tests::program;
+
+    // The value of `main`, in a program checked with the prelude
+    fn run(code: &str) -> Result<String, RuntimeErr> {
+        let (program, _) = program(code, true);
+        let main = program
+            .items
+            .iter()
+            .find(|item| item.name.value == "main")
+            .unwrap();
+        let program = &program;
+        with_stack(|| {
+            Interpreter::new(program)
+                .item(main.id)
+                .map(|v| v.to_string())
+        })
+    }
+    fn value(code: &str) -> String {
+        run(code).unwrap()
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(value("main = 1 + 2 * 3 - 8 / 3"), "5");
+        assert_eq!(value("main = (-7 / 2, -(3), +4)"), "(-3, -3, 4)");
+        assert_eq!(value("main = 1 < 2 && !(2 <= 1) || false"), "true");
+        assert_eq!(
+            value("main = (1 == 1, true != false, () == ())"),
+            "(true, true, true)"
+        );
+        // `&&` and `||` don't evaluate their right side when the left one decides
+        assert_eq!(value("main = false && 1 / 0 == 0"), "false");
+        assert_eq!(value("main = true || 1 / 0 == 0"), "true");
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(
+            value("fact n = if n == 0 then 1 else n * fact (n - 1)\nmain = fact 20\n"),
+            "2432902008176640000"
+        );
+        // Items, constructors and lambdas are curried
+        assert_eq!(
+            value(
+                "add3 a b c = a + b + c\nmain =\n  let f = add3 1\n  let g = f 2\n  g 3 + f 0 0\n"
+            ),
+            "7"
+        );
+        assert_eq!(
+            value("compose f g x = f (g x)\nmain = compose (\\x -> x * 2) (\\x -> x - 4) 10\n"),
+            "12"
+        );
+        // Closures keep the locals they were created in
+        assert_eq!(
+            value("adder n = \\x -> x + n\nmain =\n  let add2 = adder 2\n  let n = 10\n  add2 n\n"),
+            "12"
+        );
+        assert_eq!(
+            value("unit () = 1\nf x = x\nmain = (unit(), f())\n"),
+            "(1, ())"
+        );
+        assert_eq!(value("main = (\\x y -> y) 1"), "<function>");
+    }
+
+    #[test]
+    fn partial_application() {
+        // Partial applications are shared without being changed by their later arguments
+        assert_eq!(
+            value("add3 a b c = a + b + c\nmain =\n  let f = add3 1\n  let g = f 2\n  (g 3, g 4, f 5 6)\n"),
+            "(6, 7, 12)"
+        );
+        // The value of a call given too many arguments is applied to the rest
+        assert_eq!(
+            value("id x = x\nplus a b = a + b\nmain = id plus 1 2\n"),
+            "3"
+        );
+        assert_eq!(
+            value("k a = \\b -> \\c -> a + b + c\nmain = k 1 2 3\n"),
+            "6"
+        );
+        // `()` is an argument like any other
+        assert_eq!(
+            value("f () = 1\ng x () = x\nk () = \\x -> x\nmain = (f (), g 2 (), k () 3)\n"),
+            "(1, 2, 3)"
+        );
+        // The arguments have their value before the function is applied
+        let code = "k a = \\b -> a / 0\nmain = k 1 (2 / 0)\n";
+        assert_eq!(&code[run(code).unwrap_err().span.range], "(2 / 0)");
+    }
+
+    #[test]
+    fn blocks() {
+        assert_eq!(value("main =\n  let (a, b) = (1, 2)\n  a + b\n"), "3");
+        assert_eq!(
+            value("f x =\n  if x > 0 then { return 1 } else ()\n  2\nmain = (f 1, f 0)\n"),
+            "(1, 2)"
+        );
+        // A `return` leaves the innermost function, which can be a lambda
+        assert_eq!(
+            value("main =\n  let f = \\x -> { return x; 2 }\n  f 1 + 10\n"),
+            "11"
+        );
+        assert_eq!(value("main = { 1; 2 }"), "2");
+    }
+
+    #[test]
+    fn data_and_classes() {
+        let option = "data Option: T =\n  None\n  Some T\n";
+        let code = format!(
+            "{}unwrap d None = d\nunwrap _ (Some a) = a\nmain = (unwrap 0 (Some 3), unwrap 0 None, Some (Some (-1)))\n",
+            option
+        );
+        assert_eq!(value(&code), "(3, 0, Some (Some (-1)))");
+        let code = format!(
+            "{}class Size a =\n  size : a -> Int\ninstance Size Int =\n  size n = n\ninstance Size Bool =\n  size _ = 1\ntotal x y = size x + size y + 0 * x\nmain = (total 2 true, total 3 false)\n",
+            option
+        );
+        assert_eq!(value(&code), "(3, 4)");
+        // Overloaded operators call the methods of their instance
+        let code = format!(
+            "{}instance Eq (Option Int) =\n  eq a b = sameness a b\n  ne a b = !(sameness a b)\nsameness None None = true\nsameness (Some a) (Some b) = a == b\nsameness _ _ = false\nsame x y = x == y\nmain = (same (Some 1) (Some 1), Some 1 != None, same 1 2)\n",
+            option
+        );
+        assert_eq!(value(&code), "(true, true, false)");
+    }
+
+    fn error(code: &str) -> (RuntimeErrKind, String) {
+        let e = run(code).unwrap_err();
+        (e.kind, code[e.span.range].to_string())
+    }
+
+    #[test]
+    fn runtime_errors_point_at_their_node() {
+        assert_eq!(
+            error("f x = 10 / x\nmain = f 0\n"),
+            (RuntimeErrKind::DivisionByZero, "10 / x".to_string())
+        );
+        assert_eq!(
+            error("main = 9223372036854775807 + 1"),
+            (
+                RuntimeErrKind::Overflow("9223372036854775807 + 1".to_string()),
+                "9223372036854775807 + 1".to_string()
+            )
+        );
+        assert_eq!(
+            error("main = -(-9223372036854775807 - 1)").0,
+            RuntimeErrKind::Overflow("-(-9223372036854775808)".to_string())
+        );
+        assert_eq!(
+            error("f n = 1 + f (n + 1)\nmain = f 0\n"),
+            (RuntimeErrKind::StackOverflow, "f (n + 1)".to_string())
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+
+use crate::hir::*;
+
+/// The values of the locals in scope. Those bound by `mut` patterns are references to the cell
+/// holding their value
+pub type Env<'p> = HashMap<LocalId, Value<'p>>;
+
+/// A runtime value. Functions are curried: they stay partially applied until they have all of
+/// their arguments
+#[derive(Debug, Clone)]
+pub enum Value<'p> {
+    Int(i64),
+    Bool(bool),
+    Unit,
+    Tuple(Rc<[Value<'p>]>),
+    /// A constructor of a data type by index, with its name for display, applied to its fields
+    Data(usize, Ident<'p>, Rc<[Value<'p>]>),
+    Pap(Rc<Pap<'p>>),
+    Closure(Rc<Closure<'p>>),
+    /// The dictionary of an instance, with those of its context, passed to the items
+    /// constrained by its class
+    Dict(InstanceId, Rc<[Value<'p>]>),
+    /// A reference, sharing the cell of the `mut` local it borrows. Other places can't change
+    /// while they're borrowed, so references to them get a cell of their own
+    Ref(Rc<RefCell<Value<'p>>>),
+}
+
+/// A partial application: a function of the program applied to fewer arguments than it takes
+#[derive(Debug)]
+pub struct Pap<'p> {
+    pub callee: Callee,
+    pub args: Vec<Value<'p>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Callee {
+    /// An item, which takes its dictionaries then its parameters
+    Item(DefId),
+    /// A constructor of a data type by index, which takes its fields
+    Ctor(DataId, usize),
+}
+
+/// A lambda, with the locals it was created in
+#[derive(Debug)]
+pub struct Closure<'p> {
+    pub param: &'p Node<Pattern<'p>>,
+    pub body: &'p BoxNode<Expr<'p>>,
+    pub env: Env<'p>,
+}
+
+impl<'p> Value<'p> {
+    // Whether it needs parentheses to be the argument of a constructor
+    fn is_compound(&self) -> bool {
+        match self {
+            Value::Int(n) => *n < 0,
+            Value::Data(_, _, fields) => !fields.is_empty(),
+            _ => false,
+        }
+    }
+}
+impl<'p> fmt::Display for Value<'p> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Unit => write!(f, "()"),
+            Value::Tuple(elements) => {
+                write!(f, "(")?;
+                for (idx, e) in elements.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", e)?;
+                }
+                write!(f, ")")
+            }
+            Value::Data(_, name, fields) => {
+                write!(f, "{}", name)?;
+                for field in fields.iter() {
+                    match field.is_compound() {
+                        true => write!(f, " ({})", field)?,
+                        false => write!(f, " {}", field)?,
+                    }
+                }
+                Ok(())
+            }
+            Value::Pap(_) | Value::Closure(_) => write!(f, "<function>"),
+            Value::Dict(..) => write!(f, "<dictionary>"),
+            Value::Ref(cell) => write!(f, "{}", cell.borrow()),
+        }
+    }
+}
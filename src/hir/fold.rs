@@ -0,0 +1,279 @@
+//! Constant folding and algebraic simplification. Operators applied to literals are computed,
+//! `&&` and `||` with a known operand are short-circuited, and operations giving back one of
+//! their operands, like `x + 0` or `!!b`, are replaced by it. A folded expression keeps the
+//! span of the operation, so that the errors of what it became point at the same code.
+//!
+//! Folding happens once the borrows are checked: operators are primitive by then, the
+//! overloaded ones having become method calls, and `&&` and `||` whose right operand moves
+//! owned values have become `if`s dropping them. Operations that always fail, like `1 / 0`,
+//! are warned about and left to fail at runtime, as they may never be evaluated.
+use std::mem;
+
+use super::*;
+use crate::errors::fold_err::{FoldErr, FoldErrKind};
+
+/// Folds the bodies of the items of a checked program
+pub fn fold(program: &mut Program) -> Vec<FoldErr> {
+    let mut folder = Folder::default();
+    for item in &mut program.items {
+        for clause in &mut item.clauses {
+            folder.expr(&mut clause.body.value, &clause.body.span);
+        }
+    }
+    folder.warnings
+}
+
+/// Folds the items of a program and an expression using them, for the REPL. Only the
+/// expression's warnings are returned, the items' were when they were defined
+pub fn fold_expr<'a>(program: &mut Program<'a>, e: &mut Node<Expr<'a>>) -> Vec<FoldErr> {
+    fold(program);
+    let mut folder = Folder::default();
+    folder.expr(&mut e.value, &e.span);
+    folder.warnings
+}
+
+#[derive(Default)]
+struct Folder {
+    warnings: Vec<FoldErr>,
+}
+impl Folder {
+    // Folds the operands of an expression before the expression itself
+    fn expr(&mut self, value: &mut Expr, span: &Span) {
+        match value {
+            Expr::Literal(_) | Expr::Var(..) | Expr::Error | Expr::Method(..) => (),
+            Expr::Unary(_, e)
+            | Expr::OverloadedUnary(_, _, e)
+            | Expr::Lambda(_, e)
+            | Expr::EmptyCall(e)
+            | Expr::DictApp(e, _) => self.expr(&mut e.value, &e.span),
+            Expr::Binary(_, l, r) | Expr::OverloadedBinary(_, _, l, r) | Expr::Call(l, r) => {
+                self.expr(&mut l.value, &l.span);
+                self.expr(&mut r.value, &r.span);
+            }
+            Expr::Tuple(elements) => {
+                for e in elements {
+                    self.expr(&mut e.value, &e.span);
+                }
+            }
+            Expr::Block { statements, .. } => {
+                for statement in statements {
+                    match &mut statement.value {
+                        Statement::Let(_, e)
+                        | Statement::Assign(_, e)
+                        | Statement::Return(e)
+                        | Statement::Expr(e) => self.expr(&mut e.value, &e.span),
+                    }
+                }
+            }
+            Expr::If { cond, then, else_ } => {
+                for e in [cond, then, else_] {
+                    self.expr(&mut e.value, &e.span);
+                }
+            }
+        }
+        self.simplify(value, span);
+    }
+    // Replaces an operation whose operands are folded by its result, when it's known
+    fn simplify(&mut self, value: &mut Expr, span: &Span) {
+        let simplified = match value {
+            Expr::Unary(op, e) => match (*op, &mut *e.value) {
+                (UnOp::Pos, _) => take(e),
+                (UnOp::Neg, Expr::Literal(Literal::Num(n))) => match n.checked_neg() {
+                    Some(n) => Expr::Literal(Literal::Num(n)),
+                    None => return self.overflow(span, format!("-({})", n)),
+                },
+                (UnOp::Not, Expr::Literal(Literal::Bool(b))) => Expr::Literal(Literal::Bool(!*b)),
+                (UnOp::Not, Expr::Unary(UnOp::Not, e)) => take(e),
+                _ => return,
+            },
+            Expr::Binary(op, l, r) => match (*op, &*l.value, &*r.value) {
+                (op, Expr::Literal(Literal::Num(a)), Expr::Literal(Literal::Num(b)))
+                    if !matches!(op, BinOp::EqEq | BinOp::NotEq) =>
+                {
+                    match self.arithmetic(op, *a, *b, span) {
+                        Some(l) => Expr::Literal(l),
+                        None => return,
+                    }
+                }
+                (BinOp::EqEq | BinOp::NotEq, Expr::Literal(a), Expr::Literal(b)) => {
+                    Expr::Literal(Literal::Bool((a == b) == (*op == BinOp::EqEq)))
+                }
+                // The left operand is evaluated first, the right one only when it's needed
+                (BinOp::And, Expr::Literal(Literal::Bool(true)), _)
+                | (BinOp::Or, Expr::Literal(Literal::Bool(false)), _) => take(r),
+                (BinOp::And | BinOp::Or, Expr::Literal(Literal::Bool(b)), _) => {
+                    Expr::Literal(Literal::Bool(*b))
+                }
+                (BinOp::And, _, Expr::Literal(Literal::Bool(true)))
+                | (BinOp::Or, _, Expr::Literal(Literal::Bool(false)))
+                | (BinOp::Add | BinOp::Sub, _, Expr::Literal(Literal::Num(0)))
+                | (BinOp::Mul | BinOp::Div, _, Expr::Literal(Literal::Num(1))) => take(l),
+                (BinOp::Add, Expr::Literal(Literal::Num(0)), _)
+                | (BinOp::Mul, Expr::Literal(Literal::Num(1)), _) => take(r),
+                _ => return,
+            },
+            _ => return,
+        };
+        *value = simplified;
+    }
+    // The result of an operation on integers, when it doesn't fail
+    fn arithmetic(&mut self, op: BinOp, a: i64, b: i64, span: &Span) -> Option<Literal> {
+        let result = match op {
+            BinOp::Add => a.checked_add(b),
+            BinOp::Sub => a.checked_sub(b),
+            BinOp::Mul => a.checked_mul(b),
+            BinOp::Div if b == 0 => {
+                self.warnings.push(FoldErr {
+                    span: span.clone(),
+                    kind: FoldErrKind::DivisionByZero,
+                });
+                return None;
+            }
+            BinOp::Div => a.checked_div(b),
+            BinOp::LT => return Some(Literal::Bool(a < b)),
+            BinOp::LTE => return Some(Literal::Bool(a <= b)),
+            BinOp::GT => return Some(Literal::Bool(a > b)),
+            BinOp::GTE => return Some(Literal::Bool(a >= b)),
+            _ => unreachable!("`{}` doesn't take integers", op),
+        };
+        if result.is_none() {
+            self.overflow(span, format!("{} {} {}", a, op, b));
+        }
+        result.map(Literal::Num)
+    }
+    fn overflow(&mut self, span: &Span, operation: String) {
+        self.warnings.push(FoldErr {
+            span: span.clone(),
+            kind: FoldErrKind::Overflow(operation),
+        });
+    }
+}
+
+// Takes an operand out of the operation it's replacing
+fn take<'a>(e: &mut BoxNode<Expr<'a>>) -> Expr<'a> {
+    mem::replace(&mut e.value, Expr::Error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::tests::{check_code, step_errors};
+
+    // The folded value of `main`, the code it spans, and the code spanned by the warnings
+    fn main(code: &'static str) -> (Expr<'static>, &'static str, Vec<String>) {
+        let mut checked = check_code(code, true);
+        let warnings = step_errors!(checked, Fold)
+            .into_iter()
+            .map(|w| code[w.span.range].to_string())
+            .collect();
+        let main = checked
+            .program
+            .items
+            .iter()
+            .find(|item| item.name.value == "main")
+            .unwrap();
+        match &main.clauses[0].body.value {
+            Expr::Block { statements, .. } => match &statements[..] {
+                [Node {
+                    value: Statement::Expr(e),
+                    ..
+                }] => (e.value.clone(), &code[e.span.range.clone()], warnings),
+                s => panic!("expected an expression, got {:?}", s),
+            },
+            e => panic!("expected a block, got {:?}", e),
+        }
+    }
+    fn num(n: i64) -> Expr<'static> {
+        Expr::Literal(Literal::Num(n))
+    }
+    fn bool(b: bool) -> Expr<'static> {
+        Expr::Literal(Literal::Bool(b))
+    }
+
+    #[test]
+    fn literals_are_computed() {
+        assert_eq!(
+            main("main = 1 + 2 * 3 - 8 / 3"),
+            (num(5), "1 + 2 * 3 - 8 / 3", vec![])
+        );
+        assert_eq!(main("main = -(3) + +4").0, num(1));
+        assert_eq!(main("main = !(1 < 2) || 3 >= 4").0, bool(false));
+        assert_eq!(main("main = (1 == 1) != (() == ())").0, bool(false));
+        assert_eq!(
+            main("main = (1 + 1, !!true)").0,
+            Expr::Tuple(vec![
+                Node {
+                    value: num(2),
+                    span: Span::new(1, 8..13),
+                },
+                Node {
+                    value: bool(true),
+                    span: Span::new(1, 15..21),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn failing_operations_are_kept() {
+        let (e, _, warnings) = main("main = 2 * (1 / 0)");
+        assert!(matches!(e, Expr::Binary(BinOp::Mul, ..)), "{:?}", e);
+        assert_eq!(warnings, ["(1 / 0)"]);
+        let (e, _, warnings) = main("main = 9223372036854775807 + (3 - 2)");
+        match e {
+            Expr::Binary(BinOp::Add, _, r) => assert_eq!(*r.value, num(1)),
+            e => panic!("expected an addition, got {:?}", e),
+        }
+        assert_eq!(warnings, ["9223372036854775807 + (3 - 2)"]);
+        assert_eq!(main("main = if true then 1 else 1 / 0").2, ["1 / 0"]);
+    }
+
+    #[test]
+    fn known_operands_short_circuit() {
+        let f = "f x = x\n";
+        assert_eq!(main("f x = x\nmain = false && f true").0, bool(false));
+        assert_eq!(main("f x = x\nmain = true || f false").0, bool(true));
+        for code in [
+            "main = true && f true",
+            "main = false || f true",
+            "main = f true && true",
+        ] {
+            let (e, span, _) = main(Box::leak(format!("{}{}", f, code).into()));
+            assert!(matches!(e, Expr::Call(..)), "{:?}", e);
+            assert_eq!(span, &code[7..]);
+        }
+        // `f` is still evaluated, and could fail
+        let (e, _, _) = main("f x = x\nmain = f true && false");
+        assert!(matches!(e, Expr::Binary(BinOp::And, ..)), "{:?}", e);
+    }
+
+    #[test]
+    fn identities_are_removed() {
+        for code in [
+            "f x = (x + 0) * 1 - 0\nmain = f",
+            "f x = 0 + 1 * (x / 1)\nmain = f",
+        ] {
+            let mut checked = check_code(code, true);
+            assert!(step_errors!(checked, Fold).is_empty());
+            let f = checked
+                .program
+                .items
+                .iter()
+                .find(|item| item.name.value == "f")
+                .unwrap();
+            let x = match &f.clauses[0].params[0].value {
+                Pattern::Binding(id, _) => *id,
+                p => panic!("expected `x`, got {:?}", p),
+            };
+            match &f.clauses[0].body.value {
+                Expr::Block { statements, .. } => match &statements[0].value {
+                    Statement::Expr(e) => {
+                        assert_eq!(e.value, Expr::Var(Res::Local(x), "x"), "{}", code)
+                    }
+                    s => panic!("expected an expression, got {:?}", s),
+                },
+                e => panic!("expected a block, got {:?}", e),
+            }
+        }
+    }
+}
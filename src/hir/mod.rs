@@ -0,0 +1,338 @@
+//! The high-level IR: the syntax tree once every name is resolved to its definition
+pub mod fold;
+pub mod resolve;
+
+use std::{collections::HashSet, fmt};
+
+use crate::source::Span;
+pub use crate::syntax::ast::{BinOp, Ident, Literal, Node, UnOp};
+
+pub type BoxNode<T> = Node<Box<T>>;
+
+/// A top-level item, indexing `Program::items`
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub struct DefId(pub usize);
+
+/// A local binding (parameter, `let` or lambda), unique in its program
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub struct LocalId(pub usize);
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub struct ClassId(pub usize);
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub struct InstanceId(pub usize);
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub struct DataId(pub usize);
+
+/// What a name refers to
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Res {
+    Def(DefId),
+    Local(LocalId),
+    /// The method of a class, by index, implemented by one of its instances
+    Method(ClassId, usize),
+    /// The constructor of a data type, by index
+    Ctor(DataId, usize),
+}
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Program<'a> {
+    /// The functions of the program, followed by the methods of its instances
+    pub items: Vec<Item<'a>>,
+    pub classes: Vec<Class<'a>>,
+    pub instances: Vec<Instance>,
+    pub datas: Vec<Data<'a>>,
+    /// How many locals were created, `LocalId`s are below it
+    pub locals: usize,
+    /// The locals bound by `mut` patterns, which can be assigned and borrowed mutably
+    pub mutable: HashSet<LocalId>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Item<'a> {
+    pub id: DefId,
+    pub name: Node<Ident<'a>>,
+    /// The dictionaries the item takes before its parameters, filled by type checking
+    pub dicts: Vec<LocalId>,
+    /// Tried in order, they all take the same number of parameters
+    pub clauses: Vec<Clause<'a>>,
+    /// The instance, and the method of its class, that the item implements
+    pub instance: Option<(InstanceId, usize)>,
+    /// Whether a clause is marked `tailrec`, so that its calls of itself and of the other
+    /// `tailrec` items must be tail calls
+    pub tailrec: bool,
+}
+
+impl<'a> Program<'a> {
+    /// The class overloading a binary operator, and the index of the method implementing it
+    pub fn operator(&self, op: BinOp) -> (ClassId, usize) {
+        let (class, name) = operator(op);
+        self.method(class, name)
+    }
+    /// `neg`, the method of `Num` implementing `-x`
+    pub fn negation(&self) -> (ClassId, usize) {
+        self.method("Num", "neg")
+    }
+    fn method(&self, class: &str, name: &str) -> (ClassId, usize) {
+        let class = (self.classes.iter())
+            .find(|c| c.name.value == class)
+            .expect("The prelude declares the operator classes");
+        let idx = (class.methods.iter())
+            .position(|m| m.name.value == name)
+            .expect("The prelude declares the operator methods");
+        (class.id, idx)
+    }
+    /// The instance of `class` for `Int`, whose operators are primitive
+    pub fn int_instance(&self, class: ClassId) -> Option<InstanceId> {
+        (self.instances.iter())
+            .find(|i| i.class == class && i.ty.value == Ty::Int)
+            .map(|i| i.id)
+    }
+}
+
+/// The name of the class overloading a binary operator, and of the method implementing it
+pub fn operator(op: BinOp) -> (&'static str, &'static str) {
+    match op {
+        BinOp::Add => ("Num", "add"),
+        BinOp::Sub => ("Num", "sub"),
+        BinOp::Mul => ("Num", "mul"),
+        BinOp::Div => ("Num", "div"),
+        BinOp::EqEq => ("Eq", "eq"),
+        BinOp::NotEq => ("Eq", "ne"),
+        BinOp::LT => ("Ord", "lt"),
+        BinOp::LTE => ("Ord", "le"),
+        BinOp::GT => ("Ord", "gt"),
+        BinOp::GTE => ("Ord", "ge"),
+        BinOp::And | BinOp::Or => unreachable!("`&&` and `||` aren't overloaded"),
+    }
+}
+
+impl<'a> Item<'a> {
+    /// How many arguments a call of the item needs, dictionaries included
+    pub fn arity(&self) -> usize {
+        self.dicts.len() + self.clauses[0].params.len()
+    }
+}
+
+/// `name params = body`, consecutive ones with the same name define a single item
+#[derive(Debug, PartialEq, Clone)]
+pub struct Clause<'a> {
+    pub params: Vec<Node<Pattern<'a>>>,
+    pub body: Node<Expr<'a>>,
+}
+
+/// A class over a single type parameter
+#[derive(Debug, PartialEq, Clone)]
+pub struct Class<'a> {
+    pub id: ClassId,
+    pub name: Node<Ident<'a>>,
+    pub param: TypeParam<'a>,
+    pub methods: Vec<Method<'a>>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Method<'a> {
+    pub name: Node<Ident<'a>>,
+    /// Its variables are the class parameter, then the ones the signature introduces
+    pub ty: Node<Ty>,
+    pub vars: usize,
+}
+
+/// A data type, whose constructors' fields refer to its parameters by index
+#[derive(Debug, PartialEq, Clone)]
+pub struct Data<'a> {
+    pub id: DataId,
+    pub name: Node<Ident<'a>>,
+    pub params: Vec<TypeParam<'a>>,
+    pub ctors: Vec<Ctor<'a>>,
+    /// Whether its values have a unique owner, which drops them at the end of its scope,
+    /// rather than being garbage collected
+    pub owned: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Ctor<'a> {
+    pub name: Node<Ident<'a>>,
+    pub fields: Vec<Node<Ty>>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TypeParam<'a> {
+    pub name: Node<Ident<'a>>,
+    /// The kind it's annotated with, it's inferred otherwise
+    pub kind: Option<Node<Kind>>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Instance {
+    pub id: InstanceId,
+    pub class: ClassId,
+    /// The constraints of its context, each one a class and a variable of its type: the
+    /// dictionaries its methods take, in order
+    pub context: Vec<Node<(ClassId, usize)>>,
+    /// How many type variables its type has
+    pub vars: usize,
+    pub ty: Node<Ty>,
+    /// `Class type`, for diagnostics
+    pub span: Span,
+    /// The items implementing the methods of the class, in the order of the class
+    pub methods: Vec<DefId>,
+}
+
+/// A type written in the source
+#[derive(Debug, PartialEq, Clone)]
+pub enum Ty {
+    /// A type variable, by index: the parameters of the enclosing class or data type, then the
+    /// variables a method signature introduces, or those of the type of an instance
+    Var(usize),
+    Int,
+    Bool,
+    Unit,
+    Data(DataId),
+    App(BoxNode<Ty>, BoxNode<Ty>),
+    Fun(BoxNode<Ty>, BoxNode<Ty>),
+    Tuple(Vec<Node<Ty>>),
+    /// `&T`, or `&mut T` when it's mutable
+    Ref(bool, BoxNode<Ty>),
+}
+
+/// The type of a type: `*` for the types of values, `k1 -> k2` for type constructors
+#[derive(Debug, PartialEq, Clone)]
+pub enum Kind {
+    Star,
+    Arrow(Box<Kind>, Box<Kind>),
+}
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Star => write!(f, "*"),
+            Kind::Arrow(param, ret) if matches!(**param, Kind::Arrow(..)) => {
+                write!(f, "({}) -> {}", param, ret)
+            }
+            Kind::Arrow(param, ret) => write!(f, "{} -> {}", param, ret),
+        }
+    }
+}
+
+/// A dictionary: the methods of an instance, passed around at runtime
+#[derive(Debug, PartialEq, Clone)]
+pub enum Dict {
+    /// The dictionary of an instance, applied to those of its context
+    Instance(InstanceId, Vec<Dict>),
+    /// A dictionary parameter of the enclosing item
+    Param(LocalId),
+    /// Only exists during type checking, until constraints are solved
+    Hole(usize),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern<'a> {
+    Wildcard,
+    Binding(LocalId, Ident<'a>),
+    Literal(Literal),
+    /// A constructor of a data type by index, with the patterns of its fields
+    Ctor(DataId, usize, Vec<Node<Pattern<'a>>>),
+    Tuple(Vec<Node<Pattern<'a>>>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expr<'a> {
+    Literal(Literal),
+    /// A resolved name, which keeps its spelling for diagnostics
+    Var(Res, Ident<'a>),
+    Unary(UnOp, BoxNode<Expr<'a>>),
+    Binary(BinOp, BoxNode<Expr<'a>>, BoxNode<Expr<'a>>),
+    Lambda(Node<Pattern<'a>>, BoxNode<Expr<'a>>),
+    Call(BoxNode<Expr<'a>>, BoxNode<Expr<'a>>),
+    /// `f ()`, which is `f` applied to the unit value like to any other argument: it calls
+    /// functions taking `()`, and is an error on items without parameters, which are values
+    EmptyCall(BoxNode<Expr<'a>>),
+    Tuple(Vec<Node<Expr<'a>>>),
+    Block {
+        statements: Vec<Node<Statement<'a>>>,
+        returns: bool,
+    },
+    If {
+        cond: BoxNode<Expr<'a>>,
+        then: BoxNode<Expr<'a>>,
+        else_: BoxNode<Expr<'a>>,
+    },
+    /// Stands for an expression that failed to resolve, so that the rest can still be checked
+    Error,
+    /// A method taken from a dictionary
+    Method(Dict, ClassId, usize),
+    /// An item taking dictionaries, applied to them
+    DictApp(BoxNode<Expr<'a>>, Vec<Dict>),
+    /// Operators whose operands' class instance is only known once type checking is done,
+    /// they become either primitive operators or method calls. Those of a dictionary
+    /// parameter stay overloaded until runtime, where the instance for `Int` is primitive
+    OverloadedUnary(UnOp, Dict, BoxNode<Expr<'a>>),
+    OverloadedBinary(BinOp, Dict, BoxNode<Expr<'a>>, BoxNode<Expr<'a>>),
+}
+
+/// An argument of an application
+#[derive(Debug, Clone, Copy)]
+pub enum Arg<'e, 'a> {
+    Expr(&'e BoxNode<Expr<'a>>),
+    /// The `()` of an `EmptyCall`
+    Unit,
+    Dict(&'e Dict),
+}
+
+impl<'a> Expr<'a> {
+    /// Splits nested applications into the function they apply and all of their arguments in
+    /// order, `f x ()` being `f` applied to `x` and `()`. The dictionaries an item is applied
+    /// to come before its other arguments. Returns `None` when the expression isn't a call
+    pub fn spine(&self) -> Option<(&BoxNode<Expr<'a>>, Vec<Arg<'_, 'a>>)> {
+        let mut args = vec![];
+        let mut head = None;
+        let mut e = self;
+        loop {
+            let f = match e {
+                Expr::Call(f, x) => {
+                    args.push(Arg::Expr(x));
+                    f
+                }
+                Expr::EmptyCall(f) => {
+                    args.push(Arg::Unit);
+                    f
+                }
+                Expr::DictApp(f, dicts) => {
+                    args.extend(dicts.iter().rev().map(Arg::Dict));
+                    f
+                }
+                _ => break,
+            };
+            head = Some(f);
+            e = &f.value;
+        }
+        args.reverse();
+        head.map(|head| (head, args))
+    }
+    /// The expression of a block made of it alone, like the value of a `let` on its own line
+    pub fn single(&self) -> Option<&Node<Expr<'a>>> {
+        match self {
+            Expr::Block {
+                statements,
+                returns: true,
+            } => match &statements[..] {
+                [Node {
+                    value: Statement::Expr(e),
+                    ..
+                }] => Some(e),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Statement<'a> {
+    Let(Node<Pattern<'a>>, Node<Expr<'a>>),
+    /// `place = value`, the place being a local or a dereferenced reference
+    Assign(Node<Expr<'a>>, Node<Expr<'a>>),
+    Return(Node<Expr<'a>>),
+    Expr(Node<Expr<'a>>),
+}
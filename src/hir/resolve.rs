@@ -0,0 +1,1007 @@
+//! Name resolution: lowers the syntax tree to the HIR, linking every name to its definition
+use std::collections::{HashMap, HashSet};
+
+use super::*;
+use crate::{
+    errors::{
+        resolve_err::{ResolveErr, ResolveErrKind},
+        Suggestion,
+    },
+    source::{FileId, Span},
+    syntax::ast,
+    utils::closest,
+};
+
+/// Resolves a whole program. Items, class methods and constructors are visible from every item,
+/// including themselves, as are data types from every type, while locals are visible from their binding to the end of their scope: the body
+/// of a function or lambda, or the rest of the block of a `let`. The values of a file shadow
+/// those of the files before it, like the program's do the prelude's, but for the file's own
+/// items.
+///
+/// The program is always returned, with `Expr::Error` in place of unbound names, along with the
+/// errors and warnings found.
+pub fn resolve<'a>(items: &[Node<ast::Item<'a>>]) -> (Program<'a>, Vec<ResolveErr<'a>>) {
+    let mut resolver = Resolver::new(items);
+    let program = resolver.program();
+    (program, resolver.errors)
+}
+
+/// Resolves an expression against the items of a program, for the REPL
+pub fn resolve_expr<'a>(
+    items: &[Node<ast::Item<'a>>],
+    expr: &Node<ast::Expr<'a>>,
+) -> (Program<'a>, Node<Expr<'a>>, Vec<ResolveErr<'a>>) {
+    let mut resolver = Resolver::new(items);
+    let mut program = resolver.program();
+    resolver.file = expr.span.file;
+    let expr = resolver.expr(expr);
+    program.locals = resolver.locals;
+    program.mutable = resolver.mutable;
+    (program, expr, resolver.errors)
+}
+
+// The types that aren't data types, which data types can't be named after
+const BUILTIN_TYPES: [&str; 2] = ["Int", "Bool"];
+
+struct Resolver<'p, 'a> {
+    // Functions, class methods and constructors, in the order of their files
+    values: HashMap<Ident<'a>, Vec<(Res, Span)>>,
+    classes: HashMap<Ident<'a>, (ClassId, Span)>,
+    types: HashMap<Ident<'a>, (DataId, Span)>,
+    // The items defining them, duplicates left out. Functions are made of clauses
+    functions: Vec<Vec<&'p ast::Item<'a>>>,
+    class_items: Vec<&'p ast::Item<'a>>,
+    data_items: Vec<&'p ast::Item<'a>>,
+    instance_items: Vec<(&'p ast::Item<'a>, Span)>,
+    // The file of the item being resolved
+    file: FileId,
+    // The innermost scope is the last one
+    scopes: Vec<Vec<(Ident<'a>, LocalId, Span)>>,
+    locals: usize,
+    mutable: HashSet<LocalId>,
+    errors: Vec<ResolveErr<'a>>,
+}
+
+impl<'p, 'a> Resolver<'p, 'a> {
+    // Registers the items first, so that they can refer to each other regardless of their order
+    fn new(items: &'p [Node<ast::Item<'a>>]) -> Self {
+        let mut resolver = Self {
+            values: HashMap::new(),
+            classes: HashMap::new(),
+            types: HashMap::new(),
+            functions: vec![],
+            class_items: vec![],
+            data_items: vec![],
+            instance_items: vec![],
+            file: 0,
+            scopes: vec![],
+            locals: 0,
+            mutable: HashSet::new(),
+            errors: vec![],
+        };
+        for group in clauses(items) {
+            let item = group[0];
+            match item {
+                ast::Item::Function { name, .. } => {
+                    let id = DefId(resolver.functions.len());
+                    if resolver.define_value(name, Res::Def(id)) {
+                        resolver.functions.push(group);
+                    }
+                }
+                ast::Item::Class { name, methods, .. } => {
+                    let id = ClassId(resolver.class_items.len());
+                    if let Some((_, first)) = resolver.classes.get(name.value) {
+                        resolver.duplicate(name, first.clone());
+                        continue;
+                    }
+                    resolver.classes.insert(name.value, (id, name.span.clone()));
+                    resolver.class_items.push(item);
+                    for (idx, method) in methods.iter().enumerate() {
+                        resolver.define_value(&method.value.name, Res::Method(id, idx));
+                    }
+                }
+                ast::Item::Instance { class, ty, .. } => {
+                    let head = class.span.merge(&ty.span);
+                    resolver.instance_items.push((item, head));
+                }
+                ast::Item::Data { name, ctors, .. } => {
+                    let id = DataId(resolver.data_items.len());
+                    // Still defined, for its constructors
+                    if BUILTIN_TYPES.contains(&name.value) {
+                        resolver.errors.push(ResolveErr {
+                            span: name.span.clone(),
+                            kind: ResolveErrKind::BuiltinType(name.value),
+                            suggestions: vec![],
+                        });
+                    }
+                    if let Some((_, first)) = resolver.types.get(name.value) {
+                        resolver.duplicate(name, first.clone());
+                        continue;
+                    }
+                    resolver.types.insert(name.value, (id, name.span.clone()));
+                    resolver.data_items.push(item);
+                    for (idx, ctor) in ctors.iter().enumerate() {
+                        resolver.define_value(&ctor.value.name, Res::Ctor(id, idx));
+                    }
+                }
+            }
+        }
+        resolver
+    }
+    // Returns whether the name was free in the file
+    fn define_value(&mut self, name: &Node<Ident<'a>>, res: Res) -> bool {
+        let values = self.values.entry(name.value).or_default();
+        if let Some((_, first)) = values.iter().find(|(_, s)| s.file == name.span.file) {
+            let first = first.clone();
+            self.duplicate(name, first);
+            return false;
+        }
+        values.push((res, name.span.clone()));
+        true
+    }
+    // The value of the file being resolved, or else of the last file
+    fn value(&self, name: Ident<'a>) -> Option<(Res, Span)> {
+        let values = self.values.get(name)?;
+        let value = values.iter().find(|(_, span)| span.file == self.file);
+        value.or_else(|| values.last()).cloned()
+    }
+    fn duplicate(&mut self, name: &Node<Ident<'a>>, first: Span) {
+        self.errors.push(ResolveErr {
+            span: name.span.clone(),
+            kind: ResolveErrKind::Duplicate {
+                name: name.value,
+                first,
+            },
+            suggestions: vec![],
+        });
+    }
+    fn program(&mut self) -> Program<'a> {
+        let mut items = vec![];
+        for clauses in self.functions.clone() {
+            let id = DefId(items.len());
+            items.push(self.function(id, &clauses, None));
+        }
+        let classes = self
+            .class_items
+            .clone()
+            .into_iter()
+            .enumerate()
+            .map(|(idx, item)| self.class(ClassId(idx), item))
+            .collect();
+        let datas = self
+            .data_items
+            .clone()
+            .into_iter()
+            .enumerate()
+            .map(|(idx, item)| self.data(DataId(idx), item))
+            .collect();
+        let mut instances = vec![];
+        for (item, span) in self.instance_items.clone() {
+            let id = InstanceId(instances.len());
+            if let Some(instance) = self.instance(id, item, span, &mut items) {
+                instances.push(instance);
+            }
+        }
+        Program {
+            items,
+            classes,
+            instances,
+            datas,
+            locals: self.locals,
+            mutable: self.mutable.clone(),
+        }
+    }
+    // Each clause has its own scope. Those taking a different number of parameters than the
+    // first one are left out
+    fn function(
+        &mut self,
+        id: DefId,
+        clauses: &[&ast::Item<'a>],
+        instance: Option<(InstanceId, usize)>,
+    ) -> Item<'a> {
+        let mut resolved: Vec<Clause<'a>> = vec![];
+        self.file = function_name(clauses[0]).span.file;
+        for clause in clauses {
+            let (name, params, body) = match clause {
+                ast::Item::Function {
+                    name, params, body, ..
+                } => (name, params, body),
+                _ => unreachable!("Only functions have clauses"),
+            };
+            if let Some(first) = resolved.first() {
+                if first.params.len() != params.len() {
+                    self.errors.push(ResolveErr {
+                        span: name.span.clone(),
+                        kind: ResolveErrKind::ClauseArity {
+                            name: name.value,
+                            expected: first.params.len(),
+                            found: params.len(),
+                            first: function_name(clauses[0]).span.clone(),
+                        },
+                        suggestions: vec![],
+                    });
+                    continue;
+                }
+            }
+            self.scopes.push(vec![]);
+            let params = self.params(params);
+            let body = self.expr(body);
+            self.scopes.pop();
+            resolved.push(Clause { params, body });
+        }
+        Item {
+            id,
+            name: function_name(clauses[0]).clone(),
+            dicts: vec![],
+            clauses: resolved,
+            instance,
+            tailrec: (clauses.iter())
+                .any(|clause| matches!(clause, ast::Item::Function { tailrec: true, .. })),
+        }
+    }
+    fn class(&mut self, id: ClassId, item: &ast::Item<'a>) -> Class<'a> {
+        let (name, param, methods) = match item {
+            ast::Item::Class {
+                name,
+                param,
+                methods,
+            } => (name, param, methods),
+            _ => unreachable!(),
+        };
+        let methods = methods
+            .iter()
+            .map(|Node { value: sig, .. }| {
+                let mut vars = vec![param.name.value];
+                let ty = self.ty(&sig.ty.value, &sig.ty.span, &mut vars, true);
+                Method {
+                    name: sig.name.clone(),
+                    ty,
+                    vars: vars.len(),
+                }
+            })
+            .collect();
+        Class {
+            id,
+            name: name.clone(),
+            param: type_param(param),
+            methods,
+        }
+    }
+    fn data(&mut self, id: DataId, item: &ast::Item<'a>) -> Data<'a> {
+        let (name, params, ctors, owned) = match item {
+            ast::Item::Data {
+                name,
+                params,
+                ctors,
+                owned,
+            } => (name, params, ctors, *owned),
+            _ => unreachable!(),
+        };
+        let mut vars: Vec<Ident<'a>> = vec![];
+        for param in params {
+            let name = &param.name;
+            match params.iter().find(|p| p.name.value == name.value) {
+                Some(first) if first.name.span != name.span => {
+                    self.duplicate(name, first.name.span.clone())
+                }
+                _ => (),
+            }
+            vars.push(name.value);
+        }
+        let ctors = ctors
+            .iter()
+            .map(|Node { value: ctor, .. }| Ctor {
+                name: ctor.name.clone(),
+                fields: ctor
+                    .fields
+                    .iter()
+                    .map(|f| self.ty(&f.value, &f.span, &mut vars, false))
+                    .collect(),
+            })
+            .collect();
+        Data {
+            id,
+            name: name.clone(),
+            params: params.iter().map(type_param).collect(),
+            ctors,
+            owned,
+        }
+    }
+    // Instances of unknown classes, or with missing methods, are left out. Their methods are
+    // added to `items`
+    fn instance(
+        &mut self,
+        id: InstanceId,
+        item: &ast::Item<'a>,
+        span: Span,
+        items: &mut Vec<Item<'a>>,
+    ) -> Option<Instance> {
+        let (context_items, class_name, ty, methods) = match item {
+            ast::Item::Instance {
+                context,
+                class,
+                ty,
+                methods,
+            } => (context, class, ty, methods),
+            _ => unreachable!(),
+        };
+        let class = self.class_id(class_name)?;
+        let mut vars = vec![];
+        let ty = self.ty(&ty.value, &ty.span, &mut vars, true);
+        // Drop glue finds destructors by the type they drop
+        if class_name.value == "Drop" {
+            if let Some(var) = vars.first() {
+                self.errors.push(ResolveErr {
+                    span: ty.span.clone(),
+                    kind: ResolveErrKind::DropTypeVar { var },
+                    suggestions: vec![],
+                });
+                return None;
+            }
+        }
+        // Solving the context of an instance for a variable would want the same constraints
+        // again, rather than ones on parts of its type
+        let whole = matches!(ty.value, Ty::Var(_));
+        let mut context = vec![];
+        for Node { value, span } in context_items {
+            let class = self.class_id(&value.class)?;
+            match value.ty.value {
+                ast::TypeExpr::Named(name) if vars.contains(&name) && !whole => {
+                    let var = vars.iter().position(|v| *v == name).unwrap();
+                    context.push(Node {
+                        value: (class, var),
+                        span: span.clone(),
+                    });
+                }
+                _ => {
+                    self.errors.push(ResolveErr {
+                        span: value.ty.span.clone(),
+                        kind: ResolveErrKind::ContextType { whole },
+                        suggestions: vec![],
+                    });
+                    return None;
+                }
+            }
+        }
+        let class_methods = match self.class_items[class.0] {
+            ast::Item::Class { methods, .. } => methods,
+            _ => unreachable!(),
+        };
+        let mut implemented: Vec<Option<DefId>> = vec![None; class_methods.len()];
+        for method in clauses(methods) {
+            let name = function_name(method[0]);
+            let idx = match class_methods
+                .iter()
+                .position(|m| m.value.name.value == name.value)
+            {
+                Some(idx) => idx,
+                None => {
+                    self.errors.push(ResolveErr {
+                        span: name.span.clone(),
+                        kind: ResolveErrKind::NotAMethod {
+                            method: name.value,
+                            class: class_name.value,
+                        },
+                        suggestions: vec![],
+                    });
+                    continue;
+                }
+            };
+            if let Some(first) = implemented[idx] {
+                let first = items[first.0].name.span.clone();
+                self.duplicate(name, first);
+                continue;
+            }
+            let def = DefId(items.len());
+            items.push(self.function(def, &method, Some((id, idx))));
+            implemented[idx] = Some(def);
+        }
+        let missing: Vec<_> = implemented
+            .iter()
+            .zip(class_methods)
+            .filter(|(def, _)| def.is_none())
+            .map(|(_, m)| m.value.name.value)
+            .collect();
+        if !missing.is_empty() {
+            self.errors.push(ResolveErr {
+                span: span.clone(),
+                kind: ResolveErrKind::MissingMethods {
+                    class: class_name.value,
+                    missing,
+                },
+                suggestions: vec![],
+            });
+            // They're still checked, as functions
+            for def in implemented.into_iter().flatten() {
+                items[def.0].instance = None;
+            }
+            return None;
+        }
+        Some(Instance {
+            id,
+            class,
+            context,
+            vars: vars.len(),
+            ty,
+            span,
+            methods: implemented.into_iter().map(Option::unwrap).collect(),
+        })
+    }
+    // Type variables are looked up in `vars`, unknown lowercase names are added to it when
+    // they're `implicit`, as in method signatures
+    fn ty(
+        &mut self,
+        value: &ast::TypeExpr<'a>,
+        span: &Span,
+        vars: &mut Vec<Ident<'a>>,
+        implicit: bool,
+    ) -> Node<Ty> {
+        let value = match value {
+            ast::TypeExpr::Unit => Ty::Unit,
+            ast::TypeExpr::Named(name) => match vars.iter().position(|v| v == name) {
+                Some(idx) => Ty::Var(idx),
+                None => match *name {
+                    "Int" => Ty::Int,
+                    "Bool" => Ty::Bool,
+                    name => match self.types.get(name) {
+                        Some((id, _)) => Ty::Data(*id),
+                        None if implicit && name.starts_with(char::is_lowercase) => {
+                            vars.push(name);
+                            Ty::Var(vars.len() - 1)
+                        }
+                        None => {
+                            let candidates = vars
+                                .iter()
+                                .copied()
+                                .chain(BUILTIN_TYPES)
+                                .chain(self.types.keys().copied())
+                                .collect();
+                            self.unbound_in(name, span.clone(), candidates);
+                            Ty::Unit
+                        }
+                    },
+                },
+            },
+            ast::TypeExpr::App(f, arg) => Ty::App(
+                self.ty(&f.value, &f.span, vars, implicit).into_boxed(),
+                self.ty(&arg.value, &arg.span, vars, implicit).into_boxed(),
+            ),
+            ast::TypeExpr::Fun(param, ret) => Ty::Fun(
+                self.ty(&param.value, &param.span, vars, implicit)
+                    .into_boxed(),
+                self.ty(&ret.value, &ret.span, vars, implicit).into_boxed(),
+            ),
+            ast::TypeExpr::Tuple(tys) => Ty::Tuple(
+                tys.iter()
+                    .map(|ty| self.ty(&ty.value, &ty.span, vars, implicit))
+                    .collect(),
+            ),
+            ast::TypeExpr::Ref(mutable, ty) => Ty::Ref(
+                *mutable,
+                self.ty(&ty.value, &ty.span, vars, implicit).into_boxed(),
+            ),
+        };
+        Node {
+            value,
+            span: span.clone(),
+        }
+    }
+    // Binds the parameters of a clause in the current scope, they must not share names
+    fn params(&mut self, params: &[Node<ast::Pattern<'a>>]) -> Vec<Node<Pattern<'a>>> {
+        let mut bound = vec![];
+        params.iter().map(|p| self.pattern(p, &mut bound)).collect()
+    }
+    // `bound` are the bindings made by the enclosing patterns so far, which can't be repeated.
+    // Patterns that fail to resolve become wildcards
+    fn pattern(
+        &mut self,
+        pattern: &Node<ast::Pattern<'a>>,
+        bound: &mut Vec<(Ident<'a>, Span)>,
+    ) -> Node<Pattern<'a>> {
+        let span = pattern.span.clone();
+        let value = match &pattern.value {
+            ast::Pattern::Wildcard => Pattern::Wildcard,
+            ast::Pattern::Literal(l) => Pattern::Literal(*l),
+            ast::Pattern::Binding(name) | ast::Pattern::MutBinding(name) => {
+                match bound.iter().find(|(n, _)| n == name) {
+                    Some((_, first)) => {
+                        let first = first.clone();
+                        let name = Node {
+                            value: *name,
+                            span: span.clone(),
+                        };
+                        self.duplicate(&name, first);
+                        Pattern::Wildcard
+                    }
+                    None => {
+                        bound.push((name, span.clone()));
+                        let id = self.bind(name, span.clone());
+                        if let ast::Pattern::MutBinding(_) = pattern.value {
+                            self.mutable.insert(id);
+                        }
+                        Pattern::Binding(id, name)
+                    }
+                }
+            }
+            ast::Pattern::Ctor(name, fields) => {
+                let fields: Vec<_> = fields.iter().map(|f| self.pattern(f, bound)).collect();
+                match self.value(name.value) {
+                    Some((Res::Ctor(data, idx), _)) => {
+                        let expected = match self.data_items[data.0] {
+                            ast::Item::Data { ctors, .. } => ctors[idx].value.fields.len(),
+                            _ => unreachable!(),
+                        };
+                        if fields.len() == expected {
+                            Pattern::Ctor(data, idx, fields)
+                        } else {
+                            self.errors.push(ResolveErr {
+                                span: span.clone(),
+                                kind: ResolveErrKind::CtorArity {
+                                    ctor: name.value,
+                                    expected,
+                                    found: fields.len(),
+                                },
+                                suggestions: vec![],
+                            });
+                            Pattern::Wildcard
+                        }
+                    }
+                    _ => {
+                        let ctors = self
+                            .values
+                            .iter()
+                            .filter(|(_, values)| {
+                                values.iter().any(|(res, _)| matches!(res, Res::Ctor(..)))
+                            })
+                            .map(|(name, _)| *name)
+                            .collect();
+                        self.unbound_in(name.value, name.span.clone(), ctors);
+                        Pattern::Wildcard
+                    }
+                }
+            }
+            ast::Pattern::Tuple(patterns) => {
+                Pattern::Tuple(patterns.iter().map(|p| self.pattern(p, bound)).collect())
+            }
+        };
+        Node { value, span }
+    }
+    fn bind(&mut self, name: Ident<'a>, span: Span) -> LocalId {
+        // The items of files loaded after the binding's, like the program's for the prelude's,
+        // weren't there when it was written
+        let shadowed = self
+            .lookup(name)
+            .filter(|(res, shadowed)| matches!(res, Res::Local(_)) || shadowed.file <= span.file);
+        if let (false, Some((_, shadowed))) = (name.starts_with('_'), shadowed) {
+            self.errors.push(ResolveErr {
+                span: span.clone(),
+                kind: ResolveErrKind::Shadowing { name, shadowed },
+                suggestions: vec![],
+            });
+        }
+        let id = LocalId(self.locals);
+        self.locals += 1;
+        self.scopes
+            .last_mut()
+            .expect("Bindings are always made in a scope")
+            .push((name, id, span));
+        id
+    }
+    fn lookup(&self, name: Ident<'a>) -> Option<(Res, Span)> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(n, ..)| *n == name)
+            .map(|(_, id, span)| (Res::Local(*id), span.clone()))
+            .or_else(|| self.value(name))
+    }
+    fn unbound(&mut self, name: Ident<'a>, span: Span) {
+        let in_scope = self
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.iter().map(|(n, ..)| *n))
+            .chain(self.values.keys().copied())
+            .collect();
+        self.unbound_in(name, span, in_scope);
+    }
+    fn class_id(&mut self, name: &Node<Ident<'a>>) -> Option<ClassId> {
+        match self.classes.get(name.value) {
+            Some((class, _)) => Some(*class),
+            None => {
+                let candidates: Vec<_> = self.classes.keys().copied().collect();
+                self.unbound_in(name.value, name.span.clone(), candidates);
+                None
+            }
+        }
+    }
+    fn unbound_in(&mut self, name: Ident<'a>, span: Span, candidates: Vec<Ident<'a>>) {
+        // Unless they're declared, `True` and `False` are most likely misspelled booleans
+        let suggestions = match name {
+            "True" | "False" => vec![Suggestion::replace(span.clone(), name.to_lowercase())],
+            _ => closest(name, candidates)
+                .map(|c| Suggestion::replace(span.clone(), c).maybe_incorrect())
+                .into_iter()
+                .collect(),
+        };
+        self.errors.push(ResolveErr {
+            span,
+            kind: ResolveErrKind::Unbound(name),
+            suggestions,
+        });
+    }
+    fn boxed(&mut self, Node { value, span }: &ast::BoxNode<ast::Expr<'a>>) -> BoxNode<Expr<'a>> {
+        self.lower(value, span).into_boxed()
+    }
+    fn expr(&mut self, Node { value, span }: &Node<ast::Expr<'a>>) -> Node<Expr<'a>> {
+        self.lower(value, span)
+    }
+    fn lower(&mut self, value: &ast::Expr<'a>, span: &Span) -> Node<Expr<'a>> {
+        let value = match value {
+            ast::Expr::Literal(l) => Expr::Literal(*l),
+            ast::Expr::Ident(name) => match self.lookup(name) {
+                Some((res, _)) => Expr::Var(res, name),
+                None => {
+                    self.unbound(name, span.clone());
+                    Expr::Error
+                }
+            },
+            // Parentheses only matter to the parser
+            ast::Expr::Parenthesized(e) => *self.boxed(e).value,
+            ast::Expr::Unary(op, e) => Expr::Unary(*op, self.boxed(e)),
+            ast::Expr::Binary(op, l, r) => Expr::Binary(*op, self.boxed(l), self.boxed(r)),
+            ast::Expr::Lambda(param, body) => {
+                self.scopes.push(vec![]);
+                let param = self.pattern(param, &mut vec![]);
+                let body = self.boxed(body);
+                self.scopes.pop();
+                Expr::Lambda(param, body)
+            }
+            ast::Expr::Call(f, x) => Expr::Call(self.boxed(f), self.boxed(x)),
+            ast::Expr::EmptyCall(f) => Expr::EmptyCall(self.boxed(f)),
+            ast::Expr::Tuple(elements) => {
+                Expr::Tuple(elements.iter().map(|e| self.expr(e)).collect())
+            }
+            ast::Expr::Block {
+                instructions,
+                returns,
+            } => {
+                self.scopes.push(vec![]);
+                let statements = instructions.iter().map(|s| self.statement(s)).collect();
+                self.scopes.pop();
+                Expr::Block {
+                    statements,
+                    returns: *returns,
+                }
+            }
+            ast::Expr::If { cond, then, else_ } => Expr::If {
+                cond: self.boxed(cond),
+                then: self.boxed(then),
+                else_: self.boxed(else_),
+            },
+        };
+        Node {
+            value,
+            span: span.clone(),
+        }
+    }
+    fn statement(
+        &mut self,
+        Node { value, span }: &Node<ast::Statement<'a>>,
+    ) -> Node<Statement<'a>> {
+        let value = match value {
+            // The value is resolved before the binding exists, `let`s aren't recursive
+            ast::Statement::Let(pattern, e) => {
+                let e = self.expr(e);
+                Statement::Let(self.pattern(pattern, &mut vec![]), e)
+            }
+            // Only locals and what references point to can be assigned
+            ast::Statement::Assign(place, e) => {
+                let e = self.expr(e);
+                let place = self.expr(place);
+                match is_place(&place.value) {
+                    Some(true) => Statement::Assign(place, e),
+                    Some(false) => {
+                        self.errors.push(ResolveErr {
+                            span: place.span,
+                            kind: ResolveErrKind::InvalidAssign,
+                            suggestions: vec![],
+                        });
+                        Statement::Expr(e)
+                    }
+                    // Already reported
+                    None => Statement::Expr(e),
+                }
+            }
+            ast::Statement::Return(e) => Statement::Return(self.expr(e)),
+            // There are no loops to leave
+            ast::Statement::Continue => {
+                self.not_in_loop("continue", span);
+                Statement::Expr(Node {
+                    value: Expr::Error,
+                    span: span.clone(),
+                })
+            }
+            ast::Statement::Break(e) => {
+                self.not_in_loop("break", span);
+                Statement::Expr(self.expr(e))
+            }
+            ast::Statement::StmtExpr(e) => Statement::Expr(self.lower(e, span)),
+        };
+        Node {
+            value,
+            span: span.clone(),
+        }
+    }
+    fn not_in_loop(&mut self, keyword: &'static str, span: &Span) {
+        self.errors.push(ResolveErr {
+            span: span.clone(),
+            kind: ResolveErrKind::NotInLoop { keyword },
+            suggestions: vec![],
+        });
+    }
+}
+
+// Whether an expression can be assigned, `None` when it failed to resolve
+fn is_place(e: &Expr) -> Option<bool> {
+    match e {
+        Expr::Var(Res::Local(_), _) => Some(true),
+        Expr::Unary(UnOp::Deref, e) => is_place(&e.value),
+        Expr::Error => None,
+        _ => Some(false),
+    }
+}
+
+// Consecutive functions with the same name and parameters are the clauses of a single function,
+// other items are alone in their group. Values without parameters can't have several clauses
+fn clauses<'p, 'a>(items: &'p [Node<ast::Item<'a>>]) -> Vec<Vec<&'p ast::Item<'a>>> {
+    let name = |item: &ast::Item<'a>| match item {
+        ast::Item::Function { name, params, .. } if !params.is_empty() => Some(name.value),
+        _ => None,
+    };
+    let mut groups: Vec<Vec<&ast::Item<'a>>> = vec![];
+    for Node { value: item, .. } in items {
+        match groups.last_mut() {
+            Some(group) if name(item).is_some() && name(group[0]) == name(item) => group.push(item),
+            _ => groups.push(vec![item]),
+        }
+    }
+    groups
+}
+
+fn function_name<'p, 'a>(item: &'p ast::Item<'a>) -> &'p Node<Ident<'a>> {
+    match item {
+        ast::Item::Function { name, .. } => name,
+        _ => unreachable!("Instances only contain functions"),
+    }
+}
+
+fn type_param<'a>(ast::TypeParam { name, kind }: &ast::TypeParam<'a>) -> TypeParam<'a> {
+    fn lower(value: &ast::KindExpr, span: &Span) -> Node<Kind> {
+        let value = match value {
+            ast::KindExpr::Star => Kind::Star,
+            ast::KindExpr::Arrow(param, ret) => Kind::Arrow(
+                Box::new(lower(&param.value, &param.span).value),
+                Box::new(lower(&ret.value, &ret.span).value),
+            ),
+        };
+        Node {
+            value,
+            span: span.clone(),
+        }
+    }
+    TypeParam {
+        name: name.clone(),
+        kind: kind.as_ref().map(|k| lower(&k.value, &k.span)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        errors::{apply_suggestions, ErrorKind, Report},
+        syntax::parse_program,
+    };
+
+    fn codes(code: &str) -> Vec<&'static str> {
+        let items = parse_program(0, code).unwrap();
+        let (_, errors) = resolve(&items);
+        errors.iter().map(|e| e.kind.code()).collect()
+    }
+
+    #[test]
+    fn uses_link_to_their_definition() {
+        let items = parse_program(0, "f x = g x\ng y = \\z -> y z\n").unwrap();
+        let (program, errors) = resolve(&items);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(program.locals, 3);
+        let f = &program.items[0];
+        let x = match f.clauses[0].params[0].value {
+            Pattern::Binding(id, "x") => id,
+            ref p => panic!("expected `x`, got {:?}", p),
+        };
+        match &f.clauses[0].body.value {
+            Expr::Block { statements, .. } => match &statements[0].value {
+                Statement::Expr(Node {
+                    value: Expr::Call(g, arg),
+                    ..
+                }) => {
+                    assert_eq!(*g.value, Expr::Var(Res::Def(DefId(1)), "g"));
+                    assert_eq!(*arg.value, Expr::Var(Res::Local(x), "x"));
+                }
+                s => panic!("expected a call, got {:?}", s),
+            },
+            e => panic!("expected a block, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn diagnostics() {
+        assert_eq!(codes("f = y\n"), ["E0006"]);
+        assert_eq!(codes("f = 1\nf = 2\n"), ["E0007"]);
+        assert_eq!(codes("f x x = x\n"), ["E0007"]);
+        assert_eq!(codes("f x = \\x -> x\n"), ["W0001"]);
+        assert_eq!(codes("f x =\n  let x = x + 1\n  x\n"), ["W0001"]);
+        assert_eq!(codes("f _x = \\_x -> _x\n"), Vec::<&str>::new());
+        // A `let` is only visible after itself
+        assert_eq!(codes("f =\n  let x = x\n  x\n"), ["E0006"]);
+        assert_eq!(codes("f = { { let x = 1 }; x }\n"), ["E0006"]);
+        // Only places can be assigned
+        assert_eq!(
+            codes("f r =\n  let mut x = 1\n  *r = x\n  x = 2\n"),
+            Vec::<&str>::new()
+        );
+        assert_eq!(
+            codes("f x =\n  x + 1 = 2\n  (\\y -> y) = x\n"),
+            ["E0037", "E0037"]
+        );
+    }
+
+    #[test]
+    fn earlier_files_dont_shadow_later_items() {
+        // Like the prelude's locals and the program's items, the program's locals still
+        // shadowing the prelude's
+        let mut items = parse_program(0, "f x = x\n").unwrap();
+        items.extend(parse_program(1, "x = 1\ng f = f\n").unwrap());
+        let (_, errors) = resolve(&items);
+        let shadowed: Vec<_> = errors
+            .iter()
+            .map(|e| (e.kind.code(), e.span.file))
+            .collect();
+        assert_eq!(shadowed, [("W0001", 1)]);
+    }
+
+    #[test]
+    fn later_files_shadow_earlier_values() {
+        // Like the program's items do the prelude's methods, which its items still use
+        let mut items = parse_program(0, "class C a =\n  add : a -> a\nf x = add x\n").unwrap();
+        items.extend(parse_program(1, "add x y = x\ng = add 1 2\n").unwrap());
+        let (program, errors) = resolve(&items);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let body = |idx: usize| format!("{:?}", program.items[idx].clauses[0].body);
+        assert!(body(0).contains(r#"Var(Method(ClassId(0), 0), "add")"#));
+        assert!(body(2).contains(r#"Var(Def(DefId(1)), "add")"#));
+        assert_eq!(codes("add x = x\nadd = 1\n"), ["E0007"]);
+    }
+
+    #[test]
+    fn instances_implement_their_class() {
+        let code = "class C a =\n  f : a -> Int\n  g : a\ninstance C Bool =\n  g = true\n  f x = 1\nh = f g\n";
+        let items = parse_program(0, code).unwrap();
+        let (program, errors) = resolve(&items);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(matches!(
+            &program.classes[0].methods[0].ty.value,
+            Ty::Fun(param, ret) if *param.value == Ty::Var(0) && *ret.value == Ty::Int
+        ));
+        // Instance methods come after the functions, in the order of the class
+        assert_eq!(program.instances[0].methods, [DefId(2), DefId(1)]);
+        assert_eq!(program.items[1].instance, Some((InstanceId(0), 1)));
+        assert_eq!(
+            codes("class C a =\n  f : a\ninstance C Int =\n  f = 1\n  g = 2\n"),
+            ["E0011"]
+        );
+        assert_eq!(
+            codes("class C a =\n  f : a\ninstance C Int =\n  g = 2\n"),
+            ["E0011", "E0012"]
+        );
+        assert_eq!(codes("instance C Int =\n  f = 1\n"), ["E0006"]);
+        assert_eq!(codes("data Int =\n  Zero\nz = Zero\n"), ["E0040"]);
+        // `True` and `False` are fixed to booleans, unless they're declared
+        let code = "a = if True then 2 else 3\nf False = 1\n";
+        let items = parse_program(0, code).unwrap();
+        let (_, errors) = resolve(&items);
+        let suggestions: Vec<_> = errors.iter().flat_map(|e| e.suggestions()).collect();
+        let fixed = apply_suggestions(code, &suggestions);
+        assert_eq!(fixed, "a = if true then 2 else 3\nf false = 1\n");
+        assert!(
+            codes("data B =\n  True\n  False\nnot True = False\nnot False = True\n").is_empty()
+        );
+        assert_eq!(
+            codes("f x =\n  continue\ng x =\n  break x\n"),
+            ["E0042", "E0042"]
+        );
+        // Contexts constrain the variables of the instance's type
+        let code = "data Box: T =\n  Box T\nclass C a =\n  f : a -> Int\ninstance C (Box a) =\n  f x = 1\n";
+        assert!(codes(code).is_empty());
+        let code = "data Box: T =\n  Box T\nclass C a =\n  f : a -> Int\ninstance C Int => C (Box a) =\n  f x = 1\n";
+        assert_eq!(codes(code), ["E0039"]);
+        let code = "class C a =\n  f : a -> Int\ninstance C a => C a =\n  f x = 1\n";
+        assert_eq!(codes(code), ["E0039"]);
+        let code = "owned data Box: T =\n  Box T\nclass Drop a =\n  drop : a -> ()\ninstance Drop (Box a) =\n  drop _ = ()\n";
+        assert_eq!(codes(code), ["E0039"]);
+    }
+
+    #[test]
+    fn data_types() {
+        let code = "data List: A =\n  Nil\n  Cons A (List A)\nxs = Cons 1 Nil\n";
+        let items = parse_program(0, code).unwrap();
+        let (program, errors) = resolve(&items);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let fields = &program.datas[0].ctors[1].fields;
+        assert_eq!(fields[0].value, Ty::Var(0));
+        match &fields[1].value {
+            Ty::App(f, arg) => {
+                assert_eq!(*f.value, Ty::Data(DataId(0)));
+                assert_eq!(*arg.value, Ty::Var(0));
+            }
+            ty => panic!("expected a type application, got {:?}", ty),
+        }
+        match &program.items[0].clauses[0].body.value {
+            Expr::Block { statements, .. } => match &statements[0].value {
+                Statement::Expr(Node {
+                    value: Expr::Call(f, _),
+                    ..
+                }) => match &*f.value {
+                    Expr::Call(cons, _) => {
+                        assert_eq!(*cons.value, Expr::Var(Res::Ctor(DataId(0), 1), "Cons"))
+                    }
+                    e => panic!("expected a call, got {:?}", e),
+                },
+                s => panic!("expected a call, got {:?}", s),
+            },
+            e => panic!("expected a block, got {:?}", e),
+        }
+        assert_eq!(codes("data T: A =\n  C B\n"), ["E0006"]);
+        assert_eq!(codes("data T: A A =\n  C A\n"), ["E0007"]);
+        assert_eq!(codes("data T =\n  C\ndata T =\n  D\n"), ["E0007"]);
+    }
+
+    #[test]
+    fn clauses() {
+        let code = "data Option: T =\n  None\n  Some T\nf None = 0\nf (Some x) = x\ng = f None\n";
+        let items = parse_program(0, code).unwrap();
+        let (program, errors) = resolve(&items);
+        assert!(errors.is_empty(), "{:?}", errors);
+        // The clauses are a single item, each with its own scope
+        assert_eq!(program.items.len(), 2);
+        let params: Vec<_> = program.items[0]
+            .clauses
+            .iter()
+            .map(|c| c.params[0].value.clone())
+            .collect();
+        match &params[..] {
+            [Pattern::Ctor(DataId(0), 0, none), Pattern::Ctor(DataId(0), 1, some)] => {
+                assert!(none.is_empty());
+                assert!(matches!(some[0].value, Pattern::Binding(_, "x")));
+            }
+            params => panic!("expected constructors, got {:?}", params),
+        }
+        assert_eq!(codes("f 1 = 1\nf x y = 2\n"), ["E0018"]);
+        // Clauses are only grouped when they are next to each other
+        assert_eq!(codes("f 1 = 1\ng = 2\nf x = 2\n"), ["E0007"]);
+        assert_eq!(codes("data T =\n  C Int\nf (C x y) = x\n"), ["E0019"]);
+        assert_eq!(codes("f (x, x) = x\n"), ["E0007"]);
+        assert_eq!(codes("f (Nothing x) = x\n"), ["E0006"]);
+    }
+
+    #[test]
+    fn typos_get_a_suggestion() {
+        let items = parse_program(0, "length x = 1\nf = lenght 2\n").unwrap();
+        let (_, errors) = resolve(&items);
+        let suggestion = &errors[0].suggestions[0];
+        assert_eq!(suggestion.replacement, "length");
+        assert_eq!(suggestion.span.range, 17..23);
+    }
+}
@@ -1,71 +1,25 @@
-#![feature(or_patterns)]
-// TODO write the parser
 // TODO Add error handling for lexing
+#![allow(clippy::result_large_err)]
 
 #[macro_use]
 extern crate lazy_static;
 
+mod check;
+mod cli;
 mod errors;
+mod eval;
+mod hir;
+mod mir;
+mod native;
+mod prelude;
+mod repl;
+mod source;
 mod syntax;
+mod typeck;
 mod utils;
-
-use codespan_reporting::{
-    files::SimpleFile,
-    term::{
-        self,
-        termcolor::{ColorChoice, StandardStream},
-    },
-};
-use logos::Logos;
-
-use errors::syntax_err::SyntaxErr;
-
-use syntax::{
-    ast::{Expr, Node},
-    insensitive_layout::*,
-    parser::Parser,
-    tokens::{pretty_print_tokens, Token, TokenKind},
-};
-
-fn parse<'a>(code: &'a str) -> Result<Node<Expr<'a>>, Vec<SyntaxErr<'a>>> {
-    let lex = TokenKind::lexer(code);
-    let block_tokens =
-        block_inference(lex.spanned().map(|t| Token::from_tuple(t))).map_err(|e| vec![e])?;
-    pretty_print_tokens(block_tokens.iter());
-    let mut parser = Parser::new(block_tokens.into_iter());
-    match parser.expr() {
-        Ok(e) => {
-            if parser.errors.is_empty() {
-                Ok(e)
-            } else {
-                Err(parser.errors)
-            }
-        }
-        Err(e) => {
-            parser.errors.push(e);
-            Err(parser.errors)
-        }
-    }
-}
+mod vm;
+mod wasm;
 
 fn main() {
-    //FIXME a * (2 + 3) doens't work, investigate why
-    let code = "
-    a = if True then 2 else 3
-    ";
-    let expr = parse(code);
-    match expr {
-        Ok(expr) => {
-            println!("ast: {:#?}", expr);
-        }
-        Err(errors) => {
-            let file = SimpleFile::new("main.ka", code);
-            let writer = StandardStream::stderr(ColorChoice::Always);
-            let config = codespan_reporting::term::Config::default();
-            for err in errors {
-                term::emit(&mut writer.lock(), &config, &file, &err.into())
-                    .expect("Failed to write on stdout");
-            }
-        }
-    }
+    std::process::exit(cli::run(std::env::args().skip(1)));
 }
@@ -0,0 +1,998 @@
+//! Non-lexical borrow checking. A borrow lasts as long as the references made from it may
+//! still be used, rather than until the end of a scope:
+//!
+//! - Each local holding references has a region, the points of its body where it's live, and
+//!   each borrow has a region too.
+//! - Assignments and calls give outlives constraints between them: the regions of the values
+//!   flowing into a local contain its region, and a borrow's contains the region of the local
+//!   it's given to. The locals the body returns and its parameters live for the whole body.
+//! - A borrow is in scope from where it's made until it leaves its region. Using the place it
+//!   borrows in a conflicting way meanwhile, or dropping it, is an error.
+//!
+//! The regions aren't part of the types, which is enough since references can't be kept by
+//! data values or functions.
+use std::collections::{HashSet, VecDeque};
+
+use super::*;
+use crate::{
+    errors::borrow_err::{Access, BorrowErr, BorrowErrKind, Later},
+    hir::{Expr, Node, Program},
+    typeck::{lower_ty, TyVar, Types},
+};
+
+/// Checks the borrows of the items of a program, once its ownership is checked
+pub fn check_borrows(program: &Program, types: &Types) -> Vec<BorrowErr> {
+    let mut errors = ref_fields(program);
+    check_bodies(program, &lower(program, types), &mut errors);
+    errors
+}
+
+/// Checks the borrows of the items of a program and of an expression using them, for the REPL
+pub fn check_expr_borrows<'a>(
+    program: &Program<'a>,
+    e: &Node<Expr<'a>>,
+    types: &Types,
+) -> Vec<BorrowErr> {
+    let mut errors = ref_fields(program);
+    check_bodies(program, &lower_expr(program, e, types).0, &mut errors);
+    errors
+}
+
+fn check_bodies(program: &Program, mir: &Mir, errors: &mut Vec<BorrowErr>) {
+    for body in &mir.bodies {
+        Checker::new(program, body).check(errors);
+    }
+}
+
+// Data values can't hold references
+fn ref_fields(program: &Program) -> Vec<BorrowErr> {
+    let mut errors = vec![];
+    for data in &program.datas {
+        let vars: Vec<_> = (0..data.params.len())
+            .map(|v| Type::Var(TyVar(v)))
+            .collect();
+        for ctor in &data.ctors {
+            for field in &ctor.fields {
+                let ty = lower_ty(&program.datas, &field.value, &vars);
+                if ty.has_refs() {
+                    let kind = BorrowErrKind::RefField {
+                        data: data.name.value.to_string(),
+                        ty,
+                    };
+                    errors.push(BorrowErr {
+                        span: field.span.clone(),
+                        kind,
+                    });
+                }
+            }
+        }
+    }
+    errors
+}
+
+// A set of small indices
+#[derive(PartialEq, Clone)]
+struct Bits(Vec<u64>);
+impl Bits {
+    fn new(len: usize) -> Self {
+        Bits(vec![0; len.div_ceil(64)])
+    }
+    fn full(len: usize) -> Self {
+        let mut bits = Bits::new(len);
+        for idx in 0..len {
+            bits.insert(idx);
+        }
+        bits
+    }
+    fn contains(&self, idx: usize) -> bool {
+        self.0[idx / 64] & (1 << (idx % 64)) != 0
+    }
+    fn insert(&mut self, idx: usize) {
+        self.0[idx / 64] |= 1 << (idx % 64);
+    }
+    fn remove(&mut self, idx: usize) {
+        self.0[idx / 64] &= !(1 << (idx % 64));
+    }
+    // Returns whether it changed
+    fn union(&mut self, other: &Bits) -> bool {
+        let mut changed = false;
+        for (word, other) in self.0.iter_mut().zip(&other.0) {
+            changed |= *other & !*word != 0;
+            *word |= other;
+        }
+        changed
+    }
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.0.len() * 64).filter(move |idx| self.contains(*idx))
+    }
+}
+
+// How a point uses a place
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Use {
+    Read,
+    Move,
+    Borrow(bool),
+    /// Replaces the whole value of the place
+    Write,
+    Drop,
+    /// Its local goes out of scope
+    Dead,
+}
+
+struct Loan {
+    place: Place,
+    mutable: bool,
+    point: usize,
+    span: Span,
+}
+
+struct Checker<'b, 'p, 'a> {
+    program: &'p Program<'a>,
+    body: &'b Body<'a>,
+    // The block and index of each point, the statements of a block followed by its terminator
+    locations: Vec<(usize, usize)>,
+    succs: Vec<Vec<usize>>,
+    preds: Vec<Vec<usize>>,
+    loans: Vec<Loan>,
+    // The regions of the locals, then of the loans
+    regions: Vec<Bits>,
+    // `(sup, sub)`, the first region containing the second
+    constraints: Vec<(usize, usize)>,
+    // The loans in scope at each point, before it
+    in_scope: Vec<Bits>,
+}
+
+impl<'b, 'p, 'a> Checker<'b, 'p, 'a> {
+    fn new(program: &'p Program<'a>, body: &'b Body<'a>) -> Self {
+        let mut starts = vec![];
+        let mut locations = vec![];
+        for (idx, block) in body.blocks.iter().enumerate() {
+            starts.push(locations.len());
+            locations.extend((0..=block.statements.len()).map(|stmt| (idx, stmt)));
+        }
+        let succs: Vec<Vec<_>> = locations
+            .iter()
+            .enumerate()
+            .map(|(point, (block, stmt))| {
+                let block = &body.blocks[*block];
+                match *stmt < block.statements.len() {
+                    true => vec![point + 1],
+                    false => block
+                        .terminator
+                        .kind
+                        .successors()
+                        .iter()
+                        .map(|b| starts[b.0])
+                        .collect(),
+                }
+            })
+            .collect();
+        let mut preds = vec![vec![]; locations.len()];
+        for (point, succs) in succs.iter().enumerate() {
+            for succ in succs {
+                preds[*succ].push(point);
+            }
+        }
+        Self {
+            program,
+            body,
+            locations,
+            succs,
+            preds,
+            loans: vec![],
+            regions: vec![],
+            constraints: vec![],
+            in_scope: vec![],
+        }
+    }
+
+    fn check(mut self, errors: &mut Vec<BorrowErr>) {
+        self.captures(errors);
+        self.mutations(errors);
+        self.regions();
+        self.loans_in_scope();
+        let mut reported = HashSet::new();
+        let moved = self.maybe_moved();
+        for point in 0..self.locations.len() {
+            let span = self.span(point).clone();
+            let uses = self.uses(point);
+            for (use_, place) in &uses {
+                self.conflicts(point, *use_, place, &span, &mut reported, errors);
+                if let Use::Borrow(_) = use_ {
+                    if let Some((moved, ty)) = moved[point].iter().find_map(|(m, span)| {
+                        let ty = self.ty(m.local)?;
+                        Some((span, ty)).filter(|_| overlap(m, place))
+                    }) {
+                        let kind = BorrowErrKind::BorrowOfMoved {
+                            place: self.describe(place),
+                            ty: ty.clone(),
+                            moved: moved.clone(),
+                        };
+                        errors.push(BorrowErr {
+                            span: span.clone(),
+                            kind,
+                        });
+                    }
+                }
+            }
+            if let Some(TerminatorKind::Return) = self.terminator(point) {
+                for loan in self.in_scope[point].iter() {
+                    if !self.loans[loan].place.is_indirect()
+                        && reported.insert((loan, span.clone()))
+                    {
+                        errors.push(self.not_live_long_enough(loan, point, &span));
+                    }
+                }
+            }
+            self.point_errors(point, &span, errors);
+        }
+    }
+
+    fn statement(&self, point: usize) -> Option<&'b Statement<'a>> {
+        let (block, stmt) = self.locations[point];
+        self.body.blocks[block].statements.get(stmt)
+    }
+    fn terminator(&self, point: usize) -> Option<&'b TerminatorKind<'a>> {
+        let (block, stmt) = self.locations[point];
+        let block = &self.body.blocks[block];
+        Some(&block.terminator.kind).filter(|_| stmt == block.statements.len())
+    }
+    fn span(&self, point: usize) -> &'b Span {
+        let (block, stmt) = self.locations[point];
+        let block = &self.body.blocks[block];
+        match block.statements.get(stmt) {
+            Some(statement) => &statement.span,
+            None => &block.terminator.span,
+        }
+    }
+    fn ty(&self, local: Local) -> Option<&'b Type> {
+        self.body.locals[local.0].ty.as_ref()
+    }
+    fn has_refs(&self, local: Local) -> bool {
+        self.ty(local).is_some_and(Type::has_refs)
+    }
+    // The places a point uses, in order
+    fn uses(&self, point: usize) -> Vec<(Use, Place)> {
+        let mut uses = vec![];
+        let operand = |uses: &mut Vec<_>, operand: &Operand| match operand {
+            Operand::Copy(place) => uses.push((Use::Read, place.clone())),
+            Operand::Move(place) => uses.push((Use::Move, place.clone())),
+            Operand::Constant(_) => (),
+        };
+        if let Some(statement) = self.statement(point) {
+            match &statement.kind {
+                StatementKind::Assign(dest, rvalue) => {
+                    match rvalue {
+                        Rvalue::Use(op) | Rvalue::Unary(_, op) | Rvalue::Method(op, _) => {
+                            operand(&mut uses, op)
+                        }
+                        Rvalue::Ref(mutable, place) => {
+                            uses.push((Use::Borrow(*mutable), place.clone()))
+                        }
+                        Rvalue::Binary(_, l, r) => {
+                            operand(&mut uses, l);
+                            operand(&mut uses, r);
+                        }
+                        Rvalue::Tuple(ops) | Rvalue::Ctor(.., ops) | Rvalue::Closure(_, ops) => {
+                            for op in ops {
+                                operand(&mut uses, op);
+                            }
+                        }
+                    }
+                    uses.push((Use::Write, dest.clone()));
+                }
+                StatementKind::StorageDead(local) => uses.push((Use::Dead, Place::local(*local))),
+            }
+            return uses;
+        }
+        match self
+            .terminator(point)
+            .expect("Points are statements or terminators")
+        {
+            TerminatorKind::If { cond, .. } => operand(&mut uses, cond),
+            TerminatorKind::Switch { place, .. } => uses.push((Use::Read, place.clone())),
+            TerminatorKind::Call {
+                func, args, dest, ..
+            } => {
+                operand(&mut uses, func);
+                for arg in args {
+                    operand(&mut uses, arg);
+                }
+                uses.push((Use::Write, dest.clone()));
+            }
+            TerminatorKind::Drop { place, glue, .. } => {
+                operand(&mut uses, glue);
+                uses.push((Use::Drop, place.clone()));
+            }
+            TerminatorKind::Return => uses.push((Use::Read, Place::local(RETURN))),
+            TerminatorKind::Goto(_) | TerminatorKind::Unreachable => (),
+        }
+        uses
+    }
+
+    // The live locals before each point: the ones used later without being assigned first
+    fn liveness(&self) -> Vec<Bits> {
+        let locals = self.body.locals.len();
+        let mut live = vec![Bits::new(locals); self.locations.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for point in (0..self.locations.len()).rev() {
+                let mut set = Bits::new(locals);
+                for succ in &self.succs[point] {
+                    set.union(&live[*succ]);
+                }
+                let uses = self.uses(point);
+                for (use_, place) in &uses {
+                    if *use_ == Use::Write && place.projection.is_empty() {
+                        set.remove(place.local.0);
+                    }
+                }
+                for (use_, place) in &uses {
+                    if uses_references(use_, place) {
+                        set.insert(place.local.0);
+                    }
+                }
+                if set != live[point] {
+                    live[point] = set;
+                    changed = true;
+                }
+            }
+        }
+        live
+    }
+
+    // Computes the regions of the locals and loans, the smallest satisfying the constraints
+    fn regions(&mut self) {
+        let points = self.locations.len();
+        let live = self.liveness();
+        for idx in 0..self.body.locals.len() {
+            let local = Local(idx);
+            let region = match self.has_refs(local) {
+                // Given by the caller, or to it
+                true if idx <= self.body.params => Bits::full(points),
+                true => {
+                    let mut region = Bits::new(points);
+                    for (point, live) in live.iter().enumerate() {
+                        if live.contains(idx) {
+                            region.insert(point);
+                        }
+                    }
+                    region
+                }
+                false => Bits::new(points),
+            };
+            self.regions.push(region);
+        }
+        let locals = self.body.locals.len();
+        for point in 0..points {
+            let (dest, sources) = match (self.statement(point), self.terminator(point)) {
+                (
+                    Some(Statement {
+                        kind: StatementKind::Assign(dest, rvalue),
+                        span,
+                    }),
+                    _,
+                ) => {
+                    if let Rvalue::Ref(mutable, place) = rvalue {
+                        let loan = locals + self.loans.len();
+                        self.loans.push(Loan {
+                            place: place.clone(),
+                            mutable: *mutable,
+                            point,
+                            span: span.clone(),
+                        });
+                        self.regions.push(Bits::new(points));
+                        self.constraints.push((loan, dest.local.0));
+                        // What the place itself borrows outlives the new borrow
+                        if self.has_refs(place.local) {
+                            self.constraints.push((place.local.0, loan));
+                        }
+                    }
+                    (dest, self.uses(point))
+                }
+                (_, Some(TerminatorKind::Call { dest, .. })) => (dest, self.uses(point)),
+                _ => continue,
+            };
+            if !self.has_refs(dest.local) {
+                continue;
+            }
+            for (use_, place) in sources {
+                if matches!(use_, Use::Read | Use::Move)
+                    && self.has_refs(place.local)
+                    && place.local != dest.local
+                {
+                    self.constraints.push((place.local.0, dest.local.0));
+                }
+            }
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (sup, sub) in &self.constraints {
+                let sub = self.regions[*sub].clone();
+                changed |= self.regions[*sup].union(&sub);
+            }
+        }
+    }
+    fn loan_region(&self, loan: usize) -> &Bits {
+        &self.regions[self.body.locals.len() + loan]
+    }
+
+    // A loan is in scope from where it's made until it leaves its region, or until the
+    // reference it's made through is replaced
+    fn loans_in_scope(&mut self) {
+        let points = self.locations.len();
+        let mut ins = vec![Bits::new(self.loans.len()); points];
+        let mut outs = ins.clone();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for point in 0..points {
+                let mut set = Bits::new(self.loans.len());
+                for pred in &self.preds[point] {
+                    set.union(&outs[*pred]);
+                }
+                for loan in set.clone().iter() {
+                    if !self.loan_region(loan).contains(point) {
+                        set.remove(loan);
+                    }
+                }
+                let mut out = set.clone();
+                for (use_, place) in self.uses(point) {
+                    if use_ == Use::Write && place.projection.is_empty() {
+                        for (idx, loan) in self.loans.iter().enumerate() {
+                            if loan.place.local == place.local && loan.place.is_indirect() {
+                                out.remove(idx);
+                            }
+                        }
+                    }
+                }
+                for (idx, loan) in self.loans.iter().enumerate() {
+                    if loan.point == point {
+                        out.insert(idx);
+                    }
+                }
+                if set != ins[point] || out != outs[point] {
+                    ins[point] = set;
+                    outs[point] = out;
+                    changed = true;
+                }
+            }
+        }
+        self.in_scope = ins;
+    }
+
+    // The places that may have been moved before each point, with where they were
+    fn maybe_moved(&self) -> Vec<Vec<(Place, Span)>> {
+        let points = self.locations.len();
+        let mut moves = vec![];
+        for point in 0..points {
+            for (use_, place) in self.uses(point) {
+                if use_ == Use::Move {
+                    moves.push((point, place));
+                }
+            }
+        }
+        let mut ins = vec![Bits::new(moves.len()); points];
+        let mut outs = ins.clone();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for point in 0..points {
+                let mut set = Bits::new(moves.len());
+                for pred in &self.preds[point] {
+                    set.union(&outs[*pred]);
+                }
+                let mut out = set.clone();
+                for (use_, place) in self.uses(point) {
+                    if use_ == Use::Write && place.projection.is_empty() {
+                        for (idx, (_, moved)) in moves.iter().enumerate() {
+                            if moved.local == place.local {
+                                out.remove(idx);
+                            }
+                        }
+                    }
+                }
+                for (idx, (moved, _)) in moves.iter().enumerate() {
+                    if *moved == point {
+                        out.insert(idx);
+                    }
+                }
+                if set != ins[point] || out != outs[point] {
+                    ins[point] = set;
+                    outs[point] = out;
+                    changed = true;
+                }
+            }
+        }
+        ins.iter()
+            .map(|set| {
+                set.iter()
+                    .map(|idx| (moves[idx].1.clone(), self.span(moves[idx].0).clone()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    // The locals that may have been assigned before each point, since they came into scope
+    fn maybe_assigned(&self) -> Vec<Bits> {
+        let points = self.locations.len();
+        let locals = self.body.locals.len();
+        let mut ins = vec![Bits::new(locals); points];
+        let mut outs = ins.clone();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for point in 0..points {
+                let mut set = Bits::new(locals);
+                for pred in &self.preds[point] {
+                    set.union(&outs[*pred]);
+                }
+                let mut out = set.clone();
+                for (use_, place) in self.uses(point) {
+                    match use_ {
+                        Use::Write if place.projection.is_empty() => out.insert(place.local.0),
+                        Use::Dead => out.remove(place.local.0),
+                        _ => (),
+                    }
+                }
+                if set != ins[point] || out != outs[point] {
+                    ins[point] = set;
+                    outs[point] = out;
+                    changed = true;
+                }
+            }
+        }
+        ins
+    }
+    // Locals can only be assigned once bound, or borrowed mutably, when they're bound by a
+    // `mut` pattern. Lambdas capture values, so they can't change the locals they capture
+    fn mutations(&self, errors: &mut Vec<BorrowErr>) {
+        for (point, assigned) in self.maybe_assigned().iter().enumerate() {
+            for (use_, place) in self.uses(point) {
+                let decl = &self.body.locals[place.local.0];
+                let name = match decl.name {
+                    Some(name) if !decl.mutable && !place.is_indirect() => name.to_string(),
+                    _ => continue,
+                };
+                let captured = self.body.captures.contains(&place.local);
+                let binding = decl.span.clone();
+                let kind = match use_ {
+                    Use::Write | Use::Borrow(true) if captured => BorrowErrKind::MutateCaptured {
+                        name,
+                        assign: use_ == Use::Write,
+                    },
+                    Use::Write if assigned.contains(place.local.0) => {
+                        BorrowErrKind::AssignTwice { name, binding }
+                    }
+                    Use::Borrow(true) => BorrowErrKind::BorrowImmutable { name, binding },
+                    _ => continue,
+                };
+                errors.push(BorrowErr {
+                    span: self.span(point).clone(),
+                    kind,
+                });
+            }
+        }
+    }
+
+    // Checks a use of a place against the loans in scope
+    fn conflicts(
+        &self,
+        point: usize,
+        use_: Use,
+        place: &Place,
+        span: &Span,
+        reported: &mut HashSet<(usize, Span)>,
+        errors: &mut Vec<BorrowErr>,
+    ) {
+        for idx in self.in_scope[point].iter() {
+            let loan = &self.loans[idx];
+            if !overlap(&loan.place, place) {
+                continue;
+            }
+            let access = match use_ {
+                Use::Read if loan.mutable => Access::Read,
+                Use::Borrow(false) if loan.mutable => Access::Borrow(false),
+                Use::Borrow(true) => Access::Borrow(true),
+                Use::Move => Access::Move,
+                Use::Write => Access::Write,
+                Use::Drop | Use::Dead if !loan.place.is_indirect() => {
+                    if reported.insert((idx, span.clone())) {
+                        errors.push(self.not_live_long_enough(idx, point, span));
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            if !reported.insert((idx, span.clone())) {
+                continue;
+            }
+            let kind = BorrowErrKind::Conflict {
+                place: self.describe(place),
+                access,
+                borrow: loan.span.clone(),
+                mutable: loan.mutable,
+                later: self.later(idx, point),
+            };
+            errors.push(BorrowErr {
+                span: span.clone(),
+                kind,
+            });
+        }
+    }
+    fn not_live_long_enough(&self, loan: usize, point: usize, dropped: &Span) -> BorrowErr {
+        let kind = BorrowErrKind::NotLiveLongEnough {
+            place: self.describe(&self.loans[loan].place),
+            dropped: dropped.clone(),
+            later: self.later(loan, point),
+        };
+        BorrowErr {
+            span: self.loans[loan].span.clone(),
+            kind,
+        }
+    }
+    // The first use after `point` of a local holding the loan
+    fn later(&self, loan: usize, point: usize) -> Option<Later> {
+        let locals = self.body.locals.len();
+        let mut carriers = Bits::new(self.regions.len());
+        carriers.insert(locals + loan);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (sup, sub) in &self.constraints {
+                if carriers.contains(*sup) && !carriers.contains(*sub) {
+                    carriers.insert(*sub);
+                    changed = true;
+                }
+            }
+        }
+        let returned = |point| matches!(self.terminator(point), Some(TerminatorKind::Return));
+        if returned(point) && carriers.contains(RETURN.0) {
+            return Some(Later {
+                span: self.span(point).clone(),
+                returned: true,
+            });
+        }
+        let mut visited = Bits::new(self.locations.len());
+        let mut queue: VecDeque<_> = self.succs[point].iter().copied().collect();
+        while let Some(next) = queue.pop_front() {
+            if visited.contains(next) || !self.loan_region(loan).contains(next) {
+                continue;
+            }
+            visited.insert(next);
+            // Copying the reference to another local isn't a use of its own
+            if let Some(Statement {
+                kind: StatementKind::Assign(dest, Rvalue::Use(_)),
+                ..
+            }) = self.statement(next)
+            {
+                if carriers.contains(dest.local.0) && !dest.is_indirect() {
+                    queue.extend(self.succs[next].iter().copied());
+                    continue;
+                }
+            }
+            let used = self.uses(next).iter().any(|(use_, place)| {
+                uses_references(use_, place) && carriers.contains(place.local.0)
+            });
+            if used {
+                return Some(Later {
+                    span: self.span(next).clone(),
+                    returned: returned(next),
+                });
+            }
+            queue.extend(self.succs[next].iter().copied());
+        }
+        None
+    }
+
+    // The errors of a point that don't depend on the loans in scope
+    fn point_errors(&self, point: usize, span: &Span, errors: &mut Vec<BorrowErr>) {
+        let datas = &self.program.datas;
+        match (self.statement(point), self.terminator(point)) {
+            (Some(statement), _) => match &statement.kind {
+                StatementKind::Assign(dest, Rvalue::Use(Operand::Move(place)))
+                    if place.is_indirect() =>
+                {
+                    let kind = BorrowErrKind::MoveOutOfRef {
+                        place: self.describe(place),
+                        ty: self.ty(dest.local).cloned().unwrap_or(Type::Unit),
+                    };
+                    errors.push(BorrowErr {
+                        span: span.clone(),
+                        kind,
+                    });
+                }
+                StatementKind::Assign(_, Rvalue::Ref(_, place))
+                    if !place.is_indirect() && self.is_temp(place.local) =>
+                {
+                    if let Some(ty) = self.ty(place.local).filter(|ty| ty.is_owned(datas)) {
+                        let kind = BorrowErrKind::OwnedTemporary { ty: ty.clone() };
+                        errors.push(BorrowErr {
+                            span: span.clone(),
+                            kind,
+                        });
+                    }
+                }
+                _ => (),
+            },
+            (
+                _,
+                Some(TerminatorKind::Call {
+                    func, args, dest, ..
+                }),
+            ) => {
+                let arity = match func {
+                    Operand::Constant(Constant::Item(id, _)) => self.program.items[id.0].arity(),
+                    Operand::Constant(Constant::Ctor(data, idx, _)) => {
+                        datas[data.0].ctors[*idx].fields.len()
+                    }
+                    _ => 0,
+                };
+                let returns_fun = matches!(self.ty(dest.local), Some(Type::Fun(..)));
+                for (idx, arg) in args.iter().enumerate() {
+                    let kept = match idx < arity {
+                        true => args.len() < arity,
+                        false => returns_fun,
+                    };
+                    let local = match arg {
+                        Operand::Copy(place) | Operand::Move(place)
+                            if place.projection.is_empty() =>
+                        {
+                            place.local
+                        }
+                        _ => continue,
+                    };
+                    match self.ty(local) {
+                        Some(ty) if kept && ty.has_refs() => {
+                            let kind = BorrowErrKind::PartiallyApplied { ty: ty.clone() };
+                            errors.push(BorrowErr {
+                                span: self.body.locals[local.0].span.clone(),
+                                kind,
+                            });
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    fn is_temp(&self, local: Local) -> bool {
+        local.0 > self.body.params
+            && self.body.locals[local.0].name.is_none()
+            && !self.body.captures.contains(&local)
+    }
+    // References can't be captured by lambdas, which could outlive what they borrow
+    fn captures(&self, errors: &mut Vec<BorrowErr>) {
+        for local in &self.body.captures {
+            let decl = &self.body.locals[local.0];
+            if let (Some(ty), Some(name)) = (&decl.ty, decl.name) {
+                if ty.has_refs() {
+                    let kind = BorrowErrKind::Captured {
+                        name: name.to_string(),
+                        ty: ty.clone(),
+                    };
+                    errors.push(BorrowErr {
+                        span: decl.span.clone(),
+                        kind,
+                    });
+                }
+            }
+        }
+    }
+    fn describe(&self, place: &Place) -> Option<String> {
+        let name = self.body.locals[place.local.0].name?;
+        Some(place.show(name.to_string()))
+    }
+}
+
+// Whether two places share a part of their value: one of them contains the other
+// Whether the references held by the local of `place` are used: read, or written through
+fn uses_references(use_: &Use, place: &Place) -> bool {
+    !matches!(use_, Use::Write | Use::Dead) || (*use_ == Use::Write && place.is_indirect())
+}
+
+fn overlap(a: &Place, b: &Place) -> bool {
+    a.local == b.local && a.projection.iter().zip(&b.projection).all(|(a, b)| a == b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::tests::{check_code, step_errors};
+    use crate::errors::{apply_suggestions, ErrorKind, Report};
+
+    const FILE: &str = "owned data File =\n  File Int\nclose (File n) = n\nsize r =\n  let File n = *r\n  n\nget r = *r\n";
+
+    fn errors(code: &str) -> (String, Vec<BorrowErr>) {
+        let code = format!("{}{}", FILE, code);
+        let mut checked = check_code(&code, false);
+        let errors = step_errors!(checked, Borrow);
+        (code, errors)
+    }
+    // The borrow errors of a program, with the prelude and `FILE`, as a code and the code they
+    // point at
+    fn check(code: &str) -> Vec<(&'static str, String)> {
+        let (code, errors) = errors(code);
+        errors
+            .into_iter()
+            .map(|e| (e.kind.code(), code[e.span.range].trim_end().to_string()))
+            .collect()
+    }
+
+    // The borrow errors of a program as a code, the code they point at and the later use of the
+    // borrow they label
+    fn later(code: &str) -> Vec<(&'static str, String, Option<String>)> {
+        let (code, errors) = errors(code);
+        errors
+            .into_iter()
+            .map(|e| {
+                let later = match &e.kind {
+                    BorrowErrKind::Conflict { later, .. }
+                    | BorrowErrKind::NotLiveLongEnough { later, .. } => later.as_ref(),
+                    _ => None,
+                };
+                let later = later.map(|l| code[l.span.range.clone()].to_string());
+                (
+                    e.kind.code(),
+                    code[e.span.range].trim_end().to_string(),
+                    later,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn borrows_end_at_their_last_use() {
+        let code = "main =\n  let mut x = 1\n  let m = &mut x\n  let n = get m\n  get &x + n\n";
+        assert!(check(code).is_empty());
+        let code = "main =\n  let mut x = 1\n  let r = &x\n  let m = &mut x\n  get m\n";
+        assert!(check(code).is_empty());
+        // A borrow only used on one branch doesn't last on the other
+        let code = "f b =\n  let mut x = 1\n  let m = &mut x\n  if b then get m else get &x\n";
+        assert!(check(code).is_empty());
+        // Owned values are dropped at the end of their scope, once their borrows are over
+        let code = "main =\n  let f = File 1\n  let n = size &f\n  size &f + n\n";
+        assert!(check(code).is_empty());
+        // Shared borrows don't conflict, and reborrowing a reference is fine
+        assert!(
+            check("f m = get &(*m)\nmain =\n  let mut x = 1\n  get &x + get &x + f &mut x\n")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn conflicts() {
+        let code = "main =\n  let mut x = 1\n  let m = &mut x\n  get &x + get m\n";
+        assert_eq!(check(code), [("E0027", "&x".into())]);
+        let code = "main =\n  let mut x = 1\n  let m = &mut x\n  get &mut x + get m\n";
+        assert_eq!(check(code), [("E0027", "&mut x".into())]);
+        let code = "main =\n  let mut x = 1\n  let m = &mut x\n  x + get m\n";
+        assert_eq!(check(code), [("E0027", "x".into())]);
+        let code = "main =\n  let f = File 1\n  let r = &f\n  close f + size r\n";
+        assert_eq!(check(code), [("E0027", "f".into())]);
+        // The borrow and its later use are labelled
+        let (code, errors) =
+            errors("main =\n  let mut x = 1\n  let r = &x\n  get &mut x + get r\n");
+        match &errors[..] {
+            [BorrowErr {
+                kind:
+                    BorrowErrKind::Conflict {
+                        access: Access::Borrow(true),
+                        borrow,
+                        mutable: false,
+                        later: Some(later),
+                        ..
+                    },
+                ..
+            }] => {
+                assert_eq!(&code[borrow.range.clone()], "&x");
+                assert_eq!(&code[later.span.range.clone()], "get r");
+            }
+            errors => panic!("expected a conflict, got {:?}", errors),
+        }
+    }
+
+    #[test]
+    fn mutability() {
+        assert!(
+            check("main =\n  let mut x = 1\n  x = x + 1\n  let m = &mut x\n  *m = 3\n  x\n")
+                .is_empty()
+        );
+        assert_eq!(
+            check("main =\n  let x = 1\n  x = 2\n  x\n"),
+            [("E0034", "x = 2".into())]
+        );
+        assert_eq!(check("f x =\n  x = 2\n  x\n"), [("E0034", "x = 2".into())]);
+        assert_eq!(
+            check("main =\n  let x = 1\n  get &mut x\n"),
+            [("E0035", "&mut x".into())]
+        );
+        let code = "main =\n  let mut x = 1\n  let f = \\y -> { x = y }\n  let g = \\y -> get &mut x + y\n  g 2\n";
+        assert_eq!(
+            check(code),
+            [("E0036", "x = y".into()), ("E0036", "&mut x".into())]
+        );
+        // Assigning a borrowed local conflicts with the borrow
+        let code = "main =\n  let mut x = 1\n  let r = &x\n  x = 2\n  get r\n";
+        assert_eq!(check(code), [("E0027", "x = 2".into())]);
+        // Writing through a mutable borrow keeps it alive, and is labelled as its later use
+        for (code, conflict) in [
+            (
+                "main =\n  let mut x = 1\n  let a = &mut x\n  let b = &mut x\n  *a = 2\n  x\n",
+                "&mut x",
+            ),
+            (
+                "main =\n  let mut x = 1\n  let a = &mut x\n  x = 5\n  *a = 2\n  x\n",
+                "x = 5",
+            ),
+            (
+                "main =\n  let mut x = 1\n  let a = &mut x\n  let v = x\n  *a = 2\n  v\n",
+                "x",
+            ),
+            (
+                "main =\n  let mut x = 1\n  let a = &mut x\n  let s = &x\n  *a = 2\n  *s\n",
+                "&x",
+            ),
+        ] {
+            assert_eq!(
+                later(code),
+                [("E0027", conflict.into(), Some("*a = 2".into()))],
+                "{}",
+                code
+            );
+        }
+        // The suggestion makes the binding mutable
+        for (code, binding) in [
+            ("main =\n  let x = 1\n  x = 2\n  x\n", "let mut x = 1"),
+            (
+                "main =\n  let (x, y) = (1, 2)\n  get &mut x\n",
+                "let (mut x, y)",
+            ),
+        ] {
+            let (code, errors) = errors(code);
+            let fixed = apply_suggestions(&code, &errors[0].suggestions());
+            assert!(fixed.contains(binding), "{}", fixed);
+        }
+    }
+
+    #[test]
+    fn borrowed_values_outlive_their_borrows() {
+        assert_eq!(check("f x = &x\n"), [("E0028", "&x".into())]);
+        let code = "main =\n  let r =\n    let f = File 1\n    &f\n  size r\n";
+        assert_eq!(check(code), [("E0028", "&f".into())]);
+        // What a parameter borrows lives longer than the function
+        assert!(check("f r = &(*r)\n").is_empty());
+    }
+
+    #[test]
+    fn moves() {
+        let code = "main =\n  let f = File 1\n  let g = f\n  size &f + close g\n";
+        assert_eq!(check(code), [("E0029", "&f".into())]);
+        assert_eq!(check("f r = close (*r)\n"), [("E0030", "(*r)".into())]);
+        assert_eq!(
+            check("f r =\n  let g = *r\n  close g\n"),
+            [("E0030", "*r".into())]
+        );
+        assert_eq!(
+            check("main = size &(File 1)\n"),
+            [("E0033", "&(File 1)".into())]
+        );
+    }
+
+    #[test]
+    fn references_stay_in_their_function() {
+        let code = "main =\n  let x = 1\n  let r = &x\n  let f = \\y -> get r + y\n  f 1\n";
+        assert_eq!(check(code), [("E0031", "r".into())]);
+        let code = "plus a b = *a + b\nmain =\n  let x = 1\n  let f = plus &x\n  f 2\n";
+        assert_eq!(check(code), [("E0031", "&x".into())]);
+        assert!(check("plus a b = *a + b\nmain =\n  let x = 1\n  plus &x 2\n").is_empty());
+        assert_eq!(
+            check("data Ref =\n  Ref &Int\n"),
+            [("E0032", "&Int".into())]
+        );
+    }
+}
@@ -0,0 +1,505 @@
+//! An interpreter of the MIR, which agrees with the other backends. It runs the bodies the
+//! borrow checker and the code generators work on, so that what they're given can be tested
+//! against the semantics of the source.
+//!
+//! Frames live on the heap like the VM's. References are the frame and local they point to,
+//! which the borrow checker ensures outlive them.
+use std::{fmt, mem, rc::Rc};
+
+use super::*;
+use crate::{
+    errors::runtime_err::{RuntimeErr, RuntimeErrKind, MAX_DEPTH},
+    hir::{InstanceId, Program},
+};
+
+/// A runtime value. Functions are curried: they stay partially applied until they have all of
+/// their arguments
+#[derive(Debug, Clone)]
+pub enum Value<'a> {
+    Int(i64),
+    Bool(bool),
+    Unit,
+    Tuple(Rc<[Value<'a>]>),
+    /// A constructor of a data type by index, with its name for display, applied to its fields
+    Data(usize, Ident<'a>, Rc<[Value<'a>]>),
+    Fun(Rc<Fun<'a>>),
+    /// The dictionary of an instance, passed to the items constrained by its class. Those of
+    /// instances with a context are tuples of it and of the dictionaries of its context
+    Dict(InstanceId),
+    /// A reference to a part of a local of a frame, by depth, through its fields
+    Ref(usize, Local, Rc<[usize]>),
+}
+
+/// A function of the program applied to some of its arguments
+#[derive(Debug)]
+pub struct Fun<'a> {
+    pub callee: Callee,
+    /// The values a lambda captured
+    pub captures: Vec<Value<'a>>,
+    pub args: Vec<Value<'a>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Callee {
+    /// An item or a lambda, by the body it was lowered to
+    Body(BodyId),
+    /// A constructor of a data type by index, which takes its fields
+    Ctor(DataId, usize),
+}
+
+struct Frame<'m, 'a> {
+    body: &'m Body<'a>,
+    block: BlockId,
+    statement: usize,
+    locals: Vec<Value<'a>>,
+    // Where the caller puts its value, drops ignoring it
+    dest: Option<Place>,
+    // The arguments it was given more than it takes, which its value is applied to
+    pending: Vec<Value<'a>>,
+    // The call that made it
+    span: &'m Span,
+}
+
+pub struct Machine<'m, 'p, 'a> {
+    program: &'p Program<'a>,
+    mir: &'m Mir<'a>,
+    frames: Vec<Frame<'m, 'a>>,
+}
+impl<'m, 'p, 'a> Machine<'m, 'p, 'a> {
+    pub fn new(program: &'p Program<'a>, mir: &'m Mir<'a>) -> Self {
+        Self {
+            program,
+            mir,
+            frames: vec![],
+        }
+    }
+    /// The value of an item, which is only evaluated when it takes no argument
+    pub fn item(&mut self, id: DefId) -> Result<Value<'a>, RuntimeErr> {
+        match self.mir.bodies[id.0].params {
+            0 => self.run(BodyId(id.0)),
+            _ => Ok(function(Callee::Body(BodyId(id.0)))),
+        }
+    }
+    /// Runs a body that takes no argument, like the one of `lower_expr`
+    pub fn run(&mut self, body: BodyId) -> Result<Value<'a>, RuntimeErr> {
+        let body = &self.mir.bodies[body.0];
+        self.enter(body, vec![], vec![], None, vec![], &body.span)?;
+        self.execute()
+    }
+
+    fn frame(&mut self) -> &mut Frame<'m, 'a> {
+        self.frames.last_mut().expect("Code runs in a frame")
+    }
+    // Runs the frame on top of the stack until it returns
+    fn execute(&mut self) -> Result<Value<'a>, RuntimeErr> {
+        let depth = self.frames.len();
+        loop {
+            let frame = self.frame();
+            let body = frame.body;
+            let block = &body.blocks[frame.block.0];
+            if let Some(statement) = block.statements.get(frame.statement) {
+                frame.statement += 1;
+                self.statement(statement)?;
+                continue;
+            }
+            let Terminator { kind, span } = &block.terminator;
+            match kind {
+                TerminatorKind::Goto(target) => self.goto(*target),
+                TerminatorKind::If { cond, then, else_ } => match self.operand(cond)? {
+                    Value::Bool(true) => self.goto(*then),
+                    Value::Bool(false) => self.goto(*else_),
+                    value => unreachable!("Expected a `Bool`, got {:?}", value),
+                },
+                TerminatorKind::Switch {
+                    place,
+                    targets,
+                    otherwise,
+                } => {
+                    let value = self.read(place);
+                    let target = targets.iter().find(|(test, _)| passes(&value, test));
+                    self.goto(target.map_or(*otherwise, |(_, target)| *target));
+                }
+                TerminatorKind::Call {
+                    func,
+                    args,
+                    dest,
+                    target,
+                    tail,
+                } => {
+                    let f = self.operand(func)?;
+                    let args: Vec<_> = args
+                        .iter()
+                        .map(|arg| self.operand(arg))
+                        .collect::<Result<_, _>>()?;
+                    if *tail && self.enters(&f, args.len()) {
+                        // The callee takes the frame, returning its value where it would have
+                        // gone, applied to what it would have been
+                        let frame = self.frames.pop().expect("Calls are made by a frame");
+                        let args = args.into_iter().chain(frame.pending).collect();
+                        self.apply(f, args, frame.dest, span)?;
+                        continue;
+                    }
+                    self.goto(*target);
+                    if let Some(value) = self.apply(f, args, Some(dest.clone()), span)? {
+                        self.write(dest, value);
+                    }
+                }
+                TerminatorKind::Drop {
+                    place,
+                    glue,
+                    target,
+                } => {
+                    let value = self.read(place);
+                    let glue = self.operand(glue)?;
+                    self.goto(*target);
+                    self.apply(glue, vec![value], None, span)?;
+                }
+                TerminatorKind::Return => {
+                    let mut frame = self.frames.pop().expect("Returns are made by a frame");
+                    let mut value = mem::replace(&mut frame.locals[RETURN.0], Value::Unit);
+                    if !frame.pending.is_empty() {
+                        match self.apply(value, frame.pending, frame.dest.clone(), frame.span)? {
+                            Some(applied) => value = applied,
+                            None => continue,
+                        }
+                    }
+                    if self.frames.len() < depth {
+                        return Ok(value);
+                    }
+                    self.give(frame.dest.as_ref(), value);
+                }
+                TerminatorKind::Unreachable => unreachable!("Unreachable blocks aren't reached"),
+            }
+        }
+    }
+    fn goto(&mut self, block: BlockId) {
+        let frame = self.frame();
+        frame.block = block;
+        frame.statement = 0;
+    }
+    fn statement(&mut self, statement: &'m Statement<'a>) -> Result<(), RuntimeErr> {
+        if let StatementKind::Assign(place, rvalue) = &statement.kind {
+            let value = self.rvalue(rvalue, &statement.span)?;
+            self.write(place, value);
+        }
+        Ok(())
+    }
+
+    // Applies a function to arguments. A function given fewer arguments than it takes is
+    // partially applied to them, and the value of a call given more is applied to the rest once
+    // it returns. The value of a body goes to `dest` in the current frame once it returns, the
+    // others are given right away
+    fn apply(
+        &mut self,
+        f: Value<'a>,
+        args: Vec<Value<'a>>,
+        dest: Option<Place>,
+        span: &'m Span,
+    ) -> Result<Option<Value<'a>>, RuntimeErr> {
+        let fun = match f {
+            Value::Fun(fun) => fun,
+            value => unreachable!("Only functions are applied, not {:?}", value),
+        };
+        let mut taken: Vec<_> = fun.args.iter().cloned().chain(args).collect();
+        let (arity, body) = match fun.callee {
+            Callee::Body(id) => {
+                let body = &self.mir.bodies[id.0];
+                (body.params, Some(body))
+            }
+            Callee::Ctor(data, idx) => (self.program.datas[data.0].ctors[idx].fields.len(), None),
+        };
+        let value = match (body, fun.callee) {
+            _ if taken.len() < arity => Value::Fun(Rc::new(Fun {
+                callee: fun.callee,
+                captures: fun.captures.clone(),
+                args: taken,
+            })),
+            (Some(body), _) => {
+                let pending = taken.split_off(arity);
+                self.enter(body, fun.captures.clone(), taken, dest, pending, span)?;
+                return Ok(None);
+            }
+            // Data values aren't functions, so constructors are never given too many arguments
+            (_, Callee::Ctor(data, idx)) => {
+                let name = self.program.datas[data.0].ctors[idx].name.value;
+                Value::Data(idx, name, taken.into())
+            }
+            _ => unreachable!("Bodies are entered"),
+        };
+        Ok(Some(value))
+    }
+    // Whether applying a function to some arguments enters a body
+    fn enters(&self, f: &Value<'a>, args: usize) -> bool {
+        match f {
+            Value::Fun(fun) => match fun.callee {
+                Callee::Body(id) => fun.args.len() + args >= self.mir.bodies[id.0].params,
+                Callee::Ctor(..) => false,
+            },
+            _ => false,
+        }
+    }
+    fn enter(
+        &mut self,
+        body: &'m Body<'a>,
+        captures: Vec<Value<'a>>,
+        args: Vec<Value<'a>>,
+        dest: Option<Place>,
+        pending: Vec<Value<'a>>,
+        span: &'m Span,
+    ) -> Result<(), RuntimeErr> {
+        if self.frames.len() == MAX_DEPTH {
+            return Err(RuntimeErr {
+                span: span.clone(),
+                kind: RuntimeErrKind::StackOverflow,
+            });
+        }
+        let mut locals = vec![Value::Unit; body.locals.len()];
+        for (local, value) in body
+            .params()
+            .zip(args)
+            .chain(body.captures.iter().copied().zip(captures))
+        {
+            locals[local.0] = value;
+        }
+        self.frames.push(Frame {
+            body,
+            block: BlockId(0),
+            statement: 0,
+            locals,
+            dest,
+            pending,
+            span,
+        });
+        Ok(())
+    }
+    // Gives a value to `dest` in the current frame
+    fn give(&mut self, dest: Option<&Place>, value: Value<'a>) {
+        if let Some(dest) = dest {
+            self.write(dest, value);
+        }
+    }
+
+    // The frame and local a place is a part of, with the fields leading to it
+    fn locate(&self, place: &Place) -> (usize, Local, Vec<usize>) {
+        let (mut frame, mut local, mut fields) = (self.frames.len() - 1, place.local, vec![]);
+        for projection in &place.projection {
+            match projection {
+                Projection::Field(idx) => fields.push(*idx),
+                Projection::Downcast(..) => (),
+                Projection::Deref => match self.at(frame, local, &fields) {
+                    Value::Ref(to, to_local, to_fields) => {
+                        (frame, local, fields) = (*to, *to_local, to_fields.to_vec());
+                    }
+                    value => unreachable!("Expected a reference, got {:?}", value),
+                },
+            }
+        }
+        (frame, local, fields)
+    }
+    fn at(&self, frame: usize, local: Local, fields: &[usize]) -> &Value<'a> {
+        let mut value = &self.frames[frame].locals[local.0];
+        for idx in fields {
+            value = match value {
+                Value::Tuple(fields) | Value::Data(_, _, fields) => &fields[*idx],
+                value => unreachable!("Expected fields, got {:?}", value),
+            };
+        }
+        value
+    }
+    fn read(&self, place: &Place) -> Value<'a> {
+        let (frame, local, fields) = self.locate(place);
+        self.at(frame, local, &fields).clone()
+    }
+    fn write(&mut self, place: &Place, value: Value<'a>) {
+        match self.locate(place) {
+            (frame, local, fields) if fields.is_empty() => {
+                self.frames[frame].locals[local.0] = value
+            }
+            _ => unreachable!("Only locals and what references point to are assigned"),
+        }
+    }
+
+    fn operand(&mut self, operand: &Operand<'a>) -> Result<Value<'a>, RuntimeErr> {
+        Ok(match operand {
+            Operand::Copy(place) | Operand::Move(place) => self.read(place),
+            Operand::Constant(Constant::Literal(l)) => literal(l),
+            Operand::Constant(Constant::Item(id, _)) => function(Callee::Body(BodyId(id.0))),
+            Operand::Constant(Constant::Ctor(data, idx, _)) => function(Callee::Ctor(*data, *idx)),
+            Operand::Constant(Constant::Dict(Dict::Instance(id, _))) => Value::Dict(*id),
+            Operand::Constant(Constant::Dict(_)) => {
+                unreachable!("Expressions with constraints aren't run")
+            }
+        })
+    }
+    fn rvalue(&mut self, rvalue: &Rvalue<'a>, span: &'m Span) -> Result<Value<'a>, RuntimeErr> {
+        Ok(match rvalue {
+            Rvalue::Use(operand) => self.operand(operand)?,
+            Rvalue::Ref(_, place) => {
+                let (frame, local, fields) = self.locate(place);
+                Value::Ref(frame, local, fields.into())
+            }
+            Rvalue::Unary(op, operand) => match (op, self.operand(operand)?) {
+                (UnOp::Neg, Value::Int(n)) => match n.checked_neg() {
+                    Some(n) => Value::Int(n),
+                    None => return Err(overflow(span, format!("-({})", n))),
+                },
+                (UnOp::Not, Value::Bool(b)) => Value::Bool(!b),
+                (op, value) => unreachable!("Can't apply {:?} to {:?}", op, value),
+            },
+            Rvalue::Binary(op, l, r) => binary(*op, self.operand(l)?, self.operand(r)?, span)?,
+            Rvalue::Tuple(operands) => Value::Tuple(self.operands(operands)?.into()),
+            Rvalue::Ctor(_, idx, name, operands) => {
+                Value::Data(*idx, name, self.operands(operands)?.into())
+            }
+            Rvalue::Closure(body, captures) => Value::Fun(Rc::new(Fun {
+                callee: Callee::Body(*body),
+                captures: self.operands(captures)?,
+                args: vec![],
+            })),
+            // Methods are applied to the dictionaries of their instance's context, those taking
+            // nothing else are evaluated right away, in frames of their own
+            Rvalue::Method(dict, idx) => {
+                let (instance, context) = match self.operand(dict)? {
+                    Value::Dict(instance) => (instance, vec![]),
+                    Value::Tuple(fields) => match &fields[..] {
+                        [Value::Dict(instance), context @ ..] => (*instance, context.to_vec()),
+                        _ => unreachable!("Expected a dictionary, got {:?}", fields),
+                    },
+                    value => unreachable!("Expected a dictionary, got {:?}", value),
+                };
+                let id = self.program.instances[instance.0].methods[*idx];
+                let body = &self.mir.bodies[id.0];
+                match body.params == context.len() {
+                    true => {
+                        self.enter(body, vec![], context, None, vec![], span)?;
+                        self.execute()?
+                    }
+                    false => Value::Fun(Rc::new(Fun {
+                        callee: Callee::Body(BodyId(id.0)),
+                        captures: vec![],
+                        args: context,
+                    })),
+                }
+            }
+        })
+    }
+    fn operands(&mut self, operands: &[Operand<'a>]) -> Result<Vec<Value<'a>>, RuntimeErr> {
+        operands
+            .iter()
+            .map(|operand| self.operand(operand))
+            .collect()
+    }
+}
+
+fn function<'a>(callee: Callee) -> Value<'a> {
+    Value::Fun(Rc::new(Fun {
+        callee,
+        captures: vec![],
+        args: vec![],
+    }))
+}
+
+fn literal<'a>(l: &Literal) -> Value<'a> {
+    match l {
+        Literal::Num(n) => Value::Int(*n),
+        Literal::Bool(b) => Value::Bool(*b),
+        Literal::Unit => Value::Unit,
+    }
+}
+
+fn passes(value: &Value, test: &Test) -> bool {
+    match (test, value) {
+        (Test::Literal(Literal::Num(n)), Value::Int(m)) => n == m,
+        (Test::Literal(Literal::Bool(b)), Value::Bool(c)) => b == c,
+        (Test::Ctor(idx), Value::Data(ctor, _, _)) => idx == ctor,
+        (Test::Dict(id), Value::Dict(instance)) => id == instance,
+        (Test::Dict(_), Value::Tuple(_)) => false,
+        (test, value) => unreachable!("Can't test {:?} with {:?}", value, test),
+    }
+}
+
+fn binary<'a>(op: BinOp, a: Value<'a>, b: Value<'a>, span: &Span) -> Result<Value<'a>, RuntimeErr> {
+    let (a, b) = match (op, a, b) {
+        (BinOp::EqEq | BinOp::NotEq, a, b) => {
+            let equal = match (a, b) {
+                (Value::Int(a), Value::Int(b)) => a == b,
+                (Value::Bool(a), Value::Bool(b)) => a == b,
+                (Value::Unit, Value::Unit) => true,
+                (a, b) => unreachable!("Can't compare {:?} and {:?}", a, b),
+            };
+            return Ok(Value::Bool(equal == (op == BinOp::EqEq)));
+        }
+        (_, Value::Int(a), Value::Int(b)) => (a, b),
+        (op, a, b) => unreachable!("Can't apply {:?} to {:?} and {:?}", op, a, b),
+    };
+    let result = match op {
+        BinOp::Add => a.checked_add(b),
+        BinOp::Sub => a.checked_sub(b),
+        BinOp::Mul => a.checked_mul(b),
+        BinOp::Div if b == 0 => {
+            return Err(RuntimeErr {
+                span: span.clone(),
+                kind: RuntimeErrKind::DivisionByZero,
+            })
+        }
+        BinOp::Div => a.checked_div(b),
+        BinOp::LT => return Ok(Value::Bool(a < b)),
+        BinOp::LTE => return Ok(Value::Bool(a <= b)),
+        BinOp::GT => return Ok(Value::Bool(a > b)),
+        BinOp::GTE => return Ok(Value::Bool(a >= b)),
+        _ => unreachable!("`&&` and `||` are branches"),
+    };
+    result
+        .map(Value::Int)
+        .ok_or_else(|| overflow(span, format!("{} {} {}", a, op, b)))
+}
+
+fn overflow(span: &Span, operation: String) -> RuntimeErr {
+    RuntimeErr {
+        span: span.clone(),
+        kind: RuntimeErrKind::Overflow(operation),
+    }
+}
+
+impl<'a> Value<'a> {
+    // Whether it needs parentheses to be the argument of a constructor
+    fn is_compound(&self) -> bool {
+        match self {
+            Value::Int(n) => *n < 0,
+            Value::Data(_, _, fields) => !fields.is_empty(),
+            _ => false,
+        }
+    }
+}
+impl<'a> fmt::Display for Value<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Unit => write!(f, "()"),
+            Value::Tuple(elements) => {
+                write!(f, "(")?;
+                for (idx, e) in elements.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", e)?;
+                }
+                write!(f, ")")
+            }
+            Value::Data(_, name, fields) => {
+                write!(f, "{}", name)?;
+                for field in fields.iter() {
+                    match field.is_compound() {
+                        true => write!(f, " ({})", field)?,
+                        false => write!(f, " {}", field)?,
+                    }
+                }
+                Ok(())
+            }
+            Value::Fun(_) => write!(f, "<function>"),
+            Value::Dict(_) => write!(f, "<dictionary>"),
+            Value::Ref(..) => unreachable!("References don't outlive the frames they point to"),
+        }
+    }
+}
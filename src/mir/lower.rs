@@ -0,0 +1,924 @@
+//! Lowering of the HIR to the MIR. Expressions are evaluated into places in order, the
+//! values of nested expressions going to temporaries, and patterns become chains of tests
+//! followed by the bindings of their locals, once every test passed. The tests of a place
+//! following each other are merged into a switch.
+use std::collections::HashMap;
+
+use super::*;
+use crate::{
+    hir::{self, Arg, BoxNode, ClassId, Expr, Item, LocalId, Node, Pattern, Program, Res},
+    typeck::{lower_ty, TyVar, Type, Types},
+};
+
+/// Lowers the items of a program, once its ownership is checked
+pub fn lower<'a>(program: &Program<'a>, types: &Types) -> Mir<'a> {
+    let mut lowering = Lowering::new(program, types);
+    for item in &program.items {
+        lowering.item(item);
+    }
+    lowering.finish()
+}
+
+/// Lowers the items of a program and an expression using them, for the REPL. Returns the
+/// body of the expression, which takes no parameters
+pub fn lower_expr<'a>(
+    program: &Program<'a>,
+    e: &Node<Expr<'a>>,
+    types: &Types,
+) -> (Mir<'a>, BodyId) {
+    let mut lowering = Lowering::new(program, types);
+    for item in &program.items {
+        lowering.item(item);
+    }
+    let id = BodyId(lowering.bodies.len());
+    lowering.bodies.push(None);
+    let ty = lowering.ty(&e.value, &e.span);
+    lowering.builders.push(Builder::new(ty, &e.span));
+    lowering.expr_into(Place::local(RETURN), &e.value, &e.span);
+    lowering.terminate(TerminatorKind::Return, &end(&e.span));
+    lowering.bodies[id.0] = Some(lowering.body("main", &e.span, 0));
+    (lowering.finish(), id)
+}
+
+// A body being lowered
+struct Builder<'a> {
+    locals: Vec<LocalDecl<'a>>,
+    // Blocks are terminated once their statements are done
+    blocks: Vec<(Vec<Statement<'a>>, Option<Terminator<'a>>)>,
+    current: BlockId,
+    // The locals of the HIR lowered so far
+    map: HashMap<LocalId, Local>,
+    // The locals of the enclosing body captured by a lambda, with the locals they're given to
+    captures: Vec<(Local, Local)>,
+    // The locals declared by each enclosing block expression, which go out of scope at its end
+    scopes: Vec<Vec<Local>>,
+}
+impl<'a> Builder<'a> {
+    fn new(ret: Type, span: &Span) -> Self {
+        Self {
+            locals: vec![LocalDecl {
+                ty: Some(ret),
+                name: None,
+                span: span.clone(),
+                mutable: false,
+            }],
+            blocks: vec![(vec![], None)],
+            current: BlockId(0),
+            map: HashMap::new(),
+            captures: vec![],
+            scopes: vec![vec![]],
+        }
+    }
+}
+
+struct Lowering<'p, 'a> {
+    program: &'p Program<'a>,
+    types: &'p Types,
+    drop: ClassId,
+    // Reserved for the items first, the lambdas following them once they're lowered
+    bodies: Vec<Option<Body<'a>>>,
+    // The bodies being lowered, the innermost lambda last
+    builders: Vec<Builder<'a>>,
+}
+
+impl<'p, 'a> Lowering<'p, 'a> {
+    fn new(program: &'p Program<'a>, types: &'p Types) -> Self {
+        let drop = program
+            .classes
+            .iter()
+            .find(|c| c.name.value == "Drop")
+            .expect("The prelude declares `Drop`")
+            .id;
+        Self {
+            program,
+            types,
+            drop,
+            bodies: vec![None; program.items.len()],
+            builders: vec![],
+        }
+    }
+    fn finish(self) -> Mir<'a> {
+        Mir {
+            bodies: self
+                .bodies
+                .into_iter()
+                .map(|body| body.expect("Every body is lowered"))
+                .collect(),
+        }
+    }
+
+    fn item(&mut self, item: &Item<'a>) {
+        let mut ty = self.item_type(item.id);
+        let arity = item.clauses[0].params.len();
+        let mut params = vec![];
+        for _ in 0..arity {
+            match ty {
+                Type::Fun(param, ret) => {
+                    params.push(*param);
+                    ty = *ret;
+                }
+                _ => unreachable!("Items have a function type for their parameters"),
+            }
+        }
+        let span = &item.name.span;
+        self.builders.push(Builder::new(ty, span));
+        for dict in &item.dicts {
+            let local = self.local(None, None, span);
+            self.builder().map.insert(*dict, local);
+        }
+        let params: Vec<_> = params
+            .into_iter()
+            .map(|ty| Place::local(self.local(Some(ty), None, span)))
+            .collect();
+        for clause in &item.clauses {
+            let next = self.new_block();
+            for (pattern, place) in clause.params.iter().zip(&params) {
+                self.test(pattern, place, next);
+            }
+            for (pattern, place) in clause.params.iter().zip(&params) {
+                self.bind(pattern, place, &pattern.span);
+            }
+            self.expr_into(Place::local(RETURN), &clause.body.value, &clause.body.span);
+            self.terminate(TerminatorKind::Return, &end(&clause.body.span));
+            self.goto(next);
+        }
+        self.terminate(TerminatorKind::Unreachable, span);
+        self.bodies[item.id.0] = Some(self.body(item.name.value, span, item.arity()));
+    }
+    // The type of an item, or of a drop glue item, which drops values of its type
+    fn item_type(&self, id: DefId) -> Type {
+        match self.types.items.get(id.0) {
+            Some(scheme) => scheme.ty.clone(),
+            None => Type::fun(
+                self.types.glue[id.0 - self.types.items.len()].clone(),
+                Type::Unit,
+            ),
+        }
+    }
+    // Ends the innermost body
+    fn body(&mut self, name: Ident<'a>, span: &Span, params: usize) -> Body<'a> {
+        let builder = self.builders.pop().expect("A body is being lowered");
+        let mut blocks: Vec<_> = builder
+            .blocks
+            .into_iter()
+            .map(|(statements, terminator)| BasicBlock {
+                statements,
+                terminator: terminator.expect("Every block is terminated"),
+            })
+            .collect();
+        merge_switches(&mut blocks);
+        remove_unreachable(&mut blocks);
+        mark_tail_calls(&mut blocks);
+        Body {
+            name,
+            span: span.clone(),
+            locals: builder.locals,
+            params,
+            captures: builder.captures.iter().map(|(_, local)| *local).collect(),
+            blocks,
+        }
+    }
+
+    fn builder(&mut self) -> &mut Builder<'a> {
+        self.builders.last_mut().expect("A body is being lowered")
+    }
+    fn local(&mut self, ty: Option<Type>, name: Option<Ident<'a>>, span: &Span) -> Local {
+        let builder = self.builder();
+        builder.locals.push(LocalDecl {
+            ty,
+            name,
+            span: span.clone(),
+            mutable: false,
+        });
+        Local(builder.locals.len() - 1)
+    }
+    // A temporary, which goes out of scope at the end of the innermost block
+    fn temp(&mut self, ty: Type, span: &Span) -> Local {
+        let local = self.local(Some(ty), None, span);
+        self.scope(local);
+        local
+    }
+    fn scope(&mut self, local: Local) {
+        let scopes = &mut self.builder().scopes;
+        scopes.last_mut().expect("Bodies have a scope").push(local);
+    }
+    fn new_block(&mut self) -> BlockId {
+        let blocks = &mut self.builder().blocks;
+        blocks.push((vec![], None));
+        BlockId(blocks.len() - 1)
+    }
+    fn goto(&mut self, block: BlockId) {
+        self.builder().current = block;
+    }
+    fn push(&mut self, kind: StatementKind<'a>, span: &Span) {
+        let builder = self.builder();
+        let block = &mut builder.blocks[builder.current.0];
+        debug_assert!(block.1.is_none(), "Statements go to unterminated blocks");
+        block.0.push(Statement {
+            kind,
+            span: span.clone(),
+        });
+    }
+    fn assign(&mut self, place: Place, rvalue: Rvalue<'a>, span: &Span) {
+        self.push(StatementKind::Assign(place, rvalue), span);
+    }
+    fn terminate(&mut self, kind: TerminatorKind<'a>, span: &Span) {
+        let builder = self.builder();
+        builder.blocks[builder.current.0].1 = Some(Terminator {
+            kind,
+            span: span.clone(),
+        });
+    }
+    // Terminates the current block, continuing in a new one
+    fn terminate_into(&mut self, kind: impl FnOnce(BlockId) -> TerminatorKind<'a>, span: &Span) {
+        let target = self.new_block();
+        self.terminate(kind(target), span);
+        self.goto(target);
+    }
+
+    // The local of a HIR local, captured from the enclosing bodies when it isn't one of the
+    // innermost body's
+    fn lookup(&mut self, id: LocalId, span: &Span) -> Local {
+        self.lookup_in(self.builders.len() - 1, id, span)
+    }
+    fn lookup_in(&mut self, depth: usize, id: LocalId, span: &Span) -> Local {
+        if let Some(local) = self.builders[depth].map.get(&id) {
+            return *local;
+        }
+        let outer = self.lookup_in(depth - 1, id, span);
+        // Lambdas capture values, which they can't change
+        let decl = LocalDecl {
+            span: span.clone(),
+            mutable: false,
+            ..self.builders[depth - 1].locals[outer.0].clone()
+        };
+        let builder = &mut self.builders[depth];
+        builder.locals.push(decl);
+        let local = Local(builder.locals.len() - 1);
+        builder.map.insert(id, local);
+        builder.captures.push((outer, local));
+        local
+    }
+
+    // The value of a place of type `ty`, moved out of it when it's owned
+    fn read(&self, place: Place, ty: &Type) -> Operand<'a> {
+        match ty.is_owned(&self.program.datas) {
+            true => Operand::Move(place),
+            false => Operand::Copy(place),
+        }
+    }
+    // The type of the value of an expression
+    fn ty(&self, value: &Expr, span: &Span) -> Type {
+        match value {
+            Expr::Literal(l) => literal(l),
+            Expr::Var(Res::Local(id), _) => self.types.locals[id].clone(),
+            Expr::Block { returns: false, .. } => Type::Unit,
+            Expr::Method(dict, class, idx) => {
+                let method = &self.program.classes[class.0].methods[*idx];
+                let mut vars: Vec<_> = (0..method.vars).map(|v| Type::Var(TyVar(v))).collect();
+                // The variables of the instance's type follow those of the signature
+                if let Dict::Instance(id, _) = dict {
+                    let instance = &self.program.instances[id.0];
+                    let own: Vec<_> = (method.vars..method.vars + instance.vars)
+                        .map(|v| Type::Var(TyVar(v)))
+                        .collect();
+                    vars[0] = lower_ty(&self.program.datas, &instance.ty.value, &own);
+                }
+                lower_ty(&self.program.datas, &method.ty.value, &vars)
+            }
+            _ => self.types.spans[span].clone(),
+        }
+    }
+
+    // Continues in the current block once `pattern` matches `place`, in `fail` otherwise
+    fn test(&mut self, pattern: &Node<Pattern<'a>>, place: &Place, fail: BlockId) {
+        match &pattern.value {
+            Pattern::Wildcard | Pattern::Binding(..) | Pattern::Literal(Literal::Unit) => (),
+            Pattern::Literal(l) => self.branch(place, Test::Literal(*l), fail, &pattern.span),
+            Pattern::Ctor(data, idx, fields) => {
+                if self.program.datas[data.0].ctors.len() > 1 {
+                    self.branch(place, Test::Ctor(*idx), fail, &pattern.span);
+                }
+                let place = place.project(Projection::Downcast(*data, *idx));
+                for (field, pattern) in fields.iter().enumerate() {
+                    self.test(pattern, &place.project(Projection::Field(field)), fail);
+                }
+            }
+            Pattern::Tuple(patterns) => {
+                for (field, pattern) in patterns.iter().enumerate() {
+                    self.test(pattern, &place.project(Projection::Field(field)), fail);
+                }
+            }
+        }
+    }
+    fn branch(&mut self, place: &Place, test: Test, fail: BlockId, span: &Span) {
+        let place = place.clone();
+        let kind = |then| TerminatorKind::Switch {
+            place,
+            targets: vec![(test, then)],
+            otherwise: fail,
+        };
+        self.terminate_into(kind, span);
+    }
+    // Binds the locals of a pattern matching `place` to its parts, the value being read at
+    // `span`
+    fn bind(&mut self, pattern: &Node<Pattern<'a>>, place: &Place, span: &Span) {
+        match &pattern.value {
+            Pattern::Wildcard | Pattern::Literal(_) => (),
+            Pattern::Binding(id, name) => {
+                let ty = self.types.locals[id].clone();
+                let local = self.local(Some(ty.clone()), Some(name), &pattern.span);
+                let mutable = self.program.mutable.contains(id);
+                let builder = self.builder();
+                builder.locals[local.0].mutable = mutable;
+                builder.map.insert(*id, local);
+                self.scope(local);
+                let value = self.read(place.clone(), &ty);
+                self.assign(Place::local(local), Rvalue::Use(value), span);
+            }
+            Pattern::Ctor(data, idx, patterns) => {
+                let place = place.project(Projection::Downcast(*data, *idx));
+                for (field, pattern) in patterns.iter().enumerate() {
+                    self.bind(pattern, &place.project(Projection::Field(field)), span);
+                }
+            }
+            Pattern::Tuple(patterns) => {
+                for (field, pattern) in patterns.iter().enumerate() {
+                    self.bind(pattern, &place.project(Projection::Field(field)), span);
+                }
+            }
+        }
+    }
+
+    // The value of an expression, as a constant when it is one
+    fn as_operand(&mut self, value: &Expr<'a>, span: &Span) -> Operand<'a> {
+        match value {
+            Expr::Literal(l) => Operand::Constant(Constant::Literal(*l)),
+            Expr::Var(Res::Def(id), name) if self.program.items[id.0].arity() > 0 => {
+                Operand::Constant(Constant::Item(*id, name))
+            }
+            Expr::Var(Res::Ctor(data, idx), name)
+                if !self.program.datas[data.0].ctors[*idx].fields.is_empty() =>
+            {
+                Operand::Constant(Constant::Ctor(*data, *idx, name))
+            }
+            _ => {
+                let ty = self.ty(value, span);
+                let temp = self.temp(ty.clone(), span);
+                self.expr_into(Place::local(temp), value, span);
+                self.read(Place::local(temp), &ty)
+            }
+        }
+    }
+    // The place an expression refers to: a local, what a reference points to, or a temporary
+    // holding its value
+    fn as_place(&mut self, value: &Expr<'a>, span: &Span) -> Place {
+        match value {
+            Expr::Var(Res::Local(id), _) => Place::local(self.lookup(*id, span)),
+            Expr::Unary(UnOp::Deref, e) => {
+                self.as_place(&e.value, &e.span).project(Projection::Deref)
+            }
+            _ if value.single().is_some() => {
+                let e = value.single().expect("It's a single expression");
+                self.as_place(&e.value, &e.span)
+            }
+            _ => {
+                let temp = self.temp(self.ty(value, span), span);
+                self.expr_into(Place::local(temp), value, span);
+                Place::local(temp)
+            }
+        }
+    }
+    // The dictionary of an instance with a context is a tuple of the instance's, then of
+    // those of its context
+    fn dict(&mut self, dict: &Dict, span: &Span) -> Operand<'a> {
+        match dict {
+            Dict::Param(id) => Operand::Copy(Place::local(self.lookup(*id, span))),
+            Dict::Instance(id, context) if !context.is_empty() => {
+                let mut operands = vec![Operand::Constant(Constant::Dict(Dict::Instance(
+                    *id,
+                    vec![],
+                )))];
+                operands.extend(context.iter().map(|dict| self.dict(dict, span)));
+                let local = self.local(None, None, span);
+                self.assign(Place::local(local), Rvalue::Tuple(operands), span);
+                Operand::Copy(Place::local(local))
+            }
+            dict => Operand::Constant(Constant::Dict(dict.clone())),
+        }
+    }
+
+    // Evaluates an expression into `dest`
+    fn expr_into(&mut self, dest: Place, value: &Expr<'a>, span: &Span) {
+        let rvalue = match value {
+            Expr::Literal(l) => Rvalue::Use(Operand::Constant(Constant::Literal(*l))),
+            Expr::Var(Res::Local(_), _) | Expr::Unary(UnOp::Deref, _) => {
+                let place = self.as_place(value, span);
+                Rvalue::Use(self.read(place, &self.ty(value, span)))
+            }
+            // Items without parameters are evaluated when they're used
+            Expr::Var(Res::Def(id), name) if self.program.items[id.0].arity() == 0 => {
+                let func = Operand::Constant(Constant::Item(*id, name));
+                return self.call(dest, func, vec![], span);
+            }
+            Expr::Var(Res::Ctor(data, idx), name)
+                if self.program.datas[data.0].ctors[*idx].fields.is_empty() =>
+            {
+                Rvalue::Ctor(*data, *idx, name, vec![])
+            }
+            Expr::Var(..) => Rvalue::Use(self.as_operand(value, span)),
+            Expr::Method(dict, _, idx) => Rvalue::Method(self.dict(dict, span), *idx),
+            Expr::Unary(op @ (UnOp::Ref | UnOp::RefMut), e) => {
+                Rvalue::Ref(*op == UnOp::RefMut, self.as_place(&e.value, &e.span))
+            }
+            Expr::Unary(UnOp::Pos, e) => return self.expr_into(dest, &e.value, &e.span),
+            Expr::Unary(op, e) => Rvalue::Unary(*op, self.as_operand(&e.value, &e.span)),
+            // `&&` and `||` only evaluate their right operand when it decides of their value
+            Expr::Binary(op @ (BinOp::And | BinOp::Or), l, r) => {
+                let cond = self.as_operand(&l.value, &l.span);
+                let (then, else_, join) = (self.new_block(), self.new_block(), self.new_block());
+                self.terminate(TerminatorKind::If { cond, then, else_ }, span);
+                let (right, short) = match op {
+                    BinOp::And => (then, else_),
+                    _ => (else_, then),
+                };
+                self.goto(right);
+                self.expr_into(dest.clone(), &r.value, &r.span);
+                self.terminate(TerminatorKind::Goto(join), span);
+                self.goto(short);
+                let value = Operand::Constant(Constant::Literal(Literal::Bool(*op == BinOp::Or)));
+                self.assign(dest, Rvalue::Use(value), span);
+                self.terminate(TerminatorKind::Goto(join), span);
+                return self.goto(join);
+            }
+            Expr::Binary(op, l, r) => {
+                let l = self.as_operand(&l.value, &l.span);
+                Rvalue::Binary(*op, l, self.as_operand(&r.value, &r.span))
+            }
+            Expr::Lambda(param, body) => self.lambda(param, body, span),
+            Expr::Call(..) | Expr::EmptyCall(_) | Expr::DictApp(..) => {
+                let (f, args) = value.spine().expect("Calls have a spine");
+                let head = match &*f.value {
+                    Expr::Var(Res::Ctor(data, idx), name)
+                        if self.program.datas[data.0].ctors[*idx].fields.len() == args.len() =>
+                    {
+                        Some((*data, *idx, *name))
+                    }
+                    _ => None,
+                };
+                let func = match head {
+                    Some(_) => None,
+                    None => Some(self.as_operand(&f.value, &f.span)),
+                };
+                let args = args
+                    .iter()
+                    .map(|arg| match arg {
+                        Arg::Expr(e) => self.as_operand(&e.value, &e.span),
+                        Arg::Unit => Operand::Constant(Constant::Literal(Literal::Unit)),
+                        Arg::Dict(dict) => self.dict(dict, span),
+                    })
+                    .collect();
+                match (head, func) {
+                    (Some((data, idx, name)), _) => Rvalue::Ctor(data, idx, name, args),
+                    (_, Some(func)) => return self.call(dest, func, args, span),
+                    _ => unreachable!(),
+                }
+            }
+            Expr::Tuple(es) => Rvalue::Tuple(
+                es.iter()
+                    .map(|e| self.as_operand(&e.value, &e.span))
+                    .collect(),
+            ),
+            Expr::Block {
+                statements,
+                returns,
+            } => return self.block(dest, statements, *returns, span),
+            Expr::If { cond, then, else_ } => {
+                let cond = self.as_operand(&cond.value, &cond.span);
+                let (then_block, else_block, join) =
+                    (self.new_block(), self.new_block(), self.new_block());
+                let kind = TerminatorKind::If {
+                    cond,
+                    then: then_block,
+                    else_: else_block,
+                };
+                self.terminate(kind, span);
+                for (block, e) in [(then_block, then), (else_block, else_)].iter() {
+                    self.goto(*block);
+                    self.expr_into(dest.clone(), &e.value, &e.span);
+                    self.terminate(TerminatorKind::Goto(join), &end(&e.span));
+                }
+                return self.goto(join);
+            }
+            // Operators overloaded by a dictionary parameter are primitive when it's the
+            // instance for `Int`, and call the method of the other instances
+            Expr::OverloadedUnary(op, dict, e) => {
+                let ty = Type::fun(self.ty(&e.value, &e.span), self.ty(value, span));
+                let operand = self.as_operand(&e.value, &e.span);
+                let primitive = Rvalue::Unary(*op, operand.clone());
+                let method = (self.program.negation(), ty);
+                return self.overloaded(dest, dict, method, vec![operand], primitive, span);
+            }
+            Expr::OverloadedBinary(op, dict, l, r) => {
+                let ret = Type::fun(self.ty(&r.value, &r.span), self.ty(value, span));
+                let ty = Type::fun(self.ty(&l.value, &l.span), ret);
+                let l = self.as_operand(&l.value, &l.span);
+                let r = self.as_operand(&r.value, &r.span);
+                let primitive = Rvalue::Binary(*op, l.clone(), r.clone());
+                let method = (self.program.operator(*op), ty);
+                return self.overloaded(dest, dict, method, vec![l, r], primitive, span);
+            }
+            Expr::Error => unreachable!("Only checked programs are lowered"),
+        };
+        self.assign(dest, rvalue, span);
+    }
+    // Calls the method of type `ty` when the dictionary isn't the instance for `Int`, whose
+    // operator is `primitive`
+    fn overloaded(
+        &mut self,
+        dest: Place,
+        dict: &Dict,
+        ((class, idx), ty): ((ClassId, usize), Type),
+        args: Vec<Operand<'a>>,
+        primitive: Rvalue<'a>,
+        span: &Span,
+    ) {
+        let (method, join) = (self.new_block(), self.new_block());
+        let operand = self.dict(dict, span);
+        match (self.program.int_instance(class), dict, &operand) {
+            (Some(int), Dict::Param(_), Operand::Copy(place)) => {
+                self.branch(place, Test::Dict(int), method, span);
+                self.assign(dest.clone(), primitive, span);
+                self.terminate(TerminatorKind::Goto(join), span);
+            }
+            _ => self.terminate(TerminatorKind::Goto(method), span),
+        }
+        self.goto(method);
+        let func = self.temp(ty, span);
+        self.assign(Place::local(func), Rvalue::Method(operand, idx), span);
+        self.call(dest, Operand::Copy(Place::local(func)), args, span);
+        self.terminate(TerminatorKind::Goto(join), span);
+        self.goto(join);
+    }
+    fn call(&mut self, dest: Place, func: Operand<'a>, args: Vec<Operand<'a>>, span: &Span) {
+        let kind = |target| TerminatorKind::Call {
+            func,
+            args,
+            dest,
+            target,
+            tail: false,
+        };
+        self.terminate_into(kind, span);
+    }
+    fn lambda(
+        &mut self,
+        param: &Node<Pattern<'a>>,
+        body: &BoxNode<Expr<'a>>,
+        span: &Span,
+    ) -> Rvalue<'a> {
+        let (param_ty, ret) = match self.types.spans[span].clone() {
+            Type::Fun(param, ret) => (*param, *ret),
+            _ => unreachable!("Lambdas have a function type"),
+        };
+        self.builders.push(Builder::new(ret, span));
+        let arg = Place::local(self.local(Some(param_ty), None, &param.span));
+        self.bind(param, &arg, &param.span);
+        self.expr_into(Place::local(RETURN), &body.value, &body.span);
+        self.terminate(TerminatorKind::Return, &end(&body.span));
+        let captured: Vec<_> = self
+            .builder()
+            .captures
+            .iter()
+            .map(|(outer, _)| *outer)
+            .collect();
+        let body = self.body("\\", span, 1);
+        let captures = captured
+            .into_iter()
+            .map(|local| match self.builder().locals[local.0].ty.clone() {
+                Some(ty) => self.read(Place::local(local), &ty),
+                None => Operand::Copy(Place::local(local)),
+            })
+            .collect();
+        self.bodies.push(Some(body));
+        Rvalue::Closure(BodyId(self.bodies.len() - 1), captures)
+    }
+    fn block(
+        &mut self,
+        dest: Place,
+        statements: &[Node<hir::Statement<'a>>],
+        returns: bool,
+        span: &Span,
+    ) {
+        self.builder().scopes.push(vec![]);
+        let mut value = false;
+        for (idx, statement) in statements.iter().enumerate() {
+            match &statement.value {
+                hir::Statement::Let(pattern, e) => {
+                    let place = self.as_place(&e.value, &e.span);
+                    let e = e.value.single().unwrap_or(e);
+                    self.bind(pattern, &place, &e.span);
+                }
+                // Places are made of locals and dereferences only, so evaluating them has no effect
+                hir::Statement::Assign(place, e) => {
+                    let value = self.as_operand(&e.value, &e.span);
+                    let place = self.as_place(&place.value, &place.span);
+                    self.assign(place, Rvalue::Use(value), &statement.span);
+                }
+                hir::Statement::Return(e) => {
+                    self.expr_into(Place::local(RETURN), &e.value, &e.span);
+                    self.terminate(TerminatorKind::Return, &statement.span);
+                    let next = self.new_block();
+                    self.goto(next);
+                }
+                hir::Statement::Expr(e) => match self.drop_glue(&e.value) {
+                    Some((glue, dropped)) => {
+                        let place = self.as_place(&dropped.value, &dropped.span);
+                        let glue = self.as_operand(&glue.value, &glue.span);
+                        let kind = |target| TerminatorKind::Drop {
+                            place,
+                            glue,
+                            target,
+                        };
+                        self.terminate_into(kind, &end(&statement.span));
+                    }
+                    None if returns && idx + 1 == statements.len() => {
+                        self.expr_into(dest.clone(), &e.value, &e.span);
+                        value = true;
+                    }
+                    None => {
+                        self.as_operand(&e.value, &e.span);
+                    }
+                },
+            }
+        }
+        if !value {
+            let unit = Operand::Constant(Constant::Literal(Literal::Unit));
+            self.assign(dest, Rvalue::Use(unit), span);
+        }
+        // The owned locals end where they're dropped or moved, the drops of a block's locals
+        // being elaborated after it
+        let locals = self.builder().scopes.pop().expect("Scopes are balanced");
+        let datas = &self.program.datas;
+        for local in locals.into_iter().rev() {
+            match &self.builder().locals[local.0].ty {
+                Some(ty) if ty.is_owned(datas) => (),
+                _ => self.push(StatementKind::StorageDead(local), &end(span)),
+            }
+        }
+    }
+    // The drop glue and the value of a drop elaborated by the ownership checker
+    fn drop_glue<'e>(
+        &self,
+        value: &'e Expr<'a>,
+    ) -> Option<(&'e BoxNode<Expr<'a>>, &'e BoxNode<Expr<'a>>)> {
+        match value {
+            Expr::Call(f, x) => match &*f.value {
+                Expr::Method(Dict::Instance(..), class, 0) if *class == self.drop => Some((f, x)),
+                // The glue items follow the items with a type
+                Expr::Var(Res::Def(id), _) if id.0 >= self.types.items.len() => Some((f, x)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+// A switch going to an empty block that switches on the same place takes its targets, the
+// tests it already made being left out
+fn merge_switches(blocks: &mut [BasicBlock]) {
+    for idx in 0..blocks.len() {
+        while let TerminatorKind::Switch {
+            place, otherwise, ..
+        } = &blocks[idx].terminator.kind
+        {
+            let next = &blocks[otherwise.0];
+            let (targets, next_otherwise) = match &next.terminator.kind {
+                TerminatorKind::Switch {
+                    place: next_place,
+                    targets,
+                    otherwise,
+                } if next.statements.is_empty() && next_place == place => {
+                    (targets.clone(), *otherwise)
+                }
+                _ => break,
+            };
+            if let TerminatorKind::Switch {
+                targets: merged,
+                otherwise,
+                ..
+            } = &mut blocks[idx].terminator.kind
+            {
+                for (test, target) in targets {
+                    if merged.iter().all(|(other, _)| *other != test) {
+                        merged.push((test, target));
+                    }
+                }
+                *otherwise = next_otherwise;
+            }
+        }
+    }
+}
+
+// Keeps the blocks reachable from the first one, in order
+fn remove_unreachable(blocks: &mut Vec<BasicBlock>) {
+    let mut reachable = vec![false; blocks.len()];
+    let mut pending = vec![BlockId(0)];
+    while let Some(block) = pending.pop() {
+        if !std::mem::replace(&mut reachable[block.0], true) {
+            pending.extend(blocks[block.0].terminator.kind.successors());
+        }
+    }
+    let mut ids = vec![];
+    let mut next = 0;
+    for reachable in &reachable {
+        ids.push(BlockId(next));
+        next += *reachable as usize;
+    }
+    let mut idx = 0;
+    blocks.retain(|_| {
+        idx += 1;
+        reachable[idx - 1]
+    });
+    for block in blocks {
+        for target in block.terminator.kind.successors_mut() {
+            *target = ids[target.0];
+        }
+    }
+}
+
+// A call whose value is returned right away is a tail call, unless the body borrows its own
+// locals, whose frame can't be left before the call then
+fn mark_tail_calls(blocks: &mut [BasicBlock]) {
+    if frame_borrow(blocks).is_some() {
+        return;
+    }
+    let tails = in_tail_position(blocks);
+    for (block, is_tail) in blocks.iter_mut().zip(tails) {
+        if let TerminatorKind::Call { tail, .. } = &mut block.terminator.kind {
+            *tail = is_tail;
+        }
+    }
+}
+
+/// The first borrow of a local of a body, or of a temporary, which live in its frame
+pub(super) fn frame_borrow<'b>(blocks: &'b [BasicBlock]) -> Option<&'b Span> {
+    let mut statements = blocks.iter().flat_map(|block| &block.statements);
+    statements.find_map(|statement| match &statement.kind {
+        StatementKind::Assign(_, Rvalue::Ref(_, place)) if !place.is_indirect() => {
+            Some(&statement.span)
+        }
+        _ => None,
+    })
+}
+
+/// Whether each block ends with a call whose value the body returns right away
+pub(super) fn in_tail_position(blocks: &[BasicBlock]) -> Vec<bool> {
+    let returns = |mut block: BlockId| {
+        let mut seen = vec![false; blocks.len()];
+        loop {
+            let BasicBlock {
+                statements,
+                terminator,
+            } = &blocks[block.0];
+            let dead = |s: &Statement| matches!(s.kind, StatementKind::StorageDead(_));
+            if std::mem::replace(&mut seen[block.0], true) || !statements.iter().all(dead) {
+                return false;
+            }
+            match terminator.kind {
+                TerminatorKind::Goto(next) => block = next,
+                TerminatorKind::Return => return true,
+                _ => return false,
+            }
+        }
+    };
+    blocks
+        .iter()
+        .map(|block| match &block.terminator.kind {
+            TerminatorKind::Call { dest, target, .. } => {
+                *dest == Place::local(RETURN) && returns(*target)
+            }
+            _ => false,
+        })
+        .collect()
+}
+
+// The end of a span, where the locals of a block go out of scope
+fn end(span: &Span) -> Span {
+    Span::new(span.file, span.range.end..span.range.end)
+}
+
+fn literal(l: &Literal) -> Type {
+    match l {
+        Literal::Num(_) => Type::Int,
+        Literal::Bool(_) => Type::Bool,
+        Literal::Unit => Type::Unit,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::tests::program;
+
+    // A program checked with the prelude, and its MIR
+    fn mir(code: &str) -> (Program<'static>, Mir<'static>) {
+        let (program, types) = program(code, true);
+        let mir = lower(&program, &types);
+        (program, mir)
+    }
+    fn body(mir: &Mir, name: &str) -> String {
+        let body = mir
+            .bodies
+            .iter()
+            .find(|b| b.name == name && b.span.file == 1);
+        body.unwrap().to_string()
+    }
+
+    #[test]
+    fn clauses_test_their_patterns_in_order() {
+        let code = "data Shape =\n  Circle Int\n  Square Int\narea (Circle r) = 3 * r\narea (Square s) = s\n";
+        let expected = "\
+area(_1: Shape) -> _0: Int
+    let _2: Int (r)
+    let _3: Int
+    let _4: Int (s)
+  bb0:
+    switch _1 { ctor 0 => bb1, ctor 1 => bb3, _ => bb2 }
+  bb1:
+    _2 = (_1 as ctor 0).0
+    _3 = _2
+    _0 = 3 * _3
+    dead _3
+    return
+  bb2:
+    unreachable
+  bb3:
+    _4 = (_1 as ctor 1).0
+    _0 = _4
+    return
+";
+        assert_eq!(body(&mir(code).1, "area"), expected);
+    }
+
+    #[test]
+    fn literal_tests_share_a_switch() {
+        let code = "f 0 = 1\nf 1 = 2\nf _ = 3\n";
+        let expected = "\
+f(_1: Int) -> _0: Int
+  bb0:
+    switch _1 { 0 => bb1, 1 => bb3, _ => bb2 }
+  bb1:
+    _0 = 1
+    return
+  bb2:
+    _0 = 3
+    return
+  bb3:
+    _0 = 2
+    return
+";
+        assert_eq!(body(&mir(code).1, "f"), expected);
+    }
+
+    #[test]
+    fn owned_values_move_and_drop() {
+        let code = "owned data File =\n  File Int\nsize r =\n  let File n = *r\n  n\nf x =\n  let g = x\n  size &g\n";
+        let expected = "\
+f(_1: File) -> _0: Int
+    let _2: File (x)
+    let _3: Int
+    let _4: File (g)
+    let _5: &File
+    let _6: Int (_)
+  bb0:
+    _2 = move _1
+    _4 = move _2
+    _5 = &_4
+    _3 = call size#0(_5) -> bb1
+  bb1:
+    dead _5
+    _6 = _3
+    drop _4 with drop#{} -> bb2
+  bb2:
+    _0 = _6
+    dead _6
+    dead _3
+    return
+";
+        // The drop glue of `File` is the last item
+        let (program, mir) = mir(code);
+        let glue = program.items.len() - 1;
+        assert_eq!(body(&mir, "f"), expected.replace("{}", &glue.to_string()));
+    }
+
+    #[test]
+    fn returned_calls_are_tail_calls() {
+        let code = "get r = *r\ncount n acc = if n == 0 then acc else count (n - 1) (acc + 1)\nf n = if get &n == 0 then 0 else f (n - 1)\n";
+        let (_, mir) = mir(code);
+        let count = body(&mir, "count");
+        assert_eq!(count.matches(" = tail call ").count(), 1, "{}", count);
+        assert!(count.contains("_0 = tail call count#"), "{}", count);
+        // `f` borrows its parameter, which its frame holds
+        assert!(!body(&mir, "f").contains("tail call"));
+    }
+}
@@ -0,0 +1,439 @@
+//! The mid-level IR: functions as control-flow graphs of basic blocks working on locals,
+//! rather than as nested expressions. Every item and every lambda is a body of its own, whose
+//! evaluation order, temporaries, pattern tests and drops are explicit.
+//!
+//! It's lowered from the HIR once ownership is checked, so the drops it elaborated are part of
+//! it. The borrow checker works on it, and it's the target backends are generated from, which
+//! its interpreter is checked against.
+mod borrowck;
+mod interp;
+mod lower;
+mod tailrec;
+
+use std::fmt;
+
+use crate::{
+    hir::{BinOp, DataId, DefId, Dict, Ident, InstanceId, Literal, UnOp},
+    source::Span,
+    typeck::Type,
+};
+pub use borrowck::{check_borrows, check_expr_borrows};
+pub use interp::Machine;
+pub use lower::{lower, lower_expr};
+pub use tailrec::check_tail_calls;
+
+/// A local of a body, indexing `Body::locals`
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub struct Local(pub usize);
+
+/// The local holding the value a body returns
+pub const RETURN: Local = Local(0);
+
+/// A basic block of a body, indexing `Body::blocks`
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub struct BlockId(pub usize);
+
+/// A body of the program, indexing `Mir::bodies`
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub struct BodyId(pub usize);
+
+/// The bodies of a program: its items' by `DefId`, then its lambdas'
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Mir<'a> {
+    pub bodies: Vec<Body<'a>>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Body<'a> {
+    /// The item's name, or `\` for lambdas
+    pub name: Ident<'a>,
+    pub span: Span,
+    /// `RETURN` first, then the parameters, dictionaries first
+    pub locals: Vec<LocalDecl<'a>>,
+    pub params: usize,
+    /// The locals given the values a lambda captures, in the order of `Rvalue::Closure`
+    pub captures: Vec<Local>,
+    /// Evaluation starts at the first one
+    pub blocks: Vec<BasicBlock<'a>>,
+}
+impl<'a> Body<'a> {
+    pub fn params(&self) -> impl Iterator<Item = Local> {
+        (1..=self.params).map(Local)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct LocalDecl<'a> {
+    /// Dictionaries have no type
+    pub ty: Option<Type>,
+    /// The binding it was lowered from, temporaries have none
+    pub name: Option<Ident<'a>>,
+    /// Where it's bound, or the expression a temporary holds the value of. A capture is at its
+    /// first use in the lambda
+    pub span: Span,
+    /// Whether it's bound by a `mut` pattern, so that it can be assigned and borrowed mutably
+    pub mutable: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct BasicBlock<'a> {
+    pub statements: Vec<Statement<'a>>,
+    pub terminator: Terminator<'a>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Statement<'a> {
+    pub kind: StatementKind<'a>,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum StatementKind<'a> {
+    Assign(Place, Rvalue<'a>),
+    /// The local goes out of scope at the end of the block declaring it, it can't be borrowed
+    /// past it
+    StorageDead(Local),
+}
+
+/// A location holding a value: a local, or a part of the value of a local
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Place {
+    pub local: Local,
+    pub projection: Vec<Projection>,
+}
+impl Place {
+    pub fn local(local: Local) -> Self {
+        Self {
+            local,
+            projection: vec![],
+        }
+    }
+    pub fn project(&self, projection: Projection) -> Self {
+        let mut place = self.clone();
+        place.projection.push(projection);
+        place
+    }
+    /// Whether it's behind a reference
+    pub fn is_indirect(&self) -> bool {
+        self.projection.contains(&Projection::Deref)
+    }
+    /// The place written in the syntax of the source, starting at `base`
+    pub fn show(&self, base: String) -> String {
+        self.write(base, false)
+    }
+    // Downcasts only appear in the MIR, the source naming the fields of tested values alone
+    fn write(&self, base: String, downcasts: bool) -> String {
+        let (mut s, mut previous) = (base, None);
+        for projection in &self.projection {
+            s = match projection {
+                Projection::Deref => format!("*{}", s),
+                Projection::Field(field) if previous == Some(Projection::Deref) => {
+                    format!("({}).{}", s, field)
+                }
+                Projection::Field(field) => format!("{}.{}", s, field),
+                Projection::Downcast(_, idx) if downcasts => format!("({} as ctor {})", s, idx),
+                Projection::Downcast(..) => continue,
+            };
+            previous = Some(*projection);
+        }
+        s
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Projection {
+    /// The value behind a reference
+    Deref,
+    /// A field of a tuple, or of a downcast data value
+    Field(usize),
+    /// A data value seen as one of its constructors, once it's tested, for its fields to follow
+    Downcast(DataId, usize),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Operand<'a> {
+    Copy(Place),
+    /// Takes the value of an owned place, which can't be used afterwards
+    Move(Place),
+    Constant(Constant<'a>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Constant<'a> {
+    Literal(Literal),
+    /// An item taking arguments, as a function
+    Item(DefId, Ident<'a>),
+    /// A constructor taking fields, as a function
+    Ctor(DataId, usize, Ident<'a>),
+    /// The dictionary of an instance, or a hole in an expression of the REPL with constraints.
+    /// The dictionaries of instances with a context are tuples built from it
+    Dict(Dict),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Rvalue<'a> {
+    Use(Operand<'a>),
+    /// `&place`, or `&mut place` when it's mutable
+    Ref(bool, Place),
+    /// `-` or `!`
+    Unary(UnOp, Operand<'a>),
+    /// Never `&&` or `||`, which are branches
+    Binary(BinOp, Operand<'a>, Operand<'a>),
+    Tuple(Vec<Operand<'a>>),
+    /// A constructor given all of its fields
+    Ctor(DataId, usize, Ident<'a>, Vec<Operand<'a>>),
+    /// A lambda, with the values it captures
+    Closure(BodyId, Vec<Operand<'a>>),
+    /// A method of a dictionary by index, evaluated like an item: methods without parameters
+    /// are called
+    Method(Operand<'a>, usize),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Terminator<'a> {
+    pub kind: TerminatorKind<'a>,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum TerminatorKind<'a> {
+    Goto(BlockId),
+    If {
+        cond: Operand<'a>,
+        then: BlockId,
+        else_: BlockId,
+    },
+    /// Goes to the target of the first test the value of `place` passes, or to `otherwise`
+    /// when it passes none, to match patterns
+    Switch {
+        place: Place,
+        targets: Vec<(Test, BlockId)>,
+        otherwise: BlockId,
+    },
+    /// Applies a function to arguments, calling it once it has all of them: items and
+    /// constructors given fewer are partially applied, and the value of a call given more is
+    /// applied to the rest
+    Call {
+        func: Operand<'a>,
+        args: Vec<Operand<'a>>,
+        dest: Place,
+        target: BlockId,
+        /// Whether its value is returned right away and the body borrows none of its locals,
+        /// so that the callee can take the caller's frame
+        tail: bool,
+    },
+    /// Drops an owned value by calling its drop glue with it
+    Drop {
+        place: Place,
+        glue: Operand<'a>,
+        target: BlockId,
+    },
+    Return,
+    /// Never reached: after a diverging statement, or once every clause failed to match
+    Unreachable,
+}
+impl<'a> TerminatorKind<'a> {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            TerminatorKind::Goto(target)
+            | TerminatorKind::Call { target, .. }
+            | TerminatorKind::Drop { target, .. } => vec![*target],
+            TerminatorKind::If { then, else_, .. } => vec![*then, *else_],
+            TerminatorKind::Switch {
+                targets, otherwise, ..
+            } => {
+                let targets = targets.iter().map(|(_, target)| *target);
+                targets.chain([*otherwise]).collect()
+            }
+            TerminatorKind::Return | TerminatorKind::Unreachable => vec![],
+        }
+    }
+    pub fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            TerminatorKind::Goto(target)
+            | TerminatorKind::Call { target, .. }
+            | TerminatorKind::Drop { target, .. } => vec![target],
+            TerminatorKind::If { then, else_, .. } => vec![then, else_],
+            TerminatorKind::Switch {
+                targets, otherwise, ..
+            } => {
+                let targets = targets.iter_mut().map(|(_, target)| target);
+                targets.chain([otherwise]).collect()
+            }
+            TerminatorKind::Return | TerminatorKind::Unreachable => vec![],
+        }
+    }
+}
+
+/// Whether a value is equal to a literal, is made by a constructor by index, or is the
+/// dictionary of an instance
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Test {
+    Literal(Literal),
+    Ctor(usize),
+    Dict(InstanceId),
+}
+
+impl fmt::Display for Mir<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, body) in self.bodies.iter().enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "#{} {}", idx, body)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Body<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decl = |local: Local| {
+            let LocalDecl { ty, name, .. } = &self.locals[local.0];
+            let ty = ty.as_ref().map_or("dict".to_string(), |ty| ty.to_string());
+            match name {
+                Some(name) => format!("{}: {} ({})", local, ty, name),
+                None => format!("{}: {}", local, ty),
+            }
+        };
+        let params: Vec<_> = self.params().map(decl).collect();
+        writeln!(
+            f,
+            "{}({}) -> {}",
+            self.name,
+            params.join(", "),
+            decl(RETURN)
+        )?;
+        for local in &self.captures {
+            writeln!(f, "    capture {}", decl(*local))?;
+        }
+        for idx in self.params + 1..self.locals.len() {
+            if !self.captures.contains(&Local(idx)) {
+                let mutable = if self.locals[idx].mutable { "mut " } else { "" };
+                writeln!(f, "    let {}{}", mutable, decl(Local(idx)))?;
+            }
+        }
+        for (idx, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "  {}:", BlockId(idx))?;
+            for statement in &block.statements {
+                writeln!(f, "    {}", statement.kind)?;
+            }
+            writeln!(f, "    {}", block.terminator.kind)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Local {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "_{}", self.0)
+    }
+}
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+impl fmt::Display for Place {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.write(self.local.to_string(), true))
+    }
+}
+impl fmt::Display for Operand<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Copy(place) => write!(f, "{}", place),
+            Operand::Move(place) => write!(f, "move {}", place),
+            Operand::Constant(Constant::Literal(l)) => match l {
+                Literal::Num(n) => write!(f, "{}", n),
+                Literal::Bool(b) => write!(f, "{}", b),
+                Literal::Unit => write!(f, "()"),
+            },
+            Operand::Constant(Constant::Item(id, name)) => write!(f, "{}#{}", name, id.0),
+            Operand::Constant(Constant::Ctor(_, _, name)) => write!(f, "{}", name),
+            Operand::Constant(Constant::Dict(Dict::Instance(id, _))) => write!(f, "dict#{}", id.0),
+            Operand::Constant(Constant::Dict(_)) => write!(f, "dict?"),
+        }
+    }
+}
+fn list(operands: &[Operand]) -> String {
+    let operands: Vec<_> = operands.iter().map(|op| op.to_string()).collect();
+    operands.join(", ")
+}
+impl fmt::Display for StatementKind<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatementKind::Assign(place, rvalue) => write!(f, "{} = {}", place, rvalue),
+            StatementKind::StorageDead(local) => write!(f, "dead {}", local),
+        }
+    }
+}
+impl fmt::Display for Rvalue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rvalue::Use(operand) => write!(f, "{}", operand),
+            Rvalue::Ref(false, place) => write!(f, "&{}", place),
+            Rvalue::Ref(true, place) => write!(f, "&mut {}", place),
+            Rvalue::Unary(UnOp::Neg, operand) => write!(f, "-{}", operand),
+            Rvalue::Unary(_, operand) => write!(f, "!{}", operand),
+            Rvalue::Binary(op, l, r) => write!(f, "{} {} {}", l, op, r),
+            Rvalue::Tuple(operands) => write!(f, "({})", list(operands)),
+            Rvalue::Ctor(_, _, name, fields) => write!(f, "{}({})", name, list(fields)),
+            Rvalue::Closure(body, captures) => write!(f, "closure#{}({})", body.0, list(captures)),
+            Rvalue::Method(dict, idx) => write!(f, "method {} of {}", idx, dict),
+        }
+    }
+}
+impl fmt::Display for TerminatorKind<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TerminatorKind::Goto(target) => write!(f, "goto {}", target),
+            TerminatorKind::If { cond, then, else_ } => {
+                write!(f, "if {} then {} else {}", cond, then, else_)
+            }
+            TerminatorKind::Switch {
+                place,
+                targets,
+                otherwise,
+            } => {
+                write!(f, "switch {} {{ ", place)?;
+                for (test, target) in targets {
+                    match test {
+                        Test::Literal(l) => {
+                            write!(f, "{}", Operand::Constant(Constant::Literal(*l)))?
+                        }
+                        Test::Ctor(idx) => write!(f, "ctor {}", idx)?,
+                        Test::Dict(id) => write!(f, "instance {}", id.0)?,
+                    }
+                    write!(f, " => {}, ", target)?;
+                }
+                write!(f, "_ => {} }}", otherwise)
+            }
+            TerminatorKind::Call {
+                func,
+                args,
+                dest,
+                target,
+                tail,
+            } => {
+                let call = if *tail { "tail call" } else { "call" };
+                write!(
+                    f,
+                    "{} = {} {}({}) -> {}",
+                    dest,
+                    call,
+                    func,
+                    list(args),
+                    target
+                )
+            }
+            TerminatorKind::Drop {
+                place,
+                glue,
+                target,
+            } => write!(f, "drop {} with {} -> {}", place, glue, target),
+            TerminatorKind::Return => write!(f, "return"),
+            TerminatorKind::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! Checks that the items marked `tailrec` and the items they're mutually recursive with only
+//! call each other in tail position, whichever of them makes the call. The check is made on the MIR, whose tail calls are the
+//! ones every backend makes without growing the stack, so that it agrees with them: a call
+//! followed by drops, or made by a body borrowing its own values, isn't one.
+use std::collections::HashSet;
+
+use super::{
+    lower::{frame_borrow, in_tail_position},
+    *,
+};
+use crate::{
+    errors::tail_call_err::{TailCallErr, TailCallErrKind},
+    hir::{Clause, Program},
+    typeck::Types,
+};
+
+/// Checks the recursive calls of the `tailrec` items of a program, once its borrows are checked
+pub fn check_tail_calls(program: &Program, types: &Types) -> Vec<TailCallErr> {
+    let mir = lower(program, types);
+    // The item each body is a part of, lambdas being a part of the item they're in
+    let owners: Vec<_> = (mir.bodies.iter().enumerate())
+        .map(|(idx, body)| match program.items.get(idx) {
+            Some(item) => item.id,
+            None => owner(program, &body.span),
+        })
+        .collect();
+    let mut references = vec![HashSet::new(); program.items.len()];
+    for (body, owner) in mir.bodies.iter().zip(&owners) {
+        references[owner.0].extend(items(body));
+    }
+    let mut errors = vec![];
+    for ((idx, body), owner) in mir.bodies.iter().enumerate().zip(&owners) {
+        let caller = &program.items[owner.0];
+        let positions = in_tail_position(&body.blocks);
+        for (block, position) in body.blocks.iter().zip(positions) {
+            let (id, args, tail) = match &block.terminator.kind {
+                TerminatorKind::Call {
+                    func: Operand::Constant(Constant::Item(id, _)),
+                    args,
+                    tail,
+                    ..
+                } => (*id, args.len(), *tail),
+                _ => continue,
+            };
+            let params = mir.bodies[id.0].params;
+            let lambda = idx != owner.0;
+            let callee = &program.items[id.0];
+            // The caller reaches the callee, the call being one of their group when it reaches back
+            let marked = match (caller.tailrec, callee.tailrec) {
+                (true, _) => caller,
+                (false, true) => callee,
+                (false, false) => continue,
+            };
+            if (tail && !lambda)
+                || args < params
+                || !(id == *owner || reaches(&references, id, *owner))
+            {
+                continue;
+            }
+            let (callee, caller, tailrec, name) = (
+                callee.name.value.to_string(),
+                caller.name.value.to_string(),
+                marked.name.value.to_string(),
+                marked.name.span.clone(),
+            );
+            // Only the borrows keep calls of the item itself in tail position from being tail calls
+            let kind = match frame_borrow(&body.blocks) {
+                Some(borrow) if position && args == params && !lambda => {
+                    TailCallErrKind::Borrowed {
+                        callee,
+                        caller,
+                        tailrec,
+                        name,
+                        borrow: borrow.clone(),
+                    }
+                }
+                _ => TailCallErrKind::NotInTailPosition {
+                    callee,
+                    caller,
+                    tailrec,
+                    name,
+                    lambda,
+                },
+            };
+            errors.push(TailCallErr {
+                span: block.terminator.span.clone(),
+                kind,
+            });
+        }
+    }
+    errors
+}
+
+// The item whose clauses contain a lambda
+fn owner(program: &Program, span: &Span) -> DefId {
+    let contains = |clause: &Clause| {
+        let body = &clause.body.span;
+        body.file == span.file
+            && body.range.start <= span.range.start
+            && span.range.end <= body.range.end
+    };
+    let item = program
+        .items
+        .iter()
+        .find(|item| item.clauses.iter().any(contains));
+    item.expect("Lambdas are in items").id
+}
+
+// The items a body uses
+fn items(body: &Body) -> Vec<DefId> {
+    let mut operands = vec![];
+    for block in &body.blocks {
+        for statement in &block.statements {
+            if let StatementKind::Assign(_, rvalue) = &statement.kind {
+                match rvalue {
+                    Rvalue::Use(operand)
+                    | Rvalue::Unary(_, operand)
+                    | Rvalue::Method(operand, _) => operands.push(operand),
+                    Rvalue::Binary(_, l, r) => operands.extend([l, r]),
+                    Rvalue::Tuple(elements)
+                    | Rvalue::Ctor(.., elements)
+                    | Rvalue::Closure(_, elements) => operands.extend(elements),
+                    Rvalue::Ref(..) => (),
+                }
+            }
+        }
+        match &block.terminator.kind {
+            TerminatorKind::Call { func, args, .. } => {
+                operands.extend(std::iter::once(func).chain(args))
+            }
+            TerminatorKind::Drop { glue, .. } => operands.push(glue),
+            TerminatorKind::If { cond, .. } => operands.push(cond),
+            _ => (),
+        }
+    }
+    let items = operands.into_iter().filter_map(|operand| match operand {
+        Operand::Constant(Constant::Item(id, _)) => Some(*id),
+        _ => None,
+    });
+    items.collect()
+}
+
+// Whether an item uses another, directly or through the items it uses
+fn reaches(references: &[HashSet<DefId>], from: DefId, to: DefId) -> bool {
+    let (mut seen, mut pending) = (HashSet::new(), vec![from]);
+    while let Some(id) = pending.pop() {
+        if id == to {
+            return true;
+        }
+        if seen.insert(id) {
+            pending.extend(references[id.0].iter().copied());
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::check::tests::{check_code, step_errors};
+
+    const FILE: &str = "owned data File =\n  File Int\nget r = *r\n";
+
+    fn errors(code: &str) -> (String, Vec<TailCallErr>) {
+        let code = format!("{}{}", FILE, code);
+        let mut checked = check_code(&code, false);
+        let errors = step_errors!(checked, TailCall);
+        (code, errors)
+    }
+    // The calls the errors of a program, with the prelude and `FILE`, point at
+    fn check(code: &str) -> Vec<String> {
+        let (code, errors) = errors(code);
+        errors
+            .into_iter()
+            .map(|e| code[e.span.range].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn tail_calls_are_accepted() {
+        let code = "tailrec count n acc = if n == 0 then acc else count (n - 1) (acc + 1)\n";
+        assert!(check(code).is_empty());
+        // Through blocks, `&&`, `||` and `return`, between mutually recursive items
+        let code = "tailrec even n =\n  let m = n - 1\n  if n == 0 then true else odd m\ntailrec odd n = if n == 0 then false else { even (n - 1) }\n";
+        assert!(check(code).is_empty());
+        assert!(check("tailrec all n = n == 0 || n > 0 && all (n - 1)\n").is_empty());
+        assert!(check(
+            "tailrec f n =\n  if n == 0 then { return 0 } else ()\n  return f (n - 1)\n"
+        )
+        .is_empty());
+        // Only the recursive calls must be tail calls
+        let code = "inc n = n + 1\ntailrec f n = if n == 0 then inc n + inc 1 else f (n - 1)\n";
+        assert!(check(code).is_empty());
+    }
+
+    #[test]
+    fn calls_not_in_tail_position() {
+        let code = "tailrec sum n = if n == 0 then 0 else n + sum (n - 1)\n";
+        assert_eq!(check(code), ["sum (n - 1)"]);
+        // Items called by a `tailrec` item and calling it back are recursive with it
+        let code = "tailrec even n = if n == 0 then true else !(odd (n - 1))\nodd n = if n == 0 then false else even (n - 1)\n";
+        assert_eq!(check(code), ["(odd (n - 1))"]);
+        // Whichever of them makes the call
+        let code = "tailrec even n = if n == 0 then true else odd (n - 1)\nodd n = if n == 0 then false else !(even (n - 1))\n";
+        let (code, found) = errors(code);
+        match &found[..] {
+            [TailCallErr {
+                span,
+                kind:
+                    TailCallErrKind::NotInTailPosition {
+                        callee,
+                        caller,
+                        tailrec,
+                        ..
+                    },
+            }] => {
+                assert_eq!(&code[span.range.clone()], "(even (n - 1))");
+                assert_eq!(
+                    (&callee[..], &caller[..], &tailrec[..]),
+                    ("even", "odd", "even")
+                );
+            }
+            errors => panic!("expected a call of `even` by `odd`, got {:?}", errors),
+        }
+        // Items outside of the group can call it anywhere
+        assert!(check("tailrec f n = if n == 0 then 0 else f (n - 1)\ng n = 1 + f n\n").is_empty());
+        // Owned values are dropped after the call, and lambdas make calls of their own
+        let code = "tailrec f n =\n  let file = File n\n  if n == 0 then 0 else f (n - 1)\n";
+        assert_eq!(check(code), ["f (n - 1)"]);
+        let (code, errors) = errors("tailrec f n = if n == 0 then 0 else (\\x -> f x) (n - 1)\n");
+        match &errors[..] {
+            [TailCallErr {
+                span,
+                kind: TailCallErrKind::NotInTailPosition { lambda: true, .. },
+            }] => assert_eq!(&code[span.range.clone()], "f x"),
+            errors => panic!("expected a call in a lambda, got {:?}", errors),
+        }
+    }
+
+    #[test]
+    fn borrows_keep_calls_from_taking_the_frame() {
+        let (code, errors) = errors("tailrec f n = if get &n == 0 then 0 else f (n - 1)\n");
+        match &errors[..] {
+            [TailCallErr {
+                span,
+                kind: TailCallErrKind::Borrowed { borrow, .. },
+            }] => {
+                assert_eq!(&code[span.range.clone()], "f (n - 1)");
+                assert_eq!(&code[borrow.range.clone()], "&n");
+            }
+            errors => panic!("expected a borrow, got {:?}", errors),
+        }
+    }
+}
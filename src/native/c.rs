@@ -0,0 +1,772 @@
+//! Translates the bodies of the MIR to C, for compilers Cranelift doesn't target. Values are
+//! words like in the runtime: data values are pointers to a tagged union per data type, with a
+//! struct of fields per constructor, and functions are the runtime's `Fun`s. Each body is a
+//! function taking its parameters then its captures, whose basic blocks are labels, and an
+//! entry for `kamai_apply`, as in `codegen`.
+//!
+//! Tail calls of the item a body was lowered from jump back to its start with the new arguments.
+//! The items calling each other in tail position are translated to a single function, starting
+//! at the item it's called for and jumping between them, since not every C compiler guarantees
+//! tail calls. The other tail calls of items are `return`s, which the compilers supporting
+//! `musttail` guarantee when the functions take as many arguments, and can only chain as many
+//! items as the program has. Tail calls through function values are left to the runtime, which
+//! makes them once the body returns: the calls of bodies are wrapped in `kamai_settle`.
+//!
+//! `#line` directives map the statements back to the Kamai code they were lowered from, for
+//! the errors of the C compiler and debuggers.
+use std::{cell::Cell, fmt::Write, mem};
+
+use crate::{
+    errors::runtime_err::RuntimeErrKind,
+    hir::{BinOp, DataId, DefId, Dict, Literal, Program, UnOp},
+    mir::*,
+    source::{FileId, SourceDb, Span},
+    typeck::components,
+};
+
+pub struct Emitter<'m, 'p, 'a> {
+    db: &'m SourceDb,
+    program: &'p Program<'a>,
+    mir: &'m Mir<'a>,
+    // The C function of each body
+    functions: Vec<String>,
+    // Whether the entry of each body is used by a function value
+    entries: Vec<Cell<bool>>,
+    code: String,
+    // The lines written to `code`
+    lines: usize,
+    // The file of the last `#line`, and the line of the Kamai code minus the line of the C
+    // code it maps
+    mapped: Option<(FileId, isize)>,
+    // The items calling each other in tail position, translated to a single function, and the
+    // index of the group of each body
+    groups: Vec<Vec<usize>>,
+    group: Vec<Option<usize>>,
+    // The body being translated, and the prefix of its labels
+    current: usize,
+    labels: String,
+}
+impl<'m, 'p, 'a> Emitter<'m, 'p, 'a> {
+    pub fn new(db: &'m SourceDb, program: &'p Program<'a>, mir: &'m Mir<'a>) -> Self {
+        let functions = mir.bodies.iter().enumerate();
+        let functions = functions.map(|(idx, body)| match body.name {
+            "\\" => format!("lambda_{}", idx),
+            name => format!("{}_{}", identifier(name), idx),
+        });
+        // The tail calls between items, lambdas never being called back by them
+        let calls: Vec<Vec<_>> = (mir.bodies.iter().take(program.items.len()))
+            .map(|body| {
+                let calls = body
+                    .blocks
+                    .iter()
+                    .filter_map(|block| tail_call(mir, &block.terminator.kind));
+                calls.map(|id| DefId(id.0)).collect()
+            })
+            .collect();
+        let groups: Vec<Vec<_>> = (components(&calls).into_iter())
+            .filter(|group| group.len() > 1)
+            .map(|group| group.into_iter().map(|id| id.0).collect())
+            .collect();
+        let mut group = vec![None; mir.bodies.len()];
+        for (idx, members) in groups.iter().enumerate() {
+            for member in members {
+                group[*member] = Some(idx);
+            }
+        }
+        Self {
+            db,
+            program,
+            mir,
+            functions: functions.collect(),
+            entries: mir.bodies.iter().map(|_| Cell::new(false)).collect(),
+            code: String::new(),
+            lines: 0,
+            mapped: None,
+            groups,
+            group,
+            current: 0,
+            labels: String::new(),
+        }
+    }
+
+    /// The C source of every body, and `kamai_main` evaluating `main`. The bodies come last,
+    /// their `#line`s mapping every line that follows them
+    pub fn finish(mut self, main: DefId) -> String {
+        for idx in 0..self.mir.bodies.len() {
+            match self.group[idx] {
+                None => {
+                    self.line(String::new());
+                    self.body(idx);
+                }
+                Some(group) if self.groups[group][0] == idx => {
+                    self.line(String::new());
+                    self.group(group);
+                }
+                Some(_) => (),
+            }
+        }
+        let bodies = mem::take(&mut self.code);
+        let methods = self.methods();
+        self.datas();
+        let entries: Vec<_> = (0..self.mir.bodies.len())
+            .filter(|idx| self.entries[*idx].get())
+            .collect();
+        for (idx, body) in self.mir.bodies.iter().enumerate() {
+            let params = vec!["int64_t"; body.params + body.captures.len()].join(", ");
+            let params = if params.is_empty() {
+                "void".to_string()
+            } else {
+                params
+            };
+            self.line(format!(
+                "static int64_t {}({});",
+                self.functions[idx], params
+            ));
+        }
+        for idx in &entries {
+            self.line(format!(
+                "static int64_t {}_entry(int64_t *fun, int64_t *args);",
+                self.functions[*idx]
+            ));
+        }
+        self.line(String::new());
+        self.line(methods);
+        for idx in entries {
+            self.entry(idx);
+        }
+        self.line(format!(
+            "int64_t kamai_main(void) {{\n    return kamai_settle({}());\n}}",
+            self.functions[main.0]
+        ));
+        self.code + &bodies
+    }
+
+    // Appends lines of code
+    fn line(&mut self, code: String) {
+        self.lines += code.matches('\n').count() + 1;
+        self.code.push_str(&code);
+        self.code.push('\n');
+    }
+    // Maps the next line to the line of `span`, unless it already is
+    fn map(&mut self, span: &Span) {
+        let line = self.db.line(span) as isize;
+        if self.mapped != Some((span.file, line - self.lines as isize)) {
+            let directive = format!("#line {} {}", line, string(self.db.name(span.file)));
+            self.line(directive);
+            self.mapped = Some((span.file, line - self.lines as isize));
+        }
+    }
+
+    // A tagged union per data type, the fields of each constructor being a struct
+    fn datas(&mut self) {
+        self.line("// Data values".to_string());
+        for data in &self.program.datas {
+            let mut code = format!("struct {} {{\n    int64_t tag;\n", data.name.value);
+            if data.ctors.iter().any(|ctor| !ctor.fields.is_empty()) {
+                code.push_str("    union {\n");
+                for ctor in data.ctors.iter().filter(|ctor| !ctor.fields.is_empty()) {
+                    let fields: Vec<_> = (0..ctor.fields.len())
+                        .map(|i| format!("int64_t _{};", i))
+                        .collect();
+                    let _ = writeln!(
+                        code,
+                        "        struct {{ {} }} {};",
+                        fields.join(" "),
+                        ctor.name.value
+                    );
+                }
+                code.push_str("    };\n");
+            }
+            self.line(code + "};");
+        }
+        self.line(String::new());
+    }
+    // `kamai_method(dict, idx)`, the method of an instance by index applied to the dictionaries
+    // of its context: its value for methods taking nothing else, a function otherwise. The
+    // dictionary is the instance's index, or a tuple of it and of the dictionaries of its
+    // context, as in `codegen`
+    fn methods(&self) -> String {
+        let count = self.program.instances.len();
+        let mut code = format!(
+            "int64_t kamai_method(int64_t dict, int64_t idx) {{
+    int64_t instance = dict, *context = 0;
+    if ((uint64_t)dict >= {}) {{
+        instance = ((struct kamai_tuple *)(intptr_t)dict)->fields[0];
+        context = ((struct kamai_tuple *)(intptr_t)dict)->fields + 1;
+    }}
+    (void)context;
+    switch (instance) {{
+",
+            count
+        );
+        for (instance, methods) in self.program.instances.iter().enumerate() {
+            let _ = writeln!(code, "    case {}:\n        switch (idx) {{", instance);
+            let context = methods.context.len();
+            for (idx, method) in methods.methods.iter().enumerate() {
+                let args: Vec<_> = (0..context).map(|i| format!("context[{}]", i)).collect();
+                let value = match self.mir.bodies[method.0].params == context {
+                    true => format!(
+                        "kamai_settle({}({}))",
+                        self.functions[method.0],
+                        args.join(", ")
+                    ),
+                    false if context == 0 => self.function(BodyId(method.0), &[]),
+                    false => format!(
+                        "kamai_apply({}, {}, context)",
+                        self.function(BodyId(method.0), &[]),
+                        context
+                    ),
+                };
+                let _ = writeln!(code, "        case {}: return {};", idx, value);
+            }
+            code.push_str("        }\n        break;\n");
+        }
+        code.push_str("    }\n");
+        if self.program.instances.is_empty() {
+            code.push_str("    (void)idx;\n");
+        }
+        code + "    abort();\n}"
+    }
+    // Reads the captures from the function value and the arguments from their array
+    fn entry(&mut self, idx: usize) {
+        let body = &self.mir.bodies[idx];
+        let mut values: Vec<_> = (0..body.params).map(|i| format!("args[{}]", i)).collect();
+        values.extend((0..body.captures.len()).map(|i| format!("((Fun *)fun)->values[{}]", i)));
+        let mut code = format!(
+            "static int64_t {}_entry(int64_t *fun, int64_t *args) {{\n",
+            self.functions[idx]
+        );
+        if body.captures.is_empty() {
+            code.push_str("    (void)fun;\n");
+        }
+        if body.params == 0 {
+            code.push_str("    (void)args;\n");
+        }
+        self.line(format!(
+            "{}    return {}({});\n}}",
+            code,
+            self.functions[idx],
+            values.join(", ")
+        ));
+    }
+
+    fn body(&mut self, idx: usize) {
+        let body = &self.mir.bodies[idx];
+        let params: Vec<_> = body.params().chain(body.captures.iter().copied()).collect();
+        self.line(format!(
+            "// {} at {}",
+            body.name,
+            self.db.location(&body.span)
+        ));
+        self.line(format!(
+            "static int64_t {}({}) {{",
+            self.functions[idx],
+            signature(&params)
+        ));
+        self.blocks(idx, params);
+        self.line("}".to_string());
+    }
+    // The items of a group are blocks of a function taking the index of the one to start at and
+    // its arguments, which their tail calls of each other jump to after pointing `args` at the
+    // new ones. Each item's function calls it
+    fn group(&mut self, group: usize) {
+        let members = self.groups[group].clone();
+        let names: Vec<_> = members
+            .iter()
+            .map(|idx| self.mir.bodies[*idx].name)
+            .collect();
+        let size = members
+            .iter()
+            .map(|idx| self.mir.bodies[*idx].params)
+            .max()
+            .unwrap_or(0);
+        self.line(format!(
+            "// {} calling each other in tail position",
+            names.join(", ")
+        ));
+        self.line(format!(
+            "static int64_t group_{}(int64_t body, int64_t *args) {{",
+            group
+        ));
+        self.line(format!("    int64_t next[{}];", size.max(1)));
+        let mut code = "    switch (body) {\n".to_string();
+        for (idx, member) in members.iter().enumerate() {
+            let _ = writeln!(
+                code,
+                "    case {}: goto {}_start;",
+                idx, self.functions[*member]
+            );
+        }
+        self.line(code + "    default: abort();\n    }");
+        for member in &members {
+            let body = &self.mir.bodies[*member];
+            self.line(format!(
+                "// {} at {}",
+                body.name,
+                self.db.location(&body.span)
+            ));
+            self.line(format!("{}_start: {{", self.functions[*member]));
+            let params: Vec<_> = body.params().collect();
+            for (i, param) in params.iter().enumerate() {
+                self.line(format!("    int64_t {} = args[{}];", param, i));
+            }
+            self.labels = format!("{}_", self.functions[*member]);
+            self.blocks(*member, params);
+            self.labels.clear();
+            self.line("}".to_string());
+        }
+        self.line("}".to_string());
+        for (idx, member) in members.into_iter().enumerate() {
+            let params: Vec<_> = self.mir.bodies[member].params().collect();
+            let args = match params.is_empty() {
+                true => "0".to_string(),
+                false => format!(
+                    "(int64_t[]){{{}}}",
+                    params
+                        .iter()
+                        .map(Local::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            };
+            self.line(format!(
+                "static int64_t {}({}) {{\n    return group_{}({}, {});\n}}",
+                self.functions[member],
+                signature(&params),
+                group,
+                idx,
+                args
+            ));
+        }
+    }
+    // The locals of a body other than its parameters, then its blocks
+    fn blocks(&mut self, idx: usize, mut params: Vec<Local>) {
+        let body = &self.mir.bodies[idx];
+        self.current = idx;
+        params.sort();
+        for (local, decl) in body.locals.iter().enumerate() {
+            if params.binary_search(&Local(local)).is_ok() {
+                continue;
+            }
+            match decl.name {
+                Some(name) => self.line(format!("    int64_t _{} = 0; // {}", local, name)),
+                None => self.line(format!("    int64_t _{} = 0;", local)),
+            }
+        }
+        let mut targets = targets(body);
+        let recursive = |block: &BasicBlock<'a>| {
+            tail_call(self.mir, &block.terminator.kind) == Some(BodyId(idx))
+        };
+        targets[0] |= body.blocks.iter().any(recursive);
+        for (idx, block) in body.blocks.iter().enumerate() {
+            if targets[idx] {
+                self.line(format!("{}:", self.label(idx)));
+            }
+            for statement in &block.statements {
+                if let StatementKind::Assign(place, rvalue) = &statement.kind {
+                    self.map(&statement.span);
+                    self.assign(place, rvalue, &statement.span);
+                }
+            }
+            self.map(&block.terminator.span);
+            self.terminator(&block.terminator);
+        }
+    }
+    fn label(&self, block: usize) -> String {
+        format!("{}bb{}", self.labels, block)
+    }
+
+    fn terminator(&mut self, Terminator { kind, span }: &Terminator<'a>) {
+        match kind {
+            TerminatorKind::Goto(target) => {
+                self.line(format!("    goto {};", self.label(target.0)))
+            }
+            TerminatorKind::If { cond, then, else_ } => self.line(format!(
+                "    if ({}) goto {}; else goto {};",
+                self.operand(cond),
+                self.label(then.0),
+                self.label(else_.0)
+            )),
+            TerminatorKind::Switch {
+                place,
+                targets,
+                otherwise,
+            } => {
+                let value = match targets.first() {
+                    Some((Test::Ctor(_), _)) => format!("kamai_tag({})", self.place(place)),
+                    _ => self.place(place),
+                };
+                let mut code = format!("    switch ({}) {{\n", value);
+                for (test, target) in targets {
+                    let test = match test {
+                        Test::Literal(l) => literal(l),
+                        Test::Ctor(idx) => idx.to_string(),
+                        Test::Dict(id) => id.0.to_string(),
+                    };
+                    let _ = writeln!(code, "    case {}: goto {};", test, self.label(target.0));
+                }
+                self.line(format!(
+                    "{}    default: goto {};\n    }}",
+                    code,
+                    self.label(otherwise.0)
+                ));
+            }
+            TerminatorKind::Call {
+                func,
+                args,
+                dest,
+                target,
+                tail,
+            } => {
+                if let Some(id) = tail_call(self.mir, kind) {
+                    let args: Vec<_> = args.iter().map(|arg| self.operand(arg)).collect();
+                    let body = &self.mir.bodies[self.current];
+                    let group = self.group[self.current];
+                    // The arguments are all read before the parameters are assigned
+                    if id.0 == self.current && args.is_empty() {
+                        return self.line(format!("    goto {};", self.label(0)));
+                    } else if id.0 == self.current {
+                        let mut code = format!(
+                            "    {{\n        int64_t next[] = {{{}}};\n",
+                            args.join(", ")
+                        );
+                        for (i, param) in body.params().enumerate() {
+                            let _ = writeln!(code, "        {} = next[{}];", param, i);
+                        }
+                        return self.line(format!("{}    }}\n    goto {};", code, self.label(0)));
+                    } else if group.is_some() && self.group[id.0] == group {
+                        let mut code = String::new();
+                        for (i, arg) in args.iter().enumerate() {
+                            let _ = writeln!(code, "    next[{}] = {};", i, arg);
+                        }
+                        return self.line(format!(
+                            "{}    args = next;\n    goto {}_start;",
+                            code, self.functions[id.0]
+                        ));
+                    }
+                    let call = format!("return {}({});", self.functions[id.0], args.join(", "));
+                    // The function of a group takes other parameters
+                    return match group.is_none() && body.params + body.captures.len() == args.len()
+                    {
+                        true => self.line(format!("    KAMAI_TAIL {}", call)),
+                        false => self.line(format!("    {}", call)),
+                    };
+                }
+                if let (Operand::Copy(_) | Operand::Move(_), true) = (func, tail) {
+                    let args: Vec<_> = args.iter().map(|arg| self.operand(arg)).collect();
+                    let call = apply("kamai_tail", self.operand(func), &args);
+                    return self.line(format!("    return {};", call));
+                }
+                self.call(func, args, Some(dest), span);
+                self.line(format!("    goto {};", self.label(target.0)));
+            }
+            TerminatorKind::Drop {
+                place,
+                glue,
+                target,
+            } => {
+                self.call(glue, &[Operand::Copy(place.clone())], None, span);
+                self.line(format!("    goto {};", self.label(target.0)));
+            }
+            TerminatorKind::Return => self.line(format!("    return {};", RETURN)),
+            TerminatorKind::Unreachable => self.line("    abort();".to_string()),
+        }
+    }
+    // Calls a function, directly when it's an item given all of its arguments, its value going
+    // to `dest` when it's used
+    fn call(
+        &mut self,
+        func: &Operand<'a>,
+        args: &[Operand<'a>],
+        dest: Option<&Place>,
+        span: &Span,
+    ) {
+        self.line(format!(
+            "    kamai_enter({});",
+            self.message(RuntimeErrKind::StackOverflow, span)
+        ));
+        let args: Vec<_> = args.iter().map(|arg| self.operand(arg)).collect();
+        let value = match func {
+            Operand::Constant(Constant::Item(id, _))
+                if args.len() >= self.mir.bodies[id.0].params =>
+            {
+                let params = self.mir.bodies[id.0].params;
+                let value = format!(
+                    "kamai_settle({}({}))",
+                    self.functions[id.0],
+                    args[..params].join(", ")
+                );
+                match params == args.len() {
+                    true => value,
+                    false => apply("kamai_apply", value, &args[params..]),
+                }
+            }
+            Operand::Constant(Constant::Ctor(data, idx, _))
+                if args.len() == self.program.datas[data.0].ctors[*idx].fields.len() =>
+            {
+                let dest = dest.expect("Constructors are called for their value");
+                let code = self.alloc(*data, *idx, &args, dest);
+                self.line(code);
+                return self.line("    kamai_leave();".to_string());
+            }
+            func => apply("kamai_apply", self.operand(func), &args),
+        };
+        match dest {
+            Some(dest) => self.line(format!("    {} = {};", self.place(dest), value)),
+            None => self.line(format!("    {};", value)),
+        }
+        self.line("    kamai_leave();".to_string());
+    }
+
+    fn assign(&mut self, place: &Place, rvalue: &Rvalue<'a>, span: &Span) {
+        let value = match rvalue {
+            Rvalue::Use(operand) => self.operand(operand),
+            Rvalue::Ref(_, place) => format!("(int64_t)(intptr_t)&{}", self.place(place)),
+            Rvalue::Unary(UnOp::Neg, operand) => format!(
+                "kamai_neg({}, {})",
+                self.operand(operand),
+                self.message(RuntimeErrKind::Overflow(String::new()), span)
+            ),
+            Rvalue::Unary(UnOp::Not, operand) => format!("!{}", self.operand(operand)),
+            Rvalue::Unary(op, _) => unreachable!("`{:?}` isn't lowered to an operator", op),
+            Rvalue::Binary(op, l, r) => self.binary(*op, self.operand(l), self.operand(r), span),
+            Rvalue::Tuple(operands) => {
+                let fields: Vec<_> = operands.iter().map(|o| self.operand(o)).collect();
+                let mut code = format!(
+                    "    {{\n        struct kamai_tuple *o = (struct kamai_tuple *)kamai_alloc({});\n        o->tag = 0;\n",
+                    fields.len() + 1
+                );
+                for (i, field) in fields.iter().enumerate() {
+                    let _ = writeln!(code, "        o->fields[{}] = {};", i, field);
+                }
+                let _ = write!(
+                    code,
+                    "        {} = (int64_t)(intptr_t)o;\n    }}",
+                    self.place(place)
+                );
+                return self.line(code);
+            }
+            Rvalue::Ctor(data, idx, _, operands) => {
+                let fields: Vec<_> = operands.iter().map(|o| self.operand(o)).collect();
+                let code = self.alloc(*data, *idx, &fields, place);
+                return self.line(code);
+            }
+            Rvalue::Closure(body, captures) => {
+                let captures: Vec<_> = captures.iter().map(|o| self.operand(o)).collect();
+                self.function(*body, &captures)
+            }
+            Rvalue::Method(dict, idx) => format!("kamai_method({}, {})", self.operand(dict), idx),
+        };
+        self.line(format!("    {} = {};", self.place(place), value));
+    }
+    fn binary(&self, op: BinOp, l: String, r: String, span: &Span) -> String {
+        let overflow = self.message(RuntimeErrKind::Overflow(String::new()), span);
+        match op {
+            BinOp::Add => format!("kamai_add({}, {}, {})", l, r, overflow),
+            BinOp::Sub => format!("kamai_sub({}, {}, {})", l, r, overflow),
+            BinOp::Mul => format!("kamai_mul({}, {}, {})", l, r, overflow),
+            BinOp::Div => {
+                let zero = self.message(RuntimeErrKind::DivisionByZero, span);
+                format!("kamai_div({}, {}, {}, {})", l, r, zero, overflow)
+            }
+            BinOp::And | BinOp::Or => unreachable!("`&&` and `||` are branches"),
+            op => format!("{} {} {}", l, op, r),
+        }
+    }
+    // A data value made by a constructor given all of its fields, written to `dest`
+    fn alloc(&self, data: DataId, idx: usize, fields: &[String], dest: &Place) -> String {
+        let data = &self.program.datas[data.0];
+        let (name, ctor) = (data.name.value, data.ctors[idx].name.value);
+        let mut code = format!(
+            "    {{\n        struct {} *o = (struct {} *)kamai_alloc({});\n        o->tag = {};\n",
+            name,
+            name,
+            fields.len() + 1,
+            idx
+        );
+        for (i, field) in fields.iter().enumerate() {
+            let _ = writeln!(code, "        o->{}._{} = {};", ctor, i, field);
+        }
+        let _ = write!(
+            code,
+            "        {} = (int64_t)(intptr_t)o;\n    }}",
+            self.place(dest)
+        );
+        code
+    }
+    // A C string holding the message of a runtime error, to give to `kamai_panic`
+    fn message(&self, kind: RuntimeErrKind, span: &Span) -> String {
+        string(&format!("{} at {}", kind.message(), self.db.location(span)))
+    }
+
+    fn operand(&self, operand: &Operand<'a>) -> String {
+        match operand {
+            Operand::Copy(place) | Operand::Move(place) => self.place(place),
+            Operand::Constant(Constant::Literal(l)) => literal(l),
+            Operand::Constant(Constant::Item(id, _)) => self.function(BodyId(id.0), &[]),
+            Operand::Constant(Constant::Ctor(data, idx, _)) => {
+                let arity = self.program.datas[data.0].ctors[*idx].fields.len();
+                format!(
+                    "kamai_fun(kamai_ctor, NULL, {}, 1, (int64_t[]){{{}}})",
+                    arity, idx
+                )
+            }
+            Operand::Constant(Constant::Dict(Dict::Instance(id, _))) => id.0.to_string(),
+            Operand::Constant(Constant::Dict(_)) => {
+                unreachable!("Dictionary parameters are locals")
+            }
+        }
+    }
+    // A function value calling a body
+    fn function(&self, body: BodyId, captures: &[String]) -> String {
+        self.entries[body.0].set(true);
+        let values = match captures {
+            [] => "NULL".to_string(),
+            captures => format!("(int64_t[]){{{}}}", captures.join(", ")),
+        };
+        format!(
+            "kamai_fun({}_entry, NULL, {}, {}, {})",
+            self.functions[body.0],
+            self.mir.bodies[body.0].params,
+            captures.len(),
+            values
+        )
+    }
+    // Fields are those of the constructor a data value was downcast to, or of a tuple
+    fn place(&self, place: &Place) -> String {
+        let (mut code, mut downcast) = (place.local.to_string(), None);
+        for projection in &place.projection {
+            code = match projection {
+                Projection::Deref => format!("*(int64_t *)(intptr_t){}", atom(code)),
+                Projection::Downcast(data, idx) => {
+                    downcast = Some((&self.program.datas[data.0], *idx));
+                    continue;
+                }
+                Projection::Field(field) => match downcast.take() {
+                    Some((data, idx)) => format!(
+                        "((struct {} *)(intptr_t){})->{}._{}",
+                        data.name.value,
+                        atom(code),
+                        data.ctors[idx].name.value,
+                        field
+                    ),
+                    None => format!(
+                        "((struct kamai_tuple *)(intptr_t){})->fields[{}]",
+                        atom(code),
+                        field
+                    ),
+                },
+            };
+        }
+        code
+    }
+}
+
+// The item a tail call calls, when it's given all of its arguments, which is called without
+// being counted in the depth of calls
+fn tail_call(mir: &Mir, kind: &TerminatorKind) -> Option<BodyId> {
+    match kind {
+        TerminatorKind::Call {
+            func: Operand::Constant(Constant::Item(id, _)),
+            args,
+            tail: true,
+            ..
+        } if args.len() == mir.bodies[id.0].params => Some(BodyId(id.0)),
+        _ => None,
+    }
+}
+
+// The parameters of a C function taking `params`
+fn signature(params: &[Local]) -> String {
+    match params.is_empty() {
+        true => "void".to_string(),
+        false => params
+            .iter()
+            .map(|local| format!("int64_t {}", local))
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+// Whether each block of a body is jumped to, and needs a label
+fn targets(body: &Body) -> Vec<bool> {
+    let mut targets = vec![false; body.blocks.len()];
+    for block in &body.blocks {
+        match &block.terminator.kind {
+            TerminatorKind::Goto(target)
+            | TerminatorKind::Call { target, .. }
+            | TerminatorKind::Drop { target, .. } => targets[target.0] = true,
+            TerminatorKind::If { then, else_, .. } => {
+                targets[then.0] = true;
+                targets[else_.0] = true;
+            }
+            TerminatorKind::Switch {
+                targets: tests,
+                otherwise,
+                ..
+            } => {
+                for (_, target) in tests {
+                    targets[target.0] = true;
+                }
+                targets[otherwise.0] = true;
+            }
+            TerminatorKind::Return | TerminatorKind::Unreachable => (),
+        }
+    }
+    targets
+}
+
+// Applies a function value to arguments through the runtime, with `kamai_apply`, or with
+// `kamai_tail` leaving the call to whoever called the body
+fn apply(runtime: &str, func: String, args: &[String]) -> String {
+    format!(
+        "{}({}, {}, (int64_t[]){{{}}})",
+        runtime,
+        func,
+        args.len(),
+        args.join(", ")
+    )
+}
+
+fn literal(l: &Literal) -> String {
+    match l {
+        // Its negation overflows
+        Literal::Num(i64::MIN) => "INT64_MIN".to_string(),
+        Literal::Num(n) => n.to_string(),
+        Literal::Bool(b) => (*b as i64).to_string(),
+        Literal::Unit => "0".to_string(),
+    }
+}
+
+// An expression which can be operated on without parentheses
+fn atom(code: String) -> String {
+    match code.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        true => code,
+        false => format!("({})", code),
+    }
+}
+
+// A C identifier made of the ASCII letters and digits of a Kamai one
+fn identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+// A C string literal
+fn string(s: &str) -> String {
+    let mut literal = "\"".to_string();
+    for byte in s.bytes() {
+        match byte {
+            b'"' | b'\\' => {
+                literal.push('\\');
+                literal.push(byte as char);
+            }
+            b' '..=b'~' => literal.push(byte as char),
+            _ => {
+                let _ = write!(literal, "\\{:03o}", byte);
+            }
+        }
+    }
+    literal + "\""
+}
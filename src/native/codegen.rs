@@ -0,0 +1,811 @@
+//! Translates the bodies of the MIR to Cranelift functions. Each body is a function taking its
+//! parameters then its captures, and an entry taking the function value it's called through
+//! and an array of its arguments, for the runtime's `kamai_apply`. Calls of items given all of
+//! their arguments are direct, the others go through `kamai_apply`. Bodies use the tail calling
+//! convention, so that their tail calls of items given all of their arguments reuse their frame.
+//! So do tail calls through function values given the rest of their arguments, through the
+//! tail entries of their body: one per number of arguments the function was already given,
+//! taking the function value and the rest of them.
+//!
+//! Locals are Cranelift variables, but the ones that are borrowed, which live in stack slots
+//! so that references can point to them.
+use std::collections::HashMap;
+
+use cranelift_codegen::{
+    entity::EntityRef,
+    ir::{
+        condcodes::IntCC, types::I64, AbiParam, Block, InstBuilder, MemFlags, Signature, StackSlot,
+        StackSlotData, StackSlotKind, TrapCode, Value as Word,
+    },
+    isa::CallConv,
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Switch, Variable};
+use cranelift_module::{default_libcall_names, DataDescription, DataId, FuncId, Linkage, Module};
+use cranelift_object::{ObjectBuilder, ObjectModule};
+
+use crate::{
+    errors::runtime_err::RuntimeErrKind,
+    hir::{BinOp, DefId, Dict, Literal, Program, UnOp},
+    mir::*,
+    source::Span,
+};
+
+// The functions of the runtime the generated code calls
+struct Runtime {
+    alloc: FuncId,
+    fun: FuncId,
+    apply: FuncId,
+    ctor: FuncId,
+    panic: FuncId,
+    enter: FuncId,
+    leave: FuncId,
+}
+
+pub struct Codegen<'m, 'p, 'a> {
+    program: &'p Program<'a>,
+    mir: &'m Mir<'a>,
+    // Where a span is in the source, for runtime errors
+    locate: &'m dyn Fn(&Span) -> String,
+    module: ObjectModule,
+    runtime: Runtime,
+    // The direct function and the entry of each body
+    bodies: Vec<(FuncId, FuncId)>,
+    // The tail entries of each body, by how many arguments they were given, and their table
+    tails: Vec<(Vec<FuncId>, DataId)>,
+    method: FuncId,
+    // The runtime errors, by message
+    messages: HashMap<String, DataId>,
+}
+impl<'m, 'p, 'a> Codegen<'m, 'p, 'a> {
+    pub fn new(
+        program: &'p Program<'a>,
+        mir: &'m Mir<'a>,
+        locate: &'m dyn Fn(&Span) -> String,
+    ) -> Result<Self, String> {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").map_err(|e| e.to_string())?;
+        flags.set("is_pic", "true").map_err(|e| e.to_string())?;
+        // Which tail calls rely on
+        flags
+            .set("preserve_frame_pointers", "true")
+            .map_err(|e| e.to_string())?;
+        let isa = cranelift_native::builder()?
+            .finish(settings::Flags::new(flags))
+            .map_err(|e| e.to_string())?;
+        let builder =
+            ObjectBuilder::new(isa, "kamai", default_libcall_names()).map_err(|e| e.to_string())?;
+        let mut module = ObjectModule::new(builder);
+        let call_conv = module.isa().default_call_conv();
+        let mut import = |name: &str, params: usize, returns: bool| {
+            let sig = signature(call_conv, params, returns);
+            module
+                .declare_function(name, Linkage::Import, &sig)
+                .map_err(|e| e.to_string())
+        };
+        let runtime = Runtime {
+            alloc: import("kamai_alloc", 1, true)?,
+            fun: import("kamai_fun", 5, true)?,
+            apply: import("kamai_apply", 3, true)?,
+            ctor: import("kamai_ctor", 2, true)?,
+            panic: import("kamai_panic", 1, false)?,
+            enter: import("kamai_enter", 1, false)?,
+            leave: import("kamai_leave", 0, false)?,
+        };
+        let (mut bodies, mut tails) = (vec![], vec![]);
+        for (idx, body) in mir.bodies.iter().enumerate() {
+            let direct = signature(CallConv::Tail, body.params + body.captures.len(), true);
+            let direct =
+                module.declare_function(&format!("kamai_body{}", idx), Linkage::Local, &direct);
+            let entry = signature(call_conv, 2, true);
+            let entry =
+                module.declare_function(&format!("kamai_entry{}", idx), Linkage::Local, &entry);
+            bodies.push((
+                direct.map_err(|e| e.to_string())?,
+                entry.map_err(|e| e.to_string())?,
+            ));
+            let mut entries = vec![];
+            for given in 0..body.params {
+                let sig = signature(CallConv::Tail, 1 + body.params - given, true);
+                let name = format!("kamai_tail{}_{}", idx, given);
+                let entry = module.declare_function(&name, Linkage::Local, &sig);
+                entries.push(entry.map_err(|e| e.to_string())?);
+            }
+            let table =
+                module.declare_data(&format!("kamai_tails{}", idx), Linkage::Local, false, false);
+            tails.push((entries, table.map_err(|e| e.to_string())?));
+        }
+        let method = module
+            .declare_function(
+                "kamai_method",
+                Linkage::Local,
+                &signature(call_conv, 2, true),
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            program,
+            mir,
+            locate,
+            module,
+            runtime,
+            bodies,
+            tails,
+            method,
+            messages: HashMap::new(),
+        })
+    }
+
+    /// Compiles every body, and `kamai_main` evaluating `main`, to an object file
+    pub fn finish(mut self, main: DefId) -> Result<Vec<u8>, String> {
+        for idx in 0..self.mir.bodies.len() {
+            self.body(idx)?;
+            self.entry(idx)?;
+            self.tails(idx)?;
+        }
+        self.methods()?;
+        let sig = signature(self.call_conv(), 0, true);
+        let id = self
+            .module
+            .declare_function("kamai_main", Linkage::Export, &sig)
+            .map_err(|e| e.to_string())?;
+        let callee = self.bodies[main.0].0;
+        self.define(id, sig, |t, _| {
+            let f = t.cg.module.declare_func_in_func(callee, t.b.func);
+            let call = t.b.ins().call(f, &[]);
+            let value = t.b.inst_results(call)[0];
+            t.b.ins().return_(&[value]);
+        })?;
+        self.module.finish().emit().map_err(|e| e.to_string())
+    }
+
+    fn call_conv(&self) -> CallConv {
+        self.module.isa().default_call_conv()
+    }
+    // Defines a function whose code is generated by `f`, given the parameters of its entry
+    // block
+    fn define(
+        &mut self,
+        id: FuncId,
+        sig: Signature,
+        f: impl FnOnce(&mut Translator<'_, '_, 'm, 'p, 'a>, Vec<Word>),
+    ) -> Result<(), String> {
+        let mut ctx = self.module.make_context();
+        ctx.func.signature = sig;
+        let mut fctx = FunctionBuilderContext::new();
+        let mut b = FunctionBuilder::new(&mut ctx.func, &mut fctx);
+        let start = b.create_block();
+        b.append_block_params_for_function_params(start);
+        b.switch_to_block(start);
+        let params = b.block_params(start).to_vec();
+        let mut translator = Translator {
+            cg: self,
+            b,
+            locals: vec![],
+            blocks: vec![],
+        };
+        f(&mut translator, params);
+        translator.b.seal_all_blocks();
+        translator.b.finalize();
+        self.module
+            .define_function(id, &mut ctx)
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    fn body(&mut self, idx: usize) -> Result<(), String> {
+        let body = &self.mir.bodies[idx];
+        let sig = signature(CallConv::Tail, body.params + body.captures.len(), true);
+        self.define(self.bodies[idx].0, sig, |t, params| t.body(body, params))
+    }
+    // Reads the captures from the function value and the arguments from their array
+    fn entry(&mut self, idx: usize) -> Result<(), String> {
+        let body = &self.mir.bodies[idx];
+        let direct = self.bodies[idx].0;
+        let sig = signature(self.call_conv(), 2, true);
+        self.define(self.bodies[idx].1, sig, |t, params| {
+            let (fun, args) = (params[0], params[1]);
+            let mut values: Vec<_> = (0..body.params).map(|i| t.load(args, 8 * i)).collect();
+            values.extend((0..body.captures.len()).map(|i| t.load(fun, 8 * (5 + i))));
+            let f = t.cg.module.declare_func_in_func(direct, t.b.func);
+            let call = t.b.ins().call(f, &values);
+            let value = t.b.inst_results(call)[0];
+            t.b.ins().return_(&[value]);
+        })
+    }
+    // The tail entries of a body, reading the arguments the function value was given after its
+    // captures, and their table
+    fn tails(&mut self, idx: usize) -> Result<(), String> {
+        let body = &self.mir.bodies[idx];
+        let (direct, (entries, table)) = (self.bodies[idx].0, self.tails[idx].clone());
+        for (given, entry) in entries.iter().enumerate() {
+            let sig = signature(CallConv::Tail, 1 + body.params - given, true);
+            self.define(*entry, sig, |t, params| {
+                let fun = params[0];
+                let captures = body.captures.len();
+                let mut values: Vec<_> = (0..given)
+                    .map(|i| t.load(fun, 8 * (5 + captures + i)))
+                    .collect();
+                values.extend(&params[1..]);
+                values.extend((0..captures).map(|i| t.load(fun, 8 * (5 + i))));
+                let f = t.cg.module.declare_func_in_func(direct, t.b.func);
+                t.b.ins().return_call(f, &values);
+            })?;
+        }
+        // Not zero initialized, which would put the table where its addresses aren't written
+        let mut description = DataDescription::new();
+        description.define(vec![0; 8 * entries.len()].into_boxed_slice());
+        for (given, entry) in entries.into_iter().enumerate() {
+            let f = self.module.declare_func_in_data(entry, &mut description);
+            description.write_function_addr(8 * given as u32, f);
+        }
+        self.module
+            .define_data(table, &description)
+            .map_err(|e| e.to_string())
+    }
+    // `kamai_method(dict, idx)`, the method of an instance by index applied to the dictionaries
+    // of its context: its value for methods taking nothing else, a function otherwise. The
+    // dictionary is the instance's index, or a tuple of it and of the dictionaries of its
+    // context, whose address is above every index
+    fn methods(&mut self) -> Result<(), String> {
+        let program = self.program;
+        let stride = program
+            .instances
+            .iter()
+            .map(|i| i.methods.len())
+            .max()
+            .unwrap_or(0) as u64;
+        let sig = signature(self.call_conv(), 2, true);
+        self.define(self.method, sig, |t, params| {
+            let dict = params[0];
+            let (applied, found) = (t.b.create_block(), t.b.create_block());
+            t.b.append_block_param(found, I64);
+            let count = program.instances.len() as i64;
+            let index = t.b.ins().icmp_imm(IntCC::UnsignedLessThan, dict, count);
+            t.b.ins().brif(index, found, &[dict], applied, &[]);
+            t.b.switch_to_block(applied);
+            let instance = t.load(dict, 8);
+            t.b.ins().jump(found, &[instance]);
+            t.b.switch_to_block(found);
+            let instance = t.b.block_params(found)[0];
+            let key = t.b.ins().imul_imm(instance, stride as i64);
+            let key = t.b.ins().iadd(key, params[1]);
+            let mut switch = Switch::new();
+            let mut blocks = vec![];
+            for (instance, methods) in program.instances.iter().enumerate() {
+                for (idx, method) in methods.methods.iter().enumerate() {
+                    let block = t.b.create_block();
+                    switch.set_entry(instance as u128 * stride as u128 + idx as u128, block);
+                    blocks.push((block, *method, methods.context.len()));
+                }
+            }
+            let otherwise = t.b.create_block();
+            switch.emit(&mut t.b, key, otherwise);
+            for (block, method, context) in blocks {
+                t.b.switch_to_block(block);
+                let context: Vec<_> = (0..context).map(|i| t.load(dict, 8 * (2 + i))).collect();
+                let body = BodyId(method.0);
+                let value = match t.cg.mir.bodies[method.0].params == context.len() {
+                    true => t.direct(body, &context),
+                    false if context.is_empty() => t.function(body, &[]),
+                    false => {
+                        let f = t.function(body, &[]);
+                        t.apply(f, &context)
+                    }
+                };
+                t.b.ins().return_(&[value]);
+            }
+            t.b.switch_to_block(otherwise);
+            t.b.ins().trap(TrapCode::unwrap_user(1));
+        })
+    }
+
+    // A NUL terminated string holding a message, to give to `kamai_panic`
+    fn message(&mut self, message: String) -> DataId {
+        if let Some(id) = self.messages.get(&message) {
+            return *id;
+        }
+        let id = self
+            .module
+            .declare_anonymous_data(false, false)
+            .expect("Anonymous data can always be declared");
+        let mut description = DataDescription::new();
+        let mut bytes = message.clone().into_bytes();
+        bytes.push(0);
+        description.define(bytes.into_boxed_slice());
+        self.module
+            .define_data(id, &description)
+            .expect("Anonymous data is only defined once");
+        self.messages.insert(message, id);
+        id
+    }
+}
+
+fn signature(call_conv: CallConv, params: usize, returns: bool) -> Signature {
+    let mut sig = Signature::new(call_conv);
+    sig.params.extend((0..params).map(|_| AbiParam::new(I64)));
+    if returns {
+        sig.returns.push(AbiParam::new(I64));
+    }
+    sig
+}
+
+enum Slot {
+    Var(Variable),
+    Stack(StackSlot),
+}
+
+struct Translator<'t, 'c, 'm, 'p, 'a> {
+    cg: &'c mut Codegen<'m, 'p, 'a>,
+    b: FunctionBuilder<'t>,
+    locals: Vec<Slot>,
+    blocks: Vec<Block>,
+}
+impl<'t, 'c, 'm, 'p, 'a> Translator<'t, 'c, 'm, 'p, 'a> {
+    fn body(&mut self, body: &'m Body<'a>, params: Vec<Word>) {
+        let mut borrowed = vec![false; body.locals.len()];
+        for block in &body.blocks {
+            for statement in &block.statements {
+                if let StatementKind::Assign(_, Rvalue::Ref(_, place)) = &statement.kind {
+                    borrowed[place.local.0] |= place.projection.is_empty();
+                }
+            }
+        }
+        let zero = self.b.ins().iconst(I64, 0);
+        for (idx, borrowed) in borrowed.into_iter().enumerate() {
+            let slot = if borrowed {
+                let data = StackSlotData::new(StackSlotKind::ExplicitSlot, 8, 3);
+                Slot::Stack(self.b.create_sized_stack_slot(data))
+            } else {
+                let var = Variable::new(idx);
+                self.b.declare_var(var, I64);
+                Slot::Var(var)
+            };
+            self.locals.push(slot);
+            self.write_local(Local(idx), zero);
+        }
+        for (local, value) in body
+            .params()
+            .chain(body.captures.iter().copied())
+            .zip(params)
+        {
+            self.write_local(local, value);
+        }
+        self.blocks = body.blocks.iter().map(|_| self.b.create_block()).collect();
+        self.b.ins().jump(self.blocks[0], &[]);
+        for (idx, block) in body.blocks.iter().enumerate() {
+            self.b.switch_to_block(self.blocks[idx]);
+            for statement in &block.statements {
+                if let StatementKind::Assign(place, rvalue) = &statement.kind {
+                    let value = self.rvalue(rvalue, &statement.span);
+                    self.write(place, value);
+                }
+            }
+            self.terminator(&block.terminator);
+        }
+    }
+
+    fn terminator(&mut self, Terminator { kind, span }: &Terminator<'a>) {
+        match kind {
+            TerminatorKind::Goto(target) => {
+                self.b.ins().jump(self.blocks[target.0], &[]);
+            }
+            TerminatorKind::If { cond, then, else_ } => {
+                let cond = self.operand(cond);
+                self.b
+                    .ins()
+                    .brif(cond, self.blocks[then.0], &[], self.blocks[else_.0], &[]);
+            }
+            TerminatorKind::Switch {
+                place,
+                targets,
+                otherwise,
+            } => {
+                let value = self.read(place);
+                for (test, target) in targets {
+                    let passes = match test {
+                        Test::Literal(l) => self.b.ins().icmp_imm(IntCC::Equal, value, literal(l)),
+                        Test::Ctor(idx) => {
+                            let tag = self.load(value, 0);
+                            self.b.ins().icmp_imm(IntCC::Equal, tag, *idx as i64)
+                        }
+                        Test::Dict(id) => self.b.ins().icmp_imm(IntCC::Equal, value, id.0 as i64),
+                    };
+                    let next = self.b.create_block();
+                    self.b
+                        .ins()
+                        .brif(passes, self.blocks[target.0], &[], next, &[]);
+                    self.b.switch_to_block(next);
+                }
+                self.b.ins().jump(self.blocks[otherwise.0], &[]);
+            }
+            TerminatorKind::Call {
+                func,
+                args,
+                dest,
+                target,
+                tail,
+            } => {
+                if let Some(id) = self.tail_call(*tail, func, args) {
+                    let args: Vec<_> = args.iter().map(|arg| self.operand(arg)).collect();
+                    let f = self
+                        .cg
+                        .module
+                        .declare_func_in_func(self.cg.bodies[id.0].0, self.b.func);
+                    self.b.ins().return_call(f, &args);
+                    return;
+                }
+                if let (Operand::Copy(_) | Operand::Move(_), true) = (func, tail) {
+                    return self.tail_apply(func, args, dest, *target, span);
+                }
+                let value = self.call(func, args, span);
+                self.write(dest, value);
+                self.b.ins().jump(self.blocks[target.0], &[]);
+            }
+            TerminatorKind::Drop {
+                place,
+                glue,
+                target,
+            } => {
+                self.call(glue, &[Operand::Copy(place.clone())], span);
+                self.b.ins().jump(self.blocks[target.0], &[]);
+            }
+            TerminatorKind::Return => {
+                let value = self.read_local(RETURN);
+                self.b.ins().return_(&[value]);
+            }
+            TerminatorKind::Unreachable => {
+                self.b.ins().trap(TrapCode::unwrap_user(1));
+            }
+        }
+    }
+    // The item a tail call calls, when it's given all of its arguments: its function then
+    // takes the caller's frame, neither being counted in the depth of calls
+    fn tail_call(&self, tail: bool, func: &Operand<'a>, args: &[Operand<'a>]) -> Option<BodyId> {
+        match func {
+            Operand::Constant(Constant::Item(id, _))
+                if tail && args.len() == self.cg.mir.bodies[id.0].params =>
+            {
+                Some(BodyId(id.0))
+            }
+            _ => None,
+        }
+    }
+    // A tail call through a function value, which its tail entry for the arguments it was
+    // given makes in place of the caller when they're the rest of the ones its body takes.
+    // Otherwise the function is applied
+    fn tail_apply(
+        &mut self,
+        func: &Operand<'a>,
+        args: &[Operand<'a>],
+        dest: &Place,
+        target: BlockId,
+        span: &Span,
+    ) {
+        let func = self.operand(func);
+        let args: Vec<_> = args.iter().map(|arg| self.operand(arg)).collect();
+        let (tails, arity, given) = (self.load(func, 8), self.load(func, 16), self.load(func, 32));
+        let rest = self.b.ins().isub(arity, given);
+        let rest = self.b.ins().icmp_imm(IntCC::Equal, rest, args.len() as i64);
+        let compiled = self.b.ins().icmp_imm(IntCC::NotEqual, tails, 0);
+        let saturated = self.b.ins().band(rest, compiled);
+        let (tail, apply) = (self.b.create_block(), self.b.create_block());
+        self.b.ins().brif(saturated, tail, &[], apply, &[]);
+
+        self.b.switch_to_block(tail);
+        let offset = self.b.ins().ishl_imm(given, 3);
+        let entry = self.b.ins().iadd(tails, offset);
+        let entry = self.load(entry, 0);
+        let sig = signature(CallConv::Tail, 1 + args.len(), true);
+        let sig = self.b.import_signature(sig);
+        let values = [&[func][..], &args].concat();
+        self.b.ins().return_call_indirect(sig, entry, &values);
+
+        self.b.switch_to_block(apply);
+        let overflow = self.message(RuntimeErrKind::StackOverflow, span);
+        self.runtime(self.cg.runtime.enter, &[overflow]);
+        let value = self.apply(func, &args);
+        self.runtime(self.cg.runtime.leave, &[]);
+        self.write(dest, value);
+        self.b.ins().jump(self.blocks[target.0], &[]);
+    }
+    // Calls a function, directly when it's an item given all of its arguments
+    fn call(&mut self, func: &Operand<'a>, args: &[Operand<'a>], span: &Span) -> Word {
+        let args: Vec<_> = args.iter().map(|arg| self.operand(arg)).collect();
+        let overflow = self.message(RuntimeErrKind::StackOverflow, span);
+        self.runtime(self.cg.runtime.enter, &[overflow]);
+        let value = match func {
+            Operand::Constant(Constant::Item(id, _))
+                if args.len() >= self.cg.mir.bodies[id.0].params =>
+            {
+                let params = self.cg.mir.bodies[id.0].params;
+                let value = self.direct(BodyId(id.0), &args[..params]);
+                match params == args.len() {
+                    true => value,
+                    false => self.apply(value, &args[params..]),
+                }
+            }
+            Operand::Constant(Constant::Ctor(data, idx, _))
+                if args.len() == self.cg.program.datas[data.0].ctors[*idx].fields.len() =>
+            {
+                self.alloc(*idx, &args)
+            }
+            func => {
+                let func = self.operand(func);
+                self.apply(func, &args)
+            }
+        };
+        self.runtime(self.cg.runtime.leave, &[]);
+        value
+    }
+    fn direct(&mut self, body: BodyId, args: &[Word]) -> Word {
+        let f = self
+            .cg
+            .module
+            .declare_func_in_func(self.cg.bodies[body.0].0, self.b.func);
+        let call = self.b.ins().call(f, args);
+        self.b.inst_results(call)[0]
+    }
+    fn apply(&mut self, func: Word, args: &[Word]) -> Word {
+        let argc = self.b.ins().iconst(I64, args.len() as i64);
+        let argv = self.array(args);
+        self.runtime(self.cg.runtime.apply, &[func, argc, argv])
+            .unwrap()
+    }
+    fn runtime(&mut self, id: FuncId, args: &[Word]) -> Option<Word> {
+        let f = self.cg.module.declare_func_in_func(id, self.b.func);
+        let call = self.b.ins().call(f, args);
+        self.b.inst_results(call).first().copied()
+    }
+
+    fn rvalue(&mut self, rvalue: &Rvalue<'a>, span: &Span) -> Word {
+        match rvalue {
+            Rvalue::Use(operand) => self.operand(operand),
+            Rvalue::Ref(_, place) => self.address(place),
+            Rvalue::Unary(op, operand) => {
+                let value = self.operand(operand);
+                match op {
+                    UnOp::Neg => {
+                        let min = self.b.ins().icmp_imm(IntCC::Equal, value, i64::MIN);
+                        self.fail_if(min, RuntimeErrKind::Overflow(String::new()), span);
+                        self.b.ins().ineg(value)
+                    }
+                    UnOp::Not => {
+                        let not = self.b.ins().icmp_imm(IntCC::Equal, value, 0);
+                        self.b.ins().uextend(I64, not)
+                    }
+                    _ => unreachable!("`{:?}` isn't lowered to an operator", op),
+                }
+            }
+            Rvalue::Binary(op, l, r) => {
+                let (l, r) = (self.operand(l), self.operand(r));
+                self.binary(*op, l, r, span)
+            }
+            Rvalue::Tuple(operands) => {
+                let values: Vec<_> = operands.iter().map(|o| self.operand(o)).collect();
+                self.alloc(0, &values)
+            }
+            Rvalue::Ctor(_, idx, _, operands) => {
+                let values: Vec<_> = operands.iter().map(|o| self.operand(o)).collect();
+                self.alloc(*idx, &values)
+            }
+            Rvalue::Closure(body, captures) => {
+                let captures: Vec<_> = captures.iter().map(|o| self.operand(o)).collect();
+                self.function(*body, &captures)
+            }
+            Rvalue::Method(dict, idx) => {
+                let dict = self.operand(dict);
+                let idx = self.b.ins().iconst(I64, *idx as i64);
+                let f = self
+                    .cg
+                    .module
+                    .declare_func_in_func(self.cg.method, self.b.func);
+                let call = self.b.ins().call(f, &[dict, idx]);
+                self.b.inst_results(call)[0]
+            }
+        }
+    }
+    fn binary(&mut self, op: BinOp, l: Word, r: Word, span: &Span) -> Word {
+        let overflow = RuntimeErrKind::Overflow(String::new());
+        let (value, overflowed) = match op {
+            BinOp::Add => self.b.ins().sadd_overflow(l, r),
+            BinOp::Sub => self.b.ins().ssub_overflow(l, r),
+            BinOp::Mul => self.b.ins().smul_overflow(l, r),
+            BinOp::Div => {
+                let zero = self.b.ins().icmp_imm(IntCC::Equal, r, 0);
+                self.fail_if(zero, RuntimeErrKind::DivisionByZero, span);
+                let min = self.b.ins().icmp_imm(IntCC::Equal, l, i64::MIN);
+                let minus_one = self.b.ins().icmp_imm(IntCC::Equal, r, -1);
+                let overflowed = self.b.ins().band(min, minus_one);
+                self.fail_if(overflowed, overflow, span);
+                return self.b.ins().sdiv(l, r);
+            }
+            _ => {
+                let cc = match op {
+                    BinOp::LT => IntCC::SignedLessThan,
+                    BinOp::LTE => IntCC::SignedLessThanOrEqual,
+                    BinOp::GT => IntCC::SignedGreaterThan,
+                    BinOp::GTE => IntCC::SignedGreaterThanOrEqual,
+                    BinOp::EqEq => IntCC::Equal,
+                    BinOp::NotEq => IntCC::NotEqual,
+                    _ => unreachable!("`&&` and `||` are branches"),
+                };
+                let cmp = self.b.ins().icmp(cc, l, r);
+                return self.b.ins().uextend(I64, cmp);
+            }
+        };
+        self.fail_if(overflowed, overflow, span);
+        value
+    }
+    // Stops the program with an error when `cond` holds
+    fn fail_if(&mut self, cond: Word, kind: RuntimeErrKind, span: &Span) {
+        let (fail, next) = (self.b.create_block(), self.b.create_block());
+        self.b.set_cold_block(fail);
+        self.b.ins().brif(cond, fail, &[], next, &[]);
+        self.b.switch_to_block(fail);
+        self.panic(kind, span);
+        self.b.switch_to_block(next);
+    }
+    fn panic(&mut self, kind: RuntimeErrKind, span: &Span) {
+        let message = self.message(kind, span);
+        self.runtime(self.cg.runtime.panic, &[message]);
+        self.b.ins().trap(TrapCode::unwrap_user(1));
+    }
+    fn message(&mut self, kind: RuntimeErrKind, span: &Span) -> Word {
+        let message = format!("{} at {}", kind.message(), (self.cg.locate)(span));
+        let id = self.cg.message(message);
+        let global = self.cg.module.declare_data_in_func(id, self.b.func);
+        self.b.ins().symbol_value(I64, global)
+    }
+
+    fn operand(&mut self, operand: &Operand<'a>) -> Word {
+        match operand {
+            Operand::Copy(place) | Operand::Move(place) => self.read(place),
+            Operand::Constant(Constant::Literal(l)) => self.b.ins().iconst(I64, literal(l)),
+            Operand::Constant(Constant::Item(id, _)) => self.function(BodyId(id.0), &[]),
+            Operand::Constant(Constant::Ctor(data, idx, _)) => {
+                let entry = self
+                    .cg
+                    .module
+                    .declare_func_in_func(self.cg.runtime.ctor, self.b.func);
+                let entry = self.b.ins().func_addr(I64, entry);
+                let arity = self.cg.program.datas[data.0].ctors[*idx].fields.len();
+                let arity = self.b.ins().iconst(I64, arity as i64);
+                let count = self.b.ins().iconst(I64, 1);
+                let idx = self.b.ins().iconst(I64, *idx as i64);
+                let captures = self.array(&[idx]);
+                let tails = self.b.ins().iconst(I64, 0);
+                self.runtime(self.cg.runtime.fun, &[entry, tails, arity, count, captures])
+                    .unwrap()
+            }
+            Operand::Constant(Constant::Dict(Dict::Instance(id, _))) => {
+                self.b.ins().iconst(I64, id.0 as i64)
+            }
+            Operand::Constant(Constant::Dict(_)) => {
+                unreachable!("Dictionary parameters are locals")
+            }
+        }
+    }
+    // A function value calling a body
+    fn function(&mut self, body: BodyId, captures: &[Word]) -> Word {
+        let entry = self
+            .cg
+            .module
+            .declare_func_in_func(self.cg.bodies[body.0].1, self.b.func);
+        let entry = self.b.ins().func_addr(I64, entry);
+        let arity = self
+            .b
+            .ins()
+            .iconst(I64, self.cg.mir.bodies[body.0].params as i64);
+        let count = self.b.ins().iconst(I64, captures.len() as i64);
+        let captures = self.array(captures);
+        let tails = self
+            .cg
+            .module
+            .declare_data_in_func(self.cg.tails[body.0].1, self.b.func);
+        let tails = self.b.ins().symbol_value(I64, tails);
+        self.runtime(self.cg.runtime.fun, &[entry, tails, arity, count, captures])
+            .unwrap()
+    }
+    // A tuple or data value, made of the index of its constructor then its fields
+    fn alloc(&mut self, idx: usize, fields: &[Word]) -> Word {
+        let words = self.b.ins().iconst(I64, fields.len() as i64 + 1);
+        let object = self.runtime(self.cg.runtime.alloc, &[words]).unwrap();
+        let idx = self.b.ins().iconst(I64, idx as i64);
+        self.b.ins().store(MemFlags::trusted(), idx, object, 0);
+        for (i, field) in fields.iter().enumerate() {
+            self.b
+                .ins()
+                .store(MemFlags::trusted(), *field, object, 8 * (i as i32 + 1));
+        }
+        object
+    }
+    // An array of words on the stack, which lives until the function returns
+    fn array(&mut self, values: &[Word]) -> Word {
+        let size = 8 * values.len().max(1) as u32;
+        let slot = self.b.create_sized_stack_slot(StackSlotData::new(
+            StackSlotKind::ExplicitSlot,
+            size,
+            3,
+        ));
+        for (i, value) in values.iter().enumerate() {
+            self.b.ins().stack_store(*value, slot, 8 * i as i32);
+        }
+        self.b.ins().stack_addr(I64, slot, 0)
+    }
+    fn load(&mut self, address: Word, offset: usize) -> Word {
+        self.b
+            .ins()
+            .load(I64, MemFlags::trusted(), address, offset as i32)
+    }
+
+    fn read_local(&mut self, local: Local) -> Word {
+        match self.locals[local.0] {
+            Slot::Var(var) => self.b.use_var(var),
+            Slot::Stack(slot) => self.b.ins().stack_load(I64, slot, 0),
+        }
+    }
+    fn write_local(&mut self, local: Local, value: Word) {
+        match self.locals[local.0] {
+            Slot::Var(var) => self.b.def_var(var, value),
+            Slot::Stack(slot) => {
+                self.b.ins().stack_store(value, slot, 0);
+            }
+        }
+    }
+    // Fields are after the index of the constructor
+    fn address(&mut self, place: &Place) -> Word {
+        match place.projection.split_last() {
+            None => match self.locals[place.local.0] {
+                Slot::Stack(slot) => self.b.ins().stack_addr(I64, slot, 0),
+                Slot::Var(_) => unreachable!("Borrowed locals live on the stack"),
+            },
+            Some((projection, base)) => {
+                let base = self.read(&Place {
+                    local: place.local,
+                    projection: base.to_vec(),
+                });
+                match projection {
+                    Projection::Deref => base,
+                    Projection::Downcast(..) => {
+                        unreachable!("Downcast values are read, not addressed")
+                    }
+                    Projection::Field(idx) => self.b.ins().iadd_imm(base, 8 * (*idx as i64 + 1)),
+                }
+            }
+        }
+    }
+    fn read(&mut self, place: &Place) -> Word {
+        match place.projection.last() {
+            None => self.read_local(place.local),
+            // The same value, seen as one of its constructors
+            Some(Projection::Downcast(..)) => self.read(&Place {
+                local: place.local,
+                projection: place.projection[..place.projection.len() - 1].to_vec(),
+            }),
+            Some(_) => {
+                let address = self.address(place);
+                self.load(address, 0)
+            }
+        }
+    }
+    fn write(&mut self, place: &Place, value: Word) {
+        match place.projection.split_last() {
+            None => self.write_local(place.local, value),
+            Some((Projection::Deref, base)) => {
+                let address = self.read(&Place {
+                    local: place.local,
+                    projection: base.to_vec(),
+                });
+                self.b.ins().store(MemFlags::trusted(), value, address, 0);
+            }
+            Some(_) => unreachable!("Only locals and what references point to are assigned"),
+        }
+    }
+}
+
+fn literal(l: &Literal) -> i64 {
+    match l {
+        Literal::Num(n) => *n,
+        Literal::Bool(b) => *b as i64,
+        Literal::Unit => 0,
+    }
+}
@@ -0,0 +1,369 @@
+//! The native backend: programs are compiled ahead of time, from their MIR to Cranelift IR, to
+//! an object file linked with a small runtime written in C by the system's C compiler (`cc`,
+//! or `$CC`). The runtime allocates values, applies functions and reports runtime errors with
+//! where they happened, and the executable prints the value of `main` like `kamai run` does.
+//!
+//! Values don't say what they are, so printing them is done by functions generated from the
+//! type of `main`, added to the runtime.
+//!
+//! Programs can also be translated to C with the runtime, to be compiled by any C compiler:
+//! they only use the standard library, running on the stack the program starts with where the
+//! executables start a thread with a deep one.
+mod c;
+mod codegen;
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    errors::runtime_err::MAX_DEPTH,
+    hir::{DefId, Program, Ty},
+    mir::lower,
+    source::SourceDb,
+    typeck::{Type, Types},
+};
+use c::Emitter;
+use codegen::Codegen;
+
+const RUNTIME: &str = include_str!("runtime.c");
+const SUPPORT: &str = include_str!("support.c");
+const THREAD: &str = include_str!("thread.c");
+
+/// Compiles a checked program to an executable at `output`, which prints the value of `main`
+pub fn build(
+    db: &SourceDb,
+    program: &Program,
+    types: &Types,
+    main: DefId,
+    output: &Path,
+) -> Result<(), String> {
+    let mir = lower(program, types);
+    let locate = |span: &_| db.location(span);
+    let object = Codegen::new(program, &mir, &locate)?.finish(main)?;
+    let mut printers = Printers::new(program);
+    let show = printers.show(&types.items[main.0].ty);
+
+    let dir = temp_dir()?;
+    let (object_path, runtime_path) = (dir.join("program.o"), dir.join("runtime.c"));
+    let written = fs::write(&object_path, object).and_then(|()| {
+        fs::write(
+            &runtime_path,
+            format!("{}\n{}\n{}", runtime(), printers.finish(show), THREAD),
+        )
+    });
+    let linked = written
+        .map_err(|e| e.to_string())
+        .and_then(|()| cc(&[&runtime_path, &object_path], output, &["-O2", "-pthread"]));
+    // Only the executable is kept
+    let _ = fs::remove_dir_all(&dir);
+    linked
+}
+
+/// Translates a checked program to C source, with the runtime, printing the value of `main`
+pub fn emit_c(db: &SourceDb, program: &Program, types: &Types, main: DefId) -> String {
+    let mir = lower(program, types);
+    let mut printers = Printers::new(program);
+    let show = printers.show(&types.items[main.0].ty);
+    let code = Emitter::new(db, program, &mir).finish(main);
+    format!(
+        "{}\n{}\n{}\n{}",
+        runtime(),
+        SUPPORT,
+        printers.finish(show),
+        code
+    )
+}
+
+// The C runtime, with the depth calls can reach
+fn runtime() -> String {
+    format!("#define MAX_DEPTH {}\n{}", MAX_DEPTH, RUNTIME)
+}
+
+// A new directory for the files of a build
+fn temp_dir() -> Result<PathBuf, String> {
+    // Tests build several programs at once
+    static BUILDS: AtomicUsize = AtomicUsize::new(0);
+    let id = BUILDS.fetch_add(1, Ordering::Relaxed);
+    let dir = env::temp_dir().join(format!("kamai-{}-{}", process::id(), id));
+    fs::create_dir_all(&dir).map_err(|e| format!("couldn't create {}: {}", dir.display(), e))?;
+    Ok(dir)
+}
+
+// Compiles and links files to an executable with the system's C compiler, given `flags`
+fn cc(files: &[&Path], output: &Path, flags: &[&str]) -> Result<(), String> {
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = process::Command::new(&cc)
+        .args(flags)
+        .arg("-o")
+        .arg(output)
+        .args(files)
+        .status()
+        .map_err(|e| format!("couldn't run `{}`: {}", cc, e))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(format!("`{}` failed to link the program", cc)),
+    }
+}
+
+/// The type of a field of a data type applied to `args`
+pub fn field_type(program: &Program, ty: &Ty, args: &[Type]) -> Type {
+    let field = |ty: &Ty| field_type(program, ty, args);
+    match ty {
+        Ty::Var(idx) => args[*idx].clone(),
+        Ty::Int => Type::Int,
+        Ty::Bool => Type::Bool,
+        Ty::Unit => Type::Unit,
+        Ty::Data(id) => Type::Con(program.datas[id.0].name.value.to_string()),
+        Ty::App(f, arg) => Type::app(field(&f.value), field(&arg.value)),
+        Ty::Fun(param, ret) => Type::fun(field(&param.value), field(&ret.value)),
+        Ty::Tuple(elements) => Type::Tuple(elements.iter().map(|e| field(&e.value)).collect()),
+        Ty::Ref(mutable, ty) => Type::reference(*mutable, field(&ty.value)),
+    }
+}
+
+// The C functions printing the values of the types reachable from the type of `main`, each
+// taking the value and whether it's the field of a constructor, which needs parentheses when
+// it's compound
+struct Printers<'p, 'a> {
+    program: &'p Program<'a>,
+    // By type, printed with `Debug`
+    names: HashMap<String, usize>,
+    functions: Vec<String>,
+}
+impl<'p, 'a> Printers<'p, 'a> {
+    fn new(program: &'p Program<'a>) -> Self {
+        Self {
+            program,
+            names: HashMap::new(),
+            functions: vec![],
+        }
+    }
+    // The index of the function printing values of `ty`, generated the first time
+    fn show(&mut self, ty: &Type) -> usize {
+        let key = format!("{:?}", ty);
+        if let Some(idx) = self.names.get(&key) {
+            return *idx;
+        }
+        let idx = self.functions.len();
+        self.names.insert(key, idx);
+        self.functions.push(String::new());
+        let body = match ty {
+            Type::Int => {
+                "    if (nested && v < 0) printf(\"(%\" PRId64 \")\", v); else printf(\"%\" PRId64, v);\n"
+                    .to_string()
+            }
+            Type::Bool => "    fputs(v ? \"true\" : \"false\", stdout);\n".to_string(),
+            Type::Unit => "    fputs(\"()\", stdout);\n".to_string(),
+            Type::Fun(..) => "    fputs(\"<function>\", stdout);\n".to_string(),
+            Type::Tuple(elements) => {
+                let mut body = "    int64_t *o = (int64_t *)(intptr_t)v;\n    putchar('(');\n".to_string();
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        body.push_str("    fputs(\", \", stdout);\n");
+                    }
+                    body.push_str(&format!(
+                        "    show{}(o[{}], 0);\n",
+                        self.show(element),
+                        i + 1
+                    ));
+                }
+                body + "    putchar(')');\n"
+            }
+            Type::Con(_) | Type::App(..) => self.data(ty),
+            // No value has a type that's still a variable, references don't outlive `main`
+            _ => "    abort();\n".to_string(),
+        };
+        self.functions[idx] = body;
+        idx
+    }
+    fn data(&mut self, ty: &Type) -> String {
+        let (mut head, mut args) = (ty, vec![]);
+        while let Type::App(f, arg) = head {
+            args.push((**arg).clone());
+            head = f;
+        }
+        args.reverse();
+        let data = match head {
+            Type::Con(name) => self.program.datas.iter().find(|d| d.name.value == name),
+            _ => None,
+        };
+        let data = data.expect("Data types are applied by name");
+        let mut body =
+            "    int64_t *o = (int64_t *)(intptr_t)v;\n    switch (o[0]) {\n".to_string();
+        for (idx, ctor) in data.ctors.iter().enumerate() {
+            body.push_str(&format!("    case {}:\n", idx));
+            let parens = !ctor.fields.is_empty();
+            if parens {
+                body.push_str("        if (nested) putchar('(');\n");
+            }
+            body.push_str(&format!(
+                "        fputs(\"{}\", stdout);\n",
+                ctor.name.value
+            ));
+            for (i, field) in ctor.fields.iter().enumerate() {
+                let field = self.show(&field_type(self.program, &field.value, &args));
+                body.push_str(&format!(
+                    "        putchar(' ');\n        show{}(o[{}], 1);\n",
+                    field,
+                    i + 1
+                ));
+            }
+            if parens {
+                body.push_str("        if (nested) putchar(')');\n");
+            }
+            body.push_str("        break;\n");
+        }
+        body + "    }\n"
+    }
+    // The C source of the printers, `kamai_show` printing the value of `main` with `show`
+    fn finish(self, show: usize) -> String {
+        let mut code = String::new();
+        for idx in 0..self.functions.len() {
+            code.push_str(&format!(
+                "static void show{}(int64_t v, int nested);\n",
+                idx
+            ));
+        }
+        for (idx, body) in self.functions.iter().enumerate() {
+            code.push_str(&format!(
+                "static void show{}(int64_t v, int nested) {{\n    (void)v;\n    (void)nested;\n{}}}\n",
+                idx, body
+            ));
+        }
+        code + &format!(
+            "void kamai_show(int64_t v) {{\n    show{}(v, 0);\n}}\n",
+            show
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        check::tests::checked,
+        cli::{Backend, Target},
+        prelude::prelude,
+        syntax::parse_program,
+        vm::tests::SUITE,
+    };
+
+    // Compiles every program of the VM's tests with `compile`, given the executable to write,
+    // and runs them
+    fn agree_with_the_vm(compile: impl Fn(&SourceDb, &Program, &Types, DefId, &Path)) {
+        let dir = temp_dir().unwrap();
+        for (idx, (code, _)) in SUITE.iter().enumerate() {
+            let db = SourceDb::new();
+            let mut items = prelude(&db);
+            let file = db.add("main.ka", code.to_string());
+            items.extend(parse_program(file, db.source(file)).unwrap());
+            let (program, types) = checked(&items, true);
+            let main = program
+                .items
+                .iter()
+                .find(|item| item.name.value == "main")
+                .unwrap()
+                .id;
+
+            let executable = dir.join(format!("main{}", idx));
+            compile(&db, &program, &types, main, &executable);
+            let out = process::Command::new(&executable).output().unwrap();
+            let (stdout, stderr) = (
+                String::from_utf8(out.stdout).unwrap(),
+                String::from_utf8(out.stderr).unwrap(),
+            );
+            match Backend::Vm.eval(&program, &types, Target::Item(main)) {
+                Ok(value) => assert_eq!(
+                    (stdout, out.status.success()),
+                    (value + "\n", true),
+                    "{}",
+                    code
+                ),
+                Err(e) => {
+                    let error =
+                        format!("error: {} at {}\n", e.kind.message(), db.location(&e.span));
+                    assert_eq!((stderr, out.status.code()), (error, Some(1)), "{}", code);
+                }
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn executables_agree_with_the_vm() {
+        agree_with_the_vm(|db, program, types, main, executable| {
+            build(db, program, types, main, executable).unwrap()
+        });
+    }
+
+    #[test]
+    fn c_agrees_with_the_vm() {
+        // Unoptimized, the C compiler makes no tail calls of its own
+        for level in ["-O0", "-O2"] {
+            agree_with_the_vm(|db, program, types, main, executable| {
+                let source = executable.with_extension("c");
+                fs::write(&source, emit_c(db, program, types, main)).unwrap();
+                cc(&[&source], executable, &[level]).unwrap();
+            });
+        }
+    }
+
+    #[test]
+    fn c_mutual_tail_calls_dont_grow_the_stack() {
+        let db = SourceDb::new();
+        let mut items = prelude(&db);
+        let code = "even n = if n == 0 then true else odd (n - 1)\nodd n = if n == 0 then false else even (n - 1)\nmain = (even 50000000, odd 50000000)\n";
+        let file = db.add("main.ka", code.to_string());
+        items.extend(parse_program(file, db.source(file)).unwrap());
+        let (program, types) = checked(&items, true);
+        let main = program
+            .items
+            .iter()
+            .find(|item| item.name.value == "main")
+            .unwrap()
+            .id;
+        let dir = temp_dir().unwrap();
+        let (source, executable) = (dir.join("main.c"), dir.join("main"));
+        fs::write(&source, emit_c(&db, &program, &types, main)).unwrap();
+        cc(&[&source], &executable, &["-O0"]).unwrap();
+        let out = process::Command::new(&executable).output().unwrap();
+        assert_eq!(String::from_utf8(out.stdout).unwrap(), "(true, false)\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn c_maps_statements_to_their_line() {
+        let db = SourceDb::new();
+        let mut items = prelude(&db);
+        let code = "data Shape =\n  Circle Int\n  Square Int\narea (Circle r) = 3 * r\narea (Square s) = s\nmain = area (Square 2)\n";
+        let file = db.add("main.ka", code.to_string());
+        items.extend(parse_program(file, db.source(file)).unwrap());
+        let (program, types) = checked(&items, true);
+        let main = program
+            .items
+            .iter()
+            .find(|item| item.name.value == "main")
+            .unwrap()
+            .id;
+        let c = emit_c(&db, &program, &types, main);
+        assert!(
+            c.contains(
+                "struct Shape {\n    int64_t tag;\n    union {\n        struct { int64_t _0; } Circle;\n"
+            ),
+            "{}",
+            c
+        );
+        let square = "#line 5 \"main.ka\"\n    _4 = ((struct Shape *)(intptr_t)_1)->Square._0;\n";
+        assert!(c.contains(square), "{}", c);
+        assert!(
+            c.contains("kamai_mul(3, _3, \"Integer overflow at main.ka:4:19\")"),
+            "{}",
+            c
+        );
+    }
+}
@@ -0,0 +1,146 @@
+// The runtime linked with the programs compiled by the native backend. Every value is a word:
+// integers, booleans (0 or 1) and `()` (0) are unboxed, tuples and data values point to their
+// constructor's index then their fields, tuples' being 0. Functions point to a `Fun`. Words are
+// `int64_t`, pointers being converted through `intptr_t`.
+#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+// `MAX_DEPTH`, how many calls can be nested, is defined before the runtime by the backend.
+
+typedef int64_t (*Entry)(int64_t *fun, int64_t *args);
+
+// A function applied to some of its arguments. Its entry takes the function itself, to read
+// its captures, and all of its arguments. Compiled bodies also have an entry per number of
+// arguments a function can have been given, taking it and the rest of them, for their tail
+// calls through function values
+typedef struct {
+    Entry entry;
+    void **tails;
+    int64_t arity;
+    int64_t ncaptures;
+    int64_t nargs;
+    // The captures, then the arguments
+    int64_t values[];
+} Fun;
+
+// Values are never freed, owned ones being dropped without giving their memory back yet
+int64_t *kamai_alloc(int64_t words) {
+    int64_t *object = malloc(words * sizeof(int64_t));
+    if (!object) {
+        fputs("error: Out of memory\n", stderr);
+        exit(1);
+    }
+    return object;
+}
+
+int64_t kamai_fun(
+    Entry entry, void **tails, int64_t arity, int64_t ncaptures, int64_t *captures
+) {
+    Fun *fun = (Fun *)kamai_alloc(5 + ncaptures);
+    fun->entry = entry;
+    fun->tails = tails;
+    fun->arity = arity;
+    fun->ncaptures = ncaptures;
+    fun->nargs = 0;
+    memcpy(fun->values, captures, ncaptures * sizeof(int64_t));
+    return (int64_t)(intptr_t)fun;
+}
+
+// The tail call through a function value the body of emitted C returning last left to make,
+// since C compilers can't make it in place of the body. Its arguments are copied, the body's
+// frame being gone once it's made
+static struct {
+    int64_t pending;
+    int64_t fun;
+    int64_t argc;
+    int64_t *argv;
+    int64_t capacity;
+} tail;
+
+int64_t kamai_tail(int64_t value, int64_t argc, int64_t *argv) {
+    if (argc > tail.capacity) {
+        tail.argv = realloc(tail.argv, argc * sizeof(int64_t));
+        if (!tail.argv) {
+            fputs("error: Out of memory\n", stderr);
+            exit(1);
+        }
+        tail.capacity = argc;
+    }
+    memcpy(tail.argv, argv, argc * sizeof(int64_t));
+    tail.fun = value;
+    tail.argc = argc;
+    tail.pending = 1;
+    return 0;
+}
+
+static int64_t apply(int64_t value, int64_t argc, int64_t *argv);
+
+// The value of a call, making the tail calls left by the bodies it returned from one after the
+// other, so that they don't grow the stack
+int64_t kamai_settle(int64_t value) {
+    while (tail.pending) {
+        tail.pending = 0;
+        value = apply(tail.fun, tail.argc, tail.argv);
+    }
+    return value;
+}
+
+// Applies a function to arguments, partially when they're fewer than it takes, its value being
+// applied to the rest when they're more
+int64_t kamai_apply(int64_t value, int64_t argc, int64_t *argv) {
+    return kamai_settle(apply(value, argc, argv));
+}
+// The arguments are copied before the entry is called, which may leave another tail call
+static int64_t apply(int64_t value, int64_t argc, int64_t *argv) {
+    Fun *fun = (Fun *)(intptr_t)value;
+    int64_t count = fun->nargs + argc, known = fun->ncaptures + fun->nargs;
+    if (count < fun->arity) {
+        Fun *partial = (Fun *)kamai_alloc(5 + known + argc);
+        memcpy(partial, fun, (5 + known) * sizeof(int64_t));
+        memcpy(partial->values + known, argv, argc * sizeof(int64_t));
+        partial->nargs = count;
+        return (int64_t)(intptr_t)partial;
+    }
+    int64_t args[count + 1];
+    memcpy(args, fun->values + fun->ncaptures, fun->nargs * sizeof(int64_t));
+    memcpy(args + fun->nargs, argv, argc * sizeof(int64_t));
+    int64_t result = fun->entry((int64_t *)fun, args);
+    if (count > fun->arity) {
+        return apply(kamai_settle(result), count - fun->arity, args + fun->arity);
+    }
+    return result;
+}
+
+// The entry of constructors used as functions, which capture their index
+int64_t kamai_ctor(int64_t *fun, int64_t *args) {
+    Fun *ctor = (Fun *)fun;
+    int64_t *data = kamai_alloc(1 + ctor->arity);
+    data[0] = ctor->values[0];
+    memcpy(data + 1, args, ctor->arity * sizeof(int64_t));
+    return (int64_t)(intptr_t)data;
+}
+
+_Noreturn void kamai_panic(const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "error: %s\n", message);
+    exit(1);
+}
+
+static int64_t depth = 0;
+
+// Around each call, `overflow` being the error of the call when it's nested too deep
+void kamai_enter(const char *overflow) {
+    if (++depth > MAX_DEPTH) {
+        kamai_panic(overflow);
+    }
+}
+void kamai_leave(void) {
+    depth--;
+}
+
+// Generated for the program
+int64_t kamai_main(void);
+void kamai_show(int64_t value);
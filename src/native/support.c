@@ -0,0 +1,95 @@
+// What the C emitted by `kamai c` uses on top of the runtime: the layout of tuples, and the
+// operations on integers failing with a runtime error
+
+// Makes a `return` of a call a tail call, which compilers that can guarantee them need to be told
+#if defined(__has_attribute)
+#if __has_attribute(musttail)
+#define KAMAI_TAIL __attribute__((musttail))
+#endif
+#endif
+#ifndef KAMAI_TAIL
+#define KAMAI_TAIL
+#endif
+
+// Tuples have the index of their constructor, always 0, like data values
+struct kamai_tuple {
+    int64_t tag;
+    int64_t fields[];
+};
+
+// The index of the constructor of a tuple or data value
+static int64_t kamai_tag(int64_t value) {
+    return *(int64_t *)(intptr_t)value;
+}
+
+static int64_t kamai_neg(int64_t value, const char *overflow) {
+    if (value == INT64_MIN) {
+        kamai_panic(overflow);
+    }
+    return -value;
+}
+
+#if defined(__GNUC__) || defined(__clang__)
+static int64_t kamai_add(int64_t l, int64_t r, const char *overflow) {
+    int64_t value;
+    if (__builtin_add_overflow(l, r, &value)) {
+        kamai_panic(overflow);
+    }
+    return value;
+}
+static int64_t kamai_sub(int64_t l, int64_t r, const char *overflow) {
+    int64_t value;
+    if (__builtin_sub_overflow(l, r, &value)) {
+        kamai_panic(overflow);
+    }
+    return value;
+}
+static int64_t kamai_mul(int64_t l, int64_t r, const char *overflow) {
+    int64_t value;
+    if (__builtin_mul_overflow(l, r, &value)) {
+        kamai_panic(overflow);
+    }
+    return value;
+}
+#else
+static int64_t kamai_add(int64_t l, int64_t r, const char *overflow) {
+    if (r > 0 ? l > INT64_MAX - r : l < INT64_MIN - r) {
+        kamai_panic(overflow);
+    }
+    return l + r;
+}
+static int64_t kamai_sub(int64_t l, int64_t r, const char *overflow) {
+    if (r < 0 ? l > INT64_MAX + r : l < INT64_MIN + r) {
+        kamai_panic(overflow);
+    }
+    return l - r;
+}
+static int64_t kamai_mul(int64_t l, int64_t r, const char *overflow) {
+    if (l != 0 && r != 0) {
+        int fits = l > 0 ? (r > 0 ? l <= INT64_MAX / r : r >= INT64_MIN / l)
+                         : (r > 0 ? l >= INT64_MIN / r : l >= INT64_MAX / r);
+        if (!fits) {
+            kamai_panic(overflow);
+        }
+    }
+    return l * r;
+}
+#endif
+
+static int64_t kamai_div(int64_t l, int64_t r, const char *zero, const char *overflow) {
+    if (r == 0) {
+        kamai_panic(zero);
+    }
+    if (l == INT64_MIN && r == -1) {
+        kamai_panic(overflow);
+    }
+    return l / r;
+}
+
+// The program runs on the thread C starts it with, whose stack has to be deep enough for the
+// calls it nests
+int main(void) {
+    kamai_show(kamai_main());
+    putchar('\n');
+    return 0;
+}
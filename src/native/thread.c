@@ -0,0 +1,25 @@
+// The entry of the executables the native backend links, running the program on a thread with
+// a stack deep enough for `MAX_DEPTH` calls, which is only mapped once used
+#include <pthread.h>
+
+#define STACK_SIZE ((size_t)1 << 30)
+
+static void *run(void *unused) {
+    (void)unused;
+    kamai_show(kamai_main());
+    putchar('\n');
+    return NULL;
+}
+
+int main(void) {
+    pthread_attr_t attr;
+    pthread_t thread;
+    pthread_attr_init(&attr);
+    pthread_attr_setstacksize(&attr, STACK_SIZE);
+    if (pthread_create(&thread, &attr, run, NULL)) {
+        fputs("error: Can't start the program's thread\n", stderr);
+        return 1;
+    }
+    pthread_join(thread, NULL);
+    return 0;
+}
@@ -0,0 +1,16 @@
+//! The declarations every program starts with, written in Kamai
+use crate::{
+    source::SourceDb,
+    syntax::{
+        ast::{Item, Node},
+        parse_program,
+    },
+};
+
+const PRELUDE: &str = include_str!("prelude.ka");
+
+/// Adds the prelude to `db` and parses it, its items go before the program's
+pub fn prelude(db: &SourceDb) -> Vec<Node<Item<'_>>> {
+    let file = db.add("<prelude>", PRELUDE.to_string());
+    parse_program(file, db.source(file)).expect("The prelude parses")
+}
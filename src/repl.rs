@@ -0,0 +1,331 @@
+use std::{
+    io::{self, BufRead, Write},
+    mem,
+    path::Path,
+};
+
+use crate::{
+    cli::{Backend, Target},
+    errors::{emit, ErrorFormat},
+    hir::{
+        fold::{fold, fold_expr},
+        resolve::{resolve, resolve_expr},
+        Expr, Program,
+    },
+    mir::{check_borrows, check_expr_borrows, check_tail_calls},
+    prelude::prelude,
+    source::{FileId, SourceDb},
+    syntax::{
+        ast::{Item, Node},
+        insensitive_layout::is_open,
+        is_declaration, lex, parse_expr, parse_program,
+        tokens::pretty_print_tokens,
+    },
+    typeck::{
+        check_expr_ownership, check_expr_patterns, check_ownership, check_patterns, typecheck,
+        typecheck_expr, Scheme, Types,
+    },
+};
+
+const HELP: &str = "\
+Enter an expression to evaluate it, or a declaration to add it to the session.
+Expressions whose type has constraints, like `Num a => a -> a`, can't be evaluated.
+Input continues on the next lines while it is open (a trailing `=`, `then` or operator,
+an unclosed delimiter or an indented block), an empty line ends it. Commands always fit
+on a single line.
+
+Commands:
+    :type <expr>    Print the type of an expression
+    :ast <code>     Print the syntax tree of an expression or a declaration
+    :tokens <code>  Print the tokens of some code
+    :load <file>    Add the declarations of a file to the session
+    :help           Print this message
+    :quit           Exit the session";
+
+pub struct Repl {
+    // Leaked once, the items of the session borrowing their sources from it until it ends
+    db: &'static SourceDb,
+    error_format: ErrorFormat,
+    backend: Backend,
+    entries: usize,
+    prelude: Vec<Node<Item<'static>>>,
+    // The items of the session, after the prelude
+    items: Vec<Node<Item<'static>>>,
+}
+
+impl Repl {
+    pub fn new(error_format: ErrorFormat, backend: Backend) -> Self {
+        let db: &'static _ = Box::leak(Box::new(SourceDb::new()));
+        let prelude = prelude(db);
+        Self {
+            db,
+            error_format,
+            backend,
+            entries: 0,
+            prelude,
+            items: vec![],
+        }
+    }
+    pub fn run(&mut self) -> i32 {
+        let stdin = io::stdin();
+        let mut buffer = String::new();
+        loop {
+            print!("{}", if buffer.is_empty() { "> " } else { "| " });
+            io::stdout().flush().expect("Failed to write on stdout");
+            let mut line = String::new();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) => {
+                    println!();
+                    if !buffer.trim().is_empty() {
+                        self.entry(buffer);
+                    }
+                    return 0;
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    eprintln!("error: couldn't read stdin: {}", e);
+                    return 1;
+                }
+            }
+            // Commands always fit on a single line
+            let command = buffer.is_empty() && line.trim_start().starts_with(':');
+            let blank = line.trim().is_empty();
+            buffer.push_str(&line);
+            if command || blank || !is_open(lex(&buffer)) {
+                let entry = mem::take(&mut buffer);
+                if !entry.trim().is_empty() && !self.entry(entry) {
+                    return 0;
+                }
+            }
+        }
+    }
+    // Returns false once the session should end
+    fn entry(&mut self, entry: String) -> bool {
+        self.entries += 1;
+        let name = format!("<repl:{}>", self.entries);
+        let entry = entry.trim_end();
+        let (cmd, arg) = match entry.trim_start().strip_prefix(':') {
+            Some(cmd) => match cmd.find(char::is_whitespace) {
+                Some(idx) => (&cmd[..idx], cmd[idx..].trim()),
+                None => (cmd, ""),
+            },
+            None => {
+                let file = self.db.add(name, entry.to_string());
+                self.eval(file);
+                return true;
+            }
+        };
+        // Each entry gets its own virtual file, which only holds the argument of commands
+        let file = self.db.add(name, arg.to_string());
+        match cmd {
+            "q" | "quit" => return false,
+            "h" | "help" => println!("{}", HELP),
+            "t" | "type" => {
+                if let Some((_, _, scheme, _)) = self.check_expr(file) {
+                    println!("{} : {}", arg, scheme);
+                }
+            }
+            "ast" => self.ast(file),
+            "tokens" => {
+                pretty_print_tokens(&lex(arg));
+                println!();
+            }
+            "l" | "load" => self.load(Path::new(arg)),
+            _ => eprintln!("error: unknown command `:{}`, try `:help`", cmd),
+        }
+        true
+    }
+    fn eval(&mut self, file: FileId) {
+        let code = self.db.source(file);
+        if is_declaration(&lex(code)) {
+            match parse_program(file, code) {
+                Ok(items) => {
+                    self.define(file, items);
+                }
+                Err(errors) => emit(self.db, self.error_format, errors),
+            }
+        } else if let Some((program, e, scheme, types)) = self.check_expr(file) {
+            // Their dictionaries are unknown
+            if !scheme.constraints.is_empty() {
+                eprintln!(
+                    "error: can't evaluate an expression with constraints, its type is `{}`",
+                    scheme
+                );
+                return;
+            }
+            match self.backend.eval(&program, &types, Target::Expr(&e)) {
+                Ok(value) => println!("{}", value),
+                Err(e) => emit(self.db, self.error_format, vec![e]),
+            }
+        }
+    }
+    // Parses, resolves and checks the expression of `file` against the session's items. Only the
+    // resolution diagnostics of `file` are reported, the items' were when they were defined
+    fn check_expr(
+        &self,
+        file: FileId,
+    ) -> Option<(Program<'static>, Node<Expr<'static>>, Scheme, Types)> {
+        let e = match parse_expr(file, self.db.source(file)) {
+            Ok(e) => e,
+            Err(errors) => {
+                emit(self.db, self.error_format, errors);
+                return None;
+            }
+        };
+        let items = [&self.prelude[..], &self.items].concat();
+        let (mut program, mut e, diagnostics) = resolve_expr(&items, &e);
+        let diagnostics: Vec<_> = diagnostics
+            .into_iter()
+            .filter(|d| d.span.file == file)
+            .collect();
+        let failed = diagnostics.iter().any(|d| d.is_error());
+        emit(self.db, self.error_format, diagnostics);
+        if failed {
+            return None;
+        }
+        let (scheme, mut types, errors) = typecheck_expr(&mut program, &mut e);
+        if !errors.is_empty() {
+            emit(self.db, self.error_format, errors);
+            return None;
+        }
+        let diagnostics = check_expr_patterns(&program, &e);
+        let failed = diagnostics.iter().any(|d| d.is_error());
+        emit(self.db, self.error_format, diagnostics);
+        if failed {
+            return None;
+        }
+        let errors = check_expr_ownership(&mut program, &mut e, &mut types);
+        if !errors.is_empty() {
+            emit(self.db, self.error_format, errors);
+            return None;
+        }
+        let errors = check_expr_borrows(&program, &e, &types);
+        if !errors.is_empty() {
+            emit(self.db, self.error_format, errors);
+            return None;
+        }
+        emit(self.db, self.error_format, fold_expr(&mut program, &mut e));
+        Some((program, e, scheme, types))
+    }
+    fn ast(&self, file: FileId) {
+        let code = self.db.source(file);
+        if is_declaration(&lex(code)) {
+            match parse_program(file, code) {
+                Ok(items) => println!("{:#?}", items),
+                Err(errors) => emit(self.db, self.error_format, errors),
+            }
+        } else {
+            match parse_expr(file, code) {
+                Ok(e) => println!("{:#?}", e),
+                Err(errors) => emit(self.db, self.error_format, errors),
+            }
+        }
+    }
+    pub fn load(&mut self, path: &Path) {
+        let file = match self.db.load(path) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("error: couldn't read {}: {}", path.display(), e);
+                return;
+            }
+        };
+        match parse_program(file, self.db.source(file)) {
+            Ok(items) => {
+                let count = items.len();
+                if self.define(file, items) {
+                    println!("Loaded {} item(s) from {}", count, path.display());
+                }
+            }
+            Err(errors) => emit(self.db, self.error_format, errors),
+        }
+    }
+    // Newer declarations replace the older ones with the same name, instances and the prelude
+    // are never replaced. The declarations of `file`
+    // are rejected when they don't resolve or type check. Resolution diagnostics are only
+    // reported for `file`, the other entries were already checked.
+    // Returns whether they were added
+    fn define(&mut self, file: FileId, items: Vec<Node<Item<'static>>>) -> bool {
+        let name = |item: &Node<Item<'static>>| item.value.name().map(|n| n.value);
+        let mut defined: Vec<_> = self
+            .items
+            .iter()
+            .filter(|old| name(old).is_none() || items.iter().all(|item| name(item) != name(old)))
+            .cloned()
+            .collect();
+        defined.extend(items);
+        let (mut program, diagnostics) = resolve(&[&self.prelude[..], &defined].concat());
+        let diagnostics: Vec<_> = diagnostics
+            .into_iter()
+            .filter(|d| d.span.file == file)
+            .collect();
+        let failed = diagnostics.iter().any(|d| d.is_error());
+        emit(self.db, self.error_format, diagnostics);
+        if failed {
+            return false;
+        }
+        // A redefinition can break the items using it, their errors are reported too
+        let (mut types, errors) = typecheck(&mut program);
+        if !errors.is_empty() {
+            emit(self.db, self.error_format, errors);
+            return false;
+        }
+        // Redefining a data type can make the older clauses non-exhaustive
+        let diagnostics: Vec<_> = check_patterns(&program)
+            .into_iter()
+            .filter(|d| d.is_error() || d.span.file == file)
+            .collect();
+        let failed = diagnostics.iter().any(|d| d.is_error());
+        emit(self.db, self.error_format, diagnostics);
+        if failed {
+            return false;
+        }
+        let errors = check_ownership(&mut program, &mut types);
+        if !errors.is_empty() {
+            emit(self.db, self.error_format, errors);
+            return false;
+        }
+        let errors = check_borrows(&program, &types);
+        if !errors.is_empty() {
+            emit(self.db, self.error_format, errors);
+            return false;
+        }
+        let errors = check_tail_calls(&program, &types);
+        if !errors.is_empty() {
+            emit(self.db, self.error_format, errors);
+            return false;
+        }
+        let warnings = fold(&mut program)
+            .into_iter()
+            .filter(|w| w.span.file == file);
+        emit(self.db, self.error_format, warnings);
+        self.items = defined;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redefinitions() {
+        let mut repl = Repl::new(ErrorFormat::Human, Backend::Vm);
+        assert!(repl.entry("f x = x".to_string()));
+        assert!(repl.entry("g = 1\nf = 2".to_string()));
+        assert!(repl.entry("1 +".to_string()));
+        // Unbound names and type errors are rejected
+        assert!(repl.entry("g = h".to_string()));
+        assert!(repl.entry("g = 1 + true".to_string()));
+        assert!(repl.entry("f = g".to_string()));
+        let names: Vec<_> = repl
+            .items
+            .iter()
+            .map(|item| match &item.value {
+                Item::Function { name, params, .. } => (name.value, params.len()),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(names, [("g", 0), ("f", 0)]);
+        assert!(!repl.entry(":quit".to_string()));
+    }
+}
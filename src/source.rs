@@ -0,0 +1,124 @@
+use std::{fs, io, ops::Range, path::Path};
+
+use codespan_reporting::files::{Files, SimpleFile};
+use elsa::FrozenVec;
+
+pub type FileId = usize;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Span {
+    pub file: FileId,
+    pub range: Range<usize>,
+}
+impl Span {
+    pub fn new(file: FileId, range: Range<usize>) -> Self {
+        Self { file, range }
+    }
+    /// The span going from the start of `self` to the end of `end`, both in the same file
+    pub fn merge(&self, end: &Span) -> Self {
+        Self {
+            file: self.file,
+            range: self.range.start..end.range.end,
+        }
+    }
+}
+
+/// Every file loaded during a session, diagnostics are rendered against it.
+/// It owns the sources, which syntax trees borrow for as long as it lives: files are only ever
+/// added, through a shared reference, so they never move once loaded.
+pub struct SourceDb {
+    files: FrozenVec<Box<SimpleFile<String, String>>>,
+}
+impl SourceDb {
+    pub fn new() -> Self {
+        Self {
+            files: FrozenVec::new(),
+        }
+    }
+    pub fn add(&self, name: impl Into<String>, code: String) -> FileId {
+        self.files
+            .push(Box::new(SimpleFile::new(name.into(), code)));
+        self.files.len() - 1
+    }
+    pub fn load(&self, path: &Path) -> io::Result<FileId> {
+        let code = fs::read_to_string(path)?;
+        Ok(self.add(path.display().to_string(), code))
+    }
+    fn file(&self, file: FileId) -> &SimpleFile<String, String> {
+        self.files.get(file).expect("Unknown file id")
+    }
+    pub fn source(&self, file: FileId) -> &str {
+        self.file(file).source()
+    }
+    pub fn name(&self, file: FileId) -> &str {
+        self.file(file).name()
+    }
+    /// The line a span starts on, from 1
+    pub fn line(&self, span: &Span) -> usize {
+        self.file(span.file)
+            .line_index((), span.range.start)
+            .map_or(1, |line| line + 1)
+    }
+    /// Where a span starts, as `file:line:column`
+    pub fn location(&self, span: &Span) -> String {
+        let file = self.file(span.file);
+        match file.location((), span.range.start) {
+            Some(location) => format!(
+                "{}:{}:{}",
+                file.name(),
+                location.line_number,
+                location.column_number
+            ),
+            None => file.name().clone(),
+        }
+    }
+}
+impl<'a> Files<'a> for SourceDb {
+    type FileId = FileId;
+    type Name = &'a str;
+    type Source = &'a str;
+
+    fn name(&'a self, file: FileId) -> Option<&'a str> {
+        Some(self.files.get(file)?.name())
+    }
+    fn source(&'a self, file: FileId) -> Option<&'a str> {
+        Some(self.files.get(file)?.source())
+    }
+    fn line_index(&'a self, file: FileId, byte_index: usize) -> Option<usize> {
+        self.files.get(file)?.line_index((), byte_index)
+    }
+    fn line_range(&'a self, file: FileId, line_index: usize) -> Option<Range<usize>> {
+        self.files.get(file)?.line_range((), line_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse_program;
+    use codespan_reporting::{
+        diagnostic::Diagnostic,
+        term::{self, termcolor::NoColor},
+    };
+
+    #[test]
+    fn diagnostics_point_at_their_file() {
+        let db = SourceDb::new();
+        let main = db.add("main.ka", "a = 1\n".to_string());
+        // Files are added while the syntax trees of the others borrow them
+        let items = parse_program(main, db.source(main)).unwrap();
+        let other = db.add("other.ka", "b = 1 +\n".to_string());
+        assert_eq!(items.len(), 1);
+        let errors = parse_program(other, db.source(other)).unwrap_err();
+        assert!(errors.iter().all(|e| e.span.file == other));
+
+        let mut out = NoColor::new(vec![]);
+        for err in errors {
+            let diagnostic: Diagnostic<FileId> = err.into();
+            term::emit(&mut out, &term::Config::default(), &db, &diagnostic).unwrap();
+        }
+        let out = String::from_utf8(out.into_inner()).unwrap();
+        assert!(out.contains("other.ka:1:7"), "{}", out);
+        assert!(!out.contains("main.ka"));
+    }
+}
@@ -1,5 +1,7 @@
 #![allow(dead_code)]
-use std::ops::Range;
+use std::fmt;
+
+use crate::source::Span;
 
 pub type BoxNode<T> = Node<Box<T>>;
 pub type Ident<'a> = &'a str;
@@ -11,23 +13,136 @@ pub enum Expr<'a> {
     Parenthesized(BoxNode<Expr<'a>>),
     Unary(UnOp, BoxNode<Expr<'a>>),
     Binary(BinOp, BoxNode<Expr<'a>>, BoxNode<Expr<'a>>),
-    Lambda(Ident<'a>, BoxNode<Expr<'a>>),
+    Lambda(Node<Pattern<'a>>, BoxNode<Expr<'a>>),
     Call(BoxNode<Expr<'a>>, BoxNode<Expr<'a>>),
+    /// `f ()`, a call whose argument is the unit value
     EmptyCall(BoxNode<Expr<'a>>),
+    /// `(a, b, ...)`, with at least two elements
+    Tuple(Vec<Node<Expr<'a>>>),
     Block {
         instructions: Vec<Node<Statement<'a>>>,
         returns: bool,
     },
+    If {
+        cond: BoxNode<Expr<'a>>,
+        then: BoxNode<Expr<'a>>,
+        else_: BoxNode<Expr<'a>>,
+    },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Item<'a> {
+    Function {
+        name: Node<Ident<'a>>,
+        params: Vec<Node<Pattern<'a>>>,
+        body: Node<Expr<'a>>,
+        /// `tailrec`, whose recursive calls must be tail calls
+        tailrec: bool,
+    },
+    Class {
+        name: Node<Ident<'a>>,
+        param: TypeParam<'a>,
+        methods: Vec<Node<Signature<'a>>>,
+    },
+    Instance {
+        /// The constraints on the variables of its type, before its `=>`
+        context: Vec<Node<Constraint<'a>>>,
+        class: Node<Ident<'a>>,
+        ty: Node<TypeExpr<'a>>,
+        /// Only functions
+        methods: Vec<Node<Item<'a>>>,
+    },
+    Data {
+        name: Node<Ident<'a>>,
+        params: Vec<TypeParam<'a>>,
+        ctors: Vec<Node<Constructor<'a>>>,
+        /// `owned data`, whose values have a unique owner instead of being garbage collected
+        owned: bool,
+    },
+}
+impl<'a> Item<'a> {
+    /// The name the item defines, instances don't have any
+    pub fn name(&self) -> Option<&Node<Ident<'a>>> {
+        match self {
+            Item::Function { name, .. } | Item::Class { name, .. } | Item::Data { name, .. } => {
+                Some(name)
+            }
+            Item::Instance { .. } => None,
+        }
+    }
+}
+
+/// `Class ty`, in the context of an instance
+#[derive(Debug, PartialEq, Clone)]
+pub struct Constraint<'a> {
+    pub class: Node<Ident<'a>>,
+    pub ty: Node<TypeExpr<'a>>,
+}
+
+/// `name : ty`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Signature<'a> {
+    pub name: Node<Ident<'a>>,
+    pub ty: Node<TypeExpr<'a>>,
+}
+
+/// `Name field*`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Constructor<'a> {
+    pub name: Node<Ident<'a>>,
+    pub fields: Vec<Node<TypeExpr<'a>>>,
+}
+
+/// The parameter of a class or a data type, `name` or `(name : kind)`
+#[derive(Debug, PartialEq, Clone)]
+pub struct TypeParam<'a> {
+    pub name: Node<Ident<'a>>,
+    pub kind: Option<Node<KindExpr>>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum TypeExpr<'a> {
+    Named(Ident<'a>),
+    Unit,
+    App(BoxNode<TypeExpr<'a>>, BoxNode<TypeExpr<'a>>),
+    Fun(BoxNode<TypeExpr<'a>>, BoxNode<TypeExpr<'a>>),
+    Tuple(Vec<Node<TypeExpr<'a>>>),
+    /// `&T`, or `&mut T` when it's mutable
+    Ref(bool, BoxNode<TypeExpr<'a>>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum KindExpr {
+    Star,
+    Arrow(BoxNode<KindExpr>, BoxNode<KindExpr>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern<'a> {
+    Wildcard,
+    Binding(Ident<'a>),
+    /// `mut name`, a binding that can be assigned and borrowed mutably
+    MutBinding(Ident<'a>),
+    Literal(Literal),
+    /// A capitalized name, applied to the patterns of the constructor's fields
+    Ctor(Node<Ident<'a>>, Vec<Node<Pattern<'a>>>),
+    Tuple(Vec<Node<Pattern<'a>>>),
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Statement<'a> {
-    Return(Expr<'a>),
+    Let(Node<Pattern<'a>>, Node<Expr<'a>>),
+    /// `place = value`
+    Assign(Node<Expr<'a>>, Node<Expr<'a>>),
+    Return(Node<Expr<'a>>),
+    /// Only parsed, there are no loops yet
     Continue,
-    Break(Expr<'a>),
+    /// Only parsed, there are no loops yet
+    Break(Node<Expr<'a>>),
     StmtExpr(Expr<'a>),
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum BinOp {
     Add,
@@ -43,11 +158,36 @@ pub enum BinOp {
     EqEq,
     NotEq,
 }
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sym = match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::And => "&&",
+            BinOp::Or => "||",
+            BinOp::LT => "<",
+            BinOp::LTE => "<=",
+            BinOp::GT => ">",
+            BinOp::GTE => ">=",
+            BinOp::EqEq => "==",
+            BinOp::NotEq => "!=",
+        };
+        write!(f, "{}", sym)
+    }
+}
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum UnOp {
     Pos,
     Neg,
     Not,
+    /// `&e`, a shared reference
+    Ref,
+    /// `&mut e`, a mutable reference
+    RefMut,
+    /// `*e`, the value behind a reference
+    Deref,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -60,7 +200,7 @@ pub enum Literal {
 #[derive(Debug, PartialEq, Clone)]
 pub struct Node<T: Clone> {
     pub value: T,
-    pub span: Range<usize>,
+    pub span: Span,
 }
 impl<T: Clone> Node<T> {
     pub fn into_boxed(self) -> Node<Box<T>> {
@@ -1,62 +1,159 @@
-use std::io::{self, Write};
-
 use crate::{
     errors::syntax_err::*,
+    source::{FileId, Span},
     syntax::tokens::{Token, TokenKind},
 };
 
-pub fn block_inference<'a>(
-    tokens: impl IntoIterator<Item = Token<'a>>,
-) -> Result<Vec<Token<'a>>, SyntaxErr<'a>> {
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Opener {
+    Eq,
+    Then,
+    Else,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Context {
+    // An implicit block, closed as soon as a line starts at or before `column`. `multiline`
+    // is set when its opener was the last token of its line
+    Block {
+        opener: Opener,
+        column: usize,
+        multiline: bool,
+    },
+    // Explicit delimiters, only closed by their matching token
+    Paren,
+    Brace,
+}
+
+fn close_block<'a>(
+    context_stack: &mut Vec<Context>,
+    result_vec: &mut Vec<Token<'a>>,
+    pos: usize,
+) -> Option<Opener> {
+    match context_stack.last() {
+        Some(Context::Block { opener, .. }) => {
+            let opener = *opener;
+            context_stack.pop();
+            result_vec.push(Token {
+                kind: TokenKind::RBrace,
+                span: pos..pos,
+            });
+            Some(opener)
+        }
+        _ => None,
+    }
+}
+
+fn close_delimiter<'a>(
+    context_stack: &mut Vec<Context>,
+    result_vec: &mut Vec<Token<'a>>,
+    delimiter: Context,
+    pos: usize,
+) {
+    if !context_stack.contains(&delimiter) {
+        return;
+    }
+    while close_block(context_stack, result_vec, pos).is_some() {}
+    if context_stack.last() == Some(&delimiter) {
+        context_stack.pop();
+    }
+}
+
+struct Inferred<'a> {
+    tokens: Vec<Token<'a>>,
+    open_contexts: Vec<Context>,
+    can_close_instr: bool,
+}
+
+// Fails on the token that couldn't be laid out
+fn infer<'a>(tokens: impl IntoIterator<Item = Token<'a>>) -> Result<Inferred<'a>, Token<'a>> {
     let mut iter = tokens.into_iter().peekable();
     let mut result_vec = vec![];
-    let mut context_stack: Vec<usize> = vec![];
+    let mut context_stack: Vec<Context> = vec![];
     let mut can_close_instr = false;
-    let mut last_newline = 0;
+    let mut line_start = 0;
+    let mut line_indent = iter.peek().map_or(0, |t| t.span.start);
     while let Some(Token { kind, span }) = iter.next() {
         match kind {
             TokenKind::Newline => {
-                last_newline = span.start;
+                // A block opened at the end of a line lasts as long as the following lines are
+                // more indented than the line of its opener
+                if let (
+                    Some(Token {
+                        kind: TokenKind::LBrace,
+                        ..
+                    }),
+                    Some(Context::Block {
+                        column, multiline, ..
+                    }),
+                ) = (result_vec.last(), context_stack.last_mut())
+                {
+                    *column = line_indent;
+                    *multiline = true;
+                }
+                line_start = span.end;
+                // Tokens inferred at the end of a line are empty and placed right before it
+                let eol = span.start;
                 if let Some(Token { span, kind }) = iter.peek() {
-                    let start_next = span.start - last_newline;
-                    while let Some(n) = context_stack.last() {
-                        if *n >= start_next {
-                            context_stack.pop();
-                            can_close_instr = false;
-                            result_vec.push(Token {
-                                kind: TokenKind::RBrace,
-                                span: start_next..start_next,
-                            });
-                            result_vec.push(Token {
-                                kind: TokenKind::Semicolon,
-                                span: span.clone(),
-                            })
+                    let start_next = span.start - line_start;
+                    line_indent = start_next;
+                    let mut closed = false;
+                    while let Some(Context::Block { column, .. }) = context_stack.last() {
+                        if *column >= start_next {
+                            close_block(&mut context_stack, &mut result_vec, eol);
+                            closed = true;
                         } else {
                             break;
                         }
                     }
-                    if can_close_instr {
-                        match kind {
-                            TokenKind::Op(_) | TokenKind::Then | TokenKind::Else => (),
-                            _ => {
-                                result_vec.push(Token {
-                                    kind: TokenKind::Semicolon,
-                                    span: span.clone(),
-                                });
-                            }
-                        }
+                    if let Some(Context::Paren) = context_stack.last() {
+                        continue;
                     }
-                } else {
-                    if can_close_instr {
+                    let close_instr = match kind {
+                        TokenKind::Then | TokenKind::Else => false,
+                        TokenKind::Op(_) | TokenKind::Arrow | TokenKind::FatArrow => closed,
+                        _ => closed || can_close_instr,
+                    };
+                    if close_instr {
+                        can_close_instr = false;
                         result_vec.push(Token {
                             kind: TokenKind::Semicolon,
-                            span: span.clone(),
+                            span: eol..eol,
                         });
                     }
+                } else if can_close_instr {
+                    can_close_instr = false;
+                    result_vec.push(Token {
+                        kind: TokenKind::Semicolon,
+                        span: eol..eol,
+                    });
                 }
             }
             t @ (TokenKind::Then | TokenKind::Else) => {
-                context_stack.push(span.start - last_newline);
+                let opener = if t == TokenKind::Then {
+                    Opener::Then
+                } else {
+                    // An `else` on the same line as its `then` closes every block
+                    // opened since, up to the `then` block itself
+                    let then_pos = context_stack.iter().rposition(|c| match c {
+                        Context::Block { opener, .. } => *opener == Opener::Then,
+                        _ => true,
+                    });
+                    if let Some(pos) = then_pos {
+                        if let Context::Block { .. } = context_stack[pos] {
+                            while context_stack.len() > pos {
+                                close_block(&mut context_stack, &mut result_vec, span.start);
+                            }
+                        }
+                    }
+                    Opener::Else
+                };
+                context_stack.push(Context::Block {
+                    opener,
+                    column: span.start - line_start,
+                    multiline: false,
+                });
+                can_close_instr = false;
                 let end = span.end;
                 result_vec.push(Token { kind: t, span });
                 result_vec.push(Token {
@@ -67,13 +164,16 @@ pub fn block_inference<'a>(
             t @ TokenKind::Eq => {
                 let Token {
                     span: last_span, ..
-                } = result_vec.last().ok_or_else(|| SyntaxErr {
-                    kind: SyntaxErrKind::UnexpectedToken(t.clone()),
+                } = result_vec.last().ok_or_else(|| Token {
+                    kind: t.clone(),
                     span: span.clone(),
-                    expected: Expected::Item,
-                    note: Some("Maybe you meant to declare an item?"),
                 })?;
-                context_stack.push(last_span.end - last_newline);
+                context_stack.push(Context::Block {
+                    opener: Opener::Eq,
+                    column: last_span.end.saturating_sub(line_start),
+                    multiline: false,
+                });
+                can_close_instr = false;
                 let end = span.end;
                 result_vec.push(Token { kind: t, span });
                 result_vec.push(Token {
@@ -81,7 +181,51 @@ pub fn block_inference<'a>(
                     span: end..end,
                 });
             }
-            TokenKind::Op(_) | TokenKind::If | TokenKind::LBrace | TokenKind::Semicolon => {
+            TokenKind::LParen | TokenKind::LBrace => {
+                context_stack.push(if kind == TokenKind::LParen {
+                    Context::Paren
+                } else {
+                    Context::Brace
+                });
+                can_close_instr = false;
+                result_vec.push(Token { kind, span })
+            }
+            TokenKind::RParen | TokenKind::RBrace => {
+                let delimiter = if kind == TokenKind::RParen {
+                    Context::Paren
+                } else {
+                    Context::Brace
+                };
+                close_delimiter(&mut context_stack, &mut result_vec, delimiter, span.start);
+                can_close_instr = true;
+                result_vec.push(Token { kind, span })
+            }
+            TokenKind::Op(_)
+            | TokenKind::If
+            | TokenKind::Let
+            | TokenKind::Class
+            | TokenKind::Instance
+            | TokenKind::Data
+            | TokenKind::Owned
+            | TokenKind::Tailrec
+            | TokenKind::Mut
+            | TokenKind::Backslash
+            | TokenKind::Arrow
+            | TokenKind::FatArrow
+            | TokenKind::Colon
+            | TokenKind::Comma => {
+                can_close_instr = false;
+                result_vec.push(Token { kind, span })
+            }
+            TokenKind::Semicolon => {
+                // A `;` ends the blocks opened on its line whose opener didn't end the line, like
+                // the `=` of a `let` in braces
+                while let Some(Context::Block {
+                    multiline: false, ..
+                }) = context_stack.last()
+                {
+                    close_block(&mut context_stack, &mut result_vec, span.start);
+                }
                 can_close_instr = false;
                 result_vec.push(Token { kind, span })
             }
@@ -91,27 +235,88 @@ pub fn block_inference<'a>(
             }
         }
     }
-    if result_vec.len() != 0 {
-        let end = result_vec.last().unwrap().span.end;
-        for _ in context_stack {
-            result_vec.push(Token {
-                kind: TokenKind::RBrace,
-                span: end..end,
-            });
+    Ok(Inferred {
+        tokens: result_vec,
+        open_contexts: context_stack,
+        can_close_instr,
+    })
+}
+
+pub fn block_inference<'a>(
+    file: FileId,
+    tokens: impl IntoIterator<Item = Token<'a>>,
+) -> Result<Vec<Token<'a>>, SyntaxErr<'a>> {
+    let Inferred {
+        tokens: mut result_vec,
+        open_contexts,
+        can_close_instr,
+    } = infer(tokens).map_err(|Token { kind, span }| SyntaxErr {
+        kind: SyntaxErrKind::UnexpectedToken(kind),
+        span: Span::new(file, span),
+        expected: Expected::Item,
+        note: Some("Maybe you meant to declare an item?"),
+        suggestions: vec![],
+    })?;
+    let end = result_vec.last().map_or(0, |t| t.span.end);
+    let mut closed = false;
+    // Blocks opened before an unclosed delimiter are left open, the parser reports them
+    for context in open_contexts.into_iter().rev() {
+        match context {
+            Context::Block { .. } => {
+                closed = true;
+                result_vec.push(Token {
+                    kind: TokenKind::RBrace,
+                    span: end..end,
+                });
+            }
+            Context::Paren | Context::Brace => break,
         }
     }
-    if can_close_instr {
+    if can_close_instr || closed {
         result_vec.push(Token {
             kind: TokenKind::Semicolon,
-            span: result_vec.last().unwrap().span.clone(),
+            span: end..end,
         });
     }
-    io::stdout().flush().unwrap();
     Ok(result_vec)
 }
 
+/// Whether more lines could still complete `tokens`: an unclosed delimiter, a trailing
+/// operator, or a block whose opener (`=`, `then` or `else`) ended its line.
+/// Layout errors aren't waited on, they can't be fixed by the next lines.
+pub fn is_open<'a>(tokens: impl IntoIterator<Item = Token<'a>>) -> bool {
+    let Inferred {
+        tokens,
+        open_contexts,
+        ..
+    } = match infer(tokens) {
+        Ok(inferred) => inferred,
+        Err(_) => return false,
+    };
+    let trailing_token = matches!(
+        tokens.last().map(|t| &t.kind),
+        Some(
+            TokenKind::LBrace
+                | TokenKind::Op(_)
+                | TokenKind::If
+                | TokenKind::Return
+                | TokenKind::Break
+                | TokenKind::Let
+                | TokenKind::Backslash
+                | TokenKind::Arrow
+                | TokenKind::FatArrow
+                | TokenKind::Colon
+        )
+    );
+    trailing_token
+        || open_contexts.iter().any(|c| match c {
+            Context::Block { multiline, .. } => *multiline,
+            Context::Paren | Context::Brace => true,
+        })
+}
+
+#[cfg(test)]
 mod test {
-    #![allow(unused_imports)]
     use super::*;
     use logos::Logos;
     #[test]
@@ -130,7 +335,7 @@ mod test {
                 span: 3..3,
             },
             Token {
-                kind: TokenKind::Number(5),
+                kind: TokenKind::Number("5"),
                 span: 5..6,
             },
             Token {
@@ -142,7 +347,7 @@ mod test {
                 span: 11..13,
             },
             Token {
-                kind: TokenKind::Number(2),
+                kind: TokenKind::Number("2"),
                 span: 14..15,
             },
             Token {
@@ -154,7 +359,7 @@ mod test {
                 span: 0..0,
             },
             Token {
-                kind: TokenKind::Number(4),
+                kind: TokenKind::Number("4"),
                 span: 25..26,
             },
             Token {
@@ -170,7 +375,7 @@ mod test {
                 span: 0..0,
             },
             Token {
-                kind: TokenKind::Number(2),
+                kind: TokenKind::Number("2"),
                 span: 36..37,
             },
             Token {
@@ -198,7 +403,7 @@ mod test {
                 span: 40..40,
             },
             Token {
-                kind: TokenKind::Number(3),
+                kind: TokenKind::Number("3"),
                 span: 42..43,
             },
             Token {
@@ -206,7 +411,7 @@ mod test {
                 span: 44..45,
             },
             Token {
-                kind: TokenKind::Number(2),
+                kind: TokenKind::Number("2"),
                 span: 46..47,
             },
             Token {
@@ -214,7 +419,7 @@ mod test {
                 span: 48..49,
             },
             Token {
-                kind: TokenKind::Number(3),
+                kind: TokenKind::Number("3"),
                 span: 50..51,
             },
             Token {
@@ -230,7 +435,7 @@ mod test {
                 span: 53..54,
             },
             Token {
-                kind: TokenKind::Number(24),
+                kind: TokenKind::Number("24"),
                 span: 55..57,
             },
             Token {
@@ -238,7 +443,7 @@ mod test {
                 span: 58..59,
             },
             Token {
-                kind: TokenKind::Number(5),
+                kind: TokenKind::Number("5"),
                 span: 58..59,
             },
         ];
@@ -251,8 +456,8 @@ a = 3 + 2 * 3
  - 24
 5";
         let lex = TokenKind::lexer(code);
-        let result = block_inference(lex.spanned().map(|t| Token::from_tuple(t))).unwrap();
-        for (t1, t2) in vec.into_iter().zip(result.into_iter()) {
+        let result = block_inference(0, lex.spanned().map(Token::from_tuple)).unwrap();
+        for (t1, t2) in vec.into_iter().zip(result) {
             assert_eq!(t1.kind, t2.kind);
         }
     }
@@ -272,7 +477,7 @@ a = 3 + 2 * 3
                 span: 7..7,
             },
             Token {
-                kind: TokenKind::Number(5),
+                kind: TokenKind::Number("5"),
                 span: 9..10,
             },
             Token {
@@ -292,7 +497,7 @@ a = 3 + 2 * 3
                 span: 21..21,
             },
             Token {
-                kind: TokenKind::Number(8),
+                kind: TokenKind::Number("8"),
                 span: 23..24,
             },
             Token {
@@ -300,7 +505,7 @@ a = 3 + 2 * 3
                 span: 25..26,
             },
             Token {
-                kind: TokenKind::Number(2),
+                kind: TokenKind::Number("2"),
                 span: 27..28,
             },
             Token {
@@ -308,7 +513,7 @@ a = 3 + 2 * 3
                 span: 39..40,
             },
             Token {
-                kind: TokenKind::Number(5),
+                kind: TokenKind::Number("5"),
                 span: 39..40,
             },
             Token {
@@ -320,7 +525,7 @@ a = 3 + 2 * 3
                 span: 49..50,
             },
             Token {
-                kind: TokenKind::Number(2),
+                kind: TokenKind::Number("2"),
                 span: 49..50,
             },
             Token {
@@ -343,9 +548,104 @@ a = 3 + 2 * 3
         2
     ";
         let lex = TokenKind::lexer(code);
-        let result = block_inference(lex.spanned().map(|t| Token::from_tuple(t))).unwrap();
-        for (t1, t2) in vec.into_iter().zip(result.into_iter()) {
+        let result = block_inference(0, lex.spanned().map(Token::from_tuple)).unwrap();
+        for (t1, t2) in vec.into_iter().zip(result) {
             assert_eq!(t1.kind, t2.kind);
         }
     }
+    #[test]
+    fn single_line_if() {
+        let lex = TokenKind::lexer("a = if b then 1 else 2");
+        let result = block_inference(0, lex.spanned().map(Token::from_tuple)).unwrap();
+        let kinds: Vec<_> = result.into_iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Ident("a"),
+                TokenKind::Eq,
+                TokenKind::LBrace,
+                TokenKind::If,
+                TokenKind::Ident("b"),
+                TokenKind::Then,
+                TokenKind::LBrace,
+                TokenKind::Number("1"),
+                TokenKind::RBrace,
+                TokenKind::Else,
+                TokenKind::LBrace,
+                TokenKind::Number("2"),
+                TokenKind::RBrace,
+                TokenKind::RBrace,
+                TokenKind::Semicolon,
+            ]
+        );
+    }
+    #[test]
+    fn semicolons_close_single_line_blocks() {
+        let kinds = |code| {
+            let lex = TokenKind::lexer(code);
+            let result = block_inference(0, lex.spanned().map(Token::from_tuple)).unwrap();
+            result.into_iter().map(|t| t.kind).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            kinds("a = 1; b = 2"),
+            vec![
+                TokenKind::Ident("a"),
+                TokenKind::Eq,
+                TokenKind::LBrace,
+                TokenKind::Number("1"),
+                TokenKind::RBrace,
+                TokenKind::Semicolon,
+                TokenKind::Ident("b"),
+                TokenKind::Eq,
+                TokenKind::LBrace,
+                TokenKind::Number("2"),
+                TokenKind::RBrace,
+                TokenKind::Semicolon,
+            ]
+        );
+        assert_eq!(
+            kinds("a = { let y = 1; y }"),
+            vec![
+                TokenKind::Ident("a"),
+                TokenKind::Eq,
+                TokenKind::LBrace,
+                TokenKind::LBrace,
+                TokenKind::Let,
+                TokenKind::Ident("y"),
+                TokenKind::Eq,
+                TokenKind::LBrace,
+                TokenKind::Number("1"),
+                TokenKind::RBrace,
+                TokenKind::Semicolon,
+                TokenKind::Ident("y"),
+                TokenKind::RBrace,
+                TokenKind::RBrace,
+                TokenKind::Semicolon,
+            ]
+        );
+        // The statements of a block whose opener ends its line are separated by them
+        assert_eq!(
+            kinds("a =\n  1; 2\n"),
+            vec![
+                TokenKind::Ident("a"),
+                TokenKind::Eq,
+                TokenKind::LBrace,
+                TokenKind::Number("1"),
+                TokenKind::Semicolon,
+                TokenKind::Number("2"),
+                TokenKind::Semicolon,
+                TokenKind::RBrace,
+                TokenKind::Semicolon,
+            ]
+        );
+    }
+    #[test]
+    fn open_inputs() {
+        let open = |code| is_open(TokenKind::lexer(code).spanned().map(Token::from_tuple));
+        assert!(open("a = if b then"));
+        assert!(open("a = (1 +"));
+        assert!(open("f x =\n    if x"));
+        assert!(!open("a = if b then 1 else 2"));
+        assert!(!open("1 + 2"));
+    }
 }
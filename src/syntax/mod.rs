@@ -4,3 +4,71 @@ mod optable;
 pub mod parser;
 mod shunting_yard;
 pub mod tokens;
+
+use logos::Logos;
+
+use crate::{errors::syntax_err::SyntaxErr, source::FileId};
+use ast::{Expr, Item, Node};
+use insensitive_layout::block_inference;
+use parser::Parser;
+use tokens::{Token, TokenKind};
+
+pub fn lex(code: &str) -> Vec<Token<'_>> {
+    TokenKind::lexer(code)
+        .spanned()
+        .map(Token::from_tuple)
+        .collect()
+}
+
+pub fn parse_program(file: FileId, code: &str) -> Result<Vec<Node<Item<'_>>>, Vec<SyntaxErr<'_>>> {
+    let block_tokens = block_inference(file, lex(code)).map_err(|e| vec![e])?;
+    let mut parser = Parser::new(file, block_tokens.into_iter());
+    let items = parser.program();
+    if parser.errors.is_empty() {
+        Ok(items)
+    } else {
+        Err(parser.errors)
+    }
+}
+
+pub fn parse_expr(file: FileId, code: &str) -> Result<Node<Expr<'_>>, Vec<SyntaxErr<'_>>> {
+    let block_tokens = block_inference(file, lex(code)).map_err(|e| vec![e])?;
+    let mut parser = Parser::new(file, block_tokens.into_iter());
+    match parser.single_expr() {
+        Ok(e) if parser.errors.is_empty() => Ok(e),
+        Ok(_) => Err(parser.errors),
+        Err(e) => {
+            parser.errors.push(e);
+            Err(parser.errors)
+        }
+    }
+}
+
+/// Whether `tokens` start like an item declaration (`name params* =`, a class, an instance
+/// or a data type)
+/// rather than an expression
+pub fn is_declaration(tokens: &[Token]) -> bool {
+    let params = tokens
+        .iter()
+        .skip(1)
+        .take_while(|t| {
+            matches!(
+                t.kind,
+                TokenKind::Ident(_) | TokenKind::Number(_) | TokenKind::Bool(_) | TokenKind::Unit
+            )
+        })
+        .count();
+    match tokens.first().map(|t| &t.kind) {
+        Some(
+            TokenKind::Class
+            | TokenKind::Instance
+            | TokenKind::Data
+            | TokenKind::Owned
+            | TokenKind::Tailrec,
+        ) => true,
+        Some(TokenKind::Ident(_)) => {
+            matches!(tokens.get(params + 1).map(|t| &t.kind), Some(TokenKind::Eq))
+        }
+        _ => false,
+    }
+}
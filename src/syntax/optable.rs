@@ -109,6 +109,22 @@ lazy_static! {
                 sym: "!",
             },
         );
+        map.insert(
+            "&".to_string(),
+            Operator {
+                prec: 25,
+                fixity: Fixity::Prefix,
+                sym: "&",
+            },
+        );
+        map.insert(
+            "&mut".to_string(),
+            Operator {
+                prec: 25,
+                fixity: Fixity::Prefix,
+                sym: "&mut",
+            },
+        );
         map
     };
 }
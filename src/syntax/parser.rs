@@ -1,7 +1,7 @@
 // todo write some tests
 // todo cleanup the code
 
-use std::{convert::TryFrom, iter::Peekable};
+use std::{convert::TryFrom, iter::Peekable, ops::Range};
 
 use super::ast::*;
 use super::{
@@ -9,7 +9,10 @@ use super::{
     shunting_yard::*,
     tokens::{Token, TokenKind},
 };
-use crate::errors::syntax_err::*;
+use crate::{
+    errors::{syntax_err::*, Suggestion},
+    source::{FileId, Span},
+};
 
 macro_rules! tok {
     ($name: ident, $token: pat) => {
@@ -24,35 +27,563 @@ macro_rules! tok {
 }
 
 pub struct Parser<'a, I: Iterator<Item = Token<'a>>> {
+    file: FileId,
     tokens: Peekable<I>,
+    last: Option<Token<'a>>,
+    // The `=`, `then` or `else` that opened each enclosing block, `None` for explicit braces
+    blocks: Vec<Option<Token<'a>>>,
     pub errors: Vec<SyntaxErr<'a>>,
 }
 #[allow(dead_code)]
 impl<'a, I: Iterator<Item = Token<'a>>> Parser<'a, I> {
-    pub fn new(iter: I) -> Self {
+    pub fn new(file: FileId, iter: I) -> Self {
         Self {
+            file,
             tokens: iter.peekable(),
+            last: None,
+            blocks: vec![],
             errors: vec![],
         }
     }
+    pub fn program(&mut self) -> Vec<Node<Item<'a>>> {
+        let mut items = vec![];
+        while self.peek().is_some() {
+            if self.semicolon().is_some() {
+                continue;
+            }
+            // Nothing else consumes an unmatched `}` at the top level
+            if let Some(Token {
+                kind: TokenKind::RBrace,
+                ..
+            }) = self.peek()
+            {
+                let err = self.unexpected(Expected::Item);
+                self.errors.push(err);
+                self.next();
+                continue;
+            }
+            match self.item() {
+                Ok(item) => {
+                    items.push(item);
+                    if self.peek().is_some() && self.semicolon().is_none() {
+                        let err = self.unexpected(Expected::Semicolon);
+                        self.errors.push(err);
+                        self.restore();
+                    }
+                }
+                Err(e) => {
+                    self.errors.push(e);
+                    self.restore();
+                }
+            }
+        }
+        items
+    }
+    pub fn item(&mut self) -> Result<Node<Item<'a>>, SyntaxErr<'a>> {
+        if let Some(Token { span, .. }) = self.class() {
+            return self.class_decl(span);
+        }
+        if let Some(Token { span, .. }) = self.instance() {
+            return self.instance_decl(span);
+        }
+        if let Some(Token { span, .. }) = self.data() {
+            return self.data_decl(span, false);
+        }
+        if let Some(Token { span, .. }) = self.owned() {
+            self.data()
+                .ok_or_else(|| self.unexpected(Expected::Token("data")))?;
+            return self.data_decl(span, true);
+        }
+        self.function()
+    }
+    // `tailrec name params = body` starts at `tailrec`
+    fn function(&mut self) -> Result<Node<Item<'a>>, SyntaxErr<'a>> {
+        let tailrec = self.tailrec().map(|Token { span, .. }| span);
+        let name = match self.expr_ident() {
+            Some(name) => name,
+            None => {
+                let errors = self.errors.len();
+                return Err(match self.expr() {
+                    Ok(Node { value, span }) => SyntaxErr {
+                        span,
+                        kind: SyntaxErrKind::UnexpectedExpr(value),
+                        expected: Expected::Item,
+                        note: Some("Expressions can only appear inside of items"),
+                        suggestions: vec![],
+                    },
+                    Err(_) if self.errors.len() == errors => self.unexpected(Expected::Item),
+                    Err(e) => e,
+                });
+            }
+        };
+        let mut params = vec![];
+        while let Some(param) = self.pattern()? {
+            params.push(param);
+        }
+        self.eq()
+            .ok_or_else(|| self.unexpected(Expected::Token("=")))?;
+        let errors = self.errors.len();
+        let body = match self.block() {
+            Some(body) => body,
+            None => return Err(self.failed(errors, Expected::Expr)),
+        };
+        let name = match name {
+            Node {
+                value: Expr::Ident(s),
+                span,
+            } => Node { value: s, span },
+            _ => unreachable!(),
+        };
+        let span = match &tailrec {
+            Some(start) => self.span(start.start..body.span.range.end),
+            None => name.span.merge(&body.span),
+        };
+        Ok(Node {
+            span,
+            value: Item::Function {
+                name,
+                params,
+                body,
+                tailrec: tailrec.is_some(),
+            },
+        })
+    }
+    // `class Name param = { signature; ... }`
+    fn class_decl(&mut self, start: Range<usize>) -> Result<Node<Item<'a>>, SyntaxErr<'a>> {
+        let name = self.name()?;
+        let param = self.type_param()?;
+        self.eq()
+            .ok_or_else(|| self.unexpected(Expected::Token("=")))?;
+        let (methods, end) = self.members(Self::signature)?;
+        Ok(Node {
+            span: self.span(start.start..end),
+            value: Item::Class {
+                name,
+                param,
+                methods,
+            },
+        })
+    }
+    // `instance context => Class type = { function; ... }`, the context being a constraint
+    // or `(constraint, ...)`, and left out with its `=>` when it's empty
+    fn instance_decl(&mut self, start: Range<usize>) -> Result<Node<Item<'a>>, SyntaxErr<'a>> {
+        let mut context = vec![];
+        if self.lparen().is_some() {
+            context.push(self.constraint()?);
+            while self.comma().is_some() {
+                context.push(self.constraint()?);
+            }
+            self.rparen()
+                .ok_or_else(|| self.unexpected(Expected::Token(")")))?;
+            self.fat_arrow()
+                .ok_or_else(|| self.unexpected(Expected::Token("=>")))?;
+        }
+        let mut head = self.constraint()?;
+        if context.is_empty() && self.fat_arrow().is_some() {
+            context.push(head);
+            head = self.constraint()?;
+        }
+        let Constraint { class, ty } = head.value;
+        self.eq()
+            .ok_or_else(|| self.unexpected(Expected::Token("=")))?;
+        let (methods, end) = self.members(Self::function)?;
+        Ok(Node {
+            span: self.span(start.start..end),
+            value: Item::Instance {
+                context,
+                class,
+                ty,
+                methods,
+            },
+        })
+    }
+    // `Class type`
+    fn constraint(&mut self) -> Result<Node<Constraint<'a>>, SyntaxErr<'a>> {
+        let class = self.name()?;
+        let ty = self.type_atom()?;
+        Ok(Node {
+            span: class.span.merge(&ty.span),
+            value: Constraint { class, ty },
+        })
+    }
+    // `data Name: params = { constructor; ... }`, the `:` is left out without parameters.
+    // `owned data` starts at `owned`
+    fn data_decl(
+        &mut self,
+        start: Range<usize>,
+        owned: bool,
+    ) -> Result<Node<Item<'a>>, SyntaxErr<'a>> {
+        let name = self.name()?;
+        let mut params = vec![];
+        if self.colon().is_some() {
+            while matches!(
+                self.peek().map(|t| &t.kind),
+                Some(TokenKind::Ident(_) | TokenKind::LParen)
+            ) {
+                params.push(self.type_param()?);
+            }
+        }
+        self.eq()
+            .ok_or_else(|| self.unexpected(Expected::Token("=")))?;
+        let (ctors, end) = self.members(Self::constructor)?;
+        Ok(Node {
+            span: self.span(start.start..end),
+            value: Item::Data {
+                name,
+                params,
+                ctors,
+                owned,
+            },
+        })
+    }
+    fn constructor(&mut self) -> Result<Node<Constructor<'a>>, SyntaxErr<'a>> {
+        let name = self.name()?;
+        let mut fields = vec![];
+        while self.at_type_atom() {
+            fields.push(self.type_atom()?);
+        }
+        Ok(Node {
+            span: name
+                .span
+                .merge(fields.last().map_or(&name.span, |f| &f.span)),
+            value: Constructor { name, fields },
+        })
+    }
+    // `name` or `(name : kind)`
+    fn type_param(&mut self) -> Result<TypeParam<'a>, SyntaxErr<'a>> {
+        if self.lparen().is_none() {
+            let name = self.name()?;
+            return Ok(TypeParam { name, kind: None });
+        }
+        let name = self.name()?;
+        self.colon()
+            .ok_or_else(|| self.unexpected(Expected::Token(":")))?;
+        let kind = self.kind_expr()?;
+        self.rparen()
+            .ok_or_else(|| self.unexpected(Expected::Token(")")))?;
+        Ok(TypeParam {
+            name,
+            kind: Some(kind),
+        })
+    }
+    // `*`, or arrows between kinds
+    fn kind_expr(&mut self) -> Result<Node<KindExpr>, SyntaxErr<'a>> {
+        let param = match self.peek().cloned() {
+            Some(Token {
+                kind: TokenKind::Op("*"),
+                span,
+            }) => {
+                self.next();
+                Node {
+                    value: KindExpr::Star,
+                    span: self.span(span),
+                }
+            }
+            Some(Token {
+                kind: TokenKind::LParen,
+                span,
+            }) => {
+                self.next();
+                let kind = self.kind_expr()?;
+                let end = self
+                    .rparen()
+                    .ok_or_else(|| self.unexpected(Expected::Token(")")))?;
+                Node {
+                    value: kind.value,
+                    span: self.span(span.start..end.span.end),
+                }
+            }
+            _ => return Err(self.unexpected(Expected::Kind)),
+        };
+        if self.arrow().is_none() {
+            return Ok(param);
+        }
+        let ret = self.kind_expr()?;
+        Ok(Node {
+            span: param.span.merge(&ret.span),
+            value: KindExpr::Arrow(param.into_boxed(), ret.into_boxed()),
+        })
+    }
+    // The block of declarations of a class or an instance, returning where it ends
+    #[allow(clippy::type_complexity)]
+    fn members<T: Clone>(
+        &mut self,
+        member: fn(&mut Self) -> Result<Node<T>, SyntaxErr<'a>>,
+    ) -> Result<(Vec<Node<T>>, usize), SyntaxErr<'a>> {
+        let opener = self.last.clone();
+        let Token { span: start, .. } = self
+            .lbrace()
+            .ok_or_else(|| self.unexpected(Expected::Token("{")))?;
+        self.blocks
+            .push(if start.is_empty() { opener } else { None });
+        let mut members = vec![];
+        let end = loop {
+            if let Some(Token { span, .. }) = self.rbrace() {
+                break Ok(span.end);
+            }
+            if self.semicolon().is_some() {
+                continue;
+            }
+            if self.peek().is_none() {
+                break Err(self.unexpected(Expected::Token("}")));
+            }
+            match member(self) {
+                Ok(m) => {
+                    members.push(m);
+                    if !matches!(
+                        self.peek().map(|t| &t.kind),
+                        Some(TokenKind::Semicolon | TokenKind::RBrace)
+                    ) {
+                        let err = self.unexpected(Expected::Semicolon);
+                        self.errors.push(err);
+                        self.restore();
+                    }
+                }
+                Err(e) => {
+                    self.errors.push(e);
+                    self.restore();
+                }
+            }
+        };
+        self.blocks.pop();
+        end.map(|end| (members, end))
+    }
+    // `name : type`
+    fn signature(&mut self) -> Result<Node<Signature<'a>>, SyntaxErr<'a>> {
+        let name = self.name()?;
+        self.colon()
+            .ok_or_else(|| self.unexpected(Expected::Token(":")))?;
+        let ty = self.type_expr()?;
+        Ok(Node {
+            span: name.span.merge(&ty.span),
+            value: Signature { name, ty },
+        })
+    }
+    // Arrows are right associative, `a -> b -> c` is `a -> (b -> c)`
+    fn type_expr(&mut self) -> Result<Node<TypeExpr<'a>>, SyntaxErr<'a>> {
+        let param = self.type_app()?;
+        if self.arrow().is_none() {
+            return Ok(param);
+        }
+        let ret = self.type_expr()?;
+        Ok(Node {
+            span: param.span.merge(&ret.span),
+            value: TypeExpr::Fun(param.into_boxed(), ret.into_boxed()),
+        })
+    }
+    // Application is left associative, `f a b` is `(f a) b`. A reference to an application
+    // takes all of it, `&List a` is `&(List a)`
+    fn type_app(&mut self) -> Result<Node<TypeExpr<'a>>, SyntaxErr<'a>> {
+        if let Some((mutable, start)) = self.ref_prefix() {
+            let ty = self.type_app()?;
+            return Ok(Node {
+                span: start.merge(&ty.span),
+                value: TypeExpr::Ref(mutable, ty.into_boxed()),
+            });
+        }
+        let mut ty = self.type_atom()?;
+        while self.at_type_atom() {
+            let arg = self.type_atom()?;
+            ty = Node {
+                span: ty.span.merge(&arg.span),
+                value: TypeExpr::App(ty.into_boxed(), arg.into_boxed()),
+            };
+        }
+        Ok(ty)
+    }
+    fn at_type_atom(&mut self) -> bool {
+        matches!(
+            self.peek().map(|t| &t.kind),
+            Some(TokenKind::Unit | TokenKind::LParen | TokenKind::Ident(_) | TokenKind::Op("&"))
+        )
+    }
+    // `&` or `&mut`, and whether it's the latter
+    fn ref_prefix(&mut self) -> Option<(bool, Span)> {
+        if !matches!(self.peek().map(|t| &t.kind), Some(TokenKind::Op("&"))) {
+            return None;
+        }
+        let start = self.next().unwrap().span;
+        Some(match self.mut_() {
+            Some(Token { span, .. }) => (true, self.span(start.start..span.end)),
+            None => (false, self.span(start)),
+        })
+    }
+    fn type_atom(&mut self) -> Result<Node<TypeExpr<'a>>, SyntaxErr<'a>> {
+        if let Some((mutable, start)) = self.ref_prefix() {
+            let ty = self.type_atom()?;
+            return Ok(Node {
+                span: start.merge(&ty.span),
+                value: TypeExpr::Ref(mutable, ty.into_boxed()),
+            });
+        }
+        if let Some(Token { span, .. }) = self.unit() {
+            return Ok(Node {
+                value: TypeExpr::Unit,
+                span: self.span(span),
+            });
+        }
+        if let Some(Token { span, .. }) = self.lparen() {
+            let mut tys = vec![self.type_expr()?];
+            while self.comma().is_some() {
+                tys.push(self.type_expr()?);
+            }
+            let end = self
+                .rparen()
+                .ok_or_else(|| self.unexpected(Expected::Token(")")))?;
+            let value = match tys.len() {
+                1 => tys.pop().unwrap().value,
+                _ => TypeExpr::Tuple(tys),
+            };
+            return Ok(Node {
+                value,
+                span: self.span(span.start..end.span.end),
+            });
+        }
+        let Node { value, span } = self.name().map_err(|mut e| {
+            e.expected = Expected::Type;
+            e
+        })?;
+        Ok(Node {
+            value: TypeExpr::Named(value),
+            span,
+        })
+    }
+    fn name(&mut self) -> Result<Node<Ident<'a>>, SyntaxErr<'a>> {
+        match self.ident() {
+            Some(Token {
+                kind: TokenKind::Ident(name),
+                span,
+            }) => Ok(Node {
+                value: name,
+                span: self.span(span),
+            }),
+            _ => Err(self.unexpected(Expected::Name)),
+        }
+    }
+    pub fn single_expr(&mut self) -> Result<Node<Expr<'a>>, SyntaxErr<'a>> {
+        let e = self.expr()?;
+        while self.semicolon().is_some() {}
+        match self.peek() {
+            Some(_) => Err(self.unexpected(Expected::Operator)),
+            None => Ok(e),
+        }
+    }
     pub fn expr(&mut self) -> Result<Node<Expr<'a>>, SyntaxErr<'a>> {
+        let errors = self.errors.len();
         let mut op_or_expr_vec = vec![];
         while let Some(val) = self
             .atom()
-            .map(|e| OpOrExpr::Expr(e))
-            .or_else(|| self.operator().map(|op| OpOrExpr::Op(op)))
+            .map(OpOrExpr::Expr)
+            .or_else(|| self.operator().map(OpOrExpr::Op))
         {
+            let val = match val {
+                OpOrExpr::Op(op)
+                    if !op.value.is_infix()
+                        && matches!(op_or_expr_vec.last(), Some(OpOrExpr::Expr(_))) =>
+                {
+                    OpOrExpr::Expr(self.prefixed_arg(op)?)
+                }
+                val => val,
+            };
             op_or_expr_vec.push(val);
         }
+        if op_or_expr_vec.is_empty() {
+            return Err(self.failed(errors, Expected::Expr));
+        }
         shunting_yard(op_or_expr_vec)
     }
+    // A prefix operator after an operand starts an argument made of the operators and an
+    // atom, `f &x` is `f (&x)`
+    fn prefixed_arg(&mut self, op: Node<Operator<'a>>) -> Result<Node<Expr<'a>>, SyntaxErr<'a>> {
+        let operand = match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Op("!" | "&")) => {
+                let next = self.operator().unwrap();
+                self.prefixed_arg(next)?
+            }
+            _ => self.atom().ok_or_else(|| self.unexpected(Expected::Expr))?,
+        };
+        Ok(Node {
+            span: op.span.merge(&operand.span),
+            value: Expr::Unary(UnOp::try_from(op.value).unwrap(), operand.into_boxed()),
+        })
+    }
+    pub fn statement(&mut self) -> Result<Node<Statement<'a>>, SyntaxErr<'a>> {
+        if let Some(Token { span, .. }) = self.let_() {
+            let pattern = self
+                .ctor_pattern()?
+                .ok_or_else(|| self.unexpected(Expected::Pattern))?;
+            self.eq()
+                .ok_or_else(|| self.unexpected(Expected::Token("=")))?;
+            let errors = self.errors.len();
+            let value = match self.block() {
+                Some(value) => value,
+                None => return Err(self.failed(errors, Expected::Expr)),
+            };
+            return Ok(Node {
+                span: self.span(span).merge(&value.span),
+                value: Statement::Let(pattern, value),
+            });
+        }
+        if let Some(Token { span, .. }) = self.continue_() {
+            return Ok(Node {
+                span: self.span(span),
+                value: Statement::Continue,
+            });
+        }
+        if let Some(Token { span, .. }) = self.break_() {
+            let e = self.expr()?;
+            return Ok(Node {
+                span: self.span(span).merge(&e.span),
+                value: Statement::Break(e),
+            });
+        }
+        match self.return_() {
+            Some(Token { span, .. }) => {
+                let e = self.expr()?;
+                Ok(Node {
+                    span: self.span(span).merge(&e.span),
+                    value: Statement::Return(e),
+                })
+            }
+            None => {
+                let e = self.expr()?;
+                if self.eq().is_none() {
+                    return Ok(Node {
+                        span: e.span,
+                        value: Statement::StmtExpr(e.value),
+                    });
+                }
+                let errors = self.errors.len();
+                let value = match self.block() {
+                    Some(value) => value,
+                    None => return Err(self.failed(errors, Expected::Expr)),
+                };
+                Ok(Node {
+                    span: e.span.merge(&value.span),
+                    value: Statement::Assign(e, value),
+                })
+            }
+        }
+    }
+    // `&mut` is the only operator made of two tokens
     fn operator(&mut self) -> Option<Node<Operator<'a>>> {
-        self.op().map(|Token { kind, span }| match kind {
-            TokenKind::Op(sym) => Node {
-                value: *OPTABLE.get(sym).unwrap(),
+        let (sym, span) = match self.op()? {
+            Token {
+                kind: TokenKind::Op("&"),
                 span,
+            } => match self.mut_() {
+                Some(Token { span: end, .. }) => ("&mut", span.start..end.end),
+                None => ("&", span),
             },
+            Token {
+                kind: TokenKind::Op(sym),
+                span,
+            } => (sym, span),
             _ => unreachable!(),
+        };
+        Some(Node {
+            value: *OPTABLE.get(sym).unwrap(),
+            span: self.span(span),
         })
     }
     fn atom(&mut self) -> Option<Node<Expr<'a>>> {
@@ -63,44 +594,364 @@ impl<'a, I: Iterator<Item = Token<'a>>> Parser<'a, I> {
                 value: match kind {
                     TokenKind::Unit => Expr::Literal(Literal::Unit),
                     TokenKind::Bool(b) => Expr::Literal(Literal::Bool(b)),
-                    TokenKind::Number(n) => Expr::Literal(Literal::Num(n)),
+                    TokenKind::Number(n) => Expr::Literal(Literal::Num(self.number(n, &span))),
                     _ => unreachable!(),
                 },
-                span,
+                span: self.span(span),
             })
             .or_else(|| self.expr_ident())
             .or_else(|| self.parenthesized_expr())
+            .or_else(|| self.if_expr())
+            .or_else(|| self.lambda())
+            .or_else(|| self.block())
+    }
+    // `_`, a binding, a literal, a constructor without fields, or a parenthesized pattern.
+    // Returns `None` when there is no pattern
+    fn pattern(&mut self) -> Result<Option<Node<Pattern<'a>>>, SyntaxErr<'a>> {
+        if let Some(Token { span, .. }) = self.lparen() {
+            return self.parenthesized_pattern(span).map(Some);
+        }
+        if let Some(Token { span: start, .. }) = self.mut_() {
+            return match self.peek() {
+                Some(Token {
+                    kind: TokenKind::Ident(name),
+                    ..
+                }) if *name != "_" && !name.starts_with(char::is_uppercase) => {
+                    let name = *name;
+                    let Token { span: end, .. } = self.ident().unwrap();
+                    Ok(Some(Node {
+                        value: Pattern::MutBinding(name),
+                        span: self.span(start.start..end.end),
+                    }))
+                }
+                _ => Err(self.unexpected(Expected::Name)),
+            };
+        }
+        let Token { kind, span } = match self
+            .ident()
+            .or_else(|| self.num())
+            .or_else(|| self.bool())
+            .or_else(|| self.unit())
+        {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+        let span = self.span(span);
+        let value = match kind {
+            TokenKind::Ident("_") => Pattern::Wildcard,
+            TokenKind::Ident(s) if s.starts_with(char::is_uppercase) => Pattern::Ctor(
+                Node {
+                    value: s,
+                    span: span.clone(),
+                },
+                vec![],
+            ),
+            TokenKind::Ident(s) => Pattern::Binding(s),
+            TokenKind::Number(n) => Pattern::Literal(Literal::Num(self.number(n, &span.range))),
+            TokenKind::Bool(b) => Pattern::Literal(Literal::Bool(b)),
+            TokenKind::Unit => Pattern::Literal(Literal::Unit),
+            _ => unreachable!(),
+        };
+        Ok(Some(Node { value, span }))
+    }
+    // The value of the digits of a number literal, which are reported when they don't fit in an
+    // `Int`
+    fn number(&mut self, digits: &str, span: &Range<usize>) -> i64 {
+        digits.parse().unwrap_or_else(|_| {
+            self.errors.push(SyntaxErr {
+                span: self.span(span.clone()),
+                kind: SyntaxErrKind::LiteralOutOfRange,
+                expected: Expected::None,
+                note: Some("The largest `Int` is 9223372036854775807"),
+                suggestions: vec![],
+            });
+            0
+        })
+    }
+    // A constructor applied to the patterns of its fields, like `Some x`, or a single pattern
+    fn ctor_pattern(&mut self) -> Result<Option<Node<Pattern<'a>>>, SyntaxErr<'a>> {
+        let (name, span) = match self.pattern()? {
+            Some(Node {
+                value: Pattern::Ctor(name, fields),
+                span,
+            }) if fields.is_empty() => (name, span),
+            pattern => return Ok(pattern),
+        };
+        let mut fields = vec![];
+        while let Some(field) = self.pattern()? {
+            fields.push(field);
+        }
+        Ok(Some(Node {
+            span: span.merge(fields.last().map_or(&span, |f| &f.span)),
+            value: Pattern::Ctor(name, fields),
+        }))
+    }
+    // `(pattern)`, or a tuple `(pattern, pattern, ...)`
+    fn parenthesized_pattern(
+        &mut self,
+        start: Range<usize>,
+    ) -> Result<Node<Pattern<'a>>, SyntaxErr<'a>> {
+        let mut patterns = vec![];
+        loop {
+            match self.ctor_pattern()? {
+                Some(pattern) => patterns.push(pattern),
+                None => return Err(self.unexpected(Expected::Pattern)),
+            }
+            if self.comma().is_none() {
+                break;
+            }
+        }
+        match self.rparen() {
+            Some(Token { span: end, .. }) => Ok(Node {
+                span: self.span(start.start..end.end),
+                value: match patterns.len() {
+                    1 => patterns.pop().unwrap().value,
+                    _ => Pattern::Tuple(patterns),
+                },
+            }),
+            None => {
+                let mut err = self.unexpected(Expected::Token(")"));
+                err.kind = SyntaxErrKind::Unclosed {
+                    delimiter: Delimiter::Paren,
+                    opened: self.span(start),
+                };
+                let last = patterns.pop().unwrap();
+                err.suggestions.push(Suggestion::insert(last.span, ")"));
+                Err(err)
+            }
+        }
+    }
+    // `\x y -> e` is sugar for `\x -> \y -> e`
+    fn lambda(&mut self) -> Option<Node<Expr<'a>>> {
+        let Token { span, .. } = self.backslash()?;
+        let lambda = self.lambda_parts().map(|(params, body)| {
+            params.into_iter().rev().fold(body, |body, param| Node {
+                span: param.span.merge(&body.span),
+                value: Expr::Lambda(param, body.into_boxed()),
+            })
+        });
+        match lambda {
+            Ok(Node { value, span: end }) => Some(Node {
+                span: self.span(span).merge(&end),
+                value,
+            }),
+            Err(e) => {
+                self.errors.push(e);
+                self.restore();
+                None
+            }
+        }
+    }
+    #[allow(clippy::type_complexity)]
+    fn lambda_parts(&mut self) -> Result<(Vec<Node<Pattern<'a>>>, Node<Expr<'a>>), SyntaxErr<'a>> {
+        let mut params = vec![];
+        while let Some(param) = self.pattern()? {
+            params.push(param);
+        }
+        if params.is_empty() {
+            return Err(self.unexpected(Expected::Pattern));
+        }
+        self.arrow()
+            .ok_or_else(|| self.unexpected(Expected::Token("->")))?;
+        let body = self.expr()?;
+        Ok((params, body))
     }
     fn expr_ident(&mut self) -> Option<Node<Expr<'a>>> {
+        let file = self.file;
         self.ident().map(|Token { kind, span }| match kind {
             TokenKind::Ident(s) => Node {
                 value: Expr::Ident(s),
-                span,
+                span: Span::new(file, span),
             },
             _ => unreachable!(),
         })
     }
+    // `(e)`, or a tuple `(e, e, ...)`
     fn parenthesized_expr(&mut self) -> Option<Node<Expr<'a>>> {
-        let Token { span, kind } = self.lparen()?;
-        let e = self
-            .expr()
-            .map(|e| Node {
-                span: e.span.clone(),
+        let Token { span, .. } = self.lparen()?;
+        let mut elements = vec![];
+        let e = loop {
+            let e = match self.expr() {
+                Ok(e) => e,
+                Err(e) => {
+                    self.errors.push(e);
+                    self.restore();
+                    return None;
+                }
+            };
+            if self.comma().is_none() {
+                break e;
+            }
+            elements.push(e);
+        };
+        match self.rparen() {
+            Some(Token { span: end, .. }) if elements.is_empty() => Some(Node {
+                span: self.span(span.start..end.end),
                 value: Expr::Parenthesized(e.into_boxed()),
-            })
-            .ok()?;
-        self.rparen().or_else(|| {
-            // todo There could be more information here, but i don't have the variants yet to express them, i should probably fix this at some point
-            self.errors.push(SyntaxErr {
-                span,
-                kind: SyntaxErrKind::UnexpectedToken(kind),
-                expected: Expected::None,
-                note: None,
-            });
-            self.restore();
-            None
+            }),
+            Some(Token { span: end, .. }) => {
+                elements.push(e);
+                Some(Node {
+                    span: self.span(span.start..end.end),
+                    value: Expr::Tuple(elements),
+                })
+            }
+            None => {
+                let mut err = self.unexpected(Expected::Token(")"));
+                err.kind = SyntaxErrKind::Unclosed {
+                    delimiter: Delimiter::Paren,
+                    opened: self.span(span),
+                };
+                err.suggestions.push(Suggestion::insert(e.span, ")"));
+                self.errors.push(err);
+                self.restore();
+                None
+            }
+        }
+    }
+    fn if_expr(&mut self) -> Option<Node<Expr<'a>>> {
+        let Token { span, .. } = self.if_()?;
+        let span = self.span(span);
+        let if_expr = self.if_branches().map(|(cond, then, else_)| Node {
+            span: span.merge(&else_.span),
+            value: Expr::If {
+                cond: cond.into_boxed(),
+                then: then.into_boxed(),
+                else_: else_.into_boxed(),
+            },
         });
-        Some(e)
+        match if_expr {
+            Ok(e) => Some(e),
+            Err(e) => {
+                self.errors.push(e);
+                self.restore();
+                None
+            }
+        }
+    }
+    #[allow(clippy::type_complexity)]
+    fn if_branches(
+        &mut self,
+    ) -> Result<(Node<Expr<'a>>, Node<Expr<'a>>, Node<Expr<'a>>), SyntaxErr<'a>> {
+        let cond = self.expr()?;
+        self.then()
+            .ok_or_else(|| self.unexpected(Expected::Token("then")))?;
+        let errors = self.errors.len();
+        let then = match self.block() {
+            Some(then) => then,
+            None => return Err(self.failed(errors, Expected::Expr)),
+        };
+        if self.else_().is_none() {
+            let mut err = self.unexpected(Expected::Token("else"));
+            let then_end = match &then.value {
+                Expr::Block { instructions, .. } => instructions.last().map(|i| i.span.clone()),
+                _ => None,
+            };
+            err.suggestions.push(
+                Suggestion::insert(then_end.unwrap_or(then.span), " else <expr>")
+                    .with_placeholders(),
+            );
+            return Err(err);
+        }
+        let errors = self.errors.len();
+        let else_ = match self.block() {
+            Some(else_) => else_,
+            None => return Err(self.failed(errors, Expected::Expr)),
+        };
+        Ok((cond, then, else_))
+    }
+    fn block(&mut self) -> Option<Node<Expr<'a>>> {
+        let opener = self.last.clone();
+        let Token { span: start, .. } = self.lbrace()?;
+        // Inferred braces are empty and directly follow their opener
+        self.blocks
+            .push(if start.is_empty() { opener } else { None });
+        let block = self.block_body(start);
+        self.blocks.pop();
+        block
+    }
+    fn block_body(&mut self, start: Range<usize>) -> Option<Node<Expr<'a>>> {
+        let mut instructions: Vec<Node<Statement<'a>>> = vec![];
+        // Whether tokens were skipped after a statement that failed to parse
+        let mut skipped = false;
+        loop {
+            // Inferred blocks can't be empty, unlike explicit ones
+            let closing = matches!(
+                self.peek(),
+                Some(Token {
+                    kind: TokenKind::RBrace,
+                    ..
+                })
+            );
+            if start.is_empty() && instructions.is_empty() && !skipped && closing {
+                let err = self.unexpected(Expected::Expr);
+                self.errors.push(err);
+            }
+            if let Some(Token { span: end, .. }) = self.rbrace() {
+                let returns = matches!(
+                    instructions.last(),
+                    Some(Node {
+                        value: Statement::StmtExpr(_),
+                        ..
+                    })
+                );
+                return Some(Node {
+                    span: self.span(start.start..end.end),
+                    value: Expr::Block {
+                        instructions,
+                        returns,
+                    },
+                });
+            }
+            if self.semicolon().is_some() {
+                continue;
+            }
+            if self.peek().is_none() {
+                // Inferred braces are only left unclosed behind an unclosed delimiter, which is
+                // what gets reported
+                if !start.is_empty() {
+                    // The skipped tokens may still be inside the braces
+                    let eof = self.last_end();
+                    let end = match skipped {
+                        true => eof,
+                        false => instructions.last().map_or(start.end, |i| i.span.range.end),
+                    };
+                    self.errors.push(SyntaxErr {
+                        span: self.span(eof..eof),
+                        kind: SyntaxErrKind::Unclosed {
+                            delimiter: Delimiter::Brace,
+                            opened: self.span(start),
+                        },
+                        expected: Expected::Token("}"),
+                        note: None,
+                        suggestions: vec![Suggestion::insert(self.span(end..end), "}")],
+                    });
+                }
+                return None;
+            }
+            match self.statement() {
+                Ok(stmt) => {
+                    instructions.push(stmt);
+                    match self.peek() {
+                        Some(Token {
+                            kind: TokenKind::Semicolon | TokenKind::RBrace,
+                            ..
+                        }) => (),
+                        _ => {
+                            let err = self.unexpected(Expected::Semicolon);
+                            self.errors.push(err);
+                            self.restore();
+                            skipped = true;
+                        }
+                    }
+                }
+                Err(e) => {
+                    self.errors.push(e);
+                    self.restore();
+                    skipped = true;
+                }
+            }
+        }
     }
     tok!(op, TokenKind::Op(_));
     tok!(num, TokenKind::Number(_));
@@ -113,28 +964,117 @@ impl<'a, I: Iterator<Item = Token<'a>>> Parser<'a, I> {
     tok!(else_, TokenKind::Else);
     tok!(then, TokenKind::Then);
     tok!(return_, TokenKind::Return);
+    tok!(break_, TokenKind::Break);
+    tok!(continue_, TokenKind::Continue);
+    tok!(let_, TokenKind::Let);
+    tok!(class, TokenKind::Class);
+    tok!(instance, TokenKind::Instance);
+    tok!(data, TokenKind::Data);
+    tok!(owned, TokenKind::Owned);
+    tok!(tailrec, TokenKind::Tailrec);
+    tok!(mut_, TokenKind::Mut);
+    tok!(colon, TokenKind::Colon);
+    tok!(comma, TokenKind::Comma);
+    tok!(backslash, TokenKind::Backslash);
+    tok!(arrow, TokenKind::Arrow);
+    tok!(fat_arrow, TokenKind::FatArrow);
     tok!(eq, TokenKind::Eq);
     tok!(semicolon, TokenKind::Semicolon);
     tok!(rbrace, TokenKind::RBrace);
     tok!(lbrace, TokenKind::LBrace);
 
+    fn unexpected(&mut self, expected: Expected) -> SyntaxErr<'a> {
+        let opener = self.blocks.last().cloned().flatten();
+        match self.peek().cloned() {
+            // Tokens inferred by the layout are empty
+            Some(Token {
+                kind: TokenKind::RBrace,
+                span,
+            }) if span.is_empty() && opener.is_some() => {
+                let Token {
+                    kind: opener,
+                    span: opened,
+                } = opener.unwrap();
+                SyntaxErr {
+                    span: Span::new(self.file, span),
+                    kind: SyntaxErrKind::UnexpectedEndOfBlock {
+                        opener,
+                        opened: Span::new(self.file, opened),
+                    },
+                    expected,
+                    note: Some(
+                        "Inferred blocks end before the first line that isn't indented past their opener",
+                    ),
+                    suggestions: vec![],
+                }
+            }
+            Some(Token {
+                kind: TokenKind::Semicolon,
+                span,
+            }) if span.is_empty() => SyntaxErr {
+                span: Span::new(self.file, span),
+                kind: SyntaxErrKind::UnexpectedToken(TokenKind::Newline),
+                expected,
+                note: None,
+                suggestions: vec![],
+            },
+            Some(Token { kind, span }) => SyntaxErr {
+                span: Span::new(self.file, span),
+                kind: SyntaxErrKind::UnexpectedToken(kind),
+                expected,
+                note: None,
+                suggestions: vec![],
+            },
+            None => SyntaxErr {
+                span: self.span(self.last_end()..self.last_end()),
+                kind: SyntaxErrKind::UnexpectedEOF,
+                expected,
+                note: None,
+                suggestions: vec![],
+            },
+        }
+    }
+    // When an error was already reported since the parser had `errors` errors, it is handed
+    // back instead of piling up a new error on top of it
+    fn failed(&mut self, errors: usize, expected: Expected) -> SyntaxErr<'a> {
+        if self.errors.len() > errors {
+            self.errors.pop().unwrap()
+        } else {
+            self.unexpected(expected)
+        }
+    }
+    // Skips tokens up to the end of the current instruction, leaving the `}` closing the
+    // enclosing block (if any) in the stream
     fn restore(&mut self) {
+        let mut depth = 0usize;
         loop {
-            match self.next() {
-                Some(Token {
-                    kind: TokenKind::Semicolon | TokenKind::RBrace,
-                    ..
-                })
-                | None => break,
+            match self.peek().map(|t| &t.kind) {
+                Some(TokenKind::Semicolon) if depth == 0 => {
+                    self.next();
+                    break;
+                }
+                Some(TokenKind::RBrace) if depth == 0 => break,
+                Some(TokenKind::RBrace) => depth -= 1,
+                Some(TokenKind::LBrace) => depth += 1,
+                None => break,
                 _ => (),
             }
+            self.next();
         }
     }
+    fn span(&self, range: Range<usize>) -> Span {
+        Span::new(self.file, range)
+    }
+    fn last_end(&self) -> usize {
+        self.last.as_ref().map_or(0, |t| t.span.end)
+    }
     fn peek(&mut self) -> Option<&Token<'a>> {
         self.tokens.peek()
     }
     fn next(&mut self) -> Option<Token<'a>> {
-        self.tokens.next()
+        let tok = self.tokens.next()?;
+        self.last = Some(tok.clone());
+        Some(tok)
     }
 }
 
@@ -155,7 +1095,7 @@ impl<'a> Operator<'a> {
             Fixity::Infix(_) => Some(Self {
                 fixity: Fixity::Prefix,
                 prec: match self.sym {
-                    "+" | "-" => 25,
+                    "+" | "-" | "*" => 25,
                     _ => return None,
                 },
                 ..self
@@ -181,7 +1121,10 @@ impl<'a> TryFrom<Operator<'a>> for BinOp {
                 Fixity::Prefix => return Err(()),
                 Fixity::Infix(_) => BinOp::Sub,
             },
-            "*" => BinOp::Mul,
+            "*" => match op.fixity {
+                Fixity::Prefix => return Err(()),
+                Fixity::Infix(_) => BinOp::Mul,
+            },
             "/" => BinOp::Div,
             "&&" => BinOp::And,
             "||" => BinOp::Or,
@@ -207,7 +1150,13 @@ impl<'a> TryFrom<Operator<'a>> for UnOp {
                 Fixity::Prefix => UnOp::Neg,
                 Fixity::Infix(_) => return Err(()),
             },
-            "!" => UnOp::Neg,
+            "*" => match op.fixity {
+                Fixity::Prefix => UnOp::Deref,
+                Fixity::Infix(_) => return Err(()),
+            },
+            "!" => UnOp::Not,
+            "&" => UnOp::Ref,
+            "&mut" => UnOp::RefMut,
             _ => return Err(()),
         })
     }
@@ -228,8 +1177,513 @@ pub enum Fixity {
     Prefix,
     Infix(Assoc),
 }
-/*
+#[cfg(test)]
 mod tests {
     use super::*;
-    fn simple_expr()
-}*/
+    use crate::syntax::{insensitive_layout::block_inference, lex, parse_program};
+
+    fn parse_expr(code: &str) -> Expr<'_> {
+        let tokens = block_inference(0, lex(code)).unwrap();
+        let mut parser = Parser::new(0, tokens.into_iter());
+        let e = parser.expr().unwrap();
+        assert!(parser.errors.is_empty());
+        e.value
+    }
+    fn num(value: i64, span: Range<usize>) -> BoxNode<Expr<'static>> {
+        Node {
+            value: Box::new(Expr::Literal(Literal::Num(value))),
+            span: Span::new(0, span),
+        }
+    }
+
+    #[test]
+    fn simple_expr() {
+        assert_eq!(
+            parse_expr("1 + 2 * 3"),
+            Expr::Binary(
+                BinOp::Add,
+                num(1, 0..1),
+                Node {
+                    value: Box::new(Expr::Binary(BinOp::Mul, num(2, 4..5), num(3, 8..9))),
+                    span: Span::new(0, 4..9),
+                }
+            )
+        );
+    }
+    #[test]
+    fn parenthesized_operand() {
+        match parse_expr("a * (2 + 3)") {
+            Expr::Binary(BinOp::Mul, _, right) => match *right.value {
+                Expr::Parenthesized(_) => assert_eq!(right.span.range, 4..11),
+                e => panic!("expected a parenthesized expression, got {:?}", e),
+            },
+            e => panic!("expected a multiplication, got {:?}", e),
+        }
+    }
+    #[test]
+    fn empty_calls() {
+        // `f () x` is `f` applied to `()`, then to `x`
+        match parse_expr("f () x") {
+            Expr::Call(f, _) => match *f.value {
+                Expr::EmptyCall(g) => {
+                    assert_eq!(*g.value, Expr::Ident("f"));
+                    assert_eq!(f.span.range, 0..4);
+                }
+                e => panic!("expected an empty call, got {:?}", e),
+            },
+            e => panic!("expected a call, got {:?}", e),
+        }
+        // `()` alone is the unit value
+        assert_eq!(parse_expr("()"), Expr::Literal(Literal::Unit));
+        assert!(matches!(parse_expr("f (())"), Expr::Call(..)));
+    }
+    #[test]
+    fn prefix_operators() {
+        match parse_expr("- 1 + !b") {
+            Expr::Binary(BinOp::Add, left, right) => {
+                assert!(matches!(*left.value, Expr::Unary(UnOp::Neg, _)));
+                assert!(matches!(*right.value, Expr::Unary(UnOp::Not, _)));
+            }
+            e => panic!("expected an addition, got {:?}", e),
+        }
+    }
+    #[test]
+    fn references() {
+        // Prefix-only operators after an expression start an argument
+        match parse_expr("f &x &mut y") {
+            Expr::Call(f, y) => {
+                assert!(matches!(*y.value, Expr::Unary(UnOp::RefMut, _)));
+                assert!(
+                    matches!(*f.value, Expr::Call(_, ref x) if matches!(*x.value, Expr::Unary(UnOp::Ref, _)))
+                );
+            }
+            e => panic!("expected a call, got {:?}", e),
+        }
+        match parse_expr("*r * 2") {
+            Expr::Binary(BinOp::Mul, left, _) => {
+                assert!(matches!(*left.value, Expr::Unary(UnOp::Deref, _)))
+            }
+            e => panic!("expected a multiplication, got {:?}", e),
+        }
+        let code = "class Get a =\n  get : &mut List a -> &a\n";
+        let tokens = block_inference(0, lex(code)).unwrap();
+        let mut parser = Parser::new(0, tokens.into_iter());
+        let items = parser.program();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        match &items[0].value {
+            Item::Class { methods, .. } => match &methods[0].value.ty.value {
+                TypeExpr::Fun(param, ret) => {
+                    // `&` takes a whole type application
+                    assert!(
+                        matches!(&*param.value, TypeExpr::Ref(true, ty) if matches!(*ty.value, TypeExpr::App(..)))
+                    );
+                    assert!(matches!(&*ret.value, TypeExpr::Ref(false, _)));
+                }
+                ty => panic!("expected a function type, got {:?}", ty),
+            },
+            item => panic!("expected a class, got {:?}", item),
+        }
+    }
+    #[test]
+    fn assignments() {
+        let code = "f (mut x) r =\n  let mut y = x\n  *r = y\n  y = x + 1\n";
+        let tokens = block_inference(0, lex(code)).unwrap();
+        let mut parser = Parser::new(0, tokens.into_iter());
+        let items = parser.program();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        let statements = match &items[0].value {
+            Item::Function { params, body, .. } => {
+                assert_eq!(params[0].value, Pattern::MutBinding("x"));
+                match &body.value {
+                    Expr::Block { instructions, .. } => instructions,
+                    e => panic!("expected a block, got {:?}", e),
+                }
+            }
+            item => panic!("expected a function, got {:?}", item),
+        };
+        match &statements[..] {
+            [Node {
+                value: Statement::Let(pattern, _),
+                ..
+            }, Node {
+                value: Statement::Assign(deref, _),
+                ..
+            }, Node {
+                value: Statement::Assign(y, value),
+                span,
+            }] => {
+                assert_eq!(pattern.value, Pattern::MutBinding("y"));
+                assert!(matches!(deref.value, Expr::Unary(UnOp::Deref, _)));
+                assert_eq!(y.value, Expr::Ident("y"));
+                assert!(matches!(value.value, Expr::Block { .. }));
+                assert_eq!(&code[span.range.clone()], "y = x + 1");
+            }
+            statements => panic!("expected a `let` and assignments, got {:?}", statements),
+        }
+    }
+    #[test]
+    fn items() {
+        let tokens = block_inference(0, lex("a = if true then 2 else 3\nf x y = x y\n")).unwrap();
+        let mut parser = Parser::new(0, tokens.into_iter());
+        let items = parser.program();
+        assert!(parser.errors.is_empty());
+        assert_eq!(items.len(), 2);
+        match &items[1].value {
+            Item::Function { name, params, .. } => {
+                assert_eq!(name.value, "f");
+                assert_eq!(
+                    params.iter().map(|p| p.value.clone()).collect::<Vec<_>>(),
+                    [Pattern::Binding("x"), Pattern::Binding("y")]
+                );
+            }
+            item => panic!("expected a function, got {:?}", item),
+        }
+    }
+    #[test]
+    fn classes_and_instances() {
+        let code = "class Size a =\n  size : (a -> a) -> Int\ninstance Size () =\n  size _ = 0\ninstance Eq a => Size (Option a) =\n  size _ = 1\ninstance (Eq a, Size b) => Size (a, b) =\n  size _ = 2\n";
+        let tokens = block_inference(0, lex(code)).unwrap();
+        let mut parser = Parser::new(0, tokens.into_iter());
+        let items = parser.program();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        match &items[0].value {
+            Item::Class {
+                name,
+                param,
+                methods,
+            } => {
+                assert_eq!((name.value, param.name.value), ("Size", "a"));
+                assert_eq!(param.kind, None);
+                assert_eq!(methods[0].value.name.value, "size");
+                match &methods[0].value.ty.value {
+                    TypeExpr::Fun(param, ret) => {
+                        assert!(matches!(*param.value, TypeExpr::Fun(..)));
+                        assert_eq!(*ret.value, TypeExpr::Named("Int"));
+                    }
+                    ty => panic!("expected a function type, got {:?}", ty),
+                }
+            }
+            item => panic!("expected a class, got {:?}", item),
+        }
+        match &items[1].value {
+            Item::Instance {
+                context,
+                class,
+                ty,
+                methods,
+            } => {
+                assert!(context.is_empty());
+                assert_eq!(class.value, "Size");
+                assert_eq!(ty.value, TypeExpr::Unit);
+                assert_eq!(methods.len(), 1);
+            }
+            item => panic!("expected an instance, got {:?}", item),
+        }
+        // Contexts come before `=>`, in parentheses when there are several constraints
+        let contexts: Vec<Vec<_>> = items[2..]
+            .iter()
+            .map(|item| match &item.value {
+                Item::Instance { context, .. } => context
+                    .iter()
+                    .map(|c| (c.value.class.value, c.value.ty.value.clone()))
+                    .collect(),
+                item => panic!("expected an instance, got {:?}", item),
+            })
+            .collect();
+        assert_eq!(
+            contexts,
+            [
+                vec![("Eq", TypeExpr::Named("a"))],
+                vec![("Eq", TypeExpr::Named("a")), ("Size", TypeExpr::Named("b"))],
+            ]
+        );
+    }
+
+    #[test]
+    fn data_types() {
+        let code = "data Fix: (F : * -> *) A =\n  Leaf\n  In (F (Fix F A)) A\n";
+        let tokens = block_inference(0, lex(code)).unwrap();
+        let mut parser = Parser::new(0, tokens.into_iter());
+        let items = parser.program();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        match &items[0].value {
+            Item::Data {
+                name,
+                params,
+                ctors,
+                owned: false,
+            } => {
+                assert_eq!(name.value, "Fix");
+                let kinds: Vec<_> = params.iter().map(|p| p.kind.is_some()).collect();
+                assert_eq!(kinds, [true, false]);
+                assert!(matches!(
+                    &params[0].kind.as_ref().unwrap().value,
+                    KindExpr::Arrow(param, _) if *param.value == KindExpr::Star
+                ));
+                let fields: Vec<_> = ctors
+                    .iter()
+                    .map(|c| (c.value.name.value, c.value.fields.len()))
+                    .collect();
+                assert_eq!(fields, [("Leaf", 0), ("In", 2)]);
+                // Type application is left associative
+                match &ctors[1].value.fields[0].value {
+                    TypeExpr::App(f, arg) => {
+                        assert_eq!(*f.value, TypeExpr::Named("F"));
+                        assert!(matches!(*arg.value, TypeExpr::App(..)));
+                    }
+                    ty => panic!("expected a type application, got {:?}", ty),
+                }
+            }
+            item => panic!("expected a data type, got {:?}", item),
+        }
+        let tokens = block_inference(0, lex("owned data File =\n  File Int\n")).unwrap();
+        let mut parser = Parser::new(0, tokens.into_iter());
+        let items = parser.program();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        assert!(matches!(items[0].value, Item::Data { owned: true, .. }));
+        assert_eq!(items[0].span.range, 0..28);
+        let tokens = block_inference(0, lex("tailrec f n = f n\n")).unwrap();
+        let mut parser = Parser::new(0, tokens.into_iter());
+        let items = parser.program();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        assert!(matches!(
+            items[0].value,
+            Item::Function { tailrec: true, .. }
+        ));
+        assert_eq!(items[0].span.range, 0..17);
+    }
+
+    #[test]
+    fn lambdas_and_lets() {
+        let tokens = block_inference(0, lex("f =\n  let g = \\x _ -> x\n  g 1 2\n")).unwrap();
+        let mut parser = Parser::new(0, tokens.into_iter());
+        let items = parser.program();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        let instructions = match &items[0].value {
+            Item::Function {
+                body:
+                    Node {
+                        value: Expr::Block { instructions, .. },
+                        ..
+                    },
+                ..
+            } => instructions,
+            item => panic!("expected a block, got {:?}", item),
+        };
+        match &instructions[0].value {
+            Statement::Let(pattern, value) => {
+                assert_eq!(pattern.value, Pattern::Binding("g"));
+                assert_eq!(value.span.range, 13..23);
+            }
+            s => panic!("expected a let, got {:?}", s),
+        }
+        assert_eq!(
+            parse_expr("\\x _ -> x"),
+            Expr::Lambda(
+                Node {
+                    value: Pattern::Binding("x"),
+                    span: Span::new(0, 1..2),
+                },
+                Node {
+                    value: Box::new(Expr::Lambda(
+                        Node {
+                            value: Pattern::Wildcard,
+                            span: Span::new(0, 3..4),
+                        },
+                        Node {
+                            value: Box::new(Expr::Ident("x")),
+                            span: Span::new(0, 8..9),
+                        }
+                    )),
+                    span: Span::new(0, 3..9),
+                }
+            )
+        );
+    }
+    #[test]
+    fn semicolons_end_single_line_blocks() {
+        let code = "main = { let y = 1; y }\nf = \\x -> { let y = x; y + 1 }\na = 1; b = 2\n";
+        let tokens = block_inference(0, lex(code)).unwrap();
+        let mut parser = Parser::new(0, tokens.into_iter());
+        let items = parser.program();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        let names: Vec<_> = items
+            .iter()
+            .filter_map(|item| item.value.name().map(|n| n.value))
+            .collect();
+        assert_eq!(names, ["main", "f", "a", "b"]);
+        // The braces hold the `let` and the expression after it
+        for (item, value) in items.iter().zip(["1", "x"]) {
+            let mut body = match &item.value {
+                Item::Function { body, .. } => &body.value,
+                item => panic!("expected a function, got {:?}", item),
+            };
+            loop {
+                body = match body {
+                    Expr::Block { instructions, .. } if instructions.len() == 1 => {
+                        match &instructions[0].value {
+                            Statement::StmtExpr(e) => e,
+                            s => panic!("expected an expression, got {:?}", s),
+                        }
+                    }
+                    Expr::Lambda(_, e) => &e.value,
+                    _ => break,
+                };
+            }
+            match body {
+                Expr::Block { instructions, .. } => match &instructions[..] {
+                    [Node {
+                        value: Statement::Let(_, e),
+                        ..
+                    }, _] => assert_eq!(code[e.span.range.clone()].trim(), value),
+                    s => panic!("expected a let and an expression, got {:?}", s),
+                },
+                e => panic!("expected braces, got {:?}", e),
+            }
+        }
+    }
+    #[test]
+    fn patterns_and_tuples() {
+        let code = "unwrap (Some (Pair a _)) (x, 1) = (a, x)\nclass C a =\n  f : (a, Int) -> a\n";
+        let tokens = block_inference(0, lex(code)).unwrap();
+        let mut parser = Parser::new(0, tokens.into_iter());
+        let items = parser.program();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        let params = match &items[0].value {
+            Item::Function { params, .. } => params,
+            item => panic!("expected a function, got {:?}", item),
+        };
+        match &params[0].value {
+            Pattern::Ctor(some, fields) => {
+                assert_eq!(some.value, "Some");
+                match &fields[..] {
+                    [Node {
+                        value: Pattern::Ctor(pair, fields),
+                        span,
+                    }] => {
+                        assert_eq!((pair.value, fields.len()), ("Pair", 2));
+                        assert_eq!(span.range, 13..23);
+                    }
+                    fields => panic!("expected a nested constructor, got {:?}", fields),
+                }
+            }
+            p => panic!("expected a constructor, got {:?}", p),
+        }
+        match &params[1].value {
+            Pattern::Tuple(patterns) => assert_eq!(
+                patterns.iter().map(|p| p.value.clone()).collect::<Vec<_>>(),
+                [Pattern::Binding("x"), Pattern::Literal(Literal::Num(1))]
+            ),
+            p => panic!("expected a tuple, got {:?}", p),
+        }
+        match &items[1].value {
+            Item::Class { methods, .. } => match &methods[0].value.ty.value {
+                TypeExpr::Fun(param, _) => match &*param.value {
+                    TypeExpr::Tuple(tys) => assert_eq!(tys.len(), 2),
+                    ty => panic!("expected a tuple type, got {:?}", ty),
+                },
+                ty => panic!("expected a function type, got {:?}", ty),
+            },
+            item => panic!("expected a class, got {:?}", item),
+        }
+        match parse_expr("(1, (2), ())") {
+            Expr::Tuple(elements) => assert_eq!(elements.len(), 3),
+            e => panic!("expected a tuple, got {:?}", e),
+        }
+    }
+    #[test]
+    fn trailing_operator() {
+        let tokens = block_inference(0, lex("a = 1 +")).unwrap();
+        let mut parser = Parser::new(0, tokens.into_iter());
+        parser.program();
+        assert_eq!(parser.errors.len(), 1);
+    }
+    #[test]
+    fn unmatched_braces() {
+        for code in ["}\n", "main = 1\n}\n"] {
+            let tokens = block_inference(0, lex(code)).unwrap();
+            let mut parser = Parser::new(0, tokens.into_iter());
+            parser.program();
+            match &parser.errors[..] {
+                [SyntaxErr {
+                    span,
+                    kind: SyntaxErrKind::UnexpectedToken(TokenKind::RBrace),
+                    ..
+                }] => assert_eq!(&code[span.range.clone()], "}"),
+                errors => panic!("expected an unexpected `}}`, got {:?}", errors),
+            }
+        }
+    }
+    #[test]
+    fn unclosed_delimiters_point_at_their_opener() {
+        let tokens = block_inference(0, lex("a = (1 + 2\nb = 3\n")).unwrap();
+        let mut parser = Parser::new(0, tokens.into_iter());
+        parser.program();
+        match &parser.errors[..] {
+            [SyntaxErr {
+                span,
+                kind: SyntaxErrKind::Unclosed { opened, .. },
+                expected: Expected::Token(")"),
+                ..
+            }, ..] => {
+                assert_eq!(span.range, 13..14);
+                assert_eq!(opened.range, 4..5);
+            }
+            errors => panic!("expected an unclosed `(`, got {:?}", errors),
+        }
+    }
+    #[test]
+    fn literals_out_of_range() {
+        let code = "a = 99999999999999999999\nf 9223372036854775808 = 1\nb = 9223372036854775807\n";
+        match parse_program(0, code) {
+            Err(errors) => {
+                let spans: Vec<_> = errors
+                    .iter()
+                    .map(|e| {
+                        assert!(
+                            matches!(e.kind, SyntaxErrKind::LiteralOutOfRange),
+                            "{:?}",
+                            e
+                        );
+                        &code[e.span.range.clone()]
+                    })
+                    .collect();
+                assert_eq!(spans, ["99999999999999999999", "9223372036854775808"]);
+            }
+            Ok(items) => panic!("expected literals out of range, got {:?}", items),
+        }
+    }
+    #[test]
+    fn layout_blocks_point_at_their_opener() {
+        let tokens = block_inference(0, lex("a =\n  if b\n  then 1\n")).unwrap();
+        let mut parser = Parser::new(0, tokens.into_iter());
+        parser.program();
+        match &parser.errors[..] {
+            [SyntaxErr {
+                span,
+                kind: SyntaxErrKind::UnexpectedEndOfBlock { opener, opened },
+                ..
+            }] => {
+                assert_eq!(span.range, 19..19);
+                assert_eq!(*opener, TokenKind::Eq);
+                assert_eq!(opened.range, 2..3);
+            }
+            errors => panic!("expected an unexpected end of block, got {:?}", errors),
+        }
+        // Inferred blocks aren't empty, explicit ones can be
+        for (code, opened) in [("main =\n", 5..6), ("f x =\ng = 1\n", 4..5)] {
+            let tokens = block_inference(0, lex(code)).unwrap();
+            let mut parser = Parser::new(0, tokens.into_iter());
+            parser.program();
+            match &parser.errors[..] {
+                [SyntaxErr {
+                    kind: SyntaxErrKind::UnexpectedEndOfBlock { opened: o, .. },
+                    expected: Expected::Expr,
+                    ..
+                }] => assert_eq!(o.range, opened, "{}", code),
+                errors => panic!("expected an unexpected end of block, got {:?}", errors),
+            }
+        }
+        assert!(parse_program(0, "main = {}\n").is_ok());
+    }
+}
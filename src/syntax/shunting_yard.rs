@@ -2,7 +2,7 @@ use std::convert::TryInto;
 
 use super::tokens::TokenKind;
 use super::{ast::*, parser::Operator};
-use crate::{errors::syntax_err::*, utils::merge_ranges};
+use crate::errors::syntax_err::*;
 
 #[derive(Debug)]
 enum ShuntingYardState {
@@ -19,8 +19,8 @@ fn insert_bin_op<'a>(ast: &mut Vec<Node<Expr<'a>>>, Node { value: op, .. }: &Nod
     let right = ast.pop().unwrap();
     let left = ast.pop().unwrap();
     ast.push(Node {
-        span: merge_ranges(&left.span, &right.span),
-        value: Expr::Binary(op.clone().try_into().unwrap(), left.into(), right.into()),
+        span: left.span.merge(&right.span),
+        value: Expr::Binary((*op).try_into().unwrap(), left.into(), right.into()),
     })
 }
 
@@ -33,8 +33,8 @@ fn insert_un_op<'a>(
 ) {
     let right = ast.pop().unwrap();
     ast.push(Node {
-        span: merge_ranges(op_span, &right.span),
-        value: Expr::Unary(op.clone().try_into().unwrap(), right.into()),
+        span: op_span.merge(&right.span),
+        value: Expr::Unary((*op).try_into().unwrap(), right.into()),
     })
 }
 
@@ -43,17 +43,18 @@ pub(super) fn shunting_yard<'a>(
 ) -> Result<Node<Expr<'a>>, SyntaxErr<'a>> {
     let mut op_stack: Vec<Node<Operator<'a>>> = vec![];
     let mut ast: Vec<Node<Expr<'a>>> = vec![];
-    let tok_len = tokens.len();
     let mut state = ShuntingYardState::ExpectOperand;
-    for (idx, tok) in tokens.into_iter().enumerate() {
+    for tok in tokens {
         match tok {
             OpOrExpr::Expr(e) => {
                 if let ShuntingYardState::ExpectOp = state {
                     let last = ast.pop().unwrap();
-                    ast.push(Node {
-                        span: merge_ranges(&last.span, &e.span),
-                        value: Expr::Call(last.into_boxed(), e.into_boxed()),
-                    })
+                    let span = last.span.merge(&e.span);
+                    let value = match e.value {
+                        Expr::Literal(Literal::Unit) => Expr::EmptyCall(last.into_boxed()),
+                        _ => Expr::Call(last.into_boxed(), e.into_boxed()),
+                    };
+                    ast.push(Node { span, value })
                 } else {
                     state = ShuntingYardState::ExpectOp;
                     ast.push(e)
@@ -63,37 +64,31 @@ pub(super) fn shunting_yard<'a>(
                 value: mut op,
                 span,
             }) => {
-                if idx == tok_len {
-                    return Err(SyntaxErr {
-                        span: span.clone(),
-                        kind: SyntaxErrKind::UnexpectedToken(TokenKind::Op(op.sym)),
-                        expected: Expected::OneOf(vec![Expected::Operator, Expected::Semicolon]),
-                        note: None,
-                    });
-                }
                 if let ShuntingYardState::ExpectOperand = state {
                     match op.into_prefix() {
                         Some(un_op) => op = un_op,
                         None => {
                             return Err(SyntaxErr {
-                                span: span.clone(),
+                                span,
                                 kind: SyntaxErrKind::UnexpectedToken(TokenKind::Op(op.sym)),
                                 expected: Expected::Expr,
                                 note: None,
+                                suggestions: vec![],
                             })
                         }
                     }
                 }
                 state = ShuntingYardState::ExpectOperand;
-                while let Some(Node { value: last_op, .. }) = op_stack.last() {
-                    if last_op.is_infix() {
+                if op.is_infix() {
+                    while let Some(Node { value: last_op, .. }) = op_stack.last() {
                         if last_op.prec > op.prec
                             || (last_op.prec >= op.prec && last_op.is_left_assoc())
                         {
-                            if last_op.is_infix() {
-                                insert_bin_op(&mut ast, &op_stack.pop().unwrap())
+                            let last_op = op_stack.pop().unwrap();
+                            if last_op.value.is_infix() {
+                                insert_bin_op(&mut ast, &last_op)
                             } else {
-                                insert_un_op(&mut ast, &op_stack.pop().unwrap())
+                                insert_un_op(&mut ast, &last_op)
                             }
                         } else {
                             break;
@@ -104,6 +99,16 @@ pub(super) fn shunting_yard<'a>(
             }
         }
     }
+    if let ShuntingYardState::ExpectOperand = state {
+        let Node { value: op, span } = op_stack.pop().unwrap();
+        return Err(SyntaxErr {
+            span,
+            kind: SyntaxErrKind::UnexpectedToken(TokenKind::Op(op.sym)),
+            expected: Expected::Expr,
+            note: None,
+            suggestions: vec![],
+        });
+    }
     for i in op_stack.into_iter().rev() {
         if i.value.is_infix() {
             insert_bin_op(&mut ast, &i)
@@ -15,13 +15,14 @@ impl<'a> Token<'a> {
 
 #[derive(Logos, Debug, Clone, PartialEq)]
 pub enum TokenKind<'a> {
-    #[regex("[0-9]+", |lex| lex.slice().parse())]
-    Number(i64),
+    /// Its digits, which may not fit in an `Int`
+    #[regex("[0-9]+")]
+    Number(&'a str),
 
     #[regex("(true|false)", |lex| lex.slice().parse())]
     Bool(bool),
 
-    #[regex(r"[A-z_][\w_]*")]
+    #[regex(r"[A-Za-z_][\w_]*")]
     Ident(&'a str),
 
     #[token("()")]
@@ -42,6 +43,33 @@ pub enum TokenKind<'a> {
     #[token("return")]
     Return,
 
+    #[token("break")]
+    Break,
+
+    #[token("continue")]
+    Continue,
+
+    #[token("let")]
+    Let,
+
+    #[token("class")]
+    Class,
+
+    #[token("instance")]
+    Instance,
+
+    #[token("data")]
+    Data,
+
+    #[token("owned")]
+    Owned,
+
+    #[token("tailrec")]
+    Tailrec,
+
+    #[token("mut")]
+    Mut,
+
     #[token("if")]
     If,
 
@@ -60,7 +88,22 @@ pub enum TokenKind<'a> {
     #[token("=")]
     Eq,
 
-    #[regex(r"(\+|-|\*|/|&&|\|\||<=|>=|>|<|==|!=|!)")]
+    #[token("\\")]
+    Backslash,
+
+    #[token("->")]
+    Arrow,
+
+    #[token("=>")]
+    FatArrow,
+
+    #[token(":")]
+    Colon,
+
+    #[token(",")]
+    Comma,
+
+    #[regex(r"(\+|-|\*|/|&&|&|\|\||<=|>=|>|<|==|!=|!)")]
     Op(&'a str),
 
     #[regex(r"[ \f\t]+", logos::skip)]
@@ -80,25 +123,39 @@ impl<'a> fmt::Display for TokenKind<'a> {
             TokenKind::LBrace => write!(f, "{{"),
             TokenKind::RBrace => write!(f, "}}"),
             TokenKind::Return => write!(f, "return"),
+            TokenKind::Break => write!(f, "break"),
+            TokenKind::Continue => write!(f, "continue"),
+            TokenKind::Let => write!(f, "let"),
+            TokenKind::Class => write!(f, "class"),
+            TokenKind::Instance => write!(f, "instance"),
+            TokenKind::Data => write!(f, "data"),
+            TokenKind::Owned => write!(f, "owned"),
+            TokenKind::Tailrec => write!(f, "tailrec"),
+            TokenKind::Mut => write!(f, "mut"),
             TokenKind::If => write!(f, "if"),
             TokenKind::Then => write!(f, "then"),
             TokenKind::Else => write!(f, "else"),
             TokenKind::Eq => write!(f, "="),
+            TokenKind::Backslash => write!(f, "\\"),
+            TokenKind::Arrow => write!(f, "->"),
+            TokenKind::FatArrow => write!(f, "=>"),
+            TokenKind::Colon => write!(f, ":"),
+            TokenKind::Comma => write!(f, ","),
             TokenKind::Semicolon => write!(f, ";"),
             TokenKind::Op(s) => write!(f, "{}", s),
             TokenKind::Error => write!(f, "Error"),
-            TokenKind::Newline => write!(f, "\n"),
+            TokenKind::Newline => writeln!(f),
         }
     }
 }
 
 #[allow(dead_code)]
 pub fn pretty_print_tokens<'a>(tokens: impl IntoIterator<Item = &'a Token<'a>>) {
-    let mut indent_level = 0;
+    let mut indent_level = 0usize;
     for tok in tokens {
         match &tok.kind {
             TokenKind::RBrace => {
-                indent_level -= 1;
+                indent_level = indent_level.saturating_sub(1);
                 let indentation = " ".repeat(indent_level * 4);
                 print!("\n{}}}\n{}", indentation, indentation,);
             }
@@ -0,0 +1,371 @@
+//! Exhaustiveness and redundancy of patterns, with the usefulness algorithm of "Warnings for
+//! pattern matching" (Maranget). A row of patterns is useful after other rows when a value
+//! matches it but none of them: a clause is unreachable when it isn't useful after the clauses
+//! before it, and clauses are exhaustive when a row of wildcards isn't useful after them.
+//! Usefulness is proven by a value, the witness reported as not covered.
+//!
+//! Patterns are checked once the program type checks, so the patterns of a column all match
+//! values of the same type.
+use crate::{
+    errors::pattern_err::{PatternErr, PatternErrKind},
+    hir::*,
+};
+
+// A pattern, bindings being wildcards
+#[derive(Debug, PartialEq, Clone)]
+enum Pat {
+    Wild,
+    Con(Con, Vec<Pat>),
+}
+
+// What a pattern matches values with, before matching their fields
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Con {
+    Data(DataId, usize),
+    Tuple(usize),
+    Bool(bool),
+    Unit,
+    Int(i64),
+}
+
+/// Checks the clauses of the items of a program, and the patterns of their `let`s and lambdas
+pub fn check_patterns(program: &Program) -> Vec<PatternErr> {
+    let mut checker = Checker {
+        datas: &program.datas,
+        errors: vec![],
+    };
+    for item in &program.items {
+        checker.item(item);
+    }
+    checker.errors
+}
+
+/// Checks the patterns of an expression using the data types of a program, for the REPL
+pub fn check_expr_patterns(program: &Program, e: &Node<Expr>) -> Vec<PatternErr> {
+    let mut checker = Checker {
+        datas: &program.datas,
+        errors: vec![],
+    };
+    checker.expr(&e.value);
+    checker.errors
+}
+
+struct Checker<'p, 'a> {
+    datas: &'p [Data<'a>],
+    errors: Vec<PatternErr>,
+}
+impl<'p, 'a> Checker<'p, 'a> {
+    fn item(&mut self, item: &Item) {
+        let rows: Vec<Vec<Pat>> = item
+            .clauses
+            .iter()
+            .map(|clause| clause.params.iter().map(|p| lower(&p.value)).collect())
+            .collect();
+        for (idx, clause) in item.clauses.iter().enumerate() {
+            if self.useful(&rows[..idx], &rows[idx]).is_none() {
+                let span = match (clause.params.first(), clause.params.last()) {
+                    (Some(first), Some(last)) => first.span.merge(&last.span),
+                    _ => clause.body.span.clone(),
+                };
+                self.errors.push(PatternErr {
+                    span,
+                    kind: PatternErrKind::Unreachable,
+                });
+            }
+            self.expr(&clause.body.value);
+        }
+        let wildcards = vec![Pat::Wild; rows.first().map_or(0, Vec::len)];
+        if let Some(witness) = self.useful(&rows, &wildcards) {
+            let params: Vec<_> = witness.iter().map(|p| self.show(p, true)).collect();
+            self.errors.push(PatternErr {
+                span: item.name.span.clone(),
+                kind: PatternErrKind::NonExhaustive {
+                    name: item.name.value.to_string(),
+                    witness: format!("{} {}", item.name.value, params.join(" ")),
+                },
+            });
+        }
+    }
+    fn expr(&mut self, e: &Expr) {
+        match e {
+            Expr::Literal(_) | Expr::Var(..) | Expr::Error | Expr::Method(..) => (),
+            Expr::Lambda(param, body) => {
+                self.irrefutable(param);
+                self.expr(&body.value);
+            }
+            Expr::Unary(_, e)
+            | Expr::OverloadedUnary(_, _, e)
+            | Expr::EmptyCall(e)
+            | Expr::DictApp(e, _) => self.expr(&e.value),
+            Expr::Binary(_, l, r) | Expr::OverloadedBinary(_, _, l, r) | Expr::Call(l, r) => {
+                self.expr(&l.value);
+                self.expr(&r.value);
+            }
+            Expr::Tuple(elements) => {
+                for e in elements {
+                    self.expr(&e.value);
+                }
+            }
+            Expr::If { cond, then, else_ } => {
+                for e in [cond, then, else_] {
+                    self.expr(&e.value);
+                }
+            }
+            Expr::Block { statements, .. } => {
+                for s in statements {
+                    match &s.value {
+                        Statement::Let(pattern, e) => {
+                            self.expr(&e.value);
+                            self.irrefutable(pattern);
+                        }
+                        Statement::Return(e) | Statement::Expr(e) => self.expr(&e.value),
+                        Statement::Assign(place, e) => {
+                            self.expr(&place.value);
+                            self.expr(&e.value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    fn irrefutable(&mut self, pattern: &Node<Pattern>) {
+        if let Some(witness) = self.useful(&[vec![lower(&pattern.value)]], &[Pat::Wild]) {
+            self.errors.push(PatternErr {
+                span: pattern.span.clone(),
+                kind: PatternErrKind::Refutable {
+                    witness: self.show(&witness[0], false),
+                },
+            });
+        }
+    }
+
+    // Returns a witness: patterns of a value matching `row` but none of `rows`
+    fn useful(&self, rows: &[Vec<Pat>], row: &[Pat]) -> Option<Vec<Pat>> {
+        let (head, tail) = match row.split_first() {
+            Some(split) => split,
+            None if rows.is_empty() => return Some(vec![]),
+            None => return None,
+        };
+        match head {
+            Pat::Con(con, fields) => {
+                let row = [&fields[..], tail].concat();
+                let witness = self.useful(&specialize(rows, *con, fields.len()), &row)?;
+                Some(rebuild(*con, fields.len(), witness))
+            }
+            Pat::Wild => {
+                let mut heads = vec![];
+                for row in rows {
+                    match &row[0] {
+                        Pat::Con(con, _) if !heads.contains(con) => heads.push(*con),
+                        _ => (),
+                    }
+                }
+                match self.missing(&heads) {
+                    // The wildcard can only be useful for one of the constructors
+                    None => self.cons(heads[0]).into_iter().find_map(|(con, arity)| {
+                        let row = [&vec![Pat::Wild; arity][..], tail].concat();
+                        let witness = self.useful(&specialize(rows, con, arity), &row)?;
+                        Some(rebuild(con, arity, witness))
+                    }),
+                    // Only the rows starting with a wildcard match the missing constructor
+                    Some(missing) => {
+                        let rows: Vec<_> = rows
+                            .iter()
+                            .filter(|row| row[0] == Pat::Wild)
+                            .map(|row| row[1..].to_vec())
+                            .collect();
+                        let mut witness = self.useful(&rows, tail)?;
+                        witness.insert(0, missing);
+                        Some(witness)
+                    }
+                }
+            }
+        }
+    }
+    // A pattern of a value that none of `heads` match, if any. Heads come from a single column,
+    // so they're constructors of the same type
+    fn missing(&self, heads: &[Con]) -> Option<Pat> {
+        let con = match heads.first() {
+            Some(Con::Int(_)) => {
+                let n = (0..).find(|n| !heads.contains(&Con::Int(*n))).unwrap();
+                Con::Int(n)
+            }
+            Some(con) => {
+                let (con, arity) = self
+                    .cons(*con)
+                    .into_iter()
+                    .find(|(con, _)| !heads.contains(con))?;
+                return Some(Pat::Con(con, vec![Pat::Wild; arity]));
+            }
+            None => return Some(Pat::Wild),
+        };
+        Some(Pat::Con(con, vec![]))
+    }
+    // Every constructor of the type of `con`, with their number of fields
+    fn cons(&self, con: Con) -> Vec<(Con, usize)> {
+        match con {
+            Con::Data(data, _) => self.datas[data.0]
+                .ctors
+                .iter()
+                .enumerate()
+                .map(|(idx, ctor)| (Con::Data(data, idx), ctor.fields.len()))
+                .collect(),
+            Con::Tuple(arity) => vec![(con, arity)],
+            Con::Bool(_) => vec![(Con::Bool(false), 0), (Con::Bool(true), 0)],
+            Con::Unit => vec![(con, 0)],
+            Con::Int(_) => unreachable!("Integers are never all covered"),
+        }
+    }
+    // A pattern as it would be written, in parentheses when it's an argument with fields
+    fn show(&self, pat: &Pat, arg: bool) -> String {
+        match pat {
+            Pat::Wild => "_".to_string(),
+            Pat::Con(Con::Data(data, idx), fields) => {
+                let name = self.datas[data.0].ctors[*idx].name.value;
+                if fields.is_empty() {
+                    return name.to_string();
+                }
+                let fields: Vec<_> = fields.iter().map(|f| self.show(f, true)).collect();
+                match arg {
+                    true => format!("({} {})", name, fields.join(" ")),
+                    false => format!("{} {}", name, fields.join(" ")),
+                }
+            }
+            Pat::Con(Con::Tuple(_), fields) => {
+                let fields: Vec<_> = fields.iter().map(|f| self.show(f, false)).collect();
+                format!("({})", fields.join(", "))
+            }
+            Pat::Con(Con::Bool(b), _) => b.to_string(),
+            Pat::Con(Con::Unit, _) => "()".to_string(),
+            Pat::Con(Con::Int(n), _) => n.to_string(),
+        }
+    }
+}
+
+fn lower(pattern: &Pattern) -> Pat {
+    match pattern {
+        Pattern::Wildcard | Pattern::Binding(..) => Pat::Wild,
+        Pattern::Literal(Literal::Num(n)) => Pat::Con(Con::Int(*n), vec![]),
+        Pattern::Literal(Literal::Bool(b)) => Pat::Con(Con::Bool(*b), vec![]),
+        Pattern::Literal(Literal::Unit) => Pat::Con(Con::Unit, vec![]),
+        Pattern::Ctor(data, idx, fields) => Pat::Con(
+            Con::Data(*data, *idx),
+            fields.iter().map(|f| lower(&f.value)).collect(),
+        ),
+        Pattern::Tuple(patterns) => Pat::Con(
+            Con::Tuple(patterns.len()),
+            patterns.iter().map(|p| lower(&p.value)).collect(),
+        ),
+    }
+}
+
+// The rows matching `con`, with the patterns of its fields in place of their head
+fn specialize(rows: &[Vec<Pat>], con: Con, arity: usize) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter_map(|row| match &row[0] {
+            Pat::Con(head, fields) if *head == con => Some([&fields[..], &row[1..]].concat()),
+            Pat::Con(..) => None,
+            Pat::Wild => Some([&vec![Pat::Wild; arity][..], &row[1..]].concat()),
+        })
+        .collect()
+}
+
+// Puts the first `arity` patterns of a witness back into the fields of `con`
+fn rebuild(con: Con, arity: usize, mut witness: Vec<Pat>) -> Vec<Pat> {
+    let rest = witness.split_off(arity);
+    [vec![Pat::Con(con, witness)], rest].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        check::tests::{check_code, step_errors},
+        errors::ErrorKind,
+    };
+
+    // The pattern diagnostics of a program, with the prelude, as a code and a witness if any
+    fn check(code: &str) -> Vec<(&'static str, String)> {
+        let mut checked = check_code(code, false);
+        step_errors!(checked, Pattern)
+            .into_iter()
+            .map(|e| {
+                let witness = match e.kind {
+                    PatternErrKind::NonExhaustive { ref witness, .. }
+                    | PatternErrKind::Refutable { ref witness } => witness.clone(),
+                    PatternErrKind::Unreachable => String::new(),
+                };
+                (e.kind.code(), witness)
+            })
+            .collect()
+    }
+    fn witness(code: &str) -> String {
+        match &check(code)[..] {
+            [("E0020", witness)] => witness.clone(),
+            errors => panic!("expected a missing pattern, got {:?}", errors),
+        }
+    }
+
+    const OPTION: &str = "data Option: T =\n  None\n  Some T\n";
+
+    #[test]
+    fn missing_patterns() {
+        assert_eq!(
+            witness(&format!("{}unwrap (Some a) = a\n", OPTION)),
+            "unwrap None"
+        );
+        assert_eq!(
+            witness(&format!("{}f None = 0\nf (Some None) = 1\n", OPTION)),
+            "f (Some (Some _))"
+        );
+        assert_eq!(
+            witness("f (true, _) = 1\nf (_, true) = 2\n"),
+            "f (false, false)"
+        );
+        assert_eq!(witness("f 0 = 1\nf 2 = 2\nf 1 = 3\n"), "f 3");
+        assert_eq!(witness("f () true = 1\n"), "f () false");
+        // Only the first missing pattern is reported
+        assert_eq!(
+            witness(&format!("{}f None _ = 0\nf _ None = 1\n", OPTION)),
+            "f (Some _) (Some _)"
+        );
+    }
+
+    #[test]
+    fn exhaustive_clauses() {
+        let code = format!(
+            "{}f None _ = 0\nf _ None = 1\nf (Some a) (Some b) = a + b\ng 0 = 1\ng n = n\nh (a, b) = a\n",
+            OPTION
+        );
+        assert_eq!(check(&code), []);
+    }
+
+    #[test]
+    fn unreachable_clauses() {
+        assert_eq!(check("f _ = 1\nf 1 = 2\n"), [("W0002", String::new())]);
+        assert_eq!(
+            check(&format!(
+                "{}f None = 0\nf (Some _) = 1\nf (Some 2) = 2\n",
+                OPTION
+            )),
+            [("W0002", String::new())]
+        );
+        assert_eq!(
+            check("f true = 1\nf false = 2\nf b = 3\n"),
+            [("W0002", String::new())]
+        );
+    }
+
+    #[test]
+    fn refutable_patterns() {
+        assert_eq!(
+            check(&format!("{}f o =\n  let Some a = o\n  a\n", OPTION)),
+            [("E0020", "None".to_string())]
+        );
+        assert_eq!(
+            check("f = \\(a, 1) -> a\n"),
+            [("E0020", "(_, 0)".to_string())]
+        );
+        assert_eq!(check("f p =\n  let (a, _) = p\n  a\n"), []);
+    }
+}
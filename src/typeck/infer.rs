@@ -0,0 +1,1186 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    mem,
+};
+
+use super::{Constraint, Scheme, TyVar, Type, Types};
+use crate::{
+    errors::type_err::{Because, TypeErr, TypeErrKind},
+    hir::*,
+    source::Span,
+};
+
+enum UnifyErr {
+    Mismatch,
+    Infinite(TyVar, Type),
+}
+
+// A constraint to solve once its item group is inferred, and the hole of its dictionary
+struct Wanted {
+    class: ClassId,
+    ty: Type,
+    span: Span,
+    hole: usize,
+}
+
+// The types a generic item or local was used at, which can't be owned
+struct Shared {
+    span: Span,
+    name: String,
+    tys: Vec<Type>,
+}
+
+// What a dictionary hole is filled with once its constraint is solved
+#[derive(Clone)]
+enum Fill {
+    Dict(Dict),
+    // The dictionary parameter of the enclosing item for this constraint of its group
+    Param(usize),
+}
+
+/// Inference state. Type variables are bound in place (algorithm J), and get the level of the
+/// `let` or item group they were created in: the variables of a type that were created deeper
+/// than the current level can't appear in the environment, so they can be generalised.
+///
+/// Class constraints are collected while inferring a group, each one with a dictionary hole
+/// where it's needed, and solved once the group is: those on concrete types by their
+/// instance, applied to the dictionaries of its context, and those on generalised variables by
+/// dictionary parameters of the group's items.
+pub(super) struct Infer<'p, 'a> {
+    classes: &'p [Class<'a>],
+    instances: &'p [Instance],
+    datas: &'p [Data<'a>],
+    // The locals bound by `mut` patterns, which aren't generalised
+    mutable: &'p HashSet<LocalId>,
+    // The schemes of the constructors, by data type
+    ctors: Vec<Vec<Scheme>>,
+    // The classes of the overloaded operators, declared by the prelude
+    num: ClassId,
+    eq: ClassId,
+    ord: ClassId,
+    bindings: Vec<Option<Type>>,
+    levels: Vec<usize>,
+    level: usize,
+    items: Vec<Option<Scheme>>,
+    // The monomorphic types of the items being inferred together
+    group: HashMap<DefId, Type>,
+    locals: HashMap<LocalId, Scheme>,
+    // How many locals the program has, dictionary parameters are new ones
+    local_count: usize,
+    // The return types of the enclosing functions, innermost last
+    returns: Vec<Type>,
+    wanted: Vec<Wanted>,
+    holes: Vec<Option<Fill>>,
+    shared: Vec<Shared>,
+    spans: HashMap<Span, Type>,
+    errors: Vec<TypeErr>,
+}
+
+impl<'p, 'a> Infer<'p, 'a> {
+    pub fn new(
+        classes: &'p [Class<'a>],
+        instances: &'p [Instance],
+        datas: &'p [Data<'a>],
+        mutable: &'p HashSet<LocalId>,
+        local_count: usize,
+        item_count: usize,
+    ) -> Self {
+        let class = |name| {
+            classes
+                .iter()
+                .find(|c| c.name.value == name)
+                .map(|c| c.id)
+                .expect("The prelude declares the operator classes")
+        };
+        let mut infer = Self {
+            classes,
+            instances,
+            datas,
+            mutable,
+            ctors: vec![],
+            num: class("Num"),
+            eq: class("Eq"),
+            ord: class("Ord"),
+            bindings: vec![],
+            levels: vec![],
+            level: 0,
+            items: vec![None; item_count],
+            group: HashMap::new(),
+            locals: HashMap::new(),
+            local_count,
+            returns: vec![],
+            wanted: vec![],
+            holes: vec![],
+            shared: vec![],
+            spans: HashMap::new(),
+            errors: vec![],
+        };
+        infer.ctors = datas.iter().map(|data| infer.ctor_schemes(data)).collect();
+        infer.coherence();
+        infer
+    }
+    pub fn finish(mut self, locals: &mut usize) -> (Types, Vec<TypeErr>) {
+        *locals = self.local_count;
+        self.owned_vars();
+        let locals = (self.locals.iter())
+            .map(|(id, scheme)| (*id, self.zonk(&scheme.ty)))
+            .collect();
+        let spans = (self.spans.iter())
+            .map(|(span, ty)| (span.clone(), self.zonk(ty)))
+            .collect();
+        let items = self
+            .items
+            .into_iter()
+            .map(|s| s.expect("Every item is inferred"))
+            .collect();
+        let types = Types {
+            items,
+            ctors: self.ctors,
+            kinds: Default::default(),
+            locals,
+            spans,
+            glue: vec![],
+        };
+        (types, self.errors)
+    }
+    // Generic code could copy or discard the values of its type variables, which can't be
+    // owned, or keep them in a function, which can't hold references. Constructors only move
+    // their fields, and instances are checked for their type
+    fn owned_vars(&mut self) {
+        for Shared { span, name, tys } in mem::take(&mut self.shared) {
+            let tys: Vec<_> = tys.iter().map(|ty| self.zonk(ty)).collect();
+            let kind = match tys.iter().find(|ty| ty.is_owned(self.datas)) {
+                Some(ty) => TypeErrKind::OwnedVar {
+                    ty: ty.clone(),
+                    name,
+                },
+                None => match tys.into_iter().find(Type::has_refs) {
+                    Some(ty) => TypeErrKind::BorrowedVar { ty, name },
+                    None => continue,
+                },
+            };
+            self.errors.push(TypeErr { span, kind });
+        }
+    }
+    pub fn items(&mut self, items: &mut [Item<'a>], group: &[DefId]) {
+        self.level += 1;
+        for id in group {
+            let ty = self.fresh();
+            self.group.insert(*id, ty);
+        }
+        // The variables of the instance methods' types and signatures
+        let mut rigid = vec![];
+        // The instance of an instance method, alone in its group since nothing refers to it
+        // but its class' method, with the variables of the instance's type
+        let mut method = None;
+        for id in group {
+            let item = &mut items[id.0];
+            let expected = self.group[id].clone();
+            // Instance methods have the type of their class' method
+            if let Some((instance, idx)) = item.instance {
+                let (signature, vars) = self.method_type(instance, idx);
+                self.expect(&signature, &expected, &item.name.span, None);
+                let count = self.instances[instance.0].vars;
+                method = Some((instance, vars[..count].to_vec()));
+                rigid.push((*id, vars));
+            }
+            let ty = self.function(&mut item.clauses);
+            self.expect(&expected, &ty, &item.clauses[0].body.span, None);
+        }
+        self.level -= 1;
+        for (id, vars) in rigid {
+            self.rigid(&items[id.0], &vars);
+        }
+        let tys: Vec<_> = group.iter().map(|id| self.zonk(&self.group[id])).collect();
+        let given = method.map(|(instance, vars)| self.context(instance, &vars));
+        let constraints = self.solve(&tys, given);
+        for (id, ty) in group.iter().zip(tys) {
+            self.group.remove(id);
+            let item = &mut items[id.0];
+            item.dicts = constraints.iter().map(|_| self.new_local()).collect();
+            for Clause { body, .. } in &mut item.clauses {
+                self.fill(&mut body.value, &body.span, &item.dicts);
+            }
+            let scheme = self.scheme(ty, &constraints);
+            // Nothing uses `main` to pick the instances of its constraints
+            if item.name.value == "main" && !constraints.is_empty() {
+                self.errors.push(TypeErr {
+                    span: item.name.span.clone(),
+                    kind: TypeErrKind::ConstrainedMain {
+                        scheme: scheme.to_string(),
+                    },
+                });
+            }
+            self.items[id.0] = Some(scheme);
+        }
+    }
+    pub fn top_expr(&mut self, e: &mut Node<Expr<'a>>) -> Scheme {
+        self.level += 1;
+        let ty = self.expr(e);
+        self.level -= 1;
+        let ty = self.zonk(&ty);
+        let constraints = self.solve(std::slice::from_ref(&ty), None);
+        let Node { value, span } = e;
+        self.fill(value, span, &[]);
+        self.scheme(ty, &constraints)
+    }
+
+    // Two instances of the same class for types with a common instance, like `Option a` and
+    // `Option Int`, would make the program's meaning depend on which one is picked
+    fn coherence(&mut self) {
+        let instances = self.instances;
+        let tys: Vec<_> = instances
+            .iter()
+            .map(|instance| {
+                let vars: Vec<_> = (0..instance.vars).map(|_| self.fresh()).collect();
+                self.lower(&instance.ty.value, &vars)
+            })
+            .collect();
+        for (i, instance) in instances.iter().enumerate() {
+            let first = (0..i).find(|&j| {
+                instances[j].class == instance.class && self.unifiable(&tys[i], &tys[j])
+            });
+            if let Some(first) = first {
+                self.errors.push(TypeErr {
+                    span: instance.span.clone(),
+                    kind: TypeErrKind::ConflictingInstances {
+                        class: self.class_name(instance.class),
+                        ty: tys[i].clone(),
+                        first: instances[first].span.clone(),
+                    },
+                });
+            }
+        }
+    }
+    // Whether two types could be unified, leaving them as they are
+    fn unifiable(&mut self, a: &Type, b: &Type) -> bool {
+        let bindings = self.bindings.clone();
+        let unifiable = self.unify(a, b).is_ok();
+        self.bindings = bindings;
+        unifiable
+    }
+    fn class_name(&self, class: ClassId) -> String {
+        self.classes[class.0].name.value.to_string()
+    }
+    // The type of a method of an instance, with the variables of the instance's type followed
+    // by the other variables of its signature
+    fn method_type(&mut self, instance: InstanceId, method: usize) -> (Type, Vec<Type>) {
+        let Instance {
+            class,
+            ty,
+            vars: count,
+            ..
+        } = &self.instances[instance.0];
+        let method = &self.classes[class.0].methods[method];
+        let vars: Vec<_> = (1..count + method.vars).map(|_| self.fresh()).collect();
+        let (own, others) = vars.split_at(*count);
+        let mut all = vec![self.lower(&ty.value, own)];
+        all.extend(others.iter().cloned());
+        (self.lower(&method.ty.value, &all), vars)
+    }
+    // The constraints of an instance's context on `vars`, the variables of its type
+    fn context(&self, instance: InstanceId, vars: &[Type]) -> Vec<(ClassId, TyVar)> {
+        // Variables bound by a mismatch are already reported
+        (self.instances[instance.0].context.iter())
+            .filter_map(|c| match self.zonk(&vars[c.value.1]) {
+                Type::Var(v) => Some((c.value.0, v)),
+                _ => None,
+            })
+            .collect()
+    }
+    // An instance method must be as general as its instance and its signature: their
+    // variables stay distinct, and aren't bound to types or to the environment
+    fn rigid(&mut self, item: &Item, vars: &[Type]) {
+        let mut seen = vec![];
+        let general = vars.iter().all(|var| match self.zonk(var) {
+            Type::Var(v) if self.levels[v.0] > self.level && !seen.contains(&v) => {
+                seen.push(v);
+                true
+            }
+            _ => false,
+        });
+        if !general {
+            let (instance, method) = item.instance.expect("Only instance methods are rigid");
+            let (expected, _) = self.method_type(instance, method);
+            let found = self.zonk(&self.group[&item.id]);
+            self.errors.push(TypeErr {
+                span: item.name.span.clone(),
+                kind: TypeErrKind::Mismatch {
+                    expected,
+                    found,
+                    because: None,
+                },
+            });
+        }
+    }
+    // The instance of `class` for `ty`, with the types of its variables
+    fn instance(&self, class: ClassId, ty: &Type) -> Option<(InstanceId, Vec<Type>)> {
+        self.instances
+            .iter()
+            .filter(|i| i.class == class)
+            .find_map(|i| {
+                let mut vars = vec![None; i.vars];
+                self.matches(&i.ty.value, ty, &mut vars).then(|| {
+                    let vars = vars
+                        .into_iter()
+                        .map(|v| v.expect("Its type has its variables"));
+                    (i.id, vars.collect())
+                })
+            })
+    }
+    // Whether `ty` is the type `pattern` for some types of its variables, bound in `vars`
+    fn matches(&self, pattern: &Ty, ty: &Type, vars: &mut [Option<Type>]) -> bool {
+        match (pattern, ty) {
+            (Ty::Var(idx), ty) => match &vars[*idx] {
+                Some(var) => var == ty,
+                None => {
+                    vars[*idx] = Some(ty.clone());
+                    true
+                }
+            },
+            (Ty::Int, Type::Int) | (Ty::Bool, Type::Bool) | (Ty::Unit, Type::Unit) => true,
+            (Ty::Data(id), Type::Con(name)) => self.datas[id.0].name.value == name,
+            (Ty::App(f, arg), Type::App(g, x)) | (Ty::Fun(f, arg), Type::Fun(g, x)) => {
+                self.matches(&f.value, g, vars) && self.matches(&arg.value, x, vars)
+            }
+            (Ty::Tuple(patterns), Type::Tuple(tys)) => {
+                patterns.len() == tys.len()
+                    && (patterns.iter().zip(tys)).all(|(p, ty)| self.matches(&p.value, ty, vars))
+            }
+            (Ty::Ref(mutable, pattern), Type::Ref(m, ty)) => {
+                **m == Type::Mutability(*mutable) && self.matches(&pattern.value, ty, vars)
+            }
+            _ => false,
+        }
+    }
+    fn lower(&self, ty: &Ty, vars: &[Type]) -> Type {
+        lower(self.datas, ty, vars)
+    }
+    // A constructor takes its fields and returns its data type applied to the type parameters
+    fn ctor_schemes(&mut self, data: &Data) -> Vec<Scheme> {
+        let vars: Vec<_> = data.params.iter().map(|_| self.fresh()).collect();
+        let ty = vars
+            .iter()
+            .fold(Type::Con(data.name.value.to_string()), |f, var| {
+                Type::app(f, var.clone())
+            });
+        data.ctors
+            .iter()
+            .map(|ctor| Scheme {
+                vars: vars
+                    .iter()
+                    .filter_map(|var| match var {
+                        Type::Var(v) => Some(*v),
+                        _ => None,
+                    })
+                    .collect(),
+                constraints: vec![],
+                ty: ctor.fields.iter().rev().fold(ty.clone(), |ret, field| {
+                    Type::fun(self.lower(&field.value, &vars), ret)
+                }),
+            })
+            .collect()
+    }
+    fn new_local(&mut self) -> LocalId {
+        self.local_count += 1;
+        LocalId(self.local_count - 1)
+    }
+    // Wants an instance of `class` for `ty`, returning the hole of its dictionary
+    fn want(&mut self, class: ClassId, ty: Type, span: &Span) -> Dict {
+        self.holes.push(None);
+        let hole = self.holes.len() - 1;
+        self.wanted.push(Wanted {
+            class,
+            ty,
+            span: span.clone(),
+            hole,
+        });
+        Dict::Hole(hole)
+    }
+    // Solves the constraints wanted by a group whose types are `tys`, returning those left on
+    // its generalised variables, which every item of the group takes as dictionaries. Those
+    // of an instance method are the ones `given` by the context of its instance
+    fn solve(&mut self, tys: &[Type], given: Option<Vec<(ClassId, TyVar)>>) -> Vec<Constraint> {
+        let mut vars = vec![];
+        for ty in tys {
+            ty.vars(&mut vars);
+        }
+        vars.retain(|v| self.levels[v.0] > self.level);
+        let mut params = vec![];
+        let instances = self.instances;
+        let mut wanted: VecDeque<_> = mem::take(&mut self.wanted).into();
+        while let Some(Wanted {
+            class,
+            ty,
+            span,
+            hole,
+        }) = wanted.pop_front()
+        {
+            let kind = match self.zonk(&ty) {
+                Type::Var(v) if vars.contains(&v) => match &given {
+                    Some(given) if !given.contains(&(class, v)) => TypeErrKind::NoInstance {
+                        class: self.class_name(class),
+                        ty: Type::Var(v),
+                    },
+                    _ => {
+                        params.push((hole, class, v));
+                        continue;
+                    }
+                },
+                // Nothing can tell which instance to use
+                var @ Type::Var(_) => TypeErrKind::Ambiguous {
+                    class: self.class_name(class),
+                    var,
+                },
+                ty => match self.instance(class, &ty) {
+                    // The constraints of its context are wanted in turn
+                    Some((id, args)) => {
+                        let context = (instances[id.0].context.iter())
+                            .map(|c| self.want(c.value.0, args[c.value.1].clone(), &span))
+                            .collect();
+                        wanted.extend(self.wanted.drain(..));
+                        self.holes[hole] = Some(Fill::Dict(Dict::Instance(id, context)));
+                        continue;
+                    }
+                    None => TypeErrKind::NoInstance {
+                        class: self.class_name(class),
+                        ty,
+                    },
+                },
+            };
+            self.errors.push(TypeErr { span, kind });
+        }
+        let mut constraints = match given {
+            Some(given) => given,
+            None => {
+                let mut constraints: Vec<_> = params
+                    .iter()
+                    .map(|(_, class, var)| (*class, *var))
+                    .collect();
+                constraints
+                    .sort_by_key(|(class, var)| (vars.iter().position(|v| v == var), *class));
+                constraints
+            }
+        };
+        constraints.dedup();
+        for (hole, class, var) in params {
+            let idx = constraints.iter().position(|c| *c == (class, var));
+            self.holes[hole] = idx.map(Fill::Param);
+        }
+        constraints
+            .into_iter()
+            .map(|(class, var)| Constraint {
+                class,
+                name: self.class_name(class),
+                var,
+            })
+            .collect()
+    }
+    fn scheme(&self, ty: Type, constraints: &[Constraint]) -> Scheme {
+        let mut vars = vec![];
+        ty.vars(&mut vars);
+        vars.retain(|v| self.levels[v.0] > self.level);
+        for c in constraints {
+            if !vars.contains(&c.var) {
+                vars.push(c.var);
+            }
+        }
+        Scheme {
+            vars,
+            constraints: constraints.to_vec(),
+            ty,
+        }
+    }
+
+    fn fresh(&mut self) -> Type {
+        self.bindings.push(None);
+        self.levels.push(self.level);
+        Type::Var(TyVar(self.bindings.len() - 1))
+    }
+    // Follows the bindings of the outermost variable
+    fn shallow(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(v) => match &self.bindings[v.0] {
+                Some(ty) => self.shallow(ty),
+                None => ty.clone(),
+            },
+            _ => ty.clone(),
+        }
+    }
+    /// `ty` with every bound variable replaced by its binding
+    fn zonk(&self, ty: &Type) -> Type {
+        match self.shallow(ty) {
+            Type::Fun(param, ret) => Type::fun(self.zonk(&param), self.zonk(&ret)),
+            Type::App(f, arg) => Type::app(self.zonk(&f), self.zonk(&arg)),
+            Type::Ref(m, ty) => Type::Ref(Box::new(self.zonk(&m)), Box::new(self.zonk(&ty))),
+            Type::Tuple(tys) => Type::Tuple(tys.iter().map(|ty| self.zonk(ty)).collect()),
+            ty => ty,
+        }
+    }
+    fn generalize(&self, ty: &Type) -> Scheme {
+        let ty = self.zonk(ty);
+        let mut vars = vec![];
+        ty.vars(&mut vars);
+        vars.retain(|v| self.levels[v.0] > self.level);
+        Scheme {
+            vars,
+            constraints: vec![],
+            ty,
+        }
+    }
+    // Constraints are only solved for whole items, so the variables they're on can't be
+    // generalised by a `let`: they're kept at the current level
+    fn keep_constrained(&mut self) {
+        let mut vars = vec![];
+        for wanted in &self.wanted {
+            self.zonk(&wanted.ty).vars(&mut vars);
+        }
+        for v in vars {
+            self.levels[v.0] = self.levels[v.0].min(self.level);
+        }
+    }
+    // Returns the dictionaries of the scheme's constraints. The variables of the generic items
+    // and locals, named by `shared`, can't be instantiated with owned types
+    fn instantiate(
+        &mut self,
+        Scheme {
+            vars,
+            constraints,
+            ty,
+        }: &Scheme,
+        span: &Span,
+        shared: Option<&str>,
+    ) -> (Type, Vec<Dict>) {
+        if vars.is_empty() {
+            return (ty.clone(), vec![]);
+        }
+        let fresh: HashMap<TyVar, Type> = vars.iter().map(|v| (*v, self.fresh())).collect();
+        fn subst(ty: &Type, fresh: &HashMap<TyVar, Type>) -> Type {
+            match ty {
+                Type::Var(v) => fresh.get(v).cloned().unwrap_or(Type::Var(*v)),
+                Type::Fun(param, ret) => Type::fun(subst(param, fresh), subst(ret, fresh)),
+                Type::App(f, arg) => Type::app(subst(f, fresh), subst(arg, fresh)),
+                Type::Ref(m, ty) => {
+                    Type::Ref(Box::new(subst(m, fresh)), Box::new(subst(ty, fresh)))
+                }
+                Type::Tuple(tys) => Type::Tuple(tys.iter().map(|ty| subst(ty, fresh)).collect()),
+                ty => ty.clone(),
+            }
+        }
+        if let Some(name) = shared {
+            self.shared.push(Shared {
+                span: span.clone(),
+                name: name.to_string(),
+                tys: vars.iter().map(|v| fresh[v].clone()).collect(),
+            });
+        }
+        let dicts = constraints
+            .iter()
+            .map(|c| self.want(c.class, fresh[&c.var].clone(), span))
+            .collect();
+        (subst(ty, &fresh), dicts)
+    }
+    // Checks that `v` doesn't occur in `ty`, lowering the levels of the variables of `ty` to the
+    // level of `v` since they now appear where `v` does
+    fn occurs(&mut self, v: TyVar, ty: &Type) -> bool {
+        match self.shallow(ty) {
+            Type::Var(w) if w == v => true,
+            Type::Var(w) => {
+                self.levels[w.0] = self.levels[w.0].min(self.levels[v.0]);
+                false
+            }
+            Type::Fun(param, ret) | Type::App(param, ret) | Type::Ref(param, ret) => {
+                self.occurs(v, &param) || self.occurs(v, &ret)
+            }
+            Type::Tuple(tys) => tys.iter().any(|ty| self.occurs(v, ty)),
+            _ => false,
+        }
+    }
+    fn unify(&mut self, a: &Type, b: &Type) -> Result<(), UnifyErr> {
+        match (self.shallow(a), self.shallow(b)) {
+            (Type::Var(v), Type::Var(w)) if v == w => Ok(()),
+            (Type::Var(v), ty) | (ty, Type::Var(v)) => {
+                if self.occurs(v, &ty) {
+                    return Err(UnifyErr::Infinite(v, ty));
+                }
+                self.bindings[v.0] = Some(ty);
+                Ok(())
+            }
+            (Type::Fun(p1, r1), Type::Fun(p2, r2))
+            | (Type::App(p1, r1), Type::App(p2, r2))
+            | (Type::Ref(p1, r1), Type::Ref(p2, r2)) => {
+                self.unify(&p1, &p2)?;
+                self.unify(&r1, &r2)
+            }
+            (Type::Tuple(tys1), Type::Tuple(tys2)) if tys1.len() == tys2.len() => {
+                for (ty1, ty2) in tys1.iter().zip(&tys2) {
+                    self.unify(ty1, ty2)?;
+                }
+                Ok(())
+            }
+            (a, b) if a == b => Ok(()),
+            _ => Err(UnifyErr::Mismatch),
+        }
+    }
+    /// Unifies the type `found` of the expression at `span` with what it's `expected` to be,
+    /// reporting an error when they differ
+    fn expect(
+        &mut self,
+        expected: &Type,
+        found: &Type,
+        span: &Span,
+        because: Option<(Span, Because)>,
+    ) {
+        let kind = match self.unify(expected, found) {
+            Ok(()) => return,
+            Err(UnifyErr::Mismatch) => TypeErrKind::Mismatch {
+                expected: self.zonk(expected),
+                found: self.zonk(found),
+                because: because.map(|(span, because)| (span, self.zonk_because(because))),
+            },
+            Err(UnifyErr::Infinite(v, ty)) => TypeErrKind::Infinite {
+                var: Type::Var(v),
+                ty: self.zonk(&ty),
+            },
+        };
+        self.errors.push(TypeErr {
+            span: span.clone(),
+            kind,
+        });
+    }
+    fn zonk_because(&self, because: Because) -> Because {
+        match because {
+            Because::Is(ty) => Because::Is(self.zonk(&ty)),
+            Because::Expects(ty) => Because::Expects(self.zonk(&ty)),
+            b @ Because::Operator(_) => b,
+        }
+    }
+
+    // The type of a function made of `clauses`, which agree on the types of their parameters
+    // and of their bodies
+    fn function(&mut self, clauses: &mut [Clause<'a>]) -> Type {
+        let params: Vec<_> = clauses[0].params.iter().map(|_| self.fresh()).collect();
+        let ret = self.fresh();
+        self.returns.push(ret.clone());
+        for Clause {
+            params: patterns,
+            body,
+        } in clauses
+        {
+            for (param, pattern) in params.iter().zip(patterns.iter()) {
+                let ty = self.pattern(pattern);
+                self.expect(param, &ty, &pattern.span, None);
+            }
+            let body_ty = self.expr(body);
+            self.expect(&ret, &body_ty, &body.span, None);
+        }
+        self.returns.pop();
+        params
+            .into_iter()
+            .rev()
+            .fold(ret, |ret, param| Type::fun(param, ret))
+    }
+    // Binds the locals of a pattern to monomorphic types, returning the type it matches
+    fn pattern(&mut self, Node { value, span }: &Node<Pattern>) -> Type {
+        let ty = match value {
+            Pattern::Wildcard => self.fresh(),
+            Pattern::Binding(id, _) => {
+                let ty = self.fresh();
+                self.locals.insert(*id, Scheme::mono(ty.clone()));
+                ty
+            }
+            Pattern::Literal(l) => literal(l),
+            Pattern::Ctor(data, idx, fields) => {
+                let scheme = self.ctors[data.0][*idx].clone();
+                let mut ty = self.instantiate(&scheme, span, None).0;
+                for field in fields {
+                    ty = match ty {
+                        Type::Fun(param, ret) => {
+                            let field_ty = self.pattern(field);
+                            self.expect(&param, &field_ty, &field.span, None);
+                            *ret
+                        }
+                        _ => unreachable!("The resolver checks the number of fields"),
+                    };
+                }
+                ty
+            }
+            Pattern::Tuple(patterns) => {
+                Type::Tuple(patterns.iter().map(|p| self.pattern(p)).collect())
+            }
+        };
+        self.typed(span, ty)
+    }
+    fn expr(&mut self, Node { value, span }: &mut Node<Expr<'a>>) -> Type {
+        let ty = self.infer(value, span);
+        self.typed(span, ty)
+    }
+    fn boxed(&mut self, Node { value, span }: &mut BoxNode<Expr<'a>>) -> Type {
+        let ty = self.infer(value, span);
+        self.typed(span, ty)
+    }
+    // Records the type of a node, for the checks done once the program type checks
+    fn typed(&mut self, span: &Span, ty: Type) -> Type {
+        self.spans.insert(span.clone(), ty.clone());
+        ty
+    }
+    // The type of a place that's assigned or borrowed mutably, which can only be behind
+    // mutable references
+    fn mutated(&mut self, value: &mut Expr<'a>, span: &Span) -> Type {
+        let ty = match value {
+            Expr::Unary(UnOp::Deref, e) => {
+                let reference = self.mutated(&mut e.value, &e.span);
+                let target = self.fresh();
+                self.expect(
+                    &Type::reference(true, target.clone()),
+                    &reference,
+                    &e.span,
+                    None,
+                );
+                target
+            }
+            _ => self.infer(value, span),
+        };
+        self.typed(span, ty)
+    }
+    // Overloaded uses are elaborated in place, with holes for their dictionaries
+    fn infer(&mut self, value: &mut Expr<'a>, span: &Span) -> Type {
+        match value {
+            Expr::Literal(l) => literal(l),
+            Expr::Var(Res::Local(id), name) => {
+                let scheme = self.locals[id].clone();
+                self.instantiate(&scheme, span, Some(name)).0
+            }
+            Expr::Var(Res::Def(id), name) => match self.group.get(id) {
+                // Its dictionaries are the ones of the enclosing item, known once the group is
+                Some(ty) => {
+                    let ty = ty.clone();
+                    apply_dicts(value, span, vec![]);
+                    ty
+                }
+                None => {
+                    let scheme = self.items[id.0]
+                        .clone()
+                        .expect("Items are inferred after the items they refer to");
+                    let (ty, dicts) = self.instantiate(&scheme, span, Some(name));
+                    if !dicts.is_empty() {
+                        apply_dicts(value, span, dicts);
+                    }
+                    ty
+                }
+            },
+            Expr::Var(Res::Ctor(data, idx), _) => {
+                let scheme = self.ctors[data.0][*idx].clone();
+                self.instantiate(&scheme, span, None).0
+            }
+            Expr::Var(Res::Method(class, idx), name) => {
+                let (class, idx) = (*class, *idx);
+                let method = &self.classes[class.0].methods[idx];
+                let vars: Vec<_> = (0..method.vars).map(|_| self.fresh()).collect();
+                let ty = self.lower(&method.ty.value, &vars);
+                // The class parameter is given by the instance
+                if vars.len() > 1 {
+                    self.shared.push(Shared {
+                        span: span.clone(),
+                        name: name.to_string(),
+                        tys: vars[1..].to_vec(),
+                    });
+                }
+                let dict = self.want(class, vars[0].clone(), span);
+                *value = Expr::Method(dict, class, idx);
+                ty
+            }
+            Expr::Unary(UnOp::Not, e) => {
+                let ty = self.boxed(e);
+                self.expect(&Type::Bool, &ty, &e.span, None);
+                Type::Bool
+            }
+            Expr::Unary(UnOp::Ref, e) => Type::reference(false, self.boxed(e)),
+            Expr::Unary(UnOp::RefMut, e) => {
+                Type::reference(true, self.mutated(&mut e.value, &e.span))
+            }
+            // Either mutability will do
+            Expr::Unary(UnOp::Deref, e) => {
+                let ty = self.boxed(e);
+                let target = self.fresh();
+                let reference = Type::Ref(Box::new(self.fresh()), Box::new(target.clone()));
+                self.expect(&reference, &ty, &e.span, None);
+                target
+            }
+            Expr::Unary(_, e) => {
+                let ty = self.boxed(e);
+                let dict = self.want(self.num, ty.clone(), span);
+                overload(value, dict);
+                ty
+            }
+            Expr::Binary(op, l, r) => {
+                let (ty, dict) = self.binary(*op, l, r, span);
+                if let Some(dict) = dict {
+                    overload(value, dict);
+                }
+                ty
+            }
+            Expr::Lambda(param, body) => {
+                let param = self.pattern(param);
+                let ret = self.fresh();
+                self.returns.push(ret.clone());
+                let body_ty = self.boxed(body);
+                self.returns.pop();
+                self.expect(&ret, &body_ty, &body.span, None);
+                Type::fun(param, ret)
+            }
+            Expr::Call(f, x) => {
+                let f_ty = self.boxed(f);
+                let x_ty = self.boxed(x);
+                self.call(f_ty, &f.span, x_ty, &x.span, false)
+            }
+            Expr::EmptyCall(f) => {
+                let f_ty = self.boxed(f);
+                // The `()` token ends the call
+                let unit = Span::new(span.file, span.range.end - 2..span.range.end);
+                self.call(f_ty, &f.span, Type::Unit, &unit, true)
+            }
+            Expr::Tuple(elements) => {
+                Type::Tuple(elements.iter_mut().map(|e| self.expr(e)).collect())
+            }
+            Expr::Block {
+                statements,
+                returns,
+            } => self.block(statements, *returns),
+            Expr::If { cond, then, else_ } => {
+                let cond_ty = self.boxed(cond);
+                self.expect(&Type::Bool, &cond_ty, &cond.span, None);
+                let then_ty = self.boxed(then);
+                let else_ty = self.boxed(else_);
+                self.expect(
+                    &then_ty,
+                    &else_ty,
+                    tail_span(&else_.value, &else_.span),
+                    Some((
+                        tail_span(&then.value, &then.span).clone(),
+                        Because::Is(then_ty.clone()),
+                    )),
+                );
+                then_ty
+            }
+            Expr::Error => self.fresh(),
+            Expr::Method(..)
+            | Expr::DictApp(..)
+            | Expr::OverloadedUnary(..)
+            | Expr::OverloadedBinary(..) => unreachable!("Only type checking elaborates"),
+        }
+    }
+    // The type of the operation, and the dictionary of the class it's overloaded by
+    fn binary(
+        &mut self,
+        op: BinOp,
+        l: &mut BoxNode<Expr<'a>>,
+        r: &mut BoxNode<Expr<'a>>,
+        span: &Span,
+    ) -> (Type, Option<Dict>) {
+        let l_ty = self.boxed(l);
+        let r_ty = self.boxed(r);
+        let (class, result) = match op {
+            BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div => (self.num, l_ty.clone()),
+            BinOp::LT | BinOp::LTE | BinOp::GT | BinOp::GTE => (self.ord, Type::Bool),
+            BinOp::EqEq | BinOp::NotEq => (self.eq, Type::Bool),
+            BinOp::And | BinOp::Or => {
+                for (ty, e) in [(l_ty, &l.span), (r_ty, &r.span)] {
+                    self.expect(
+                        &Type::Bool,
+                        &ty,
+                        e,
+                        Some((span.clone(), Because::Operator(op))),
+                    );
+                }
+                return (Type::Bool, None);
+            }
+        };
+        // Both sides have the same type, which must have an instance of the class
+        self.expect(
+            &l_ty,
+            &r_ty,
+            &r.span,
+            Some((l.span.clone(), Because::Is(l_ty.clone()))),
+        );
+        let dict = self.want(class, l_ty, span);
+        (result, Some(dict))
+    }
+    fn call(&mut self, f_ty: Type, f_span: &Span, x_ty: Type, x_span: &Span, unit: bool) -> Type {
+        match self.shallow(&f_ty) {
+            Type::Fun(param, ret) => {
+                self.expect(
+                    &param,
+                    &x_ty,
+                    x_span,
+                    Some((f_span.clone(), Because::Expects((*param).clone()))),
+                );
+                *ret
+            }
+            Type::Var(_) => {
+                let ret = self.fresh();
+                self.expect(&f_ty, &Type::fun(x_ty, ret.clone()), f_span, None);
+                ret
+            }
+            ty => {
+                self.errors.push(TypeErr {
+                    span: f_span.clone(),
+                    kind: TypeErrKind::NotAFunction {
+                        ty,
+                        arg: x_span.clone(),
+                        unit,
+                    },
+                });
+                self.fresh()
+            }
+        }
+    }
+    fn block(&mut self, statements: &mut [Node<Statement<'a>>], returns: bool) -> Type {
+        let mut last = Type::Unit;
+        for Node { value, .. } in statements.iter_mut() {
+            last = match value {
+                Statement::Let(pattern, e) => {
+                    self.level += 1;
+                    let ty = self.expr(e);
+                    self.level -= 1;
+                    self.keep_constrained();
+                    match &pattern.value {
+                        Pattern::Binding(id, _) if !self.mutable.contains(id) => {
+                            let scheme = self.generalize(&ty);
+                            self.locals.insert(*id, scheme);
+                        }
+                        _ => {
+                            let pattern_ty = self.pattern(pattern);
+                            self.expect(&pattern_ty, &ty, &e.span, None);
+                        }
+                    }
+                    Type::Unit
+                }
+                // The value is evaluated before the place
+                Statement::Assign(place, e) => {
+                    let ty = self.expr(e);
+                    let place_ty = self.mutated(&mut place.value, &place.span);
+                    self.expect(&place_ty, &ty, &e.span, None);
+                    Type::Unit
+                }
+                Statement::Return(e) => {
+                    let ty = self.expr(e);
+                    let ret = self
+                        .returns
+                        .last()
+                        .cloned()
+                        .expect("`return` is always in a function");
+                    self.expect(&ret, &ty, &e.span, None);
+                    // The rest of the block is never reached, so it can have any type
+                    self.fresh()
+                }
+                Statement::Expr(e) => self.expr(e),
+            };
+        }
+        match statements.last().map(|s| &s.value) {
+            Some(Statement::Expr(_)) if !returns => Type::Unit,
+            Some(Statement::Let(..) | Statement::Assign(..)) | None => Type::Unit,
+            _ => last,
+        }
+    }
+
+    // Replaces the holes of an expression by the dictionaries solving them, `dicts` being the
+    // dictionary parameters of the enclosing item, and lowers the overloaded operators
+    fn fill(&self, value: &mut Expr<'a>, span: &Span, dicts: &[LocalId]) {
+        match value {
+            Expr::Literal(_) | Expr::Var(..) | Expr::Error => (),
+            Expr::Method(dict, ..) => *dict = self.dict(dict, dicts),
+            // Recursive uses within a group take the dictionaries of the enclosing item
+            Expr::DictApp(_, args) if args.is_empty() => {
+                if let Expr::DictApp(f, _) = mem::replace(value, Expr::Error) {
+                    *value = if dicts.is_empty() {
+                        *f.value
+                    } else {
+                        Expr::DictApp(f, dicts.iter().map(|l| Dict::Param(*l)).collect())
+                    };
+                }
+            }
+            Expr::DictApp(_, args) => {
+                for dict in args {
+                    *dict = self.dict(dict, dicts);
+                }
+            }
+            Expr::Unary(_, e) | Expr::Lambda(_, e) | Expr::EmptyCall(e) => {
+                self.fill(&mut e.value, &e.span, dicts)
+            }
+            Expr::Binary(_, l, r) | Expr::Call(l, r) => {
+                self.fill(&mut l.value, &l.span, dicts);
+                self.fill(&mut r.value, &r.span, dicts);
+            }
+            Expr::Tuple(elements) => {
+                for e in elements {
+                    self.fill(&mut e.value, &e.span, dicts);
+                }
+            }
+            Expr::If { cond, then, else_ } => {
+                for e in [cond, then, else_] {
+                    self.fill(&mut e.value, &e.span, dicts);
+                }
+            }
+            Expr::Block { statements, .. } => {
+                for s in statements {
+                    match &mut s.value {
+                        Statement::Let(_, e) | Statement::Return(e) | Statement::Expr(e) => {
+                            self.fill(&mut e.value, &e.span, dicts)
+                        }
+                        Statement::Assign(place, e) => {
+                            self.fill(&mut place.value, &place.span, dicts);
+                            self.fill(&mut e.value, &e.span, dicts);
+                        }
+                    }
+                }
+            }
+            Expr::OverloadedUnary(_, dict, e) => {
+                self.fill(&mut e.value, &e.span, dicts);
+                *dict = self.dict(dict, dicts);
+                self.lower_operator(value, span);
+            }
+            Expr::OverloadedBinary(_, dict, l, r) => {
+                self.fill(&mut l.value, &l.span, dicts);
+                self.fill(&mut r.value, &r.span, dicts);
+                *dict = self.dict(dict, dicts);
+                self.lower_operator(value, span);
+            }
+        }
+    }
+    fn dict(&self, dict: &Dict, dicts: &[LocalId]) -> Dict {
+        match dict {
+            Dict::Hole(hole) => match &self.holes[*hole] {
+                Some(Fill::Dict(filled)) => self.dict(filled, dicts),
+                // Left as a hole outside of items, or when the constraint failed to solve
+                Some(Fill::Param(idx)) => dicts.get(*idx).map_or(dict.clone(), |l| Dict::Param(*l)),
+                None => dict.clone(),
+            },
+            Dict::Instance(id, context) => {
+                Dict::Instance(*id, context.iter().map(|d| self.dict(d, dicts)).collect())
+            }
+            Dict::Param(_) => dict.clone(),
+        }
+    }
+    // Operators on the primitive instances stay primitive, the others call the methods of
+    // their dictionary. Those of dictionary parameters are left for runtime to tell
+    fn lower_operator(&self, value: &mut Expr<'a>, span: &Span) {
+        *value = match mem::replace(value, Expr::Error) {
+            e @ (Expr::OverloadedUnary(UnOp::Neg, Dict::Param(_), _)
+            | Expr::OverloadedBinary(_, Dict::Param(_), ..)) => e,
+            Expr::OverloadedUnary(op, dict, e) => {
+                if self.is_primitive(self.num, &dict) {
+                    Expr::Unary(op, e)
+                } else if op == UnOp::Pos {
+                    *e.value
+                } else {
+                    Expr::Call(self.method(self.num, "neg", dict, span), e)
+                }
+            }
+            Expr::OverloadedBinary(op, dict, l, r) => {
+                let (class, name) = self.operator(op);
+                if self.is_primitive(class, &dict) {
+                    Expr::Binary(op, l, r)
+                } else {
+                    let partial = Expr::Call(self.method(class, name, dict, span), l);
+                    Expr::Call(
+                        Node {
+                            value: Box::new(partial),
+                            span: span.clone(),
+                        },
+                        r,
+                    )
+                }
+            }
+            _ => unreachable!("Only operators are lowered"),
+        }
+    }
+    fn operator(&self, op: BinOp) -> (ClassId, &'static str) {
+        let (class, name) = operator(op);
+        let class = match class {
+            "Num" => self.num,
+            "Eq" => self.eq,
+            _ => self.ord,
+        };
+        (class, name)
+    }
+    fn method(&self, class: ClassId, name: &str, dict: Dict, span: &Span) -> BoxNode<Expr<'a>> {
+        let idx = self.classes[class.0]
+            .methods
+            .iter()
+            .position(|m| m.name.value == name)
+            .expect("The prelude declares the operator methods");
+        Node {
+            value: Box::new(Expr::Method(dict, class, idx)),
+            span: span.clone(),
+        }
+    }
+    // Whether the operators of `class` are built into the evaluator for the type of `dict`
+    fn is_primitive(&self, class: ClassId, dict: &Dict) -> bool {
+        match dict {
+            Dict::Instance(id, _) => match self.instances[id.0].ty.value {
+                Ty::Int => true,
+                Ty::Bool | Ty::Unit => class == self.eq,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+/// A type written in the source, with `vars` as the types of its variables
+pub fn lower(datas: &[Data], ty: &Ty, vars: &[Type]) -> Type {
+    let lower = |ty: &Ty| lower(datas, ty, vars);
+    match ty {
+        Ty::Var(idx) => vars[*idx].clone(),
+        Ty::Int => Type::Int,
+        Ty::Bool => Type::Bool,
+        Ty::Unit => Type::Unit,
+        Ty::Data(id) => Type::Con(datas[id.0].name.value.to_string()),
+        Ty::App(f, arg) => Type::app(lower(&f.value), lower(&arg.value)),
+        Ty::Fun(param, ret) => Type::fun(lower(&param.value), lower(&ret.value)),
+        Ty::Tuple(tys) => Type::Tuple(tys.iter().map(|ty| lower(&ty.value)).collect()),
+        Ty::Ref(mutable, ty) => Type::reference(*mutable, lower(&ty.value)),
+    }
+}
+
+// The span of the value of a block, or of the expression itself if it isn't a block
+fn tail_span<'e>(e: &'e Expr, span: &'e Span) -> &'e Span {
+    match e {
+        Expr::Block {
+            statements,
+            returns: true,
+        } => match statements.last().map(|s| &s.value) {
+            Some(Statement::Expr(Node { value, span })) => tail_span(value, span),
+            _ => span,
+        },
+        _ => span,
+    }
+}
+
+fn literal(l: &Literal) -> Type {
+    match l {
+        Literal::Num(_) => Type::Int,
+        Literal::Bool(_) => Type::Bool,
+        Literal::Unit => Type::Unit,
+    }
+}
+
+fn apply_dicts(value: &mut Expr, span: &Span, dicts: Vec<Dict>) {
+    let f = mem::replace(value, Expr::Error);
+    *value = Expr::DictApp(
+        Node {
+            value: Box::new(f),
+            span: span.clone(),
+        },
+        dicts,
+    );
+}
+
+fn overload(value: &mut Expr, dict: Dict) {
+    *value = match mem::replace(value, Expr::Error) {
+        Expr::Unary(op, e) => Expr::OverloadedUnary(op, dict, e),
+        Expr::Binary(op, l, r) => Expr::OverloadedBinary(op, dict, l, r),
+        _ => unreachable!("Only operators are overloaded"),
+    }
+}
@@ -0,0 +1,220 @@
+//! Kind inference for data types and class parameters, and kind checking of every type written
+//! in the program. Kinds aren't polymorphic: what nothing constrains defaults to `*`.
+use super::Kinds;
+use crate::{
+    errors::type_err::{TypeErr, TypeErrKind},
+    hir::*,
+    source::Span,
+};
+
+enum UnifyErr {
+    Mismatch,
+    Infinite,
+}
+
+// A kind during inference
+#[derive(Debug, PartialEq, Clone)]
+enum K {
+    Var(usize),
+    Star,
+    Arrow(Box<K>, Box<K>),
+}
+impl K {
+    fn arrow(param: K, ret: K) -> Self {
+        K::Arrow(Box::new(param), Box::new(ret))
+    }
+}
+impl From<&Kind> for K {
+    fn from(kind: &Kind) -> Self {
+        match kind {
+            Kind::Star => K::Star,
+            Kind::Arrow(param, ret) => K::arrow((&**param).into(), (&**ret).into()),
+        }
+    }
+}
+
+/// Infers the kinds of the data types, then of the classes, and checks instances against them
+pub(super) fn check(program: &Program, errors: &mut Vec<TypeErr>) -> Kinds {
+    let mut infer = KindInfer {
+        bindings: vec![],
+        datas: vec![],
+        errors,
+    };
+    // Data types can refer to each other, their kinds are inferred together
+    let params: Vec<Vec<K>> = program
+        .datas
+        .iter()
+        .map(|data| data.params.iter().map(|p| infer.param(p)).collect())
+        .collect();
+    infer.datas = params
+        .iter()
+        .map(|params| {
+            params
+                .iter()
+                .rev()
+                .fold(K::Star, |ret, p| K::arrow(p.clone(), ret))
+        })
+        .collect();
+    for (data, params) in program.datas.iter().zip(&params) {
+        for ctor in &data.ctors {
+            for field in &ctor.fields {
+                infer.star(&field.value, &field.span, params);
+            }
+        }
+    }
+    let datas: Vec<Kind> = infer
+        .datas
+        .clone()
+        .iter()
+        .map(|k| infer.default(k))
+        .collect();
+    infer.datas = datas.iter().map(K::from).collect();
+    let classes: Vec<Kind> = program
+        .classes
+        .iter()
+        .map(|class| {
+            let param = infer.param(&class.param);
+            for method in &class.methods {
+                let mut vars = vec![param.clone()];
+                vars.extend((1..method.vars).map(|_| infer.fresh()));
+                infer.star(&method.ty.value, &method.ty.span, &vars);
+            }
+            infer.default(&param)
+        })
+        .collect();
+    for instance in &program.instances {
+        let vars: Vec<_> = (0..instance.vars).map(|_| infer.fresh()).collect();
+        let kind = infer.infer(&instance.ty.value, &vars);
+        let expected = K::from(&classes[instance.class.0]);
+        infer.expect(&expected, &kind, &instance.ty.span);
+        // The variables of its context have the kinds of their classes' parameters
+        for Node { value, span } in &instance.context {
+            let (class, var) = value;
+            infer.expect(&K::from(&classes[class.0]), &vars[*var], span);
+        }
+    }
+    Kinds { datas, classes }
+}
+
+struct KindInfer<'e> {
+    bindings: Vec<Option<K>>,
+    // The kinds of the data types
+    datas: Vec<K>,
+    errors: &'e mut Vec<TypeErr>,
+}
+impl<'e> KindInfer<'e> {
+    fn fresh(&mut self) -> K {
+        self.bindings.push(None);
+        K::Var(self.bindings.len() - 1)
+    }
+    fn param(&mut self, param: &TypeParam) -> K {
+        match &param.kind {
+            Some(kind) => (&kind.value).into(),
+            None => self.fresh(),
+        }
+    }
+    fn shallow(&self, kind: &K) -> K {
+        match kind {
+            K::Var(v) => match &self.bindings[*v] {
+                Some(kind) => self.shallow(kind),
+                None => kind.clone(),
+            },
+            _ => kind.clone(),
+        }
+    }
+    // The kind, with the variables left defaulted to `*`
+    fn default(&mut self, kind: &K) -> Kind {
+        match self.shallow(kind) {
+            K::Var(v) => {
+                self.bindings[v] = Some(K::Star);
+                Kind::Star
+            }
+            K::Star => Kind::Star,
+            K::Arrow(param, ret) => {
+                Kind::Arrow(Box::new(self.default(&param)), Box::new(self.default(&ret)))
+            }
+        }
+    }
+    fn occurs(&self, v: usize, kind: &K) -> bool {
+        match self.shallow(kind) {
+            K::Var(w) => v == w,
+            K::Star => false,
+            K::Arrow(param, ret) => self.occurs(v, &param) || self.occurs(v, &ret),
+        }
+    }
+    fn unify(&mut self, a: &K, b: &K) -> Result<(), UnifyErr> {
+        match (self.shallow(a), self.shallow(b)) {
+            (K::Var(v), K::Var(w)) if v == w => Ok(()),
+            (K::Var(v), kind) | (kind, K::Var(v)) => {
+                if self.occurs(v, &kind) {
+                    return Err(UnifyErr::Infinite);
+                }
+                self.bindings[v] = Some(kind);
+                Ok(())
+            }
+            (K::Star, K::Star) => Ok(()),
+            (K::Arrow(p1, r1), K::Arrow(p2, r2)) => {
+                self.unify(&p1, &p2)?;
+                self.unify(&r1, &r2)
+            }
+            _ => Err(UnifyErr::Mismatch),
+        }
+    }
+    fn expect(&mut self, expected: &K, found: &K, span: &Span) {
+        let kind = match self.unify(expected, found) {
+            Ok(()) => return,
+            Err(UnifyErr::Mismatch) => TypeErrKind::KindMismatch {
+                expected: self.default(expected),
+                found: self.default(found),
+            },
+            Err(UnifyErr::Infinite) => TypeErrKind::InfiniteKind,
+        };
+        self.errors.push(TypeErr {
+            span: span.clone(),
+            kind,
+        });
+    }
+    // Types of values, like fields and signatures, have kind `*`
+    fn star(&mut self, ty: &Ty, span: &Span, vars: &[K]) {
+        let kind = self.infer(ty, vars);
+        self.expect(&K::Star, &kind, span);
+    }
+    fn infer(&mut self, ty: &Ty, vars: &[K]) -> K {
+        match ty {
+            Ty::Var(idx) => vars[*idx].clone(),
+            Ty::Int | Ty::Bool | Ty::Unit => K::Star,
+            Ty::Data(id) => self.datas[id.0].clone(),
+            Ty::App(f, arg) => {
+                let f_kind = self.infer(&f.value, vars);
+                let arg_kind = self.infer(&arg.value, vars);
+                match self.shallow(&f_kind) {
+                    K::Arrow(param, ret) => {
+                        self.expect(&param, &arg_kind, &arg.span);
+                        *ret
+                    }
+                    // A variable becomes a constructor, while `*` can't be applied
+                    _ => {
+                        let ret = self.fresh();
+                        self.expect(&K::arrow(arg_kind, ret.clone()), &f_kind, &f.span);
+                        ret
+                    }
+                }
+            }
+            Ty::Fun(param, ret) => {
+                self.star(&param.value, &param.span, vars);
+                self.star(&ret.value, &ret.span, vars);
+                K::Star
+            }
+            Ty::Tuple(tys) => {
+                for ty in tys {
+                    self.star(&ty.value, &ty.span, vars);
+                }
+                K::Star
+            }
+            Ty::Ref(_, ty) => {
+                self.star(&ty.value, &ty.span, vars);
+                K::Star
+            }
+        }
+    }
+}
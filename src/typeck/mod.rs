@@ -0,0 +1,680 @@
+//! Hindley-Milner type inference over the HIR
+mod exhaustive;
+mod infer;
+mod kinds;
+mod ownership;
+
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    errors::type_err::TypeErr,
+    hir::{ClassId, Data, DefId, Expr, Item, Kind, LocalId, Node, Program, Res, Statement},
+    source::Span,
+};
+pub use exhaustive::{check_expr_patterns, check_patterns};
+pub use infer::lower as lower_ty;
+use infer::Infer;
+pub use ownership::{check_expr_ownership, check_ownership};
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, PartialOrd, Ord)]
+pub struct TyVar(pub usize);
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Type {
+    Var(TyVar),
+    Int,
+    Bool,
+    Unit,
+    /// A data type, by name
+    Con(String),
+    App(Box<Type>, Box<Type>),
+    Fun(Box<Type>, Box<Type>),
+    Tuple(Vec<Type>),
+    /// A reference, `&T` or `&mut T`. Its mutability is a type of its own so that it can be
+    /// inferred like the rest, `*r` works on both
+    Ref(Box<Type>, Box<Type>),
+    /// The mutability of a reference, `true` for `&mut`
+    Mutability(bool),
+}
+impl Type {
+    pub fn fun(param: Type, ret: Type) -> Self {
+        Type::Fun(Box::new(param), Box::new(ret))
+    }
+    pub fn app(f: Type, arg: Type) -> Self {
+        Type::App(Box::new(f), Box::new(arg))
+    }
+    pub fn reference(mutable: bool, ty: Type) -> Self {
+        Type::Ref(Box::new(Type::Mutability(mutable)), Box::new(ty))
+    }
+    /// Whether its values have a unique owner: the owned data types, and the types containing
+    /// them, functions aside since they can't capture owned values
+    pub fn is_owned(&self, datas: &[Data]) -> bool {
+        match self {
+            Type::Con(name) => datas.iter().any(|d| d.owned && d.name.value == name),
+            Type::App(f, arg) => f.is_owned(datas) || arg.is_owned(datas),
+            Type::Tuple(tys) => tys.iter().any(|ty| ty.is_owned(datas)),
+            Type::Var(_)
+            | Type::Int
+            | Type::Bool
+            | Type::Unit
+            | Type::Fun(..)
+            | Type::Ref(..)
+            | Type::Mutability(_) => false,
+        }
+    }
+    /// Whether its values contain references, functions aside since they can't capture them
+    pub fn has_refs(&self) -> bool {
+        match self {
+            Type::Ref(..) => true,
+            Type::App(f, arg) => f.has_refs() || arg.has_refs(),
+            Type::Tuple(tys) => tys.iter().any(Type::has_refs),
+            _ => false,
+        }
+    }
+    fn vars(&self, acc: &mut Vec<TyVar>) {
+        match self {
+            Type::Var(v) if !acc.contains(v) => acc.push(*v),
+            Type::App(a, b) | Type::Fun(a, b) | Type::Ref(a, b) => {
+                a.vars(acc);
+                b.vars(acc);
+            }
+            Type::Tuple(tys) => {
+                for ty in tys {
+                    ty.vars(acc);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+// Where a type is written, to know whether it needs parentheses
+#[derive(PartialEq, PartialOrd)]
+enum Position {
+    Top,
+    Param,
+    Arg,
+}
+
+/// A type quantified over `vars`, like `a -> a` for the identity, which may only be
+/// instantiated with types satisfying its constraints, like `Eq a => a -> a -> Bool`.
+/// Each constraint is a dictionary passed to the item, in order
+#[derive(Debug, PartialEq, Clone)]
+pub struct Scheme {
+    pub vars: Vec<TyVar>,
+    pub constraints: Vec<Constraint>,
+    pub ty: Type,
+}
+impl Scheme {
+    pub fn mono(ty: Type) -> Self {
+        Self {
+            vars: vec![],
+            constraints: vec![],
+            ty,
+        }
+    }
+}
+
+/// `var` must have an instance of `class`, whose name is kept for display
+#[derive(Debug, PartialEq, Clone)]
+pub struct Constraint {
+    pub class: ClassId,
+    pub name: String,
+    pub var: TyVar,
+}
+
+/// Names type variables `a`, `b`, ... in their order of appearance, sharing the names between
+/// every type it prints so that they can be compared
+#[derive(Default)]
+pub struct TypeNames {
+    names: HashMap<TyVar, String>,
+}
+impl TypeNames {
+    pub fn show(&mut self, ty: &Type) -> String {
+        let mut s = String::new();
+        self.write(ty, &mut s, Position::Top);
+        s
+    }
+    fn write(&mut self, ty: &Type, s: &mut String, position: Position) {
+        match ty {
+            Type::Var(v) => {
+                let len = self.names.len();
+                let name = self.names.entry(*v).or_insert_with(|| var_name(len));
+                s.push_str(name);
+            }
+            Type::Int => s.push_str("Int"),
+            Type::Bool => s.push_str("Bool"),
+            Type::Unit => s.push_str("()"),
+            Type::Con(name) => s.push_str(name),
+            Type::App(f, arg) => {
+                if position == Position::Arg {
+                    s.push('(');
+                }
+                self.write(f, s, Position::Param);
+                s.push(' ');
+                self.write(arg, s, Position::Arg);
+                if position == Position::Arg {
+                    s.push(')');
+                }
+            }
+            Type::Fun(param, ret) => {
+                if position >= Position::Param {
+                    s.push('(');
+                }
+                self.write(param, s, Position::Param);
+                s.push_str(" -> ");
+                self.write(ret, s, Position::Top);
+                if position >= Position::Param {
+                    s.push(')');
+                }
+            }
+            Type::Tuple(tys) => {
+                s.push('(');
+                for (idx, ty) in tys.iter().enumerate() {
+                    if idx > 0 {
+                        s.push_str(", ");
+                    }
+                    self.write(ty, s, Position::Top);
+                }
+                s.push(')');
+            }
+            Type::Ref(mutability, ty) => {
+                if position == Position::Arg {
+                    s.push('(');
+                }
+                s.push('&');
+                match **mutability {
+                    Type::Mutability(false) => (),
+                    Type::Mutability(true) => s.push_str("mut "),
+                    // Either one
+                    _ => s.push_str("mut? "),
+                }
+                self.write(ty, s, Position::Param);
+                if position == Position::Arg {
+                    s.push(')');
+                }
+            }
+            Type::Mutability(mutable) => s.push_str(if *mutable { "mut" } else { "shared" }),
+        }
+    }
+}
+fn var_name(idx: usize) -> String {
+    let letter = (b'a' + (idx % 26) as u8) as char;
+    match idx / 26 {
+        0 => letter.to_string(),
+        n => format!("{}{}", letter, n),
+    }
+}
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", TypeNames::default().show(self))
+    }
+}
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The type is named first, so that its variables are named in order
+        let mut names = TypeNames::default();
+        let ty = names.show(&self.ty);
+        let constraints: Vec<_> = self
+            .constraints
+            .iter()
+            .map(|c| format!("{} {}", c.name, names.show(&Type::Var(c.var))))
+            .collect();
+        match &constraints[..] {
+            [] => write!(f, "{}", ty),
+            [c] => write!(f, "{} => {}", c, ty),
+            cs => write!(f, "({}) => {}", cs.join(", "), ty),
+        }
+    }
+}
+
+/// The types of a program, its items' are indexed by `DefId`
+#[derive(Debug, Default)]
+pub struct Types {
+    pub items: Vec<Scheme>,
+    /// By `DataId`, then in the order of the data type
+    pub ctors: Vec<Vec<Scheme>>,
+    pub kinds: Kinds,
+    /// The types of the locals, generalised ones being instantiated with their variables
+    pub locals: HashMap<LocalId, Type>,
+    /// The types of the expressions and patterns by span, the outermost node's when several
+    /// share a span
+    pub spans: HashMap<Span, Type>,
+    /// The types dropped by the drop glue items, which follow the program's items in order
+    pub glue: Vec<Type>,
+}
+
+/// The kinds of the data types and of the classes' parameters, by id
+#[derive(Debug, Default)]
+pub struct Kinds {
+    pub datas: Vec<Kind>,
+    pub classes: Vec<Kind>,
+}
+
+/// Infers the type of every item, generalising each group of mutually recursive items
+/// once the group is inferred.
+///
+/// The program is elaborated to dictionary passing along the way: constrained items take
+/// their dictionaries in `Item::dicts`, and their uses, the class methods and the overloaded
+/// operators get the dictionaries of the instances they use. Operators on the primitive
+/// instances stay primitive.
+pub fn typecheck(program: &mut Program) -> (Types, Vec<TypeErr>) {
+    let mut errors = vec![];
+    let kinds = kinds::check(program, &mut errors);
+    // Types can't be compared when their kinds are wrong
+    if !errors.is_empty() {
+        return (Types::default(), errors);
+    }
+    let Program {
+        items,
+        classes,
+        instances,
+        datas,
+        locals,
+        mutable,
+    } = program;
+    let mut infer = Infer::new(classes, instances, datas, mutable, *locals, items.len());
+    for group in item_groups(items) {
+        infer.items(items, &group);
+    }
+    let (mut types, type_errors) = infer.finish(locals);
+    types.kinds = kinds;
+    errors.extend(type_errors);
+    (types, errors)
+}
+
+/// Infers the type of an expression using the items of a program, for the REPL. The items are
+/// expected to be kind checked already. The dictionaries of the expression's own constraints are left as holes
+pub fn typecheck_expr<'a>(
+    program: &mut Program<'a>,
+    expr: &mut Node<Expr<'a>>,
+) -> (Scheme, Types, Vec<TypeErr>) {
+    let Program {
+        items,
+        classes,
+        instances,
+        datas,
+        locals,
+        mutable,
+    } = program;
+    let mut infer = Infer::new(classes, instances, datas, mutable, *locals, items.len());
+    for group in item_groups(items) {
+        infer.items(items, &group);
+    }
+    let scheme = infer.top_expr(expr);
+    let (types, errors) = infer.finish(locals);
+    (scheme, types, errors)
+}
+
+/// The strongly connected components of the graph of item references, each one after the
+/// components it refers to
+fn item_groups(items: &[Item]) -> Vec<Vec<DefId>> {
+    let edges: Vec<Vec<DefId>> = items
+        .iter()
+        .map(|item| {
+            let mut refs = vec![];
+            for clause in &item.clauses {
+                expr_refs(&clause.body.value, &mut refs);
+            }
+            refs
+        })
+        .collect();
+    components(&edges)
+}
+
+/// The strongly connected components of a graph, given the nodes each node has edges to, each
+/// one after the components it has edges to
+pub(crate) fn components(edges: &[Vec<DefId>]) -> Vec<Vec<DefId>> {
+    Tarjan {
+        edges,
+        index: vec![None; edges.len()],
+        low: vec![0; edges.len()],
+        stack: vec![],
+        on_stack: vec![false; edges.len()],
+        next: 0,
+        groups: vec![],
+    }
+    .run()
+}
+
+fn expr_refs(e: &Expr, refs: &mut Vec<DefId>) {
+    match e {
+        Expr::Var(Res::Def(id), _) => refs.push(*id),
+        Expr::Literal(_) | Expr::Var(..) | Expr::Error | Expr::Method(..) => (),
+        Expr::Unary(_, e)
+        | Expr::OverloadedUnary(_, _, e)
+        | Expr::Lambda(_, e)
+        | Expr::EmptyCall(e)
+        | Expr::DictApp(e, _) => expr_refs(&e.value, refs),
+        Expr::Binary(_, l, r) | Expr::OverloadedBinary(_, _, l, r) | Expr::Call(l, r) => {
+            expr_refs(&l.value, refs);
+            expr_refs(&r.value, refs);
+        }
+        Expr::Tuple(elements) => {
+            for e in elements {
+                expr_refs(&e.value, refs);
+            }
+        }
+        Expr::If { cond, then, else_ } => {
+            expr_refs(&cond.value, refs);
+            expr_refs(&then.value, refs);
+            expr_refs(&else_.value, refs);
+        }
+        Expr::Block { statements, .. } => {
+            for s in statements {
+                match &s.value {
+                    Statement::Let(_, e) | Statement::Return(e) | Statement::Expr(e) => {
+                        expr_refs(&e.value, refs)
+                    }
+                    Statement::Assign(place, e) => {
+                        expr_refs(&place.value, refs);
+                        expr_refs(&e.value, refs);
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Tarjan<'e> {
+    edges: &'e [Vec<DefId>],
+    index: Vec<Option<usize>>,
+    low: Vec<usize>,
+    stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    next: usize,
+    groups: Vec<Vec<DefId>>,
+}
+impl<'e> Tarjan<'e> {
+    fn run(mut self) -> Vec<Vec<DefId>> {
+        for v in 0..self.edges.len() {
+            if self.index[v].is_none() {
+                self.visit(v);
+            }
+        }
+        self.groups
+    }
+    fn visit(&mut self, v: usize) {
+        self.index[v] = Some(self.next);
+        self.low[v] = self.next;
+        self.next += 1;
+        self.stack.push(v);
+        self.on_stack[v] = true;
+        for &DefId(w) in &self.edges[v] {
+            match self.index[w] {
+                None => {
+                    self.visit(w);
+                    self.low[v] = self.low[v].min(self.low[w]);
+                }
+                Some(idx) if self.on_stack[w] => self.low[v] = self.low[v].min(idx),
+                Some(_) => (),
+            }
+        }
+        if Some(self.low[v]) == self.index[v] {
+            let mut group = vec![];
+            while let Some(w) = self.stack.pop() {
+                self.on_stack[w] = false;
+                group.push(DefId(w));
+                if w == v {
+                    break;
+                }
+            }
+            group.sort();
+            self.groups.push(group);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        check::tests::{check_code, step_errors},
+        errors::{type_err::TypeErrKind, ErrorKind},
+        hir::{BinOp, Dict, InstanceId},
+    };
+
+    // The program, with the prelude, checked
+    fn check(code: &'static str) -> (Program<'static>, Types, Vec<TypeErr>) {
+        let mut checked = check_code(code, false);
+        let errors = step_errors!(checked, Type);
+        (checked.program, checked.types.expect(code), errors)
+    }
+    // The types of the program's own functions
+    fn types(code: &'static str) -> Vec<String> {
+        let (program, types, errors) = check(code);
+        assert!(errors.is_empty(), "{:?}", errors);
+        program
+            .items
+            .iter()
+            .zip(&types.items)
+            .filter(|(item, _)| item.name.span.file == 1 && item.instance.is_none())
+            .map(|(_, s)| s.to_string())
+            .collect()
+    }
+    fn errors(code: &'static str) -> Vec<TypeErr> {
+        check(code).2
+    }
+
+    #[test]
+    fn inference() {
+        assert_eq!(
+            types("id x = x\nconst x _ = x\ncompose f g x = f (g x)\n"),
+            ["a -> a", "a -> b -> a", "(a -> b) -> (c -> a) -> c -> b"]
+        );
+        assert_eq!(
+            types("f x = if x then 1 else -2\ng x y = x == y && !(x != y)\n"),
+            ["Bool -> Int", "Eq a => a -> a -> Bool"]
+        );
+        assert_eq!(types("f x =\n  return x + 1\n  x\n"), ["Int -> Int"]);
+        assert!(!errors("f x =\n  return x + 1\n  true\n").is_empty());
+        // Assignments through a reference need it to be mutable
+        assert_eq!(types("set r v = *r = v\n"), ["&mut a -> a -> ()"]);
+        assert!(!errors("set r v = *r = v\nf = set &1 2\n").is_empty());
+        assert!(!errors("f =\n  let mut x = 1\n  x = true\n").is_empty());
+    }
+
+    #[test]
+    fn generalisation() {
+        // Items are generalised, whatever their order
+        assert_eq!(
+            types("a = id 1 + (if id true then 1 else 2)\nid x = x\n")[0],
+            "Int"
+        );
+        // Lets too
+        assert_eq!(
+            types("f =\n  let id = \\x -> x\n  if id true then id 1 else 2\n"),
+            ["Int"]
+        );
+        // Mutually recursive items are monomorphic within their group
+        assert_eq!(
+            types("even n = if n == 0 then true else odd (n - 1)\nodd n = if n == 0 then false else even (n - 1)\n"),
+            ["Int -> Bool", "Int -> Bool"]
+        );
+        assert!(!errors("f x = g x\ng x = if f true then f 1 else 2\n").is_empty());
+        // Neither are `mut` locals, nor parameters
+        assert!(
+            !errors("f =\n  let mut id = \\x -> x\n  if id true then id 1 else 2\n").is_empty()
+        );
+        assert!(!errors("f id = if id true then id 1 else 2\n").is_empty());
+    }
+
+    #[test]
+    fn mismatches_label_both_sides() {
+        let errors = errors("f x = if x then 1 else true\n");
+        match &errors[..] {
+            [TypeErr {
+                span,
+                kind:
+                    TypeErrKind::Mismatch {
+                        expected: Type::Int,
+                        found: Type::Bool,
+                        because: Some((other, _)),
+                    },
+            }] => {
+                assert_eq!(span.range, 23..27);
+                assert_eq!(other.range, 16..17);
+            }
+            errors => panic!("expected a mismatch, got {:?}", errors),
+        }
+        let codes = |code| -> Vec<_> { self::errors(code).iter().map(|e| e.kind.code()).collect() };
+        assert_eq!(codes("f x = x x\n"), ["E0009"]);
+        assert_eq!(codes("f = 1 2\n"), ["E0010"]);
+        // Items without parameters are values, they aren't called with `()`
+        assert_eq!(codes("x = 1\ny = x ()\n"), ["E0010"]);
+        assert!(self::errors("f () = 1\ny = f ()\n").is_empty());
+        assert_eq!(codes("f g = g 1 + g true\n"), ["E0008"]);
+    }
+
+    #[test]
+    fn constraints() {
+        assert_eq!(
+            types("double x = x + x\nmax x y = if x > y then x else y\nf x y = x == x && y < y\n"),
+            [
+                "Num a => a -> a",
+                "Ord a => a -> a -> a",
+                "(Eq a, Ord b) => a -> b -> Bool"
+            ]
+        );
+        // Constraints on concrete types are solved by instances
+        assert_eq!(types("a = 1 + 2 == 3\nb = neg 1\n"), ["Bool", "Int"]);
+        assert_eq!(
+            types("class Size a =\n  size : a -> Int\ninstance Size Bool =\n  size b = 1\nf x = size x + size true\n"),
+            ["Size a => a -> Int"]
+        );
+        // Constrained lets aren't generalised
+        assert!(!errors("f =\n  let same = \\x -> x == x\n  same 1 && same true\n").is_empty());
+        let codes = |code| -> Vec<_> { errors(code).iter().map(|e| e.kind.code()).collect() };
+        assert_eq!(codes("a = true < false\n"), ["E0013"]);
+        assert_eq!(codes("instance Ord Int =\n  lt x y = true\n  le x y = true\n  gt x y = true\n  ge x y = true\n"), ["E0014"]);
+        assert_eq!(codes("a = (\\_ -> 1) (\\y -> y == y)\n"), ["E0015"]);
+        // Nothing uses `main` to pick its instances
+        assert_eq!(codes("double x = x + x\nmain = double\n"), ["E0043"]);
+        assert!(errors("double x = x + x\nmain = double 2\n").is_empty());
+        // Instance methods have the type of the class' method
+        assert_eq!(
+            codes("class Size a =\n  size : a -> Int\ninstance Size Bool =\n  size b = b\n"),
+            ["E0008"]
+        );
+        // Instances with a context want the instances of the variables of their type, which
+        // their methods are given
+        assert_eq!(
+            types("f x y = (x, 1) == (x, y)\n"),
+            ["Eq a => a -> Int -> Bool"]
+        );
+        assert_eq!(codes("a = (1, \\x -> x) == (1, \\x -> x)\n"), ["E0013"]);
+        let option = "data Option: T =\n  None\n  Some T\ninstance Eq (Option a) =\n  eq (Some x) (Some y) = x == y\n  eq _ _ = false\n  ne _ _ = false\n";
+        assert_eq!(codes(option), ["E0013"]);
+        let option = "data Option: T =\n  None\n  Some T\ninstance Eq a => Eq (Option a) =\n  eq (Some x) (Some y) = x == y\n  eq _ _ = false\n  ne _ _ = false\n";
+        assert!(errors(option).is_empty());
+        // Their types overlap when they have a common instance
+        let overlapping = "data Option: T =\n  None\n  Some T\ninstance Eq a => Eq (Option a) =\n  eq _ _ = true\n  ne _ _ = false\ninstance Eq (Option Int) =\n  eq _ _ = true\n  ne _ _ = false\n";
+        assert_eq!(codes(overlapping), ["E0014"]);
+    }
+
+    #[test]
+    fn kinds() {
+        let code = "data Fix: F =\n  In (F (Fix F))\ndata Pair: A B =\n  Pair A B\nclass Functor f =\n  fmap : (a -> b) -> f a -> f b\n";
+        let (_, checked, errs) = check(code);
+        assert!(errs.is_empty(), "{:?}", errs);
+        let kinds: Vec<_> = checked.kinds.datas.iter().map(|k| k.to_string()).collect();
+        assert_eq!(kinds, ["(* -> *) -> *", "* -> * -> *"]);
+        assert_eq!(checked.kinds.classes[4].to_string(), "* -> *");
+        assert_eq!(checked.ctors[1][0].to_string(), "a -> b -> Pair a b");
+        // Higher-kinded classes are instantiated with type constructors
+        let functor = "data Option: T =\n  None\n  Some T\nclass Functor f =\n  fmap : (a -> b) -> f a -> f b\ninstance Functor ";
+        assert_eq!(
+            types(Box::leak(
+                format!(
+                    "{}Option =\n  fmap f o = None\nmapped = fmap (\\y -> y + 1) (Some 1)\n",
+                    functor
+                )
+                .into()
+            )),
+            ["Option Int"]
+        );
+        let codes = |code: String| -> Vec<_> {
+            errors(Box::leak(code.into()))
+                .iter()
+                .map(|e| e.kind.code())
+                .collect()
+        };
+        assert_eq!(
+            codes(format!("{}Int =\n  fmap f o = o\n", functor)),
+            ["E0016"]
+        );
+        // An instance method can't be less general than its signature
+        assert_eq!(
+            codes(format!("{}Option =\n  fmap f o = o\n", functor)),
+            ["E0008"]
+        );
+        assert_eq!(codes("data T: A =\n  C (A Int) A\n".into()), ["E0016"]);
+        // The variables of an instance's context have the kinds of their classes' parameters
+        assert_eq!(
+            codes(format!("{}Option =\n  fmap f o = None\ninstance Functor a => Eq (Option a) =\n  eq _ _ = true\n  ne _ _ = false\n", functor)),
+            ["E0016"]
+        );
+        assert_eq!(codes("data T: A =\n  C (A A)\n".into()), ["E0017"]);
+        assert_eq!(codes("data T: (A : * -> *) =\n  C A\n".into()), ["E0016"]);
+    }
+
+    #[test]
+    fn clauses_and_tuples() {
+        let option = "data Option: T =\n  None\n  Some T\n";
+        assert_eq!(
+            types(Box::leak(
+                format!("{}unwrap d None = d\nunwrap _ (Some a) = a\n", option).into()
+            )),
+            ["a -> Option a -> a"]
+        );
+        assert_eq!(types("swap (a, b) = (b, a)\n"), ["(a, b) -> (b, a)"]);
+        assert_eq!(
+            types("f 0 (x, true) = x\nf n (_, b) = if b then n else -n\n"),
+            ["Int -> (Int, Bool) -> Int"]
+        );
+        let codes = |code| -> Vec<_> { errors(code).iter().map(|e| e.kind.code()).collect() };
+        assert_eq!(codes("f 0 = 1\nf true = 2\n"), ["E0008"]);
+        assert_eq!(codes("f (a, b) = a\ng = f (1, 2, 3)\n"), ["E0008"]);
+    }
+
+    // The value of a block
+    fn tail<'e, 'a>(e: &'e Expr<'a>) -> &'e Expr<'a> {
+        match e {
+            Expr::Block { statements, .. } => match &statements.last().unwrap().value {
+                Statement::Expr(e) => tail(&e.value),
+                s => panic!("expected an expression, got {:?}", s),
+            },
+            e => e,
+        }
+    }
+
+    #[test]
+    fn elaboration() {
+        let (program, _, errors) = check("double x = x + x\nf = double 1 + 2\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let item = |name| program.items.iter().find(|i| i.name.value == name).unwrap();
+        // `double` takes the dictionary of `Num a`, which its addition is overloaded by until
+        // runtime
+        let double = item("double");
+        match (&double.dicts[..], tail(&double.clauses[0].body.value)) {
+            ([dict], Expr::OverloadedBinary(BinOp::Add, Dict::Param(param), ..)) => {
+                assert_eq!(param, dict)
+            }
+            (dicts, e) => panic!(
+                "expected an overloaded addition with a dictionary, got {:?} {:?}",
+                dicts, e
+            ),
+        }
+        // `f` passes `Num Int` to `double`, and adds `Int`s primitively
+        match tail(&item("f").clauses[0].body.value) {
+            Expr::Binary(BinOp::Add, l, _) => match l.value.as_ref() {
+                Expr::Call(f, _) => assert!(matches!(
+                    f.value.as_ref(),
+                    Expr::DictApp(_, dicts) if dicts[..] == [Dict::Instance(InstanceId(0), vec![])]
+                )),
+                e => panic!("expected a call, got {:?}", e),
+            },
+            e => panic!("expected a primitive addition, got {:?}", e),
+        }
+    }
+}
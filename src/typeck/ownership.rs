@@ -0,0 +1,792 @@
+//! Unique ownership of the values of owned types. Such a value has a single owner: the local
+//! it's bound to, or the expression it's given to. Using an owned local moves its value out of
+//! it, after which it can't be used anymore, and the owned locals still holding their value at
+//! the end of their scope are dropped there, the last declared first. A local moved on a
+//! single branch of an `if` is dropped at the end of the other one, and a `return` drops
+//! every local of its function.
+//!
+//! Drops are elaborated into the HIR as calls of drop glue: the `drop` method of the type's
+//! `Drop` instance when it has one, or an item generated for the type, which takes its values
+//! apart to drop their owned fields. The backends run them like any other call.
+//!
+//! Ownership is checked once the patterns are, using the types inferred for the locals and
+//! expressions.
+use std::{collections::HashMap, mem, slice};
+
+use super::{infer::lower, TyVar, Type, Types};
+use crate::{
+    errors::ownership_err::{OwnershipErr, OwnershipErrKind},
+    hir::*,
+    source::Span,
+};
+
+/// Checks the moves of the owned values of a program, and elaborates their drops. The items of
+/// the drop glue are added after the program's, and the types they drop to `Types::glue`
+pub fn check_ownership(program: &mut Program, types: &mut Types) -> Vec<OwnershipErr> {
+    let mut items = mem::take(&mut program.items);
+    let arities = items.iter().map(Item::arity).collect();
+    let mut checker = Checker::new(program, types, arities);
+    checker.datas();
+    for item in &mut items {
+        checker.item(item);
+    }
+    let (glue, locals, errors) = checker.finish();
+    items.extend(glue);
+    program.items = items;
+    program.locals = locals;
+    errors
+}
+
+/// Checks the moves of the items of a program and of an expression using them, for the REPL
+pub fn check_expr_ownership<'a>(
+    program: &mut Program<'a>,
+    e: &mut Node<Expr<'a>>,
+    types: &mut Types,
+) -> Vec<OwnershipErr> {
+    let mut errors = check_ownership(program, types);
+    let arities = program.items.iter().map(Item::arity).collect();
+    let mut checker = Checker::new(program, types, arities);
+    checker.function(&mut [], &mut e.value, &e.span, false);
+    let (glue, locals, expr_errors) = checker.finish();
+    program.items.extend(glue);
+    program.locals = locals;
+    errors.extend(expr_errors);
+    errors
+}
+
+// An owned local, with the number of functions enclosing it
+#[derive(Clone)]
+struct Owned<'a> {
+    name: Ident<'a>,
+    depth: usize,
+    span: Span,
+}
+
+struct Checker<'p, 'a> {
+    datas: &'p [Data<'a>],
+    types: &'p mut Types,
+    // The number of arguments each item of the program takes
+    arities: Vec<usize>,
+    drop: ClassId,
+    // The types with a `Drop` instance, with their instance
+    destructors: Vec<(Type, InstanceId)>,
+    // The drop glue items generated for types, their ids following the program's items
+    glue: Vec<(Type, DefId)>,
+    glue_items: Vec<Option<Item<'a>>>,
+    local_count: usize,
+    // The owned locals in scope by scope, innermost last, and the first scope of each
+    // enclosing function
+    scopes: Vec<Vec<LocalId>>,
+    functions: Vec<usize>,
+    owned: HashMap<LocalId, Owned<'a>>,
+    // Where the moved locals were moved
+    moved: HashMap<LocalId, Span>,
+    // Whether the end of the expression being checked is never reached, after a `return`
+    diverges: bool,
+    // The type whose destructor is being checked, which may take its values apart
+    destructor: Option<Type>,
+    errors: Vec<OwnershipErr>,
+}
+impl<'p, 'a> Checker<'p, 'a> {
+    fn new(program: &'p Program<'a>, types: &'p mut Types, arities: Vec<usize>) -> Self {
+        let drop = program
+            .classes
+            .iter()
+            .find(|c| c.name.value == "Drop")
+            .map(|c| c.id)
+            .expect("The prelude declares `Drop`");
+        let destructors = (program.instances.iter())
+            .filter(|i| i.class == drop)
+            .map(|i| (lower(&program.datas, &i.ty.value, &[]), i.id))
+            .collect();
+        Self {
+            datas: &program.datas,
+            types,
+            arities,
+            drop,
+            destructors,
+            glue: vec![],
+            glue_items: vec![],
+            local_count: program.locals,
+            scopes: vec![],
+            functions: vec![],
+            owned: HashMap::new(),
+            moved: HashMap::new(),
+            diverges: false,
+            destructor: None,
+            errors: vec![],
+        }
+    }
+    fn finish(self) -> (Vec<Item<'a>>, usize, Vec<OwnershipErr>) {
+        self.types
+            .glue
+            .extend(self.glue.iter().map(|(ty, _)| ty.clone()));
+        let glue = self
+            .glue_items
+            .into_iter()
+            .map(|item| item.expect("Glue is generated"));
+        (glue.collect(), self.local_count, self.errors)
+    }
+    // The values of garbage collected data types are shared, they can't own their fields
+    fn datas(&mut self) {
+        for data in self.datas.iter().filter(|data| !data.owned) {
+            let vars: Vec<_> = (0..data.params.len())
+                .map(|v| Type::Var(TyVar(v)))
+                .collect();
+            for field in data.ctors.iter().flat_map(|ctor| &ctor.fields) {
+                let ty = lower(self.datas, &field.value, &vars);
+                if ty.is_owned(self.datas) {
+                    self.errors.push(OwnershipErr {
+                        span: field.span.clone(),
+                        kind: OwnershipErrKind::OwnedField {
+                            data: data.name.value.to_string(),
+                            ty,
+                        },
+                    });
+                }
+            }
+        }
+    }
+    // A destructor is given the value it drops, which it doesn't drop again
+    fn item(&mut self, item: &mut Item<'a>) {
+        self.destructor = (self.destructors.iter())
+            .find(|(_, instance)| Some(*instance) == item.instance.map(|(i, _)| i))
+            .map(|(ty, _)| ty.clone());
+        let forget = self.destructor.is_some();
+        for Clause { params, body } in &mut item.clauses {
+            self.function(params, &mut body.value, &body.span, forget);
+        }
+        self.destructor = None;
+    }
+    // Checks a function, whose parameters are dropped at the end of its body unless they're
+    // forgotten
+    fn function(
+        &mut self,
+        params: &mut [Node<Pattern<'a>>],
+        body: &mut Expr<'a>,
+        span: &Span,
+        forget: bool,
+    ) {
+        self.functions.push(self.scopes.len());
+        self.scopes.push(vec![]);
+        let diverges = mem::replace(&mut self.diverges, false);
+        for param in params {
+            self.bind(param, forget);
+        }
+        self.expr(body, span);
+        let drops = self.close();
+        if !self.diverges {
+            self.scope(body, span, drops);
+        }
+        self.diverges = diverges;
+        self.functions.pop();
+    }
+    // Declares the owned locals of a pattern in the innermost scope. Owned values matched by
+    // wildcards are bound to new locals, so that they're dropped with the others
+    fn bind(&mut self, Node { value, span }: &mut Node<Pattern<'a>>, forget: bool) {
+        match value {
+            Pattern::Binding(id, name) => {
+                if self.types.locals[id].is_owned(self.datas) {
+                    self.declare(*id, name, span, forget);
+                }
+            }
+            Pattern::Wildcard if !forget => {
+                let ty = self.types.spans[span].clone();
+                if ty.is_owned(self.datas) {
+                    let id = self.local(ty);
+                    *value = Pattern::Binding(id, "_");
+                    self.declare(id, "_", span, false);
+                }
+            }
+            Pattern::Wildcard | Pattern::Literal(_) => (),
+            Pattern::Ctor(_, _, fields) => {
+                let ty = &self.types.spans[span];
+                let destructor = self.destructors.iter().any(|(t, _)| t == ty);
+                if destructor && self.destructor.as_ref() != Some(ty) {
+                    self.errors.push(OwnershipErr {
+                        span: span.clone(),
+                        kind: OwnershipErrKind::Destructured { ty: ty.clone() },
+                    });
+                }
+                for field in fields {
+                    self.bind(field, false);
+                }
+            }
+            Pattern::Tuple(patterns) => {
+                for pattern in patterns {
+                    self.bind(pattern, false);
+                }
+            }
+        }
+    }
+    fn declare(&mut self, id: LocalId, name: Ident<'a>, span: &Span, forget: bool) {
+        let depth = self.functions.len();
+        let span = span.clone();
+        self.owned.insert(id, Owned { name, depth, span });
+        if !forget {
+            self.scopes
+                .last_mut()
+                .expect("Locals are in a scope")
+                .push(id);
+        }
+    }
+    fn local(&mut self, ty: Type) -> LocalId {
+        let id = LocalId(self.local_count);
+        self.local_count += 1;
+        self.types.locals.insert(id, ty);
+        id
+    }
+    // Ends the innermost scope, returning its locals to drop
+    fn close(&mut self) -> Vec<LocalId> {
+        let scope = self.scopes.pop().expect("Scopes are balanced");
+        let drops = scope
+            .iter()
+            .rev()
+            .filter(|l| !self.moved.contains_key(l))
+            .copied()
+            .collect();
+        for local in &scope {
+            self.moved.remove(local);
+        }
+        drops
+    }
+    // The owned locals of the scopes from `start` that satisfy `f`, in the order they're dropped
+    fn drops(&self, start: usize, f: impl Fn(&LocalId) -> bool) -> Vec<LocalId> {
+        let scopes = self.scopes[start..].iter().rev();
+        scopes
+            .flat_map(|scope| scope.iter().rev())
+            .filter(|l| f(l))
+            .copied()
+            .collect()
+    }
+
+    fn expr(&mut self, value: &mut Expr<'a>, span: &Span) {
+        match value {
+            Expr::Literal(_)
+            | Expr::Error
+            | Expr::Method(..)
+            | Expr::Var(Res::Def(_) | Res::Ctor(..) | Res::Method(..), _) => (),
+            Expr::Var(Res::Local(id), _) => self.use_local(*id, span),
+            // Borrowing a local doesn't move it, the borrow checker makes sure it has its value
+            Expr::Unary(UnOp::Ref | UnOp::RefMut, e) => match &*e.value {
+                Expr::Var(Res::Local(id), _) => self.borrow_local(*id, &e.span),
+                _ => self.expr(&mut e.value, &e.span),
+            },
+            Expr::Unary(_, e) | Expr::OverloadedUnary(_, _, e) => self.expr(&mut e.value, &e.span),
+            Expr::Binary(BinOp::And | BinOp::Or, ..) => self.short_circuit(value, span),
+            Expr::Binary(_, l, r) | Expr::OverloadedBinary(_, _, l, r) => {
+                self.expr(&mut l.value, &l.span);
+                self.expr(&mut r.value, &r.span);
+            }
+            Expr::Call(..) | Expr::EmptyCall(_) | Expr::DictApp(..) => {
+                self.application(value, span);
+                self.partial_application(value, span);
+            }
+            Expr::Lambda(param, body) => {
+                self.function(slice::from_mut(param), &mut body.value, &body.span, false)
+            }
+            Expr::Tuple(elements) => {
+                for e in elements {
+                    self.expr(&mut e.value, &e.span);
+                }
+            }
+            Expr::Block {
+                statements,
+                returns,
+            } => {
+                let drops = self.block(statements, *returns);
+                if !self.diverges {
+                    self.scope(value, span, drops);
+                }
+            }
+            Expr::If { cond, then, else_ } => {
+                self.expr(&mut cond.value, &cond.span);
+                self.branches(then, else_);
+            }
+        }
+    }
+    // Moves an owned local, which must still have its value and be owned by the function
+    // using it
+    fn use_local(&mut self, id: LocalId, span: &Span) {
+        let Owned {
+            name,
+            depth,
+            span: declared,
+        } = match self.owned.get(&id) {
+            Some(owned) => owned.clone(),
+            None => return,
+        };
+        let (name, ty) = (name.to_string(), self.types.locals[&id].clone());
+        let kind = if depth < self.functions.len() {
+            OwnershipErrKind::Captured { name, ty, declared }
+        } else if let Some(moved) = self.moved.get(&id) {
+            let moved = moved.clone();
+            OwnershipErrKind::UseAfterMove { name, ty, moved }
+        } else {
+            self.moved.insert(id, span.clone());
+            return;
+        };
+        self.errors.push(OwnershipErr {
+            span: span.clone(),
+            kind,
+        });
+    }
+    // Borrows an owned local, which must be owned by the function borrowing it
+    fn borrow_local(&mut self, id: LocalId, span: &Span) {
+        match self.owned.get(&id) {
+            Some(Owned {
+                name,
+                depth,
+                span: declared,
+            }) if *depth < self.functions.len() => {
+                let kind = OwnershipErrKind::Captured {
+                    name: name.to_string(),
+                    ty: self.types.locals[&id].clone(),
+                    declared: declared.clone(),
+                };
+                self.errors.push(OwnershipErr {
+                    span: span.clone(),
+                    kind,
+                });
+            }
+            _ => (),
+        }
+    }
+    // Checks the function and arguments of an application. Nested calls are a single
+    // application, only checked once all of its arguments are known
+    fn application(&mut self, value: &mut Expr<'a>, span: &Span) {
+        match value {
+            Expr::Call(f, x) => {
+                self.application(&mut f.value, &f.span);
+                self.expr(&mut x.value, &x.span);
+            }
+            Expr::EmptyCall(f) | Expr::DictApp(f, _) => self.application(&mut f.value, &f.span),
+            _ => self.expr(value, span),
+        }
+    }
+    // An owned argument can't be kept by a partial application, which could be called any
+    // number of times. Items and constructors are partially applied until they have all of
+    // their arguments, the values they return when the application's type says so
+    fn partial_application(&mut self, value: &Expr<'a>, span: &Span) {
+        let (head, args) = match value.spine() {
+            Some(spine) => spine,
+            None => return,
+        };
+        let arity = match &*head.value {
+            Expr::Var(Res::Def(id), _) => self.arities[id.0],
+            Expr::Var(Res::Ctor(data, idx), _) => self.datas[data.0].ctors[*idx].fields.len(),
+            _ => 0,
+        };
+        let partial = matches!(self.types.spans.get(span), Some(Type::Fun(..)));
+        for (idx, arg) in args.iter().enumerate() {
+            let x = match arg {
+                Arg::Expr(x) => x,
+                Arg::Unit | Arg::Dict(_) => continue,
+            };
+            let captured = match idx < arity {
+                true => args.len() < arity,
+                false => partial,
+            };
+            match self.types.spans.get(&x.span) {
+                Some(ty) if captured && ty.is_owned(self.datas) => {
+                    self.errors.push(OwnershipErr {
+                        span: x.span.clone(),
+                        kind: OwnershipErrKind::PartiallyApplied { ty: ty.clone() },
+                    });
+                }
+                _ => (),
+            }
+        }
+    }
+    // Checks the branches of an `if`, which don't see each other's moves. The locals moved by
+    // a single branch are dropped at the end of the other, unless it returns
+    fn branches(&mut self, then: &mut BoxNode<Expr<'a>>, else_: &mut BoxNode<Expr<'a>>) {
+        let before = self.moved.clone();
+        let diverges = self.diverges;
+        self.expr(&mut then.value, &then.span);
+        let then_moved = mem::replace(&mut self.moved, before);
+        let then_diverges = mem::replace(&mut self.diverges, diverges);
+        self.expr(&mut else_.value, &else_.span);
+        match (then_diverges, self.diverges) {
+            (true, _) => (),
+            (false, true) => {
+                self.moved = then_moved;
+                self.diverges = false;
+            }
+            (false, false) => {
+                let start = self.functions.last().copied().unwrap_or(0);
+                let then_drops = self.drops(start, |l| {
+                    self.moved.contains_key(l) && !then_moved.contains_key(l)
+                });
+                let else_drops = self.drops(start, |l| {
+                    then_moved.contains_key(l) && !self.moved.contains_key(l)
+                });
+                self.scope(&mut then.value, &then.span, then_drops);
+                self.scope(&mut else_.value, &else_.span, else_drops);
+                self.moved.extend(then_moved);
+            }
+        }
+    }
+    // The right side of `&&` and `||` is only evaluated for one value of the left one. When
+    // it moves locals, the operator becomes an `if` dropping them on the other path
+    fn short_circuit(&mut self, value: &mut Expr<'a>, span: &Span) {
+        let (l, r) = match value {
+            Expr::Binary(_, l, r) => (l, r),
+            _ => unreachable!("Only `&&` and `||` short-circuit"),
+        };
+        self.expr(&mut l.value, &l.span);
+        let before = self.moved.clone();
+        let diverges = self.diverges;
+        self.expr(&mut r.value, &r.span);
+        if mem::replace(&mut self.diverges, diverges) {
+            self.moved = before;
+            return;
+        }
+        let start = self.functions.last().copied().unwrap_or(0);
+        let drops = self.drops(start, |l| {
+            self.moved.contains_key(l) && !before.contains_key(l)
+        });
+        if drops.is_empty() {
+            return;
+        }
+        let (op, l, r) = match mem::replace(value, Expr::Error) {
+            Expr::Binary(op, l, r) => (op, l, r),
+            _ => unreachable!(),
+        };
+        let mut skipped = Node {
+            value: Box::new(Expr::Literal(Literal::Bool(op == BinOp::Or))),
+            span: span.clone(),
+        };
+        self.scope(&mut skipped.value, span, drops);
+        *value = match op {
+            BinOp::And => Expr::If {
+                cond: l,
+                then: r,
+                else_: skipped,
+            },
+            _ => Expr::If {
+                cond: l,
+                then: skipped,
+                else_: r,
+            },
+        };
+    }
+    // Checks the statements of a block, returning the locals it drops at its end
+    fn block(&mut self, statements: &mut [Node<Statement<'a>>], returns: bool) -> Vec<LocalId> {
+        self.scopes.push(vec![]);
+        let count = statements.len();
+        for (idx, Node { value, .. }) in statements.iter_mut().enumerate() {
+            match value {
+                // A pattern matching the value behind a reference only copies its fields,
+                // the borrow checker rejects the moves
+                Statement::Let(pattern, e) => {
+                    self.expr(&mut e.value, &e.span);
+                    if !is_deref(&e.value) {
+                        self.bind(pattern, false);
+                    }
+                }
+                Statement::Assign(place, e) => {
+                    self.expr(&mut e.value, &e.span);
+                    self.assign(place, e);
+                }
+                Statement::Return(e) => {
+                    self.expr(&mut e.value, &e.span);
+                    let start = self.functions.last().copied().unwrap_or(0);
+                    let drops = self.drops(start, |l| !self.moved.contains_key(l));
+                    self.scope(&mut e.value, &e.span, drops);
+                    self.diverges = true;
+                }
+                // Owned values that aren't the value of the block are dropped right away
+                Statement::Expr(e) => {
+                    self.expr(&mut e.value, &e.span);
+                    let ty = &self.types.spans[&e.span];
+                    if (idx + 1 < count || !returns) && ty.is_owned(self.datas) {
+                        let ty = ty.clone();
+                        let dropped = Node {
+                            value: mem::replace(&mut e.value, Expr::Error),
+                            span: e.span.clone(),
+                        };
+                        e.value = self.drop_call(dropped, &ty).value;
+                    }
+                }
+            }
+        }
+        self.close()
+    }
+
+    // An assigned local gets its value back when it was moved. Otherwise, the old value of an
+    // owned place is dropped once the new one is evaluated
+    fn assign(&mut self, place: &mut Node<Expr<'a>>, e: &mut Node<Expr<'a>>) {
+        match &mut place.value {
+            Expr::Var(Res::Local(id), _) => {
+                if !self.owned.contains_key(id) || self.moved.remove(id).is_some() {
+                    return;
+                }
+            }
+            Expr::Unary(_, r) => self.expr(&mut r.value, &r.span),
+            _ => unreachable!("Only places are assigned"),
+        }
+        let ty = self.types.spans[&place.span].clone();
+        if !ty.is_owned(self.datas) {
+            return;
+        }
+        let result = self.local(ty.clone());
+        let span = &e.span;
+        let value = mem::replace(&mut e.value, Expr::Error);
+        let old = node(place.value.clone(), &place.span);
+        e.value = Expr::Block {
+            statements: vec![
+                node(
+                    Statement::Let(node(Pattern::Binding(result, "_"), span), node(value, span)),
+                    span,
+                ),
+                node(Statement::Expr(self.drop_call(old, &ty)), span),
+                node(
+                    Statement::Expr(node(Expr::Var(Res::Local(result), "_"), span)),
+                    span,
+                ),
+            ],
+            returns: true,
+        };
+    }
+    // Drops locals once `value` is evaluated, keeping its value
+    fn scope(&mut self, value: &mut Expr<'a>, span: &Span, drops: Vec<LocalId>) {
+        if drops.is_empty() {
+            return;
+        }
+        let result = self.local(self.types.spans[span].clone());
+        let body = mem::replace(value, Expr::Error);
+        let mut statements = vec![node(
+            Statement::Let(node(Pattern::Binding(result, "_"), span), node(body, span)),
+            span,
+        )];
+        for local in drops {
+            let ty = self.types.locals[&local].clone();
+            let var = node(Expr::Var(Res::Local(local), self.owned[&local].name), span);
+            statements.push(node(Statement::Expr(self.drop_call(var, &ty)), span));
+        }
+        let result = node(Expr::Var(Res::Local(result), "_"), span);
+        statements.push(node(Statement::Expr(result), span));
+        *value = Expr::Block {
+            statements,
+            returns: true,
+        };
+    }
+    fn drop_call(&mut self, value: Node<Expr<'a>>, ty: &Type) -> Node<Expr<'a>> {
+        let span = value.span.clone();
+        let glue = Node {
+            value: Box::new(self.glue(ty, &span)),
+            span: span.clone(),
+        };
+        Node {
+            value: Expr::Call(glue, value.into_boxed()),
+            span,
+        }
+    }
+    // The function dropping values of an owned type
+    fn glue(&mut self, ty: &Type, span: &Span) -> Expr<'a> {
+        if let Some((_, instance)) = self.destructors.iter().find(|(t, _)| t == ty) {
+            return Expr::Method(Dict::Instance(*instance, vec![]), self.drop, 0);
+        }
+        if let Some((_, id)) = self.glue.iter().find(|(t, _)| t == ty) {
+            return Expr::Var(Res::Def(*id), "drop");
+        }
+        // Reserved before the clauses are made, for the types containing themselves
+        let id = DefId(self.arities.len() + self.glue.len());
+        self.glue.push((ty.clone(), id));
+        self.glue_items.push(None);
+        let clauses = match ty {
+            Type::Tuple(tys) => vec![self.glue_clause(tys, span, Pattern::Tuple)],
+            _ => {
+                let (data, args) = self.data(ty);
+                let ctors = self.types.ctors[data.0].clone();
+                let mut clauses = vec![];
+                for (idx, scheme) in ctors.iter().enumerate() {
+                    let mut fields = vec![];
+                    let mut ctor = subst(&scheme.ty, &scheme.vars, &args);
+                    while let Type::Fun(field, ret) = ctor {
+                        fields.push(*field);
+                        ctor = *ret;
+                    }
+                    let pattern = |fields| Pattern::Ctor(data, idx, fields);
+                    clauses.push(self.glue_clause(&fields, span, pattern));
+                }
+                clauses
+            }
+        };
+        let idx = id.0 - self.arities.len();
+        self.glue_items[idx] = Some(Item {
+            id,
+            name: Node {
+                value: "drop",
+                span: span.clone(),
+            },
+            dicts: vec![],
+            clauses,
+            instance: None,
+            tailrec: false,
+        });
+        Expr::Var(Res::Def(id), "drop")
+    }
+    // A clause of drop glue, taking a value apart with `pattern` to drop its owned fields in
+    // order
+    fn glue_clause(
+        &mut self,
+        tys: &[Type],
+        span: &Span,
+        pattern: impl FnOnce(Vec<Node<Pattern<'a>>>) -> Pattern<'a>,
+    ) -> Clause<'a> {
+        let mut fields = vec![];
+        let mut drops = vec![];
+        for ty in tys {
+            if ty.is_owned(self.datas) {
+                let id = self.local(ty.clone());
+                fields.push(node(Pattern::Binding(id, "_"), span));
+                let var = node(Expr::Var(Res::Local(id), "_"), span);
+                drops.push(node(Statement::Expr(self.drop_call(var, ty)), span));
+            } else {
+                fields.push(node(Pattern::Wildcard, span));
+            }
+        }
+        let body = match drops.is_empty() {
+            true => Expr::Literal(Literal::Unit),
+            false => Expr::Block {
+                statements: drops,
+                returns: false,
+            },
+        };
+        Clause {
+            params: vec![node(pattern(fields), span)],
+            body: node(body, span),
+        }
+    }
+    // The data type of a type, and the types it's applied to
+    fn data(&self, ty: &Type) -> (DataId, Vec<Type>) {
+        let mut args = vec![];
+        let mut head = ty;
+        while let Type::App(f, arg) = head {
+            args.push((**arg).clone());
+            head = f;
+        }
+        args.reverse();
+        let data = match head {
+            Type::Con(name) => self.datas.iter().find(|d| d.name.value == name),
+            _ => None,
+        };
+        (data.expect("Owned types are data types or tuples").id, args)
+    }
+}
+
+fn is_deref(e: &Expr) -> bool {
+    match e.single() {
+        Some(e) => is_deref(&e.value),
+        None => matches!(e, Expr::Unary(UnOp::Deref, _)),
+    }
+}
+
+// The nodes of the elaborated drops have the span of what they drop
+fn node<T: Clone>(value: T, span: &Span) -> Node<T> {
+    Node {
+        value,
+        span: span.clone(),
+    }
+}
+
+// `ty` with the variables `vars` replaced by `args`
+fn subst(ty: &Type, vars: &[TyVar], args: &[Type]) -> Type {
+    match ty {
+        Type::Var(v) => match vars.iter().position(|var| var == v) {
+            Some(idx) => args[idx].clone(),
+            None => ty.clone(),
+        },
+        Type::App(f, arg) => Type::app(subst(f, vars, args), subst(arg, vars, args)),
+        Type::Fun(param, ret) => Type::fun(subst(param, vars, args), subst(ret, vars, args)),
+        Type::Tuple(tys) => Type::Tuple(tys.iter().map(|ty| subst(ty, vars, args)).collect()),
+        Type::Ref(m, ty) => Type::Ref(
+            Box::new(subst(m, vars, args)),
+            Box::new(subst(ty, vars, args)),
+        ),
+        ty => ty.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        check::tests::{check_code, step_errors},
+        errors::ErrorKind,
+    };
+
+    const FILE: &str = "owned data File =\n  File Int\nclose (File n) = n\n";
+
+    // The ownership errors of a program, with the prelude and `FILE`, and its code
+    fn errors(code: &str) -> (String, Vec<OwnershipErr>) {
+        let code = format!("{}{}", FILE, code);
+        let mut checked = check_code(&code, false);
+        (code, step_errors!(checked, Ownership))
+    }
+    // The ownership errors of a program as a code and the code they point at
+    fn check(code: &str) -> Vec<(&'static str, String)> {
+        let (code, errors) = errors(code);
+        errors
+            .into_iter()
+            .map(|e| (e.kind.code(), code[e.span.range].to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn moves() {
+        assert_eq!(
+            check("main =\n  let f = File 1\n  close f + close f\n"),
+            [("E0022", "f".into())]
+        );
+        // Both spans are kept, the move's being secondary
+        match &errors("main =\n  let f = File 1\n  (close f, close f)\n").1[..] {
+            [OwnershipErr {
+                span,
+                kind: OwnershipErrKind::UseAfterMove { moved, .. },
+            }] => assert!(moved.range.end < span.range.start),
+            errors => panic!("expected a use after move, got {:?}", errors),
+        }
+        // Each branch can move what the other one does
+        assert!(check("f b x = if b then close x else close x + 1\n").is_empty());
+        assert!(check("f b x = if b then close x else 0\n").is_empty());
+        assert_eq!(
+            check("f b x =\n  let n = if b then close x else 0\n  close x + n\n"),
+            [("E0022", "x".into())]
+        );
+        // Past a `return`, what it moved is still there
+        assert!(check("f b x =\n  if b then { return close x } else ()\n  close x\n").is_empty());
+        // Owned values used in several elements of a tuple move on the first
+        assert_eq!(check("f x = (close x, close x)\n"), [("E0022", "x".into())]);
+    }
+
+    #[test]
+    fn functions_own_nothing() {
+        assert_eq!(check("f x = \\y -> close x\n"), [("E0023", "x".into())]);
+        assert!(check("f = \\x -> close x\n").is_empty());
+        let pair = "pair (File a) (File b) = a + b\n";
+        assert!(check(&format!("{}main = pair (File 1) (File 2)\n", pair)).is_empty());
+        assert_eq!(
+            check(&format!("{}main = pair (File 1)\n", pair)),
+            [("E0023", "(File 1)".into())]
+        );
+    }
+
+    #[test]
+    fn drops_respect_their_type() {
+        assert_eq!(
+            check("data Files =\n  Files File Int\n"),
+            [("E0024", "File".into())]
+        );
+        assert!(check("owned data Files =\n  Files File Int\n").is_empty());
+        let drop =
+            "owned data Handle =\n  Handle Int\ninstance Drop Handle =\n  drop (Handle n) = ()\n";
+        assert!(check(drop).is_empty());
+        assert_eq!(
+            check(&format!("{}size (Handle n) = n\n", drop)),
+            [("E0025", "(Handle n)".into())]
+        );
+    }
+}
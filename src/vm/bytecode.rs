@@ -0,0 +1,120 @@
+use crate::{
+    hir::{DefId, Ident},
+    source::Span,
+};
+
+use super::value::Value;
+
+/// An instruction of the stack machine. Operands are indices: into the constant pool, the
+/// slots of the current frame, the upvalues of the current closure or the prototypes
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Instr {
+    /// Pushes a constant of the pool
+    Const(u32),
+    /// Pushes a slot of the frame
+    Local(u32),
+    /// Pops a value into a slot of the frame
+    Store(u32),
+    /// Pushes a value captured by the current closure
+    Upvalue(u32),
+    /// Pushes an item, or its value when it takes no argument
+    Item(u32),
+    /// Pushes a constructor of a data type, or its value when it has no field
+    Ctor(u32, u32),
+    /// Pushes the dictionary of an instance. Those of instances with a context are tupled with
+    /// the dictionaries of their context
+    Dict(u32),
+    /// Pops a dictionary and pushes the item implementing a method of its class, by index,
+    /// applied to the dictionaries of its context
+    Method(u32),
+    /// Pops a dictionary and pushes whether it's the one of an instance, by index
+    IsDict(u32),
+    /// Pushes a closure of a prototype, with the captures it lists taken from the frame
+    Closure(u32),
+    /// Pops elements, the last one on top, and pushes a tuple of them
+    Tuple(u32),
+    /// Pops a tuple or a data value and pushes one of its fields
+    Field(u32),
+    /// Pops a data value and pushes whether it was built by a constructor, by index
+    IsCtor(u32),
+    /// Pops a value and pushes a reference to a new cell holding it
+    Cell,
+    /// Pops a reference and pushes the value of its cell
+    Load,
+    /// Pops a reference and the value below it, which replaces the value of its cell
+    Write,
+    Neg,
+    Not,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    Jump(u32),
+    /// Pops a `Bool`, and jumps when it's `false`
+    JumpIfFalse(u32),
+    Pop,
+    /// Pops arguments and the function below them, and applies it to them
+    Call(u32),
+    /// A call whose value is returned, which replaces the frame of the caller
+    TailCall(u32),
+    /// Pops all of the arguments an item takes, and calls it
+    CallItem(u32),
+    TailCallItem(u32),
+    /// Pops the value of the frame and returns it to the caller
+    Return,
+}
+
+/// Where a closure takes one of its captures from, in the function creating it
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Capture {
+    Local(u32),
+    Upvalue(u32),
+    /// A slot holding the cell of a `mut` local, whose value is captured
+    Cell(u32),
+}
+
+/// The code of a function: an item, a lambda, or the expression evaluated by the REPL
+#[derive(Debug, Default)]
+pub struct Proto {
+    /// How many arguments it takes before running, dictionaries included
+    pub arity: u32,
+    /// Its arguments, then its locals and the temporaries of its patterns
+    pub slots: u32,
+    pub code: Vec<Instr>,
+    pub captures: Vec<Capture>,
+    pub spans: SpanTable,
+}
+
+/// The spans of the instructions that can fail, sorted by their offset in the code
+#[derive(Debug, Default)]
+pub struct SpanTable(Vec<(u32, Span)>);
+impl SpanTable {
+    pub fn push(&mut self, offset: usize, span: &Span) {
+        self.0.push((offset as u32, span.clone()));
+    }
+    pub fn get(&self, offset: usize) -> &Span {
+        let idx = self
+            .0
+            .binary_search_by_key(&(offset as u32), |(offset, _)| *offset)
+            .expect("Instructions that can fail have a span");
+        &self.0[idx].1
+    }
+}
+
+/// A program lowered to bytecode
+#[derive(Debug, Default)]
+pub struct Bytecode<'p> {
+    /// The items, indexed like `DefId`s, then the lambdas
+    pub protos: Vec<Proto>,
+    pub constants: Vec<Value>,
+    /// The names and numbers of fields of the constructors of each data type
+    pub ctors: Vec<Vec<(Ident<'p>, u32)>>,
+    /// The items implementing the methods of each instance
+    pub methods: Vec<Vec<DefId>>,
+}
@@ -0,0 +1,518 @@
+//! Lowering of the elaborated HIR to bytecode. Each function gets its own frame of slots:
+//! its arguments first, then its locals. Bindings of patterns share the slot of the value they
+//! match, and lambdas capture the values of the locals of enclosing functions they use. `mut`
+//! locals have a slot of their own holding a reference to their cell, which they're borrowed as.
+use std::collections::HashMap;
+
+use super::bytecode::{Bytecode, Capture, Instr, Proto};
+use super::value::Value;
+use crate::{hir::*, source::Span};
+
+/// Lowers every item of a program, the prototype of an item having the index of its `DefId`
+pub fn compile<'p>(program: &'p Program<'p>) -> Bytecode<'p> {
+    Compiler::new(program).bytecode
+}
+
+/// Lowers a program and an expression using its items, for the REPL. Returns the prototype
+/// evaluating the expression
+pub fn compile_expr<'p>(program: &'p Program<'p>, e: &'p Node<Expr<'p>>) -> (Bytecode<'p>, u32) {
+    let mut compiler = Compiler::new(program);
+    let entry = compiler.reserve();
+    compiler.funs.push(Fun::default());
+    compiler.expr(e, true);
+    compiler.emit(Instr::Return);
+    let proto = compiler.funs.pop().unwrap().proto;
+    compiler.bytecode.protos[entry as usize] = proto;
+    (compiler.bytecode, entry)
+}
+
+// A function being compiled
+#[derive(Default)]
+struct Fun {
+    proto: Proto,
+    locals: HashMap<LocalId, u32>,
+    // The locals of enclosing functions it captures, by index of upvalue
+    upvalues: Vec<LocalId>,
+}
+
+struct Compiler<'p> {
+    program: &'p Program<'p>,
+    bytecode: Bytecode<'p>,
+    // The constants of the pool
+    literals: Vec<Literal>,
+    // The function being compiled is last, after the ones it's nested in
+    funs: Vec<Fun>,
+}
+impl<'p> Compiler<'p> {
+    fn new(program: &'p Program<'p>) -> Self {
+        let mut compiler = Self {
+            program,
+            bytecode: Bytecode {
+                ctors: program
+                    .datas
+                    .iter()
+                    .map(|data| {
+                        let ctors = data.ctors.iter();
+                        ctors
+                            .map(|c| (c.name.value, c.fields.len() as u32))
+                            .collect()
+                    })
+                    .collect(),
+                methods: program
+                    .instances
+                    .iter()
+                    .map(|i| i.methods.clone())
+                    .collect(),
+                ..Default::default()
+            },
+            literals: vec![],
+            funs: vec![],
+        };
+        for _ in &program.items {
+            compiler.reserve();
+        }
+        for item in &program.items {
+            compiler.item(item);
+        }
+        compiler
+    }
+    fn reserve(&mut self) -> u32 {
+        self.bytecode.protos.push(Proto::default());
+        self.bytecode.protos.len() as u32 - 1
+    }
+    fn fun(&mut self) -> &mut Fun {
+        self.funs.last_mut().expect("Code is always in a function")
+    }
+    fn emit(&mut self, instr: Instr) -> usize {
+        let code = &mut self.fun().proto.code;
+        code.push(instr);
+        code.len() - 1
+    }
+    // Emits an instruction that can fail, with the span its errors are reported at
+    fn emit_at(&mut self, instr: Instr, span: &Span) {
+        let offset = self.emit(instr);
+        self.fun().proto.spans.push(offset, span);
+    }
+    // Makes the jump at `offset` go to the next instruction emitted
+    fn patch(&mut self, offset: usize) {
+        let code = &mut self.fun().proto.code;
+        let target = code.len() as u32;
+        match &mut code[offset] {
+            Instr::Jump(to) | Instr::JumpIfFalse(to) => *to = target,
+            instr => unreachable!("Only jumps are patched, not {:?}", instr),
+        }
+    }
+    fn slot(&mut self) -> u32 {
+        let proto = &mut self.fun().proto;
+        proto.slots += 1;
+        proto.slots - 1
+    }
+    fn constant(&mut self, l: &Literal) -> u32 {
+        let idx = match self.literals.iter().position(|other| other == l) {
+            Some(idx) => idx,
+            None => {
+                self.literals.push(*l);
+                self.bytecode.constants.push(match l {
+                    Literal::Num(n) => Value::Int(*n),
+                    Literal::Bool(b) => Value::Bool(*b),
+                    Literal::Unit => Value::Unit,
+                });
+                self.literals.len() - 1
+            }
+        };
+        idx as u32
+    }
+
+    // The clauses are tried in order, the patterns of the last one always match
+    fn item(&mut self, item: &'p Item<'p>) {
+        let mut fun = Fun::default();
+        fun.proto.arity = item.arity() as u32;
+        fun.proto.slots = fun.proto.arity;
+        fun.locals = item
+            .dicts
+            .iter()
+            .zip(0..)
+            .map(|(id, slot)| (*id, slot))
+            .collect();
+        self.funs.push(fun);
+        let first = item.dicts.len() as u32;
+        for (idx, clause) in item.clauses.iter().enumerate() {
+            let mut fails = vec![];
+            let last = idx == item.clauses.len() - 1;
+            for (param, slot) in clause.params.iter().zip(first..) {
+                self.pattern(&param.value, slot, (!last).then_some(&mut fails));
+            }
+            self.expr(&clause.body, true);
+            self.emit(Instr::Return);
+            for offset in fails {
+                self.patch(offset);
+            }
+        }
+        let fun = self.funs.pop().unwrap();
+        self.bytecode.protos[item.id.0] = fun.proto;
+    }
+    // Binds the locals of a pattern matching the value of `slot`. The jumps taken when it
+    // doesn't match are added to `fails`, there are none when it can't fail
+    fn pattern(&mut self, pattern: &'p Pattern<'p>, slot: u32, mut fails: Option<&mut Vec<usize>>) {
+        let fields = match pattern {
+            Pattern::Wildcard => return,
+            Pattern::Binding(id, _) => {
+                let slot = match self.program.mutable.contains(id) {
+                    true => {
+                        let cell = self.slot();
+                        self.emit(Instr::Local(slot));
+                        self.emit(Instr::Cell);
+                        self.emit(Instr::Store(cell));
+                        cell
+                    }
+                    false => slot,
+                };
+                self.fun().locals.insert(*id, slot);
+                return;
+            }
+            Pattern::Literal(l) => {
+                if let Some(fails) = fails {
+                    self.emit(Instr::Local(slot));
+                    let constant = self.constant(l);
+                    self.emit(Instr::Const(constant));
+                    self.emit(Instr::Eq);
+                    fails.push(self.emit(Instr::JumpIfFalse(0)));
+                }
+                return;
+            }
+            Pattern::Ctor(_, idx, fields) => {
+                if let Some(fails) = fails.as_deref_mut() {
+                    self.emit(Instr::Local(slot));
+                    self.emit(Instr::IsCtor(*idx as u32));
+                    fails.push(self.emit(Instr::JumpIfFalse(0)));
+                }
+                fields
+            }
+            Pattern::Tuple(patterns) => patterns,
+        };
+        for (field, idx) in fields.iter().zip(0..) {
+            if field.value == Pattern::Wildcard {
+                continue;
+            }
+            let field_slot = self.slot();
+            self.emit(Instr::Local(slot));
+            self.emit(Instr::Field(idx));
+            self.emit(Instr::Store(field_slot));
+            self.pattern(&field.value, field_slot, fails.as_deref_mut());
+        }
+    }
+    // Pushes the value of a local, capturing it when it belongs to an enclosing function
+    fn local(&mut self, id: LocalId) {
+        match self.access(self.funs.len() - 1, id) {
+            Capture::Local(slot) => {
+                self.emit(Instr::Local(slot));
+                if self.program.mutable.contains(&id) {
+                    self.emit(Instr::Load);
+                }
+            }
+            Capture::Upvalue(idx) => {
+                self.emit(Instr::Upvalue(idx));
+            }
+            Capture::Cell(_) => unreachable!("Locals are accessed through their slot"),
+        }
+    }
+    // Where the function at `level` finds a local
+    fn access(&mut self, level: usize, id: LocalId) -> Capture {
+        let fun = &self.funs[level];
+        if let Some(slot) = fun.locals.get(&id) {
+            return Capture::Local(*slot);
+        }
+        if let Some(idx) = fun.upvalues.iter().position(|other| *other == id) {
+            return Capture::Upvalue(idx as u32);
+        }
+        let capture = match self.access(level - 1, id) {
+            Capture::Local(slot) if self.program.mutable.contains(&id) => Capture::Cell(slot),
+            capture => capture,
+        };
+        let fun = &mut self.funs[level];
+        fun.upvalues.push(id);
+        fun.proto.captures.push(capture);
+        Capture::Upvalue(fun.upvalues.len() as u32 - 1)
+    }
+    fn dict(&mut self, dict: &Dict) {
+        match dict {
+            // Applied to the dictionaries of its context, in a tuple
+            Dict::Instance(id, context) => {
+                self.emit(Instr::Dict(id.0 as u32));
+                if !context.is_empty() {
+                    for dict in context {
+                        self.dict(dict);
+                    }
+                    self.emit(Instr::Tuple(context.len() as u32 + 1));
+                }
+            }
+            Dict::Param(id) => self.local(*id),
+            Dict::Hole(_) => unreachable!("Expressions with constraints aren't compiled"),
+        }
+    }
+    // The function is pushed first, then its arguments from left to right. Calls in tail
+    // position replace the frame of the function they're in
+    fn call(&mut self, f: &'p BoxNode<Expr<'p>>, args: &[Arg<'p, 'p>], span: &Span, tail: bool) {
+        let instr = match &*f.value {
+            // Items given all of their arguments are called without a partial application
+            Expr::Var(Res::Def(id), _) if self.program.items[id.0].arity() == args.len() => {
+                let id = id.0 as u32;
+                match tail {
+                    true => Instr::TailCallItem(id),
+                    false => Instr::CallItem(id),
+                }
+            }
+            _ => {
+                self.boxed(f, false);
+                let args = args.len() as u32;
+                match tail {
+                    true => Instr::TailCall(args),
+                    false => Instr::Call(args),
+                }
+            }
+        };
+        for arg in args {
+            match arg {
+                Arg::Expr(e) => self.boxed(e, false),
+                Arg::Unit => {
+                    let unit = self.constant(&Literal::Unit);
+                    self.emit(Instr::Const(unit));
+                }
+                Arg::Dict(dict) => self.dict(dict),
+            }
+        }
+        self.emit_at(instr, span);
+    }
+
+    // Pushes the value of an expression, `tail` being whether it's the value of the function
+    fn expr(&mut self, Node { value, span }: &'p Node<Expr<'p>>, tail: bool) {
+        self.expr_in(value, span, tail)
+    }
+    fn boxed(&mut self, Node { value, span }: &'p BoxNode<Expr<'p>>, tail: bool) {
+        self.expr_in(value, span, tail)
+    }
+    fn expr_in(&mut self, value: &'p Expr<'p>, span: &Span, tail: bool) {
+        match value {
+            Expr::Literal(l) => {
+                let constant = self.constant(l);
+                self.emit(Instr::Const(constant));
+            }
+            Expr::Var(Res::Local(id), _) => self.local(*id),
+            Expr::Var(Res::Def(id), _) => self.emit_at(Instr::Item(id.0 as u32), span),
+            Expr::Var(Res::Ctor(data, idx), _) => {
+                self.emit(Instr::Ctor(data.0 as u32, *idx as u32));
+            }
+            Expr::Method(dict, _, idx) => {
+                self.dict(dict);
+                self.emit_at(Instr::Method(*idx as u32), span);
+            }
+            Expr::Unary(UnOp::Pos, e) => self.boxed(e, tail),
+            Expr::Unary(UnOp::Ref | UnOp::RefMut, e) => self.reference(&e.value, &e.span),
+            Expr::Unary(UnOp::Deref, e) => {
+                self.boxed(e, false);
+                self.emit(Instr::Load);
+            }
+            Expr::Unary(op, e) => {
+                self.boxed(e, false);
+                match op {
+                    UnOp::Neg => self.emit_at(Instr::Neg, span),
+                    _ => {
+                        self.emit(Instr::Not);
+                    }
+                }
+            }
+            // `l && r` is `if l then r else false`, and `l || r` is `if l then true else r`
+            Expr::Binary(op @ (BinOp::And | BinOp::Or), l, r) => {
+                self.boxed(l, false);
+                let otherwise = self.emit(Instr::JumpIfFalse(0));
+                let (then, else_) = match op {
+                    BinOp::And => (Some(r), None),
+                    _ => (None, Some(r)),
+                };
+                self.short_circuit(then, *op == BinOp::Or, tail);
+                let end = self.emit(Instr::Jump(0));
+                self.patch(otherwise);
+                self.short_circuit(else_, false, tail);
+                self.patch(end);
+            }
+            Expr::Binary(op, l, r) => {
+                self.boxed(l, false);
+                self.boxed(r, false);
+                self.emit_at(binary(*op), span);
+            }
+            Expr::Lambda(param, body) => {
+                let proto = self.reserve();
+                let mut fun = Fun::default();
+                fun.proto.arity = 1;
+                fun.proto.slots = 1;
+                self.funs.push(fun);
+                self.pattern(&param.value, 0, None);
+                self.boxed(body, true);
+                self.emit(Instr::Return);
+                let fun = self.funs.pop().unwrap();
+                self.bytecode.protos[proto as usize] = fun.proto;
+                self.emit(Instr::Closure(proto));
+            }
+            Expr::Call(..) | Expr::EmptyCall(..) | Expr::DictApp(..) => {
+                let (f, args) = value.spine().expect("Calls have a spine");
+                self.call(f, &args, span, tail);
+            }
+            Expr::Tuple(elements) => {
+                for e in elements {
+                    self.expr(e, false);
+                }
+                self.emit(Instr::Tuple(elements.len() as u32));
+            }
+            Expr::Block {
+                statements,
+                returns,
+            } => self.block(statements, *returns, tail),
+            Expr::If { cond, then, else_ } => {
+                self.boxed(cond, false);
+                let otherwise = self.emit(Instr::JumpIfFalse(0));
+                self.boxed(then, tail);
+                let end = self.emit(Instr::Jump(0));
+                self.patch(otherwise);
+                self.boxed(else_, tail);
+                self.patch(end);
+            }
+            Expr::OverloadedUnary(_, dict, e) => {
+                let method = self.program.negation();
+                self.overloaded(dict, method, &[e], Instr::Neg, span, tail);
+            }
+            Expr::OverloadedBinary(op, dict, l, r) => {
+                let method = self.program.operator(*op);
+                self.overloaded(dict, method, &[l, r], binary(*op), span, tail);
+            }
+            Expr::Var(Res::Method(..), _) | Expr::Error => {
+                unreachable!("Only elaborated programs are compiled")
+            }
+        }
+    }
+    // Operators overloaded by a dictionary parameter are `instr` when it's the instance for
+    // `Int`, and call the method of the other instances. Their operands are evaluated first,
+    // into slots both branches read
+    fn overloaded(
+        &mut self,
+        dict: &Dict,
+        (class, idx): (ClassId, usize),
+        operands: &[&'p BoxNode<Expr<'p>>],
+        instr: Instr,
+        span: &Span,
+        tail: bool,
+    ) {
+        let slots: Vec<_> = (operands.iter())
+            .map(|e| {
+                self.boxed(e, false);
+                let slot = self.slot();
+                self.emit(Instr::Store(slot));
+                slot
+            })
+            .collect();
+        let mut end = None;
+        if let Some(int) = self.program.int_instance(class) {
+            self.dict(dict);
+            self.emit(Instr::IsDict(int.0 as u32));
+            let otherwise = self.emit(Instr::JumpIfFalse(0));
+            for slot in &slots {
+                self.emit(Instr::Local(*slot));
+            }
+            self.emit_at(instr, span);
+            end = Some(self.emit(Instr::Jump(0)));
+            self.patch(otherwise);
+        }
+        self.dict(dict);
+        self.emit_at(Instr::Method(idx as u32), span);
+        for slot in &slots {
+            self.emit(Instr::Local(*slot));
+        }
+        let args = slots.len() as u32;
+        match tail {
+            true => self.emit_at(Instr::TailCall(args), span),
+            false => self.emit_at(Instr::Call(args), span),
+        }
+        if let Some(end) = end {
+            self.patch(end);
+        }
+    }
+    // Pushes a reference to a place: the cell of a `mut` local, or a new one holding a value,
+    // which can't change while it's borrowed
+    fn reference(&mut self, value: &'p Expr<'p>, span: &Span) {
+        match value {
+            Expr::Var(Res::Local(id), _) if self.program.mutable.contains(id) => {
+                // Lambdas capture the value of `mut` locals rather than their cell
+                if let Capture::Local(slot) = self.access(self.funs.len() - 1, *id) {
+                    self.emit(Instr::Local(slot));
+                    return;
+                }
+            }
+            Expr::Unary(UnOp::Deref, e) => return self.boxed(e, false),
+            _ => {
+                if let Some(e) = value.single() {
+                    return self.reference(&e.value, &e.span);
+                }
+            }
+        }
+        self.expr_in(value, span, false);
+        self.emit(Instr::Cell);
+    }
+    // A branch of `&&` or `||`: the right side, or the value `value`
+    fn short_circuit(&mut self, e: Option<&'p BoxNode<Expr<'p>>>, value: bool, tail: bool) {
+        match e {
+            Some(e) => self.boxed(e, tail),
+            None => {
+                let constant = self.constant(&Literal::Bool(value));
+                self.emit(Instr::Const(constant));
+            }
+        }
+    }
+    fn block(&mut self, statements: &'p [Node<Statement<'p>>], returns: bool, tail: bool) {
+        for (idx, Node { value, .. }) in statements.iter().enumerate() {
+            let last = idx == statements.len() - 1;
+            match value {
+                Statement::Let(pattern, e) => {
+                    self.expr(e, false);
+                    let slot = self.slot();
+                    self.emit(Instr::Store(slot));
+                    self.pattern(&pattern.value, slot, None);
+                }
+                // The value is pushed before the reference it's written through
+                Statement::Assign(place, e) => {
+                    self.expr(e, false);
+                    self.reference(&place.value, &place.span);
+                    self.emit(Instr::Write);
+                }
+                Statement::Return(e) => {
+                    self.expr(e, true);
+                    self.emit(Instr::Return);
+                }
+                Statement::Expr(e) if last && returns => {
+                    self.expr(e, tail);
+                    return;
+                }
+                Statement::Expr(e) => {
+                    self.expr(e, false);
+                    self.emit(Instr::Pop);
+                }
+            }
+        }
+        let unit = self.constant(&Literal::Unit);
+        self.emit(Instr::Const(unit));
+    }
+}
+
+fn binary(op: BinOp) -> Instr {
+    match op {
+        BinOp::Add => Instr::Add,
+        BinOp::Sub => Instr::Sub,
+        BinOp::Mul => Instr::Mul,
+        BinOp::Div => Instr::Div,
+        BinOp::LT => Instr::Lt,
+        BinOp::LTE => Instr::Le,
+        BinOp::GT => Instr::Gt,
+        BinOp::GTE => Instr::Ge,
+        BinOp::EqEq => Instr::Eq,
+        BinOp::NotEq => Instr::Ne,
+        BinOp::And | BinOp::Or => unreachable!("`&&` and `||` are jumps"),
+    }
+}
@@ -0,0 +1,251 @@
+//! The heap of the VM, where tuples, data values, functions and cells live. Objects are freed by a
+//! precise mark-and-sweep collector: roots are the values on the stack of the VM and the
+//! closures of its frames, everything they don't lead to is garbage.
+use std::{
+    fmt, mem,
+    time::{Duration, Instant},
+};
+
+use super::value::{Data, Fun, Object, Value};
+
+/// How large the heap can grow, in bytes, before it's first collected
+const MIN_THRESHOLD: usize = 1024 * 1024;
+
+/// A reference to an object of the heap
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Gc(u32);
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HeapStats {
+    pub collections: usize,
+    pub allocations: usize,
+    /// The time spent collecting
+    pub paused: Duration,
+    pub longest_pause: Duration,
+    /// The size of the live objects, in bytes
+    pub size: usize,
+    pub peak_size: usize,
+}
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "gc: {} collections, paused for {:?} (at most {:?} at once)",
+            self.collections, self.paused, self.longest_pause
+        )?;
+        write!(
+            f,
+            "gc: {} allocations, heap of {} bytes (at most {} bytes)",
+            self.allocations, self.size, self.peak_size
+        )
+    }
+}
+
+pub struct Heap<'p> {
+    // Freed objects leave their slot empty, for the next allocations
+    objects: Vec<Option<Object<'p>>>,
+    free: Vec<u32>,
+    marked: Vec<bool>,
+    // The size of the heap that triggers the next collection
+    threshold: usize,
+    // Whether every allocation collects first, to find the objects that aren't rooted
+    stress: bool,
+    stats: HeapStats,
+}
+impl<'p> Heap<'p> {
+    pub fn new() -> Self {
+        Self {
+            objects: vec![],
+            free: vec![],
+            marked: vec![],
+            threshold: MIN_THRESHOLD,
+            stress: false,
+            stats: HeapStats::default(),
+        }
+    }
+    /// A heap collecting before every allocation
+    #[cfg(test)]
+    pub fn stressed() -> Self {
+        Self {
+            stress: true,
+            ..Self::new()
+        }
+    }
+    pub fn stats(&self) -> &HeapStats {
+        &self.stats
+    }
+    /// Whether the next allocation of an object must be preceded by a collection
+    pub fn is_full(&self, object: &Object<'p>) -> bool {
+        self.stress || self.stats.size + size(object) > self.threshold
+    }
+    /// Moves an object to the heap. It's never collected right away, whether it has roots or
+    /// not, so the caller must root it before allocating again
+    pub fn alloc(&mut self, object: Object<'p>) -> Gc {
+        self.stats.allocations += 1;
+        self.stats.size += size(&object);
+        self.stats.peak_size = self.stats.peak_size.max(self.stats.size);
+        match self.free.pop() {
+            Some(idx) => {
+                self.objects[idx as usize] = Some(object);
+                Gc(idx)
+            }
+            None => {
+                self.objects.push(Some(object));
+                self.marked.push(false);
+                Gc(self.objects.len() as u32 - 1)
+            }
+        }
+    }
+    /// Frees the objects that the roots don't lead to
+    pub fn collect<'v>(&mut self, roots: impl IntoIterator<Item = &'v Value>) {
+        let start = Instant::now();
+        // Marks with a worklist rather than recursion, for long lists
+        let mut pending: Vec<Gc> = roots.into_iter().filter_map(reference).collect();
+        while let Some(Gc(idx)) = pending.pop() {
+            let marked = mem::replace(&mut self.marked[idx as usize], true);
+            if !marked {
+                pending.extend(self.get(Gc(idx)).children().filter_map(reference));
+            }
+        }
+        for (idx, slot) in self.objects.iter_mut().enumerate() {
+            let marked = mem::replace(&mut self.marked[idx], false);
+            if let (false, Some(object)) = (marked, &slot) {
+                self.stats.size -= size(object);
+                *slot = None;
+                self.free.push(idx as u32);
+            }
+        }
+        self.threshold = MIN_THRESHOLD.max(self.stats.size * 2);
+        let pause = start.elapsed();
+        self.stats.collections += 1;
+        self.stats.paused += pause;
+        self.stats.longest_pause = self.stats.longest_pause.max(pause);
+    }
+
+    pub fn get(&self, Gc(idx): Gc) -> &Object<'p> {
+        self.objects[idx as usize]
+            .as_ref()
+            .expect("Objects that are referred to aren't collected")
+    }
+    pub fn tuple(&self, gc: Gc) -> &[Value] {
+        match self.get(gc) {
+            Object::Tuple(elements) => elements,
+            object => unreachable!("Expected a tuple, got {:?}", object),
+        }
+    }
+    pub fn data(&self, gc: Gc) -> &Data<'p> {
+        match self.get(gc) {
+            Object::Data(data) => data,
+            object => unreachable!("Expected a data value, got {:?}", object),
+        }
+    }
+    pub fn fun(&self, gc: Gc) -> &Fun {
+        match self.get(gc) {
+            Object::Fun(fun) => fun,
+            object => unreachable!("Expected a function, got {:?}", object),
+        }
+    }
+    pub fn cell(&self, gc: Gc) -> Value {
+        match self.get(gc) {
+            Object::Cell(value) => *value,
+            object => unreachable!("Expected a cell, got {:?}", object),
+        }
+    }
+    pub fn write(&mut self, Gc(idx): Gc, value: Value) {
+        match &mut self.objects[idx as usize] {
+            Some(Object::Cell(cell)) => *cell = value,
+            object => unreachable!("Expected a cell, got {:?}", object),
+        }
+    }
+    /// Displays a value like the interpreter does
+    pub fn show(&self, value: Value) -> Shown<'_, 'p> {
+        Shown { heap: self, value }
+    }
+}
+
+fn reference(value: &Value) -> Option<Gc> {
+    match value {
+        Value::Tuple(gc) | Value::Data(gc) | Value::Fun(gc) | Value::Ref(gc) => Some(*gc),
+        _ => None,
+    }
+}
+
+// What an object takes on the heap
+fn size(object: &Object) -> usize {
+    let values = match object {
+        Object::Tuple(elements) => elements.capacity(),
+        Object::Data(data) => data.fields.capacity(),
+        Object::Fun(fun) => fun.captures.capacity() + fun.args.capacity(),
+        Object::Cell(_) => 1,
+    };
+    mem::size_of::<Object>() + values * mem::size_of::<Value>()
+}
+
+pub struct Shown<'h, 'p> {
+    heap: &'h Heap<'p>,
+    value: Value,
+}
+impl<'h, 'p> fmt::Display for Shown<'h, 'p> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.value {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Unit => write!(f, "()"),
+            Value::Tuple(gc) => {
+                write!(f, "(")?;
+                for (idx, e) in self.heap.tuple(gc).iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", self.heap.show(*e))?;
+                }
+                write!(f, ")")
+            }
+            Value::Data(gc) => {
+                let data = self.heap.data(gc);
+                write!(f, "{}", data.name)?;
+                for field in &data.fields {
+                    let field = *field;
+                    match field {
+                        Value::Int(n) if n < 0 => write!(f, " ({})", self.heap.show(field))?,
+                        Value::Data(gc) if !self.heap.data(gc).fields.is_empty() => {
+                            write!(f, " ({})", self.heap.show(field))?
+                        }
+                        _ => write!(f, " {}", self.heap.show(field))?,
+                    }
+                }
+                Ok(())
+            }
+            Value::Fun(_) => write!(f, "<function>"),
+            Value::Ref(gc) => write!(f, "{}", self.heap.show(self.heap.cell(gc))),
+            Value::Dict(_) => write!(f, "<dictionary>"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unreachable_objects_are_freed() {
+        let mut heap = Heap::new();
+        let inner = heap.alloc(Object::Tuple(vec![Value::Int(1), Value::Int(-2)]));
+        let outer = heap.alloc(Object::Tuple(vec![Value::Tuple(inner), Value::Unit]));
+        let garbage = heap.alloc(Object::Tuple(vec![Value::Tuple(outer)]));
+        let size = heap.stats().size;
+        heap.collect([&Value::Tuple(outer)]);
+        assert_eq!(heap.show(Value::Tuple(outer)).to_string(), "((1, -2), ())");
+        assert_eq!(heap.stats().size, size - size_of_tuple(1));
+        assert_eq!(heap.stats().collections, 1);
+        // The slot of the garbage is reused
+        assert_eq!(heap.alloc(Object::Tuple(vec![])), garbage);
+        heap.collect([]);
+        assert_eq!(heap.stats().size, 0);
+        assert_eq!(heap.stats().peak_size, size);
+    }
+
+    fn size_of_tuple(len: usize) -> usize {
+        size(&Object::Tuple(Vec::with_capacity(len)))
+    }
+}
@@ -0,0 +1,793 @@
+//! A stack machine running programs lowered to bytecode, which agrees with the tree-walking
+//! interpreter. Frames live on the heap, so recursion isn't limited by the native stack, and
+//! calls in tail position reuse the frame of their caller. Values that don't fit on the stack
+//! are objects of a garbage collected heap.
+mod bytecode;
+mod compile;
+mod heap;
+mod value;
+
+pub use bytecode::Bytecode;
+use bytecode::{Capture, Instr};
+pub use compile::{compile, compile_expr};
+pub use heap::{Heap, HeapStats};
+pub use value::Value;
+use value::{Callee, Data, Fun, Object};
+
+use crate::{
+    errors::runtime_err::{RuntimeErr, RuntimeErrKind, MAX_DEPTH},
+    hir::DefId,
+    source::Span,
+};
+use heap::{Gc, Shown};
+
+struct Frame {
+    proto: u32,
+    ip: usize,
+    // Where its slots start on the stack
+    base: usize,
+    // The function whose captures are the upvalues of the frame, for closures
+    closure: Option<Gc>,
+    // How many arguments below `base` its value is applied to, when it was given more than it
+    // takes
+    pending: usize,
+}
+
+pub struct Vm<'b, 'p> {
+    bytecode: &'b Bytecode<'p>,
+    heap: Heap<'p>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+}
+impl<'b, 'p> Vm<'b, 'p> {
+    pub fn new(bytecode: &'b Bytecode<'p>) -> Self {
+        Self::with_heap(bytecode, Heap::new())
+    }
+    pub fn with_heap(bytecode: &'b Bytecode<'p>, heap: Heap<'p>) -> Self {
+        Self {
+            bytecode,
+            heap,
+            stack: vec![],
+            frames: vec![],
+        }
+    }
+    /// The value of an item, which is only evaluated when it takes no argument
+    pub fn item(&mut self, id: DefId) -> Result<Value, RuntimeErr> {
+        match self.bytecode.protos[id.0].arity {
+            0 => self.run(id.0 as u32),
+            _ => Ok(self.function(Callee::Proto(id.0 as u32))),
+        }
+    }
+    /// Runs a prototype that takes no argument, like the one of `compile_expr`
+    pub fn run(&mut self, proto: u32) -> Result<Value, RuntimeErr> {
+        self.stack.clear();
+        self.frames.clear();
+        self.enter(proto, None, vec![], false)
+            .expect("The first frame can't overflow");
+        self.execute()
+    }
+    /// Displays a value the VM gave, until it runs again
+    pub fn show(&self, value: Value) -> Shown<'_, 'p> {
+        self.heap.show(value)
+    }
+    pub fn heap_stats(&self) -> &HeapStats {
+        self.heap.stats()
+    }
+
+    // Moves an object to the heap, collecting it first when it's full. Every other value that
+    // is still used must be on the stack
+    fn alloc(&mut self, object: Object<'p>) -> Gc {
+        if self.heap.is_full(&object) {
+            let closures = self
+                .frames
+                .iter()
+                .filter_map(|frame| frame.closure.map(Value::Fun));
+            let closures: Vec<_> = closures.collect();
+            self.heap
+                .collect(self.stack.iter().chain(&closures).chain(object.children()));
+        }
+        self.heap.alloc(object)
+    }
+    fn function(&mut self, callee: Callee) -> Value {
+        Value::Fun(self.alloc(Object::Fun(Fun {
+            callee,
+            captures: vec![],
+            args: vec![],
+        })))
+    }
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("Code runs in a frame")
+    }
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("The stack underflowed")
+    }
+    fn int(&mut self) -> i64 {
+        match self.pop() {
+            Value::Int(n) => n,
+            value => unreachable!("Expected an `Int`, got {:?}", value),
+        }
+    }
+    fn reference(&mut self) -> Gc {
+        match self.pop() {
+            Value::Ref(gc) => gc,
+            value => unreachable!("Expected a reference, got {:?}", value),
+        }
+    }
+    // The span of the instruction that was just executed
+    fn error(&self, kind: RuntimeErrKind) -> RuntimeErr {
+        let frame = self.frame();
+        let span = self.bytecode.protos[frame.proto as usize]
+            .spans
+            .get(frame.ip - 1);
+        RuntimeErr {
+            span: span.clone(),
+            kind,
+        }
+    }
+    fn span(&self) -> Span {
+        self.error(RuntimeErrKind::StackOverflow).span
+    }
+    fn upvalue(&self, closure: Option<Gc>, idx: u32) -> Value {
+        let closure = closure.expect("Only closures have upvalues");
+        self.heap.fun(closure).captures[idx as usize]
+    }
+
+    fn execute(&mut self) -> Result<Value, RuntimeErr> {
+        loop {
+            let frame = self.frames.last_mut().expect("Code runs in a frame");
+            let instr = self.bytecode.protos[frame.proto as usize].code[frame.ip];
+            frame.ip += 1;
+            let base = frame.base;
+            match instr {
+                Instr::Const(idx) => self.stack.push(self.bytecode.constants[idx as usize]),
+                Instr::Local(slot) => self.stack.push(self.stack[base + slot as usize]),
+                Instr::Store(slot) => {
+                    let value = self.pop();
+                    self.stack[base + slot as usize] = value;
+                }
+                Instr::Upvalue(idx) => self.stack.push(self.upvalue(self.frame().closure, idx)),
+                Instr::Item(id) => self.item_value(id, vec![])?,
+                Instr::Ctor(data, idx) => {
+                    let value = match self.bytecode.ctors[data as usize][idx as usize] {
+                        (name, 0) => Value::Data(self.alloc(Object::Data(Data {
+                            ctor: idx,
+                            name,
+                            fields: vec![],
+                        }))),
+                        _ => self.function(Callee::Ctor(data, idx)),
+                    };
+                    self.stack.push(value);
+                }
+                Instr::Dict(id) => self.stack.push(Value::Dict(id)),
+                Instr::Method(idx) => {
+                    let (instance, context) = match self.pop() {
+                        Value::Dict(instance) => (instance, vec![]),
+                        Value::Tuple(gc) => match self.heap.tuple(gc) {
+                            [Value::Dict(instance), context @ ..] => (*instance, context.to_vec()),
+                            _ => unreachable!("Expected a dictionary"),
+                        },
+                        value => unreachable!("Expected a dictionary, got {:?}", value),
+                    };
+                    let id = self.bytecode.methods[instance as usize][idx as usize];
+                    self.item_value(id.0 as u32, context)?;
+                }
+                Instr::IsDict(id) => {
+                    let is = match self.pop() {
+                        Value::Dict(instance) => instance == id,
+                        Value::Tuple(_) => false,
+                        value => unreachable!("Expected a dictionary, got {:?}", value),
+                    };
+                    self.stack.push(Value::Bool(is));
+                }
+                Instr::Closure(proto) => {
+                    let frame = self.frame();
+                    let captures = &self.bytecode.protos[proto as usize].captures;
+                    let captures = captures
+                        .iter()
+                        .map(|capture| match capture {
+                            Capture::Local(slot) => self.stack[frame.base + *slot as usize],
+                            Capture::Upvalue(idx) => self.upvalue(frame.closure, *idx),
+                            Capture::Cell(slot) => match self.stack[frame.base + *slot as usize] {
+                                Value::Ref(gc) => self.heap.cell(gc),
+                                value => unreachable!("Expected a reference, got {:?}", value),
+                            },
+                        })
+                        .collect();
+                    let fun = self.alloc(Object::Fun(Fun {
+                        callee: Callee::Proto(proto),
+                        captures,
+                        args: vec![],
+                    }));
+                    self.stack.push(Value::Fun(fun));
+                }
+                Instr::Tuple(len) => {
+                    let elements = self.stack.split_off(self.stack.len() - len as usize);
+                    let tuple = self.alloc(Object::Tuple(elements));
+                    self.stack.push(Value::Tuple(tuple));
+                }
+                Instr::Field(idx) => {
+                    let field = match self.pop() {
+                        Value::Tuple(gc) => self.heap.tuple(gc)[idx as usize],
+                        Value::Data(gc) => self.heap.data(gc).fields[idx as usize],
+                        value => unreachable!("Expected fields, got {:?}", value),
+                    };
+                    self.stack.push(field);
+                }
+                Instr::IsCtor(ctor) => {
+                    let is = match self.pop() {
+                        Value::Data(gc) => self.heap.data(gc).ctor == ctor,
+                        value => unreachable!("Expected a data value, got {:?}", value),
+                    };
+                    self.stack.push(Value::Bool(is));
+                }
+                Instr::Cell => {
+                    let value = self.pop();
+                    let cell = self.alloc(Object::Cell(value));
+                    self.stack.push(Value::Ref(cell));
+                }
+                Instr::Load => {
+                    let gc = self.reference();
+                    self.stack.push(self.heap.cell(gc));
+                }
+                Instr::Write => {
+                    let gc = self.reference();
+                    let value = self.pop();
+                    self.heap.write(gc, value);
+                }
+                Instr::Neg => {
+                    let n = self.int();
+                    match n.checked_neg() {
+                        Some(n) => self.stack.push(Value::Int(n)),
+                        None => {
+                            return Err(self.error(RuntimeErrKind::Overflow(format!("-({})", n))))
+                        }
+                    }
+                }
+                Instr::Not => match self.pop() {
+                    Value::Bool(b) => self.stack.push(Value::Bool(!b)),
+                    value => unreachable!("Expected a `Bool`, got {:?}", value),
+                },
+                Instr::Add | Instr::Sub | Instr::Mul | Instr::Div => {
+                    let b = self.int();
+                    let a = self.int();
+                    let (result, op) = match instr {
+                        Instr::Add => (a.checked_add(b), "+"),
+                        Instr::Sub => (a.checked_sub(b), "-"),
+                        Instr::Mul => (a.checked_mul(b), "*"),
+                        _ if b == 0 => return Err(self.error(RuntimeErrKind::DivisionByZero)),
+                        _ => (a.checked_div(b), "/"),
+                    };
+                    match result {
+                        Some(n) => self.stack.push(Value::Int(n)),
+                        None => {
+                            let operation = format!("{} {} {}", a, op, b);
+                            return Err(self.error(RuntimeErrKind::Overflow(operation)));
+                        }
+                    }
+                }
+                Instr::Lt | Instr::Le | Instr::Gt | Instr::Ge => {
+                    let b = self.int();
+                    let a = self.int();
+                    let result = match instr {
+                        Instr::Lt => a < b,
+                        Instr::Le => a <= b,
+                        Instr::Gt => a > b,
+                        _ => a >= b,
+                    };
+                    self.stack.push(Value::Bool(result));
+                }
+                Instr::Eq | Instr::Ne => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    let equal = match (a, b) {
+                        (Value::Int(a), Value::Int(b)) => a == b,
+                        (Value::Bool(a), Value::Bool(b)) => a == b,
+                        (Value::Unit, Value::Unit) => true,
+                        (a, b) => unreachable!("Can't compare {:?} and {:?}", a, b),
+                    };
+                    self.stack.push(Value::Bool(equal == (instr == Instr::Eq)));
+                }
+                Instr::Jump(to) => self.frames.last_mut().unwrap().ip = to as usize,
+                Instr::JumpIfFalse(to) => {
+                    if let Value::Bool(false) = self.pop() {
+                        self.frames.last_mut().unwrap().ip = to as usize;
+                    }
+                }
+                Instr::Pop => {
+                    self.pop();
+                }
+                Instr::Call(args) | Instr::TailCall(args) => {
+                    let args = self.stack.split_off(self.stack.len() - args as usize);
+                    let f = self.pop();
+                    let tail = matches!(instr, Instr::TailCall(_));
+                    // Functions that aren't entered give their value right away
+                    if !self.apply(f, args, tail)? && tail {
+                        if let Some(value) = self.leave()? {
+                            return Ok(value);
+                        }
+                    }
+                }
+                Instr::CallItem(id) | Instr::TailCallItem(id) => {
+                    let arity = self.bytecode.protos[id as usize].arity as usize;
+                    let args = self.stack.split_off(self.stack.len() - arity);
+                    self.enter(id, None, args, matches!(instr, Instr::TailCallItem(_)))?;
+                }
+                Instr::Return => {
+                    if let Some(value) = self.leave()? {
+                        return Ok(value);
+                    }
+                }
+            }
+        }
+    }
+    // Pushes an item applied to some of its arguments, or calls it when they're all of them
+    fn item_value(&mut self, id: u32, args: Vec<Value>) -> Result<(), RuntimeErr> {
+        if self.bytecode.protos[id as usize].arity as usize == args.len() {
+            return self.enter(id, None, args, false);
+        }
+        let fun = self.alloc(Object::Fun(Fun {
+            callee: Callee::Proto(id),
+            captures: vec![],
+            args,
+        }));
+        self.stack.push(Value::Fun(fun));
+        Ok(())
+    }
+    // Applies a function to arguments. A function given fewer arguments than it takes is
+    // partially applied to them, and the value of a call given more is applied to the rest once
+    // it returns. Returns whether a frame was entered, the value being pushed otherwise
+    fn apply(&mut self, f: Value, args: Vec<Value>, tail: bool) -> Result<bool, RuntimeErr> {
+        let gc = match f {
+            Value::Fun(gc) => gc,
+            value => unreachable!("Only functions are applied, not {:?}", value),
+        };
+        let fun = self.heap.fun(gc);
+        let callee = fun.callee;
+        let mut taken = [&fun.args[..], &args].concat();
+        let value = match callee {
+            Callee::Proto(proto) => {
+                let arity = self.bytecode.protos[proto as usize].arity as usize;
+                if taken.len() < arity {
+                    let captures = fun.captures.clone();
+                    let fun = self.alloc(Object::Fun(Fun {
+                        callee,
+                        captures,
+                        args: taken,
+                    }));
+                    self.stack.push(Value::Fun(fun));
+                    return Ok(false);
+                }
+                let closure = (!fun.captures.is_empty()).then_some(gc);
+                let rest = taken.split_off(arity);
+                if rest.is_empty() {
+                    self.enter(proto, closure, taken, tail)?;
+                } else {
+                    // The arguments left wait below the frame, which can't replace its caller
+                    let pending = rest.len();
+                    self.stack.extend(rest);
+                    self.enter(proto, closure, taken, false)?;
+                    self.frames.last_mut().unwrap().pending = pending;
+                }
+                return Ok(true);
+            }
+            // Data values aren't functions, so constructors are never given too many arguments
+            Callee::Ctor(data, ctor) => match self.bytecode.ctors[data as usize][ctor as usize] {
+                (name, arity) if arity as usize == taken.len() => {
+                    Value::Data(self.alloc(Object::Data(Data {
+                        ctor,
+                        name,
+                        fields: taken,
+                    })))
+                }
+                _ => Value::Fun(self.alloc(Object::Fun(Fun {
+                    callee,
+                    captures: vec![],
+                    args: taken,
+                }))),
+            },
+        };
+        self.stack.push(value);
+        Ok(false)
+    }
+    // Pushes the frame of a call, replacing the current one for tail calls, which then gives
+    // its value to what the current one would have given it to
+    fn enter(
+        &mut self,
+        proto: u32,
+        closure: Option<Gc>,
+        args: Vec<Value>,
+        tail: bool,
+    ) -> Result<(), RuntimeErr> {
+        let mut pending = 0;
+        if tail {
+            let frame = self.frames.pop().expect("Tail calls are made by a frame");
+            self.stack.truncate(frame.base);
+            pending = frame.pending;
+        } else if self.frames.len() == MAX_DEPTH {
+            return Err(RuntimeErr {
+                span: self.span(),
+                kind: RuntimeErrKind::StackOverflow,
+            });
+        }
+        let base = self.stack.len();
+        self.stack.extend(args);
+        let slots = self.bytecode.protos[proto as usize].slots as usize;
+        self.stack.resize(base + slots, Value::Unit);
+        self.frames.push(Frame {
+            proto,
+            ip: 0,
+            base,
+            closure,
+            pending,
+        });
+        Ok(())
+    }
+    // Pops the current frame and gives its value to the caller, or applies it to the arguments
+    // it was given too many. Returns it when there is no caller
+    fn leave(&mut self) -> Result<Option<Value>, RuntimeErr> {
+        let value = self.pop();
+        let frame = self.frames.pop().expect("Returns are made by a frame");
+        self.stack.truncate(frame.base);
+        if frame.pending > 0 {
+            let args = self.stack.split_off(self.stack.len() - frame.pending);
+            self.apply(value, args, false)?;
+            return Ok(None);
+        }
+        if self.frames.is_empty() {
+            return Ok(Some(value));
+        }
+        self.stack.push(value);
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        check::tests::program,
+        cli::{Backend, Target},
+        hir::{fold::fold, resolve::resolve_expr},
+        mir::check_expr_borrows,
+        prelude::prelude,
+        source::SourceDb,
+        syntax::{parse_expr, parse_program},
+        typeck::{check_expr_ownership, typecheck_expr},
+    };
+
+    // The value of `main` on every backend, which must agree, folded or not
+    fn run(code: &str) -> Result<String, RuntimeErr> {
+        let (program, types) = program(code, false);
+        let main = program
+            .items
+            .iter()
+            .find(|item| item.name.value == "main")
+            .unwrap()
+            .id;
+        let tree = Backend::Tree.eval(&program, &types, Target::Item(main));
+        let vm = Backend::Vm.eval(&program, &types, Target::Item(main));
+        let mir = Backend::Mir.eval(&program, &types, Target::Item(main));
+        assert_eq!(tree, vm, "{}", code);
+        assert_eq!(mir, vm, "{}", code);
+        // Folding keeps the value, and the span of the error
+        let mut folded = program.clone();
+        fold(&mut folded);
+        assert_eq!(
+            Backend::Vm.eval(&folded, &types, Target::Item(main)),
+            vm,
+            "{}",
+            code
+        );
+        vm
+    }
+
+    // Programs covering the language, with their value or the error they stop with, which the
+    // native backend runs too
+    pub(crate) const SUITE: &[(&str, &str)] = &[
+        ("main = 1 + 2 * 3 - 8 / 3", "5"),
+        ("main = (-7 / 2, -(3), +4)", "(-3, -3, 4)"),
+        ("main = 1 < 2 && !(2 <= 1) || false", "true"),
+        ("main = (1 == 1, true != false, () == (), 3 >= 4)", "(true, true, true, false)"),
+        ("main = false && 1 / 0 == 0 || true", "true"),
+        ("fact n = if n == 0 then 1 else n * fact (n - 1)\nmain = fact 20", "2432902008176640000"),
+        ("add3 a b c = a + b + c\nmain =\n  let f = add3 1\n  let g = f 2\n  g 3 + f 0 0", "7"),
+        ("compose f g x = f (g x)\nmain = compose (\\x -> x * 2) (\\x -> x - 4) 10", "12"),
+        ("adder n = \\x -> x + n\nmain =\n  let add2 = adder 2\n  let n = 10\n  add2 n", "12"),
+        // Lambdas capture the locals of every function they're nested in
+        ("f a = \\b -> \\c -> \\d -> (a, b, c, d)\nmain = f 1 2 3 4", "(1, 2, 3, 4)"),
+        (
+            "f x =\n  let y = x * 2\n  let g = \\z -> \\w -> x + y + z + w\n  g 10 100\nmain = f 1",
+            "113",
+        ),
+        ("unit () = 1\nf x = x\nmain = (unit(), f())", "(1, ())"),
+        ("main = (\\x y -> y) 1", "<function>"),
+        (
+            "add3 a b c = a + b + c\nmain =\n  let f = add3 1\n  let g = f 2\n  (g 3, g 4, f 5 6)",
+            "(6, 7, 12)",
+        ),
+        // Calls given too many arguments, in and out of tail position
+        ("id x = x\nplus a b = a + b\nmain = id plus 1 2", "3"),
+        ("k a = \\b -> \\c -> a + b + c\nmain = k 1 2 3", "6"),
+        (
+            "pick b = if b then (\\x -> x + 1) else (\\x -> x - 1)\nf b n = pick b n\nmain = (f true 1, f false 1)",
+            "(2, 0)",
+        ),
+        ("f n = if n == 0 then \\x -> x * 2 else f (n - 1)\nmain = f 3 5 + 1", "11"),
+        ("f () = 1\ng x () = x\nk () = \\x -> x\nmain = (f (), g 2 (), k () 3, f)", "(1, 2, 3, <function>)"),
+        ("f x = \\y -> 1 + y / x\nmain = f 0 1", "error: y / x"),
+        ("k a = \\b -> a / 0\nmain = k 1 (2 / 0)", "error: (2 / 0)"),
+        ("main =\n  let (a, (b, _)) = (1, (2, 3))\n  a + b", "3"),
+        ("f x =\n  if x > 0 then { return 1 } else ()\n  2\nmain = (f 1, f 0)", "(1, 2)"),
+        ("main =\n  let f = \\x -> { return x; 2 }\n  f 1 + 10", "11"),
+        ("main = { 1; 2 }", "2"),
+        ("main = { 1; let x = 2 }", "()"),
+        (
+            "data Option: T =\n  None\n  Some T\nunwrap d None = d\nunwrap _ (Some a) = a\nmain = (unwrap 0 (Some 3), unwrap 0 None, Some (Some (-1)))",
+            "(3, 0, Some (Some (-1)))",
+        ),
+        (
+            "data List: A =\n  Nil\n  Cons A (List A)\nlen Nil = 0\nlen (Cons _ xs) = 1 + len xs\nzip (Cons a as) (Cons b bs) = Cons (a, b) (zip as bs)\nzip _ _ = Nil\nmain = (len (zip (Cons 1 (Cons 2 Nil)) (Cons true Nil)), zip (Cons 1 Nil) (Cons 2 Nil))",
+            "(1, Cons (1, 2) Nil)",
+        ),
+        ("f 0 (true, _) = 1\nf 0 _ = 2\nf n (_, k) = n * k\nmain = (f 0 (true, 1), f 0 (false, 1), f 3 (false, -1))", "(1, 2, -3)"),
+        (
+            "data Pair: A B =\n  Pair A B\nswap (Pair a b) = Pair b a\nmain = swap (Pair (Pair 1 2) true)",
+            "Pair true (Pair 1 2)",
+        ),
+        // Constructors are curried too
+        ("data T =\n  T Int Bool Int\nmain =\n  let f = T 1\n  (f true 2, f false 3)", "(T 1 true 2, T 1 false 3)"),
+        (
+            "class Size a =\n  size : a -> Int\ninstance Size Int =\n  size n = n\ninstance Size Bool =\n  size _ = 1\ntotal x y = size x + size y + 0 * x\nmain = (total 2 true, total 3 false)",
+            "(3, 4)",
+        ),
+        (
+            "data Option: T =\n  None\n  Some T\ninstance Eq (Option Int) =\n  eq a b = sameness a b\n  ne a b = !(sameness a b)\nsameness None None = true\nsameness (Some a) (Some b) = a == b\nsameness _ _ = false\nsame x y = x == y\nmain = (same (Some 1) (Some 1), Some 1 != None, same 1 2)",
+            "(true, true, false)",
+        ),
+        // Instances with a context, given the dictionaries of the variables of their type
+        ("main = ((1, 2) == (1, 2), (1, (true, ())) != (1, (false, ())), (1, 2, 3) == (1, 2, 4))", "(true, true, false)"),
+        (
+            "data Option: T =\n  None\n  Some T\ninstance Eq a => Eq (Option a) =\n  eq None None = true\n  eq (Some a) (Some b) = a == b\n  eq _ _ = false\n  ne a b = !(eq a b)\nclass Default a =\n  default : a\ninstance Default Int =\n  default = 7\ninstance Default a => Default (Option a) =\n  default = Some default\nsame x y = x == y\nmain = (same (Some (1, true)) (Some (1, true)), Some 1 != None, default == Some (Some 7), same (Some default) (Some 7))",
+            "(true, true, true, true)",
+        ),
+        // Overloaded items used at several types, partially applied to their dictionaries
+        ("double x = x + x\nquad = \\x -> double (double x)\nmain = (quad 3, double 2)", "(12, 4)"),
+        ("max x y = if x > y then x else y\nmain =\n  let m = max 3\n  (m 1, m 5)", "(3, 5)"),
+        // Mutual recursion, through items of the same group
+        (
+            "even n = if n == 0 then true else odd (n - 1)\nodd n = if n == 0 then false else even (n - 1)\nmain = (even 10, odd 7, even 3)",
+            "(true, true, false)",
+        ),
+        ("f x = 10 / x\nmain = f 0", "error: 10 / x"),
+        ("main = 9223372036854775807 + 1", "error: 9223372036854775807 + 1"),
+        ("main = -(-9223372036854775807 - 1)", "error: -(-9223372036854775807 - 1)"),
+        ("main = (-9223372036854775807 - 1) / -1", "error: (-9223372036854775807 - 1) / -1"),
+        ("main = (\\x -> x * x * x) 3000000", "error: x * x * x"),
+        // Also in overloaded items given the instance for `Int`, rather than in the prelude
+        ("f x = x * x\nmain = f 4000000000", "error: x * x"),
+        ("f x = -x\nmain = f (-9223372036854775807 - 1)", "error: -x"),
+        // Owned locals are dropped at the end of their scope, the last declared first, unless
+        // they were moved. Destructors dividing by zero show which one ran first
+        (
+            "owned data File =\n  File Int\ninstance Drop File =\n  drop (File n) = { 1 / (n - 2); () }\nmain =\n  let a = File 1\n  let b = File 2\n  3",
+            "error: 1 / (n - 2)",
+        ),
+        (
+            "owned data File =\n  File Int\ninstance Drop File =\n  drop (File n) = { 1 / (n - 2); () }\nmain =\n  let a = File 1\n  let b = File 2\n  drop b\n  3",
+            "error: 1 / (n - 2)",
+        ),
+        (
+            "owned data File =\n  File Int\ninstance Drop File =\n  drop (File n) = { 1 / n; () }\nkeep f b = if b then f else File 1\nmain =\n  let a = keep (File 0) true\n  let b = File 3\n  if false then { drop a; 1 } else 2",
+            "error: 1 / n",
+        ),
+        // Without a destructor, the drop glue drops the owned fields
+        (
+            "owned data File =\n  File Int\ninstance Drop File =\n  drop (File n) = { 1 / n; () }\nowned data Pair =\n  Pair File File\nmain =\n  let p = Pair (File 1) (File 0)\n  let (x, y) = (File 2, 5)\n  y",
+            "error: 1 / n",
+        ),
+        (
+            "owned data File =\n  File Int\ninstance Drop File =\n  drop (File n) = { 1 / n; () }\nowned data Pair =\n  Pair File File\nmain =\n  let p = Pair (File 1) (File 2)\n  let (x, y) = (File 3, 5)\n  y",
+            "5",
+        ),
+        // References read the value they borrow, owned values being borrowed in place
+        ("get r = *r\nmain =\n  let mut x = 20\n  let m = &mut x\n  let n = get m\n  get &x + n + get &2", "42"),
+        (
+            "owned data File =\n  File Int\ninstance Drop File =\n  drop (File n) = { 1 / (n - 2); () }\nsize r =\n  let File n = *r\n  n\nmain =\n  let f = File 2\n  let g = File 7\n  size &f + size &g",
+            "error: 1 / (n - 2)",
+        ),
+        (
+            "data Pair: A B =\n  Pair A B\nfirst r =\n  let Pair a _ = *r\n  a\nmain =\n  let p = Pair 1 true\n  (first &p, *(&(p, 3)))",
+            "(1, (Pair 1 true, 3))",
+        ),
+        // Assignments change `mut` locals, and what `&mut` borrows. Lambdas capture the value
+        // a `mut` local has when they're made
+        (
+            "set r v = *r = v\nmain =\n  let mut x = 1\n  set &mut x 5\n  let f = \\y -> x + y\n  x = x * 2\n  let r = &mut x\n  *r = *r + 1\n  (x, f 0)",
+            "(11, 5)",
+        ),
+        ("inc (mut n) =\n  n = n + 1\n  n\nmain =\n  let mut p = (1, 2)\n  let q = p\n  p = (inc 3, 5)\n  (p, q)", "((4, 5), (1, 2))"),
+        // The value an owned local is assigned replaces the one it had, which is dropped
+        (
+            "owned data File =\n  File Int\ninstance Drop File =\n  drop (File n) = { 1 / (n - 2); () }\nmain =\n  let mut f = File 2\n  f = File 3\n  0",
+            "error: 1 / (n - 2)",
+        ),
+        // Calls in tail position take the frame of the function they're in, through `if`s,
+        // blocks, `&&` and `||`, so recursing in them never overflows the stack
+        ("tailrec count n acc = if n == 0 then acc else count (n - 1) (acc + 1)\nmain = count 300000 0", "300000"),
+        (
+            "even n =\n  let m = n - 1\n  if n == 0 then true else odd m\nodd n = if n == 0 then false else { even (n - 1) }\nmain = (even 300001, odd 300001)",
+            "(false, true)",
+        ),
+        ("all n = n == 0 || n > 0 && all (n - 1)\nmain = all 300000", "true"),
+        // Through function values too
+        ("app f x = f x\nloop n = if n == 0 then 0 else app loop (n - 1)\nmain = loop 300000", "0"),
+        (
+            "app f x = f x\nloop k n = if n == 0 then k else app (loop k) (n - 1)\nmain = loop 7 300000",
+            "7",
+        ),
+    ];
+
+    #[test]
+    fn backends_agree() {
+        for (code, expected) in SUITE {
+            let shown = match run(code) {
+                Ok(value) => value,
+                Err(e) => format!("error: {}", &code[e.span.range]),
+            };
+            assert_eq!(&shown, expected, "{}", code);
+        }
+    }
+
+    #[test]
+    fn expressions_agree() {
+        let db = SourceDb::new();
+        let mut items = prelude(&db);
+        items.extend(parse_program(1, "double x = x + x\n").unwrap());
+        for (code, expected) in [
+            ("double 21", "42"),
+            ("(\\x -> (x, \\z -> x + z)) 1", "(1, <function>)"),
+            ("\\x -> double (x + 1)", "<function>"),
+        ] {
+            let e = parse_expr(2, code).unwrap();
+            let (mut program, mut e, errors) = resolve_expr(&items, &e);
+            assert!(errors.iter().all(|e| e.span.file != 2), "{:?}", errors);
+            let (scheme, mut types, errors) = typecheck_expr(&mut program, &mut e);
+            assert!(
+                errors.is_empty() && scheme.constraints.is_empty(),
+                "{:?}",
+                errors
+            );
+            assert!(check_expr_ownership(&mut program, &mut e, &mut types).is_empty());
+            assert!(check_expr_borrows(&program, &e, &types).is_empty());
+            let tree = Backend::Tree.eval(&program, &types, Target::Expr(&e));
+            let vm = Backend::Vm.eval(&program, &types, Target::Expr(&e));
+            let mir = Backend::Mir.eval(&program, &types, Target::Expr(&e));
+            assert_eq!(tree, vm);
+            assert_eq!(mir, vm);
+            assert_eq!(vm.unwrap(), expected);
+        }
+    }
+
+    // The value of `main` on the VM with a heap, and the statistics of the heap
+    fn run_with(code: &str, heap: Heap<'static>) -> (Result<String, RuntimeErr>, HeapStats) {
+        let program = Box::leak(Box::new(program(code, false).0));
+        let main = program
+            .items
+            .iter()
+            .find(|item| item.name.value == "main")
+            .unwrap()
+            .id;
+        let bytecode = compile(program);
+        let mut vm = Vm::with_heap(&bytecode, heap);
+        let value = vm.item(main).map(|v| vm.show(v).to_string());
+        (value, vm.heap_stats().clone())
+    }
+
+    const LISTS: &str = "\
+data List: A =
+  Nil
+  Cons A (List A)
+range a b = if a > b then Nil else Cons a (range (a + 1) b)
+map f Nil = Nil
+map f (Cons x xs) = Cons (f x) (map f xs)
+fold f acc Nil = acc
+fold f acc (Cons x xs) = fold f (f acc x) xs
+main =
+  let xs = map (\\x -> (x, x * x)) (range 1 300)
+  let sum = fold (\\acc (a, b) -> acc + a + b) 0 xs
+  let pairs = map (\\x -> Cons x Nil) (range 1 3)
+  (sum, pairs, fold (\\_ x -> x) (0, 0) xs)
+";
+
+    #[test]
+    fn stressed_collections_keep_live_values() {
+        // Collecting before every allocation frees what isn't rooted as soon as possible
+        for (code, _) in SUITE.iter().chain([(LISTS, "")].iter()) {
+            let (value, stats) = run_with(code, Heap::stressed());
+            assert_eq!(value, run_with(code, Heap::new()).0, "{}", code);
+            assert_eq!(stats.collections, stats.allocations, "{}", code);
+        }
+        assert_eq!(
+            run_with(LISTS, Heap::stressed()).0.unwrap(),
+            "(9090200, Cons (Cons 1 Nil) (Cons (Cons 2 Nil) (Cons (Cons 3 Nil) Nil)), (300, 90000))"
+        );
+    }
+
+    #[test]
+    fn garbage_is_collected() {
+        // Each iteration leaves a tuple and a list behind
+        let code = "\
+data List: A =
+  Nil
+  Cons A (List A)
+count n acc = if n == 0 then acc else count (n - 1) (n, Cons n Nil)
+main = count 100000 (0, Nil)
+";
+        let (value, stats) = run_with(code, Heap::new());
+        assert_eq!(value.unwrap(), "(1, Cons 1 Nil)");
+        assert!(stats.allocations > 300_000);
+        assert!(stats.collections > 0);
+        assert!(stats.peak_size < 4 * 1024 * 1024, "{:?}", stats);
+    }
+
+    #[test]
+    fn tail_calls_reuse_their_frame() {
+        // Deeper than the interpreter's stack allows
+        let code = "count n acc = if n == 0 then acc else count (n - 1) (acc + 1)\nmain = count 100000 0\n";
+        let (program, types) = program(code, false);
+        let main = program
+            .items
+            .iter()
+            .find(|item| item.name.value == "main")
+            .unwrap()
+            .id;
+        assert_eq!(
+            Backend::Vm
+                .eval(&program, &types, Target::Item(main))
+                .unwrap(),
+            "100000"
+        );
+        let bytecode = compile(&program);
+        let count = &bytecode.protos[main.0 - 1];
+        assert_eq!(
+            count
+                .code
+                .iter()
+                .filter(|i| matches!(i, Instr::TailCallItem(_)))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn saturated_calls_of_items_are_direct() {
+        let code = "plus a b = a + b + 0\nf x = plus x\nmain = (plus 1 2, f 1 2)\n";
+        assert_eq!(run(code).unwrap(), "(3, 3)");
+        let (program, _) = program(code, false);
+        let bytecode = compile(&program);
+        let plus = program
+            .items
+            .iter()
+            .find(|item| item.name.value == "plus")
+            .unwrap()
+            .id;
+        let main = program
+            .items
+            .iter()
+            .find(|item| item.name.value == "main")
+            .unwrap()
+            .id;
+        let code = &bytecode.protos[main.0].code;
+        assert!(code.contains(&Instr::CallItem(plus.0 as u32)));
+        // `f` takes a single argument, its value is applied to the second one
+        assert!(code.contains(&Instr::Call(2)));
+    }
+
+    #[test]
+    fn stack_overflows_point_at_the_call() {
+        let code = "f n = 1 + f (n + 1)\nmain = f 0\n";
+        let e = run(code).unwrap_err();
+        assert_eq!(e.kind, RuntimeErrKind::StackOverflow);
+        assert_eq!(&code[e.span.range], "f (n + 1)");
+    }
+}
@@ -0,0 +1,64 @@
+use crate::hir::Ident;
+
+use super::heap::Gc;
+
+/// A value of the stack machine. Tuples, data values, functions and cells are objects of the heap,
+/// which only lets them be shown through it
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Value {
+    Int(i64),
+    Bool(bool),
+    Unit,
+    Tuple(Gc),
+    Data(Gc),
+    Fun(Gc),
+    /// A reference to a cell, shared with the `mut` local it borrows
+    Ref(Gc),
+    /// The dictionary of an instance
+    Dict(u32),
+}
+
+/// An object of the heap
+#[derive(Debug)]
+pub enum Object<'p> {
+    Tuple(Vec<Value>),
+    Data(Data<'p>),
+    Fun(Fun),
+    Cell(Value),
+}
+impl<'p> Object<'p> {
+    /// The values it refers to
+    pub fn children(&self) -> impl Iterator<Item = &Value> {
+        let (first, second): (&[Value], &[Value]) = match self {
+            Object::Tuple(elements) => (elements, &[]),
+            Object::Data(data) => (&data.fields, &[]),
+            Object::Fun(fun) => (&fun.captures, &fun.args),
+            Object::Cell(value) => (std::slice::from_ref(value), &[]),
+        };
+        first.iter().chain(second)
+    }
+}
+
+/// A constructor by index, with its name for display, applied to its fields
+#[derive(Debug)]
+pub struct Data<'p> {
+    pub ctor: u32,
+    pub name: Ident<'p>,
+    pub fields: Vec<Value>,
+}
+
+/// A function applied to some of its arguments
+#[derive(Debug)]
+pub struct Fun {
+    pub callee: Callee,
+    /// The values a closure captured, its upvalues
+    pub captures: Vec<Value>,
+    pub args: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Callee {
+    Proto(u32),
+    /// A constructor of a data type, by index
+    Ctor(u32, u32),
+}
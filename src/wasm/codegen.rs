@@ -0,0 +1,954 @@
+//! Translates the bodies of the MIR to WebAssembly functions. Each body is a function taking
+//! its parameters then its captures, and an entry in the table of functions, taking the
+//! function value it's called through and the address of its arguments, for the runtime's
+//! `apply`. Calls of items given all of their arguments are direct, and `return_call`s when
+//! they're tail calls, the others go through `apply`, which tail calls through function values
+//! `return_call` too.
+//!
+//! WebAssembly's control flow is structured, so the basic blocks of a body are dispatched on
+//! by a loop, around blocks nested in each other which a `br_table` breaks out of to reach the
+//! block to run. Locals are WebAssembly locals, but the ones that are borrowed, which are
+//! allocated in the linear memory so that references can point to them.
+use std::{borrow::Cow, collections::HashMap};
+
+use wasm_encoder::{
+    BlockType, CodeSection, ConstExpr, DataSection, ElementSection, Elements, EntityType,
+    ExportKind, ExportSection, Function, FunctionSection, GlobalSection, GlobalType, ImportSection,
+    Instruction as I, MemArg, MemorySection, MemoryType, Module, NameMap, NameSection, RefType,
+    TableSection, TableType, TypeSection, ValType,
+};
+
+use super::runtime::{self, Runtime, DATA};
+use crate::{
+    errors::runtime_err::RuntimeErrKind,
+    hir::{BinOp, DefId, Dict, Literal, Program, UnOp},
+    mir::*,
+    native::field_type,
+    source::Span,
+    typeck::Type,
+};
+
+/// The type of entries, `(fun, args) -> value`, the first one
+pub const ENTRY: u32 = 0;
+
+/// A function being defined
+pub struct Func {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+    /// After the parameters
+    pub locals: Vec<ValType>,
+    pub code: Vec<I<'static>>,
+}
+
+pub struct Codegen<'m, 'p, 'a> {
+    program: &'p Program<'a>,
+    mir: &'m Mir<'a>,
+    // Where a span is in the source, for runtime errors
+    locate: &'m dyn Fn(&Span) -> String,
+    types: Vec<(Vec<ValType>, Vec<ValType>)>,
+    // The strings of the program by their content, at their address in `data`
+    strings: HashMap<String, u32>,
+    data: Vec<u8>,
+    // The functions printing the values of each type, printed with `Debug`
+    shows: HashMap<String, u32>,
+    printers: Vec<Option<Func>>,
+}
+impl<'m, 'p, 'a> Codegen<'m, 'p, 'a> {
+    pub fn new(
+        program: &'p Program<'a>,
+        mir: &'m Mir<'a>,
+        locate: &'m dyn Fn(&Span) -> String,
+    ) -> Self {
+        let mut cg = Self {
+            program,
+            mir,
+            locate,
+            types: vec![],
+            strings: HashMap::new(),
+            data: vec![],
+            shows: HashMap::new(),
+            printers: vec![],
+        };
+        cg.ty(&[ValType::I64, ValType::I32], &[ValType::I64]);
+        cg
+    }
+
+    /// The module running `main` and printing its value, as a WASI command
+    pub fn finish(mut self, main: DefId, ty: &Type) -> Vec<u8> {
+        let mut functions = runtime::functions(&mut self);
+        for idx in 0..self.mir.bodies.len() {
+            functions.push(self.body(idx));
+        }
+        for idx in 0..self.mir.bodies.len() {
+            functions.push(self.entry(idx));
+        }
+        functions.push(self.methods());
+        functions.push(self.start(main, ty));
+        functions.extend(
+            self.printers
+                .drain(..)
+                .map(|f| f.expect("Printers are generated")),
+        );
+
+        let mut types = TypeSection::new();
+        let mut imports = ImportSection::new();
+        let fd_write = self.ty(&[ValType::I32; 4], &[ValType::I32]);
+        imports.import(
+            "wasi_snapshot_preview1",
+            "fd_write",
+            EntityType::Function(fd_write),
+        );
+        let proc_exit = self.ty(&[ValType::I32], &[]);
+        imports.import(
+            "wasi_snapshot_preview1",
+            "proc_exit",
+            EntityType::Function(proc_exit),
+        );
+        let mut declared = FunctionSection::new();
+        let mut code = CodeSection::new();
+        for f in &functions {
+            declared.function(self.ty(&f.params, &f.results));
+            let mut function = Function::new_with_locals_types(f.locals.iter().copied());
+            for instruction in &f.code {
+                function.instruction(instruction);
+            }
+            code.function(&function);
+        }
+        for (params, results) in &self.types {
+            types
+                .ty()
+                .function(params.iter().copied(), results.iter().copied());
+        }
+
+        // Every body's entry, then the entry of constructors
+        let entries = self.mir.bodies.len() as u64 + 1;
+        let mut tables = TableSection::new();
+        tables.table(TableType {
+            element_type: RefType::FUNCREF,
+            table64: false,
+            minimum: entries,
+            maximum: Some(entries),
+            shared: false,
+        });
+        let mut elements = ElementSection::new();
+        let mut indices: Vec<_> = (0..self.mir.bodies.len())
+            .map(|idx| self.entry_index(idx))
+            .collect();
+        indices.push(Runtime::Ctor as u32);
+        elements.active(
+            None,
+            &ConstExpr::i32_const(0),
+            Elements::Functions(Cow::Owned(indices)),
+        );
+
+        // Above the indices of the instances, which dictionaries are told apart from tuples by
+        let heap = (DATA as usize + self.data.len())
+            .max(self.program.instances.len())
+            .div_ceil(8)
+            * 8;
+        let mut memories = MemorySection::new();
+        memories.memory(MemoryType {
+            minimum: (heap as u64 >> 16) + 1,
+            maximum: None,
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        let mut globals = GlobalSection::new();
+        let global = GlobalType {
+            val_type: ValType::I32,
+            mutable: true,
+            shared: false,
+        };
+        globals.global(global, &ConstExpr::i32_const(heap as i32));
+        globals.global(global, &ConstExpr::i32_const(0));
+        let mut exports = ExportSection::new();
+        exports.export("memory", ExportKind::Memory, 0);
+        exports.export("_start", ExportKind::Func, self.start_index());
+        let mut data = DataSection::new();
+        data.active(
+            0,
+            &ConstExpr::i32_const(DATA as i32),
+            self.data.iter().copied(),
+        );
+
+        let mut module = Module::new();
+        module
+            .section(&types)
+            .section(&imports)
+            .section(&declared)
+            .section(&tables)
+            .section(&memories)
+            .section(&globals)
+            .section(&exports)
+            .section(&elements)
+            .section(&code)
+            .section(&data)
+            .section(&self.names(functions.len() as u32));
+        module.finish()
+    }
+    // The names of the functions, for the stack traces of runtimes
+    fn names(&self, defined: u32) -> NameSection {
+        let mut functions = NameMap::new();
+        let runtime = [
+            "fd_write",
+            "proc_exit",
+            "alloc",
+            "panic",
+            "write",
+            "write_int",
+            "enter",
+            "leave",
+            "apply",
+            "ctor",
+            "add",
+            "sub",
+            "mul",
+            "div",
+            "neg",
+        ];
+        for (idx, name) in runtime.iter().enumerate() {
+            functions.append(idx as u32, name);
+        }
+        // By increasing index
+        for (idx, body) in self.mir.bodies.iter().enumerate() {
+            functions.append(self.body_index(idx), body.name);
+        }
+        for (idx, body) in self.mir.bodies.iter().enumerate() {
+            functions.append(self.entry_index(idx), &format!("{} entry", body.name));
+        }
+        functions.append(self.method_index(), "method");
+        functions.append(self.start_index(), "_start");
+        for idx in self.start_index() + 1..Runtime::IMPORTS + defined {
+            functions.append(idx, &format!("show{}", idx - self.start_index() - 1));
+        }
+        let mut names = NameSection::new();
+        names.functions(&functions);
+        names
+    }
+
+    // The index of a function type, added the first time
+    fn ty(&mut self, params: &[ValType], results: &[ValType]) -> u32 {
+        let ty = (params.to_vec(), results.to_vec());
+        match self.types.iter().position(|t| *t == ty) {
+            Some(idx) => idx as u32,
+            None => {
+                self.types.push(ty);
+                self.types.len() as u32 - 1
+            }
+        }
+    }
+    /// The address and length of a string in the linear memory
+    pub fn string(&mut self, s: &str) -> (i32, i32) {
+        let address = match self.strings.get(s) {
+            Some(address) => *address,
+            None => {
+                let address = DATA + self.data.len() as u32;
+                self.data.extend(s.bytes());
+                self.strings.insert(s.to_string(), address);
+                address
+            }
+        };
+        (address as i32, s.len() as i32)
+    }
+    fn message(&mut self, kind: RuntimeErrKind, span: &Span) -> [I<'static>; 2] {
+        let (ptr, len) = self.string(&format!("{} at {}", kind.message(), (self.locate)(span)));
+        [I::I32Const(ptr), I::I32Const(len)]
+    }
+
+    // The functions of the bodies, then their entries, then the other ones
+    fn body_index(&self, body: usize) -> u32 {
+        Runtime::COUNT + body as u32
+    }
+    fn entry_index(&self, body: usize) -> u32 {
+        Runtime::COUNT + (self.mir.bodies.len() + body) as u32
+    }
+    fn method_index(&self) -> u32 {
+        Runtime::COUNT + 2 * self.mir.bodies.len() as u32
+    }
+    fn start_index(&self) -> u32 {
+        self.method_index() + 1
+    }
+
+    fn body(&mut self, idx: usize) -> Func {
+        let body = &self.mir.bodies[idx];
+        let mut t = Translator::new(self, body.params + body.captures.len());
+        t.body(body);
+        t.finish()
+    }
+    // Reads the captures from the function value and the arguments from their address
+    fn entry(&mut self, idx: usize) -> Func {
+        let body = &self.mir.bodies[idx];
+        let mut code = vec![];
+        for i in 0..body.params {
+            code.extend([I::LocalGet(1), I::I64Load(word(8 * i as u64))]);
+        }
+        for i in 0..body.captures.len() {
+            code.extend([
+                I::LocalGet(0),
+                I::I32WrapI64,
+                I::I64Load(word(8 * (4 + i) as u64)),
+            ]);
+        }
+        code.extend([I::ReturnCall(self.body_index(idx)), I::End]);
+        Func {
+            params: vec![ValType::I64, ValType::I32],
+            results: vec![ValType::I64],
+            locals: vec![],
+            code,
+        }
+    }
+    // `method(dict, idx)`, the method of an instance by index applied to the dictionaries of
+    // its context: its value for methods taking nothing else, a function otherwise. The
+    // dictionary is the instance's index, or a tuple of it and of the dictionaries of its
+    // context, allocated above every index
+    fn methods(&mut self) -> Func {
+        let mut t = Translator::new(self, 2);
+        let (instance, context) = (t.local(ValType::I64), t.local(ValType::I32));
+        let count = t.cg.program.instances.len() as i64;
+        t.code.extend([
+            I::LocalGet(0),
+            I::I64Const(count),
+            I::I64LtU,
+            I::If(BlockType::Empty),
+            I::LocalGet(0),
+            I::LocalSet(instance),
+            I::Else,
+            I::LocalGet(0),
+            I::I32WrapI64,
+            I::I64Load(word(8)),
+            I::LocalSet(instance),
+            I::LocalGet(0),
+            I::I32WrapI64,
+            I::I32Const(16),
+            I::I32Add,
+            I::LocalSet(context),
+            I::End,
+        ]);
+        for (id, methods) in t.cg.program.instances.iter().enumerate() {
+            let given = methods.context.len();
+            for (idx, method) in methods.methods.iter().enumerate() {
+                t.code.extend([
+                    I::LocalGet(instance),
+                    I::I64Const(id as i64),
+                    I::I64Eq,
+                    I::LocalGet(1),
+                    I::I64Const(idx as i64),
+                    I::I64Eq,
+                    I::I32And,
+                    I::If(BlockType::Empty),
+                ]);
+                let body = BodyId(method.0);
+                if given == 0 {
+                    t.value(body);
+                } else if t.cg.mir.bodies[method.0].params == given {
+                    for i in 0..given {
+                        t.code
+                            .extend([I::LocalGet(context), I::I64Load(word(8 * i as u64))]);
+                    }
+                    t.code.push(I::Call(t.cg.body_index(method.0)));
+                } else {
+                    t.function(t.operand, body, &[]);
+                    t.code.extend([
+                        I::I32Const(given as i32),
+                        I::LocalGet(context),
+                        Runtime::Apply.call(),
+                    ]);
+                }
+                t.code.extend([I::Return, I::End]);
+            }
+        }
+        t.code.extend([I::Unreachable, I::End]);
+        t.finish()
+    }
+    // `_start`, printing the value of `main` followed by a newline
+    fn start(&mut self, main: DefId, ty: &Type) -> Func {
+        let show = self.show(ty);
+        let mut t = Translator::new(self, 0);
+        t.value(BodyId(main.0));
+        t.code.extend([I::I32Const(0), I::Call(show)]);
+        let newline = runtime::print(t.cg, 1, "\n");
+        t.code.extend(newline);
+        t.code.push(I::End);
+        let mut f = t.finish();
+        f.results.clear();
+        f
+    }
+
+    // The function printing values of `ty`, given whether they're the field of a constructor,
+    // which needs parentheses when it's compound
+    fn show(&mut self, ty: &Type) -> u32 {
+        let key = format!("{:?}", ty);
+        if let Some(idx) = self.shows.get(&key) {
+            return *idx;
+        }
+        let idx = self.start_index() + 1 + self.printers.len() as u32;
+        self.shows.insert(key, idx);
+        self.printers.push(None);
+        let mut code = match ty {
+            Type::Int => vec![I::LocalGet(0), I::LocalGet(1), Runtime::WriteInt.call()],
+            Type::Bool => {
+                let mut code = vec![I::LocalGet(0), I::I32WrapI64, I::If(BlockType::Empty)];
+                code.extend(runtime::print(self, 1, "true"));
+                code.push(I::Else);
+                code.extend(runtime::print(self, 1, "false"));
+                code.push(I::End);
+                code
+            }
+            Type::Unit => runtime::print(self, 1, "()"),
+            Type::Fun(..) => runtime::print(self, 1, "<function>"),
+            Type::Tuple(elements) => {
+                let mut code = runtime::print(self, 1, "(");
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        code.extend(runtime::print(self, 1, ", "));
+                    }
+                    let show = self.show(element);
+                    code.extend(field(i));
+                    code.extend([I::I32Const(0), I::Call(show)]);
+                }
+                code.extend(runtime::print(self, 1, ")"));
+                code
+            }
+            Type::Con(_) | Type::App(..) => self.data(ty),
+            // No value has a type that's still a variable, references don't outlive `main`
+            _ => vec![I::Unreachable],
+        };
+        code.push(I::End);
+        let slot = (idx - self.start_index() - 1) as usize;
+        self.printers[slot] = Some(Func {
+            params: vec![ValType::I64, ValType::I32],
+            results: vec![],
+            locals: vec![],
+            code,
+        });
+        idx
+    }
+    fn data(&mut self, ty: &Type) -> Vec<I<'static>> {
+        let (mut head, mut args) = (ty, vec![]);
+        while let Type::App(f, arg) = head {
+            args.push((**arg).clone());
+            head = f;
+        }
+        args.reverse();
+        let data = match head {
+            Type::Con(name) => self.program.datas.iter().find(|d| d.name.value == name),
+            _ => None,
+        };
+        let data = data.expect("Data types are applied by name");
+        let mut code = vec![];
+        for (idx, ctor) in data.ctors.iter().enumerate() {
+            code.extend([
+                I::LocalGet(0),
+                I::I32WrapI64,
+                I::I64Load(word(0)),
+                I::I64Const(idx as i64),
+                I::I64Eq,
+                I::If(BlockType::Empty),
+            ]);
+            let parens = !ctor.fields.is_empty();
+            if parens {
+                code.extend([I::LocalGet(1), I::If(BlockType::Empty)]);
+                code.extend(runtime::print(self, 1, "("));
+                code.push(I::End);
+            }
+            code.extend(runtime::print(self, 1, ctor.name.value));
+            for (i, ty) in ctor.fields.iter().enumerate() {
+                let show = self.show(&field_type(self.program, &ty.value, &args));
+                code.extend(runtime::print(self, 1, " "));
+                code.extend(field(i));
+                code.extend([I::I32Const(1), I::Call(show)]);
+            }
+            if parens {
+                code.extend([I::LocalGet(1), I::If(BlockType::Empty)]);
+                code.extend(runtime::print(self, 1, ")"));
+                code.push(I::End);
+            }
+            code.extend([I::Return, I::End]);
+        }
+        code.push(I::Unreachable);
+        code
+    }
+}
+
+fn word(offset: u64) -> MemArg {
+    MemArg {
+        offset,
+        align: 3,
+        memory_index: 0,
+    }
+}
+// The field of the tuple or data value that's the first parameter
+fn field(idx: usize) -> [I<'static>; 3] {
+    [
+        I::LocalGet(0),
+        I::I32WrapI64,
+        I::I64Load(word(8 * (idx as u64 + 1))),
+    ]
+}
+
+enum Slot {
+    Var(u32),
+    // The address of a word holding its value
+    Memory(u32),
+}
+
+struct Translator<'c, 'm, 'p, 'a> {
+    cg: &'c mut Codegen<'m, 'p, 'a>,
+    code: Vec<I<'static>>,
+    params: u32,
+    locals: Vec<ValType>,
+    slots: Vec<Slot>,
+    // The block to run next, for the dispatching loop
+    next: u32,
+    // A value being written to a place
+    value: u32,
+    // The objects being allocated by rvalues, and by the operands they're made of
+    object: u32,
+    operand: u32,
+    // How many blocks the current one is nested in, inside the dispatching loop
+    depth: u32,
+}
+impl<'c, 'm, 'p, 'a> Translator<'c, 'm, 'p, 'a> {
+    fn new(cg: &'c mut Codegen<'m, 'p, 'a>, params: usize) -> Self {
+        let mut t = Self {
+            cg,
+            code: vec![],
+            params: params as u32,
+            locals: vec![],
+            slots: vec![],
+            next: 0,
+            value: 0,
+            object: 0,
+            operand: 0,
+            depth: 0,
+        };
+        t.next = t.local(ValType::I32);
+        t.value = t.local(ValType::I64);
+        t.object = t.local(ValType::I32);
+        t.operand = t.local(ValType::I32);
+        t
+    }
+    fn local(&mut self, ty: ValType) -> u32 {
+        self.locals.push(ty);
+        self.params + self.locals.len() as u32 - 1
+    }
+    fn finish(self) -> Func {
+        Func {
+            params: vec![ValType::I64; self.params as usize],
+            results: vec![ValType::I64],
+            locals: self.locals,
+            code: self.code,
+        }
+    }
+
+    fn body(&mut self, body: &'m Body<'a>) {
+        let mut borrowed = vec![false; body.locals.len()];
+        for block in &body.blocks {
+            for statement in &block.statements {
+                if let StatementKind::Assign(_, Rvalue::Ref(_, place)) = &statement.kind {
+                    borrowed[place.local.0] |= place.projection.is_empty();
+                }
+            }
+        }
+        let mut params = HashMap::new();
+        for (idx, local) in body
+            .params()
+            .chain(body.captures.iter().copied())
+            .enumerate()
+        {
+            params.insert(local, idx as u32);
+        }
+        for (idx, borrowed) in borrowed.into_iter().enumerate() {
+            let var = match params.get(&Local(idx)) {
+                Some(param) => *param,
+                None => self.local(ValType::I64),
+            };
+            let slot = match borrowed {
+                true => {
+                    let address = self.local(ValType::I32);
+                    self.code.extend([
+                        I::I32Const(1),
+                        Runtime::Alloc.call(),
+                        I::LocalTee(address),
+                        I::LocalGet(var),
+                        I::I64Store(word(0)),
+                    ]);
+                    Slot::Memory(address)
+                }
+                false => Slot::Var(var),
+            };
+            self.slots.push(slot);
+        }
+
+        let blocks = body.blocks.len() as u32;
+        self.code.push(I::Loop(BlockType::Empty));
+        self.code
+            .extend((0..blocks).map(|_| I::Block(BlockType::Empty)));
+        let targets: Vec<_> = (0..blocks).collect();
+        self.code.extend([
+            I::LocalGet(self.next),
+            I::BrTable(Cow::Owned(targets), 0),
+            I::End,
+        ]);
+        for (idx, block) in body.blocks.iter().enumerate() {
+            // Breaking out of the blocks left continues the loop
+            self.depth = blocks - 1 - idx as u32;
+            for statement in &block.statements {
+                if let StatementKind::Assign(place, rvalue) = &statement.kind {
+                    self.rvalue(rvalue, &statement.span);
+                    self.write(place);
+                }
+            }
+            self.terminator(&block.terminator, BlockId(idx));
+            self.code.push(I::End);
+        }
+        self.code.extend([I::Unreachable, I::End]);
+    }
+
+    // Runs a block, falling through to the next one
+    fn goto(&mut self, target: BlockId, current: BlockId) {
+        if target.0 != current.0 + 1 {
+            self.jump(target, 0);
+        }
+    }
+    // Runs a block from inside `nested` blocks of the current one
+    fn jump(&mut self, target: BlockId, nested: u32) {
+        self.code.extend([
+            I::I32Const(target.0 as i32),
+            I::LocalSet(self.next),
+            I::Br(self.depth + nested),
+        ]);
+    }
+    fn terminator(&mut self, Terminator { kind, span }: &Terminator<'a>, current: BlockId) {
+        match kind {
+            TerminatorKind::Goto(target) => self.goto(*target, current),
+            TerminatorKind::If { cond, then, else_ } => {
+                self.operand(cond);
+                self.code.extend([I::I32WrapI64, I::If(BlockType::Empty)]);
+                self.jump(*then, 1);
+                self.code.push(I::End);
+                self.goto(*else_, current);
+            }
+            TerminatorKind::Switch {
+                place,
+                targets,
+                otherwise,
+            } => {
+                self.read(place);
+                if let Some((Test::Ctor(_), _)) = targets.first() {
+                    self.code.extend([I::I32WrapI64, I::I64Load(word(0))]);
+                }
+                self.code.push(I::LocalSet(self.value));
+                for (test, target) in targets {
+                    let test = match test {
+                        Test::Literal(l) => literal(l),
+                        Test::Ctor(idx) => *idx as i64,
+                        Test::Dict(id) => id.0 as i64,
+                    };
+                    self.code.extend([
+                        I::LocalGet(self.value),
+                        I::I64Const(test),
+                        I::I64Eq,
+                        I::If(BlockType::Empty),
+                    ]);
+                    self.jump(*target, 1);
+                    self.code.push(I::End);
+                }
+                self.goto(*otherwise, current);
+            }
+            TerminatorKind::Call {
+                func,
+                args,
+                dest,
+                target,
+                tail,
+            } => {
+                match func {
+                    // The callee takes the frame, without being counted in the depth of calls
+                    Operand::Constant(Constant::Item(id, _))
+                        if *tail && args.len() == self.cg.mir.bodies[id.0].params =>
+                    {
+                        for arg in args {
+                            self.operand(arg);
+                        }
+                        return self.code.push(I::ReturnCall(self.cg.body_index(id.0)));
+                    }
+                    // `apply` calls the function in tail position when it's given all of
+                    // its arguments
+                    Operand::Copy(_) | Operand::Move(_) if *tail => {
+                        self.operand(func);
+                        return self.apply(args, true);
+                    }
+                    _ => self.call(func, args, span),
+                }
+                self.write(dest);
+                self.goto(*target, current);
+            }
+            TerminatorKind::Drop {
+                place,
+                glue,
+                target,
+            } => {
+                self.call(glue, &[Operand::Copy(place.clone())], span);
+                self.code.push(I::Drop);
+                self.goto(*target, current);
+            }
+            TerminatorKind::Return => {
+                self.read(&Place::local(RETURN));
+                self.code.push(I::Return);
+            }
+            TerminatorKind::Unreachable => self.code.push(I::Unreachable),
+        }
+    }
+    // Calls a function, directly when it's an item given all of its arguments
+    fn call(&mut self, func: &Operand<'a>, args: &[Operand<'a>], span: &Span) {
+        let overflow = self.cg.message(RuntimeErrKind::StackOverflow, span);
+        self.code.extend(overflow);
+        self.code.push(Runtime::Enter.call());
+        match func {
+            Operand::Constant(Constant::Item(id, _))
+                if args.len() >= self.cg.mir.bodies[id.0].params =>
+            {
+                let params = self.cg.mir.bodies[id.0].params;
+                for arg in &args[..params] {
+                    self.operand(arg);
+                }
+                self.code.push(I::Call(self.cg.body_index(id.0)));
+                if params < args.len() {
+                    self.apply(&args[params..], false);
+                }
+            }
+            Operand::Constant(Constant::Ctor(data, idx, _))
+                if args.len() == self.cg.program.datas[data.0].ctors[*idx].fields.len() =>
+            {
+                self.alloc(*idx as i64, args);
+            }
+            func => {
+                self.operand(func);
+                self.apply(args, false);
+            }
+        }
+        self.code.push(Runtime::Leave.call());
+    }
+    // Applies the function on the stack to arguments, returning what it returns when `tail`
+    fn apply(&mut self, args: &[Operand<'a>], tail: bool) {
+        self.code.push(I::I32Const(args.len() as i32));
+        self.words(self.object, args.len(), |t, i| t.operand(&args[i]));
+        match tail {
+            true => self.code.push(I::ReturnCall(Runtime::Apply as u32)),
+            false => self.code.push(Runtime::Apply.call()),
+        }
+    }
+
+    fn rvalue(&mut self, rvalue: &Rvalue<'a>, span: &Span) {
+        match rvalue {
+            Rvalue::Use(operand) => self.operand(operand),
+            Rvalue::Ref(_, place) => {
+                self.address(place);
+                self.code.push(I::I64ExtendI32U);
+            }
+            Rvalue::Unary(UnOp::Neg, operand) => {
+                self.operand(operand);
+                let overflow = self
+                    .cg
+                    .message(RuntimeErrKind::Overflow(String::new()), span);
+                self.code.extend(overflow);
+                self.code.push(Runtime::Neg.call());
+            }
+            Rvalue::Unary(UnOp::Not, operand) => {
+                self.operand(operand);
+                self.code.extend([I::I64Eqz, I::I64ExtendI32U]);
+            }
+            Rvalue::Unary(op, _) => unreachable!("`{:?}` isn't lowered to an operator", op),
+            Rvalue::Binary(op, l, r) => {
+                self.operand(l);
+                self.operand(r);
+                self.binary(*op, span);
+            }
+            Rvalue::Tuple(operands) => self.alloc(0, operands),
+            Rvalue::Ctor(_, idx, _, operands) => self.alloc(*idx as i64, operands),
+            Rvalue::Closure(body, captures) => self.function(self.object, *body, captures),
+            Rvalue::Method(dict, idx) => {
+                self.operand(dict);
+                self.code
+                    .extend([I::I64Const(*idx as i64), I::Call(self.cg.method_index())]);
+            }
+        }
+    }
+    fn binary(&mut self, op: BinOp, span: &Span) {
+        let overflow = self
+            .cg
+            .message(RuntimeErrKind::Overflow(String::new()), span);
+        let checked = match op {
+            BinOp::Add => Runtime::Add,
+            BinOp::Sub => Runtime::Sub,
+            BinOp::Mul => Runtime::Mul,
+            BinOp::Div => {
+                let zero = self.cg.message(RuntimeErrKind::DivisionByZero, span);
+                self.code.extend(zero);
+                Runtime::Div
+            }
+            _ => {
+                let cmp = match op {
+                    BinOp::LT => I::I64LtS,
+                    BinOp::LTE => I::I64LeS,
+                    BinOp::GT => I::I64GtS,
+                    BinOp::GTE => I::I64GeS,
+                    BinOp::EqEq => I::I64Eq,
+                    BinOp::NotEq => I::I64Ne,
+                    _ => unreachable!("`&&` and `||` are branches"),
+                };
+                self.code.extend([cmp, I::I64ExtendI32U]);
+                return;
+            }
+        };
+        self.code.extend(overflow);
+        self.code.push(checked.call());
+    }
+    // A tuple or data value, made of the index of its constructor then its fields
+    fn alloc(&mut self, idx: i64, fields: &[Operand<'a>]) {
+        self.words(self.object, fields.len() + 1, |t, i| match i {
+            0 => t.code.push(I::I64Const(idx)),
+            i => t.operand(&fields[i - 1]),
+        });
+        self.code.push(I::I64ExtendI32U);
+    }
+    // Allocates words, leaving their address on the stack, `value` pushing the value of each
+    fn words(&mut self, address: u32, count: usize, mut value: impl FnMut(&mut Self, usize)) {
+        self.code.extend([
+            I::I32Const(count as i32),
+            Runtime::Alloc.call(),
+            I::LocalSet(address),
+        ]);
+        for i in 0..count {
+            self.code.push(I::LocalGet(address));
+            value(self, i);
+            self.code.push(I::I64Store(word(8 * i as u64)));
+        }
+        self.code.push(I::LocalGet(address));
+    }
+    // A function value calling a body, allocated through `address`
+    fn function(&mut self, address: u32, body: BodyId, captures: &[Operand<'a>]) {
+        let entry = body.0 as i64;
+        let arity = self.cg.mir.bodies[body.0].params as i64;
+        self.words(address, 4 + captures.len(), |t, i| match i {
+            0 => t.code.push(I::I64Const(entry)),
+            1 => t.code.push(I::I64Const(arity)),
+            2 => t.code.push(I::I64Const(captures.len() as i64)),
+            3 => t.code.push(I::I64Const(0)),
+            i => t.operand(&captures[i - 4]),
+        });
+        self.code.push(I::I64ExtendI32U);
+    }
+    // The value of an item: a function when it takes parameters
+    fn value(&mut self, body: BodyId) {
+        match self.cg.mir.bodies[body.0].params {
+            0 => self.code.push(I::Call(self.cg.body_index(body.0))),
+            _ => self.function(self.operand, body, &[]),
+        }
+    }
+
+    fn operand(&mut self, operand: &Operand<'a>) {
+        match operand {
+            Operand::Copy(place) | Operand::Move(place) => self.read(place),
+            Operand::Constant(Constant::Literal(l)) => self.code.push(I::I64Const(literal(l))),
+            Operand::Constant(Constant::Item(id, _)) => {
+                self.function(self.operand, BodyId(id.0), &[])
+            }
+            Operand::Constant(Constant::Ctor(data, idx, _)) => {
+                let entry = self.cg.mir.bodies.len() as i64;
+                let arity = self.cg.program.datas[data.0].ctors[*idx].fields.len() as i64;
+                let values = [entry, arity, 1, 0, *idx as i64];
+                self.words(self.operand, values.len(), |t, i| {
+                    t.code.push(I::I64Const(values[i]))
+                });
+                self.code.push(I::I64ExtendI32U);
+            }
+            Operand::Constant(Constant::Dict(Dict::Instance(id, _))) => {
+                self.code.push(I::I64Const(id.0 as i64))
+            }
+            Operand::Constant(Constant::Dict(_)) => {
+                unreachable!("Dictionary parameters are locals")
+            }
+        }
+    }
+
+    // Fields are after the index of the constructor
+    fn address(&mut self, place: &Place) {
+        match place.projection.split_last() {
+            None => match self.slots[place.local.0] {
+                Slot::Memory(address) => self.code.push(I::LocalGet(address)),
+                Slot::Var(_) => unreachable!("Borrowed locals are in the memory"),
+            },
+            Some((projection, base)) => {
+                self.read(&Place {
+                    local: place.local,
+                    projection: base.to_vec(),
+                });
+                self.code.push(I::I32WrapI64);
+                match projection {
+                    Projection::Deref => (),
+                    Projection::Downcast(..) => {
+                        unreachable!("Downcast values are read, not addressed")
+                    }
+                    Projection::Field(idx) => self
+                        .code
+                        .extend([I::I32Const(8 * (*idx as i32 + 1)), I::I32Add]),
+                }
+            }
+        }
+    }
+    fn read(&mut self, place: &Place) {
+        match place.projection.last() {
+            None => match self.slots[place.local.0] {
+                Slot::Var(var) => self.code.push(I::LocalGet(var)),
+                Slot::Memory(address) => self
+                    .code
+                    .extend([I::LocalGet(address), I::I64Load(word(0))]),
+            },
+            // The same value, seen as one of its constructors
+            Some(Projection::Downcast(..)) => self.read(&Place {
+                local: place.local,
+                projection: place.projection[..place.projection.len() - 1].to_vec(),
+            }),
+            Some(_) => {
+                self.address(place);
+                self.code.push(I::I64Load(word(0)));
+            }
+        }
+    }
+    // Writes the value on the stack to a place
+    fn write(&mut self, place: &Place) {
+        match (place.projection.split_last(), &self.slots[place.local.0]) {
+            (None, Slot::Var(var)) => self.code.push(I::LocalSet(*var)),
+            (None, Slot::Memory(address)) => {
+                let address = *address;
+                self.code.extend([
+                    I::LocalSet(self.value),
+                    I::LocalGet(address),
+                    I::LocalGet(self.value),
+                    I::I64Store(word(0)),
+                ]);
+            }
+            (Some((Projection::Deref, base)), _) => {
+                self.code.push(I::LocalSet(self.value));
+                self.read(&Place {
+                    local: place.local,
+                    projection: base.to_vec(),
+                });
+                self.code
+                    .extend([I::I32WrapI64, I::LocalGet(self.value), I::I64Store(word(0))]);
+            }
+            _ => unreachable!("Only locals and what references point to are assigned"),
+        }
+    }
+}
+
+fn literal(l: &Literal) -> i64 {
+    match l {
+        Literal::Num(n) => *n,
+        Literal::Bool(b) => *b as i64,
+        Literal::Unit => 0,
+    }
+}
@@ -0,0 +1,134 @@
+//! The WebAssembly backend: programs are compiled from their MIR to a WebAssembly module, a
+//! WASI command whose `_start` prints the value of `main` like `kamai run` does, which any WASI
+//! runtime can run. Values are words in the linear memory, laid out like the native backend's,
+//! and functions are called through a table when they're values.
+//!
+//! The runtime is generated along with the program, only `fd_write` and `proc_exit` are
+//! imported from WASI. Tail calls need the tail call proposal, which runtimes have enabled by
+//! default for a while.
+mod codegen;
+mod runtime;
+
+use crate::{
+    hir::{DefId, Program},
+    mir::lower,
+    source::SourceDb,
+    typeck::Types,
+};
+use codegen::Codegen;
+
+/// Compiles a checked program to a WebAssembly module printing the value of `main`
+pub fn build(db: &SourceDb, program: &Program, types: &Types, main: DefId) -> Vec<u8> {
+    let mir = lower(program, types);
+    let locate = |span: &_| db.location(span);
+    Codegen::new(program, &mir, &locate).finish(main, &types.items[main.0].ty)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use wasmi::{Caller, Config, Engine, Extern, Linker, Module, StackLimits, Store};
+
+    use super::*;
+    use crate::{
+        check::tests::checked,
+        cli::{Backend, Target},
+        prelude::prelude,
+        syntax::parse_program,
+        vm::tests::SUITE,
+    };
+
+    // What a module wrote to stdout and stderr
+    #[derive(Default)]
+    struct Output {
+        stdout: Vec<u8>,
+        stderr: Vec<u8>,
+    }
+
+    fn run(module: &[u8]) -> (String, String, i32) {
+        let mut config = Config::default();
+        config.wasm_tail_call(true);
+        // Calls nest as deep as the runtime allows before reporting a stack overflow
+        config.set_stack_limits(StackLimits::new(1 << 10, 1 << 24, 1 << 20).unwrap());
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, module).unwrap();
+        let mut store = Store::new(&engine, Output::default());
+        let mut linker = Linker::<Output>::new(&engine);
+        let fd_write = |mut caller: Caller<Output>, fd: i32, iovs: i32, len: i32, written: i32| {
+            let memory = match caller.get_export("memory") {
+                Some(Extern::Memory(memory)) => memory,
+                _ => unreachable!("Modules export their memory"),
+            };
+            let (data, output) = memory.data_and_store_mut(&mut caller);
+            let word = |at: i32| {
+                u32::from_le_bytes(data[at as usize..at as usize + 4].try_into().unwrap()) as usize
+            };
+            let mut bytes = vec![];
+            for iov in (0..len).map(|i| iovs + 8 * i) {
+                bytes.extend_from_slice(&data[word(iov)..word(iov) + word(iov + 4)]);
+            }
+            data[written as usize..written as usize + 4]
+                .copy_from_slice(&(bytes.len() as u32).to_le_bytes());
+            match fd {
+                1 => output.stdout.extend(bytes),
+                _ => output.stderr.extend(bytes),
+            }
+            0
+        };
+        linker
+            .func_wrap("wasi_snapshot_preview1", "fd_write", fd_write)
+            .unwrap();
+        let proc_exit = |_: Caller<Output>, code: i32| -> Result<(), wasmi::Error> {
+            Err(wasmi::Error::i32_exit(code))
+        };
+        linker
+            .func_wrap("wasi_snapshot_preview1", "proc_exit", proc_exit)
+            .unwrap();
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+        let start = instance.get_typed_func::<(), ()>(&store, "_start").unwrap();
+        let code = match start.call(&mut store, ()) {
+            Ok(()) => 0,
+            Err(e) => e.i32_exit_status().unwrap_or_else(|| panic!("{}", e)),
+        };
+        let output = store.into_data();
+        (
+            String::from_utf8(output.stdout).unwrap(),
+            String::from_utf8(output.stderr).unwrap(),
+            code,
+        )
+    }
+
+    #[test]
+    fn modules_agree_with_the_vm() {
+        for (code, _) in SUITE {
+            let db = SourceDb::new();
+            let mut items = prelude(&db);
+            let file = db.add("main.ka", code.to_string());
+            items.extend(parse_program(file, db.source(file)).unwrap());
+            let (program, types) = checked(&items, true);
+            let main = program
+                .items
+                .iter()
+                .find(|item| item.name.value == "main")
+                .unwrap()
+                .id;
+
+            let module = build(&db, &program, &types, main);
+            wasmparser::Validator::new().validate_all(&module).unwrap();
+            let (stdout, stderr, status) = run(&module);
+            match Backend::Vm.eval(&program, &types, Target::Item(main)) {
+                Ok(value) => assert_eq!((stdout, status), (value + "\n", 0), "{}", code),
+                Err(e) => {
+                    let error =
+                        format!("error: {} at {}\n", e.kind.message(), db.location(&e.span));
+                    assert_eq!((stderr, status), (error, 1), "{}", code);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,599 @@
+//! The functions of the runtime, written in WebAssembly. They mirror the C runtime of the
+//! native backend: values are allocated by bumping a pointer through the linear memory, which
+//! grows when it's full, functions are applied partially or to more arguments than they take,
+//! and runtime errors are written to stderr before exiting.
+use wasm_encoder::{BlockType, Instruction as I, MemArg, ValType};
+
+use super::codegen::{Codegen, Func, ENTRY};
+use crate::errors::runtime_err::MAX_DEPTH;
+
+/// The functions imported from WASI, then the ones of the runtime, by index
+#[derive(Debug, Clone, Copy)]
+pub enum Runtime {
+    FdWrite,
+    ProcExit,
+    Alloc,
+    Panic,
+    Write,
+    WriteInt,
+    Enter,
+    Leave,
+    Apply,
+    Ctor,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Neg,
+}
+impl Runtime {
+    pub const IMPORTS: u32 = 2;
+    pub const COUNT: u32 = 15;
+    pub fn call(self) -> I<'static> {
+        I::Call(self as u32)
+    }
+}
+
+/// The global pointing to the next allocated word, then the one counting the nested calls
+pub const HEAP: u32 = 0;
+pub const DEPTH: u32 = 1;
+
+/// The iovec given to `fd_write`, followed by how many bytes it wrote
+pub const IOVEC: i32 = 8;
+/// Integers are written backwards, ending at `DIGITS`
+pub const DIGITS: i32 = 48;
+/// Where the strings of the program start
+pub const DATA: u32 = 48;
+
+const STDOUT: i32 = 1;
+const STDERR: i32 = 2;
+
+/// The functions defined by the runtime, in the order of `Runtime`
+pub fn functions(cg: &mut Codegen) -> Vec<Func> {
+    vec![
+        alloc(cg),
+        panic(cg),
+        write(),
+        write_int(cg),
+        enter(),
+        leave(),
+        apply(),
+        ctor(),
+        add(),
+        sub(),
+        mul(),
+        div(),
+        neg(),
+    ]
+}
+
+fn func(
+    params: &[ValType],
+    results: &[ValType],
+    locals: &[ValType],
+    code: Vec<I<'static>>,
+) -> Func {
+    Func {
+        params: params.to_vec(),
+        results: results.to_vec(),
+        locals: locals.to_vec(),
+        code,
+    }
+}
+fn word(offset: u64) -> MemArg {
+    MemArg {
+        offset,
+        align: 3,
+        memory_index: 0,
+    }
+}
+fn byte() -> MemArg {
+    MemArg {
+        offset: 0,
+        align: 0,
+        memory_index: 0,
+    }
+}
+fn copy() -> I<'static> {
+    I::MemoryCopy {
+        src_mem: 0,
+        dst_mem: 0,
+    }
+}
+// Writes a string of the program to a file descriptor
+pub fn print(cg: &mut Codegen, fd: i32, s: &str) -> Vec<I<'static>> {
+    let (ptr, len) = cg.string(s);
+    vec![
+        I::I32Const(fd),
+        I::I32Const(ptr),
+        I::I32Const(len),
+        Runtime::Write.call(),
+    ]
+}
+// Reports the runtime error whose message is at `ptr` and `len`
+fn fail(ptr: u32, len: u32) -> Vec<I<'static>> {
+    vec![
+        I::If(BlockType::Empty),
+        I::LocalGet(ptr),
+        I::LocalGet(len),
+        Runtime::Panic.call(),
+        I::End,
+    ]
+}
+
+// `alloc(words) -> address`
+fn alloc(cg: &mut Codegen) -> Func {
+    let (ptr, len) = cg.string("Out of memory");
+    let code = vec![
+        I::GlobalGet(HEAP),
+        I::LocalSet(1),
+        I::GlobalGet(HEAP),
+        I::LocalGet(0),
+        I::I32Const(3),
+        I::I32Shl,
+        I::I32Add,
+        I::GlobalSet(HEAP),
+        // Grows the memory by the pages the heap is missing
+        I::GlobalGet(HEAP),
+        I::MemorySize(0),
+        I::I32Const(16),
+        I::I32Shl,
+        I::I32GtU,
+        I::If(BlockType::Empty),
+        I::GlobalGet(HEAP),
+        I::MemorySize(0),
+        I::I32Const(16),
+        I::I32Shl,
+        I::I32Sub,
+        I::I32Const(0xffff),
+        I::I32Add,
+        I::I32Const(16),
+        I::I32ShrU,
+        I::MemoryGrow(0),
+        I::I32Const(-1),
+        I::I32Eq,
+        I::If(BlockType::Empty),
+        I::I32Const(ptr),
+        I::I32Const(len),
+        Runtime::Panic.call(),
+        I::End,
+        I::End,
+        I::LocalGet(1),
+        I::End,
+    ];
+    func(&[ValType::I32], &[ValType::I32], &[ValType::I32], code)
+}
+
+// `panic(message, len)`, prints `error: message` to stderr and exits with 1
+fn panic(cg: &mut Codegen) -> Func {
+    let mut code = print(cg, STDERR, "error: ");
+    code.extend([
+        I::I32Const(STDERR),
+        I::LocalGet(0),
+        I::LocalGet(1),
+        Runtime::Write.call(),
+    ]);
+    code.extend(print(cg, STDERR, "\n"));
+    code.extend([
+        I::I32Const(1),
+        Runtime::ProcExit.call(),
+        I::Unreachable,
+        I::End,
+    ]);
+    func(&[ValType::I32, ValType::I32], &[], &[], code)
+}
+
+// `write(fd, bytes, len)`
+fn write() -> Func {
+    let code = vec![
+        I::I32Const(IOVEC),
+        I::LocalGet(1),
+        I::I32Store(MemArg {
+            offset: 0,
+            align: 2,
+            memory_index: 0,
+        }),
+        I::I32Const(IOVEC),
+        I::LocalGet(2),
+        I::I32Store(MemArg {
+            offset: 4,
+            align: 2,
+            memory_index: 0,
+        }),
+        I::LocalGet(0),
+        I::I32Const(IOVEC),
+        I::I32Const(1),
+        I::I32Const(IOVEC + 8),
+        Runtime::FdWrite.call(),
+        I::Drop,
+        I::End,
+    ];
+    func(&[ValType::I32, ValType::I32, ValType::I32], &[], &[], code)
+}
+
+// `write_int(n, nested)` prints an integer to stdout, in parentheses when it's negative and
+// nested
+fn write_int(cg: &mut Codegen) -> Func {
+    // The end of the digits, their magnitude, and whether they're in parentheses
+    let (end, magnitude, parens) = (2, 3, 4);
+    let mut code = vec![
+        I::LocalGet(1),
+        I::LocalGet(0),
+        I::I64Const(0),
+        I::I64LtS,
+        I::I32And,
+        I::LocalTee(parens),
+        I::If(BlockType::Empty),
+    ];
+    code.extend(print(cg, STDOUT, "("));
+    code.extend([
+        I::End,
+        I::I32Const(DIGITS),
+        I::LocalSet(end),
+        // `i64::MIN` has no opposite, but its magnitude is unsigned
+        I::I64Const(0),
+        I::LocalGet(0),
+        I::I64Sub,
+        I::LocalGet(0),
+        I::LocalGet(0),
+        I::I64Const(0),
+        I::I64LtS,
+        I::Select,
+        I::LocalSet(magnitude),
+        I::Loop(BlockType::Empty),
+        I::LocalGet(end),
+        I::I32Const(1),
+        I::I32Sub,
+        I::LocalTee(end),
+        I::LocalGet(magnitude),
+        I::I64Const(10),
+        I::I64RemU,
+        I::I64Const(b'0' as i64),
+        I::I64Add,
+        I::I64Store8(byte()),
+        I::LocalGet(magnitude),
+        I::I64Const(10),
+        I::I64DivU,
+        I::LocalTee(magnitude),
+        I::I64Const(0),
+        I::I64Ne,
+        I::BrIf(0),
+        I::End,
+        I::LocalGet(0),
+        I::I64Const(0),
+        I::I64LtS,
+        I::If(BlockType::Empty),
+        I::LocalGet(end),
+        I::I32Const(1),
+        I::I32Sub,
+        I::LocalTee(end),
+        I::I32Const(b'-' as i32),
+        I::I32Store8(byte()),
+        I::End,
+        I::I32Const(STDOUT),
+        I::LocalGet(end),
+        I::I32Const(DIGITS),
+        I::LocalGet(end),
+        I::I32Sub,
+        Runtime::Write.call(),
+        I::LocalGet(parens),
+        I::If(BlockType::Empty),
+    ]);
+    code.extend(print(cg, STDOUT, ")"));
+    code.extend([I::End, I::End]);
+    let locals = [ValType::I32, ValType::I64, ValType::I32];
+    func(&[ValType::I64, ValType::I32], &[], &locals, code)
+}
+
+// `enter(overflow, len)` around each call, `overflow` being the error of the call when it's
+// nested too deep
+fn enter() -> Func {
+    let mut code = vec![
+        I::GlobalGet(DEPTH),
+        I::I32Const(1),
+        I::I32Add,
+        I::GlobalSet(DEPTH),
+        I::GlobalGet(DEPTH),
+        I::I32Const(MAX_DEPTH as i32),
+        I::I32GtS,
+    ];
+    code.extend(fail(0, 1));
+    code.push(I::End);
+    func(&[ValType::I32, ValType::I32], &[], &[], code)
+}
+fn leave() -> Func {
+    let code = vec![
+        I::GlobalGet(DEPTH),
+        I::I32Const(1),
+        I::I32Sub,
+        I::GlobalSet(DEPTH),
+        I::End,
+    ];
+    func(&[], &[], &[], code)
+}
+
+// `apply(fun, argc, argv)` applies a function to arguments, partially when they're fewer than
+// it takes, its value being applied to the rest when they're more. Functions are their entry's
+// index in the table, their arity, how many values they captured and how many arguments they
+// were given, then these values
+fn apply() -> Func {
+    let (fun, argc, argv) = (0, 1, 2);
+    let (object, count, known, values, result) = (3, 4, 5, 6, 7);
+    let field = |offset| [I::LocalGet(object), I::I64Load(word(offset)), I::I32WrapI64];
+    let mut code = vec![I::LocalGet(fun), I::I32WrapI64, I::LocalSet(object)];
+    code.extend(field(24));
+    code.extend([I::LocalGet(argc), I::I32Add, I::LocalSet(count)]);
+    code.extend(field(16));
+    code.extend(field(24));
+    code.extend([I::I32Add, I::LocalSet(known), I::LocalGet(count)]);
+    code.extend(field(8));
+    code.extend([
+        I::I32LtS,
+        I::If(BlockType::Empty),
+        // A copy of the function, given the arguments too
+        I::I32Const(4),
+        I::LocalGet(known),
+        I::I32Add,
+        I::LocalGet(argc),
+        I::I32Add,
+        Runtime::Alloc.call(),
+        I::LocalSet(values),
+        I::LocalGet(values),
+        I::LocalGet(object),
+        I::I32Const(4),
+        I::LocalGet(known),
+        I::I32Add,
+        I::I32Const(3),
+        I::I32Shl,
+        copy(),
+        I::LocalGet(values),
+        I::I32Const(4),
+        I::LocalGet(known),
+        I::I32Add,
+        I::I32Const(3),
+        I::I32Shl,
+        I::I32Add,
+        I::LocalGet(argv),
+        I::LocalGet(argc),
+        I::I32Const(3),
+        I::I32Shl,
+        copy(),
+        I::LocalGet(values),
+        I::LocalGet(count),
+        I::I64ExtendI32S,
+        I::I64Store(word(24)),
+        I::LocalGet(values),
+        I::I64ExtendI32U,
+        I::Return,
+        I::End,
+        // The arguments it was given, then the new ones
+        I::LocalGet(count),
+        Runtime::Alloc.call(),
+        I::LocalSet(values),
+        I::LocalGet(values),
+        I::LocalGet(object),
+        I::I32Const(32),
+        I::I32Add,
+    ]);
+    code.extend(field(16));
+    code.extend([I::I32Const(3), I::I32Shl, I::I32Add]);
+    code.extend(field(24));
+    code.extend([I::I32Const(3), I::I32Shl, copy(), I::LocalGet(values)]);
+    code.extend(field(24));
+    code.extend([
+        I::I32Const(3),
+        I::I32Shl,
+        I::I32Add,
+        I::LocalGet(argv),
+        I::LocalGet(argc),
+        I::I32Const(3),
+        I::I32Shl,
+        copy(),
+        // Given as many arguments as it takes, it's called in tail position, so that tail
+        // calls through function values don't grow the stack
+        I::LocalGet(count),
+    ]);
+    code.extend(field(8));
+    code.extend([
+        I::I32Eq,
+        I::If(BlockType::Empty),
+        I::LocalGet(fun),
+        I::LocalGet(values),
+    ]);
+    code.extend(field(0));
+    code.extend([
+        I::ReturnCallIndirect {
+            type_index: ENTRY,
+            table_index: 0,
+        },
+        I::End,
+        I::LocalGet(fun),
+        I::LocalGet(values),
+    ]);
+    code.extend(field(0));
+    code.extend([
+        I::CallIndirect {
+            type_index: ENTRY,
+            table_index: 0,
+        },
+        I::LocalSet(result),
+        I::LocalGet(count),
+    ]);
+    code.extend(field(8));
+    code.extend([
+        I::I32GtS,
+        I::If(BlockType::Empty),
+        I::LocalGet(result),
+        I::LocalGet(count),
+    ]);
+    code.extend(field(8));
+    code.extend([I::I32Sub, I::LocalGet(values)]);
+    code.extend(field(8));
+    code.extend([
+        I::I32Const(3),
+        I::I32Shl,
+        I::I32Add,
+        Runtime::Apply.call(),
+        I::Return,
+        I::End,
+        I::LocalGet(result),
+        I::End,
+    ]);
+    let locals = [
+        ValType::I32,
+        ValType::I32,
+        ValType::I32,
+        ValType::I32,
+        ValType::I64,
+    ];
+    func(
+        &[ValType::I64, ValType::I32, ValType::I32],
+        &[ValType::I64],
+        &locals,
+        code,
+    )
+}
+
+// The entry of constructors used as functions, which capture their index
+fn ctor() -> Func {
+    let (fun, args, object, data) = (0, 1, 2, 3);
+    let arity = [I::LocalGet(object), I::I64Load(word(8)), I::I32WrapI64];
+    let mut code = vec![I::LocalGet(fun), I::I32WrapI64, I::LocalSet(object)];
+    code.extend(arity.clone());
+    code.extend([
+        I::I32Const(1),
+        I::I32Add,
+        Runtime::Alloc.call(),
+        I::LocalSet(data),
+        I::LocalGet(data),
+        I::LocalGet(object),
+        I::I64Load(word(32)),
+        I::I64Store(word(0)),
+        I::LocalGet(data),
+        I::I32Const(8),
+        I::I32Add,
+        I::LocalGet(args),
+    ]);
+    code.extend(arity);
+    code.extend([
+        I::I32Const(3),
+        I::I32Shl,
+        copy(),
+        I::LocalGet(data),
+        I::I64ExtendI32U,
+        I::End,
+    ]);
+    func(
+        &[ValType::I64, ValType::I32],
+        &[ValType::I64],
+        &[ValType::I32, ValType::I32],
+        code,
+    )
+}
+
+// The operations on integers take the error they fail with
+const CHECKED: [ValType; 4] = [ValType::I64, ValType::I64, ValType::I32, ValType::I32];
+
+fn add() -> Func {
+    let mut code = vec![
+        I::LocalGet(0),
+        I::LocalGet(1),
+        I::I64Add,
+        I::LocalSet(4),
+        // The operands have the same sign, which the sum doesn't have
+        I::LocalGet(0),
+        I::LocalGet(4),
+        I::I64Xor,
+        I::LocalGet(1),
+        I::LocalGet(4),
+        I::I64Xor,
+        I::I64And,
+        I::I64Const(0),
+        I::I64LtS,
+    ];
+    code.extend(fail(2, 3));
+    code.extend([I::LocalGet(4), I::End]);
+    func(&CHECKED, &[ValType::I64], &[ValType::I64], code)
+}
+fn sub() -> Func {
+    let mut code = vec![
+        I::LocalGet(0),
+        I::LocalGet(1),
+        I::I64Sub,
+        I::LocalSet(4),
+        // The operands have different signs, and the difference doesn't have the first's
+        I::LocalGet(0),
+        I::LocalGet(1),
+        I::I64Xor,
+        I::LocalGet(0),
+        I::LocalGet(4),
+        I::I64Xor,
+        I::I64And,
+        I::I64Const(0),
+        I::I64LtS,
+    ];
+    code.extend(fail(2, 3));
+    code.extend([I::LocalGet(4), I::End]);
+    func(&CHECKED, &[ValType::I64], &[ValType::I64], code)
+}
+fn mul() -> Func {
+    // Dividing the product by an operand doesn't give back the other one, `-1 * i64::MIN`
+    // being checked first as its division overflows too
+    let mut code = vec![
+        I::LocalGet(0),
+        I::I64Const(-1),
+        I::I64Eq,
+        I::LocalGet(1),
+        I::I64Const(i64::MIN),
+        I::I64Eq,
+        I::I32And,
+    ];
+    code.extend(fail(2, 3));
+    code.extend([
+        I::LocalGet(0),
+        I::LocalGet(1),
+        I::I64Mul,
+        I::LocalSet(4),
+        I::LocalGet(0),
+        I::I64Const(0),
+        I::I64Ne,
+        I::If(BlockType::Empty),
+        I::LocalGet(4),
+        I::LocalGet(0),
+        I::I64DivS,
+        I::LocalGet(1),
+        I::I64Ne,
+    ]);
+    code.extend(fail(2, 3));
+    code.extend([I::End, I::LocalGet(4), I::End]);
+    func(&CHECKED, &[ValType::I64], &[ValType::I64], code)
+}
+// Takes the error of a division by zero, then of an overflow
+fn div() -> Func {
+    let mut code = vec![I::LocalGet(1), I::I64Eqz];
+    code.extend(fail(2, 3));
+    code.extend([
+        I::LocalGet(0),
+        I::I64Const(i64::MIN),
+        I::I64Eq,
+        I::LocalGet(1),
+        I::I64Const(-1),
+        I::I64Eq,
+        I::I32And,
+    ]);
+    code.extend(fail(4, 5));
+    code.extend([I::LocalGet(0), I::LocalGet(1), I::I64DivS, I::End]);
+    let params = [CHECKED.as_slice(), &[ValType::I32, ValType::I32]].concat();
+    func(&params, &[ValType::I64], &[], code)
+}
+fn neg() -> Func {
+    let mut code = vec![I::LocalGet(0), I::I64Const(i64::MIN), I::I64Eq];
+    code.extend(fail(1, 2));
+    code.extend([I::I64Const(0), I::LocalGet(0), I::I64Sub, I::End]);
+    func(
+        &[ValType::I64, ValType::I32, ValType::I32],
+        &[ValType::I64],
+        &[],
+        code,
+    )
+}